@@ -0,0 +1,338 @@
+//! The security audit points out what in a kickstart weakens the system it installs, such as
+//! passwords in plain text or a disabled firewall. How bad a finding is decides its severity: an
+//! error is open to anyone, a warning lowers a defence and a note is worth a second look.
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::kickstart::{Kickstart, SectionKind};
+use crate::translate::options::Options;
+use crate::translate::words;
+use crate::version::Version;
+
+/// The options of `user` that take a value.
+const USER_VALUES: &[&str] = &[
+    "--name",
+    "--password",
+    "--groups",
+    "--homedir",
+    "--shell",
+    "--uid",
+    "--gid",
+    "--gecos",
+];
+
+/// Prefixes of password hashes crypt(3) still considers strong: yescrypt, gost-yescrypt,
+/// scrypt, SHA-512, SHA-256 and bcrypt.
+const STRONG_HASHES: &[&str] = &["$y$", "$gy$", "$7$", "$6$", "$5$", "$2b$", "$2y$"];
+
+/// Everything the audit finds in `kickstart`, what the installer does by default depends on
+/// `version`.
+pub fn audit(kickstart: &Kickstart, version: Version) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    for section in kickstart.sections() {
+        let script = match section.kind() {
+            SectionKind::Command => false,
+            SectionKind::Post => true,
+            _ => continue,
+        };
+
+        for (line, span) in section.lines() {
+            let found = if script {
+                script_line(line)
+            } else {
+                command(line, version)
+            };
+
+            diagnostics.extend(found.into_iter().map(|diagnostic| diagnostic.at(span)));
+        }
+    }
+
+    diagnostics
+}
+
+/// The findings in a command line.
+fn command(line: &str, version: Version) -> Vec<Diagnostic> {
+    let words = match words::split(line) {
+        Ok(words) => words,
+        Err(_) => return Vec::new(),
+    };
+
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (name.as_str(), args),
+        None => return Vec::new(),
+    };
+
+    let values = match name {
+        "user" => USER_VALUES,
+        _ => &[],
+    };
+
+    let options = match Options::parse(args, values) {
+        Ok(options) => options,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut found = Vec::new();
+
+    match name {
+        "rootpw" | "user" => {
+            let password = match name {
+                "user" => options.value("--password"),
+                _ => options.positional().first().map(String::as_str),
+            };
+
+            if let Some(password) = password {
+                found.extend(password_strength(name, password, &options));
+            }
+
+            if name == "rootpw" && !options.flag("--lock") {
+                if options.flag("--allow-ssh") {
+                    found.push(
+                        Diagnostic::new(
+                            "root-ssh-password",
+                            Severity::Error,
+                            "'rootpw --allow-ssh' lets root log in over SSH with a password"
+                                .to_string(),
+                        )
+                        .fix("leave out --allow-ssh and add an `sshkey` for root".to_string()),
+                    );
+                } else if password.is_some() && version.root_ssh_password() {
+                    found.push(
+                        Diagnostic::new(
+                            "root-ssh-password",
+                            Severity::Error,
+                            format!(
+                                "root can log in over SSH with its password in {} kickstarts",
+                                version
+                            ),
+                        )
+                        .fix(
+                            "lock the password with --lock and add an `sshkey` for root"
+                                .to_string(),
+                        ),
+                    );
+                }
+            }
+        }
+        "selinux" if options.flag("--disabled") => found.push(Diagnostic::new(
+            "selinux",
+            Severity::Error,
+            "SELinux is disabled".to_string(),
+        )),
+        "selinux" if options.flag("--permissive") => found.push(Diagnostic::new(
+            "selinux",
+            Severity::Warning,
+            "SELinux is permissive, its policy isn't enforced".to_string(),
+        )),
+        "firewall" if options.flag("--disabled") || options.flag("--disable") => {
+            found.push(Diagnostic::new(
+                "firewall-disabled",
+                Severity::Warning,
+                "the firewall is disabled".to_string(),
+            ))
+        }
+        _ => {}
+    }
+
+    found
+}
+
+/// The finding for the password of `rootpw` or `user`, if it's in plain text or weakly hashed.
+fn password_strength(name: &str, password: &str, options: &Options) -> Option<Diagnostic> {
+    if !options.flag("--iscrypted") || options.flag("--plaintext") {
+        return Some(
+            Diagnostic::new(
+                "password-plaintext",
+                Severity::Error,
+                format!("'{}' has a password in plain text", name),
+            )
+            .fix("hash it with `openssl passwd -6` and add --iscrypted".to_string()),
+        );
+    }
+
+    if STRONG_HASHES
+        .iter()
+        .any(|prefix| password.starts_with(prefix))
+    {
+        return None;
+    }
+
+    Some(
+        Diagnostic::new(
+            "password-weak-hash",
+            Severity::Warning,
+            format!("'{}' has a password hashed with a weak algorithm", name),
+        )
+        .fix("hash it with `openssl passwd -6`".to_string()),
+    )
+}
+
+/// The findings in a line of a `%post` script.
+fn script_line(line: &str) -> Vec<Diagnostic> {
+    let line = line.trim();
+
+    if line.starts_with('#') {
+        return Vec::new();
+    }
+
+    let mut found = Vec::new();
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    for (index, word) in words.iter().enumerate() {
+        let (command, mode) = match *word {
+            "chmod" => (
+                *word,
+                words[index + 1..]
+                    .iter()
+                    .find(|arg| !arg.starts_with('-'))
+                    .copied(),
+            ),
+            "mkdir" | "install" => (
+                *word,
+                words[index + 1..]
+                    .iter()
+                    .zip(&words[index + 2..])
+                    .find(|(option, _)| **option == "-m" || **option == "--mode")
+                    .map(|(_, mode)| *mode)
+                    .or_else(|| {
+                        words[index + 1..]
+                            .iter()
+                            .find_map(|arg| arg.strip_prefix("--mode="))
+                    }),
+            ),
+            _ => continue,
+        };
+
+        let sticky = match mode.and_then(world_writable) {
+            Some(sticky) => sticky,
+            None => continue,
+        };
+
+        found.push(if sticky {
+            Diagnostic::new(
+                "world-writable",
+                Severity::Note,
+                format!(
+                    "'{}' in %post creates a world-writable directory with the sticky bit",
+                    command
+                ),
+            )
+        } else {
+            Diagnostic::new(
+                "world-writable",
+                Severity::Warning,
+                format!("'{}' in %post makes a file world-writable", command),
+            )
+            .fix("leave write permission to the owner and group".to_string())
+        });
+    }
+
+    if line
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .windows(2)
+        .any(|pair| pair[0].ends_with("PermitRootLogin") && pair[1].starts_with("yes"))
+    {
+        found.push(Diagnostic::new(
+            "root-ssh-password",
+            Severity::Error,
+            "%post sets 'PermitRootLogin yes', root can log in over SSH with a password"
+                .to_string(),
+        ));
+    }
+
+    found
+}
+
+/// Whether `mode` lets others write, and if so whether it has the sticky bit. `None` when it
+/// doesn't let others write or isn't a mode.
+fn world_writable(mode: &str) -> Option<bool> {
+    if mode.chars().all(|c| c.is_digit(8)) {
+        let bits = u32::from_str_radix(mode, 8).ok()?;
+
+        return (bits & 0o002 != 0).then_some(bits & 0o1000 != 0);
+    }
+
+    let writable = mode.split(',').any(|clause| {
+        let (who, perms) = match clause.find(['+', '=']) {
+            Some(index) => clause.split_at(index),
+            None => return false,
+        };
+
+        (who.contains('o') || who.contains('a')) && perms.contains('w')
+    });
+
+    writable.then_some(mode.contains('t'))
+}
+
+#[test]
+fn audit_world_writable() {
+    assert_eq!(world_writable("777"), Some(false));
+    assert_eq!(world_writable("0644"), None);
+    assert_eq!(world_writable("1777"), Some(true));
+    assert_eq!(world_writable("o+w"), Some(false));
+    assert_eq!(world_writable("u+w,a+rwt"), Some(true));
+    assert_eq!(world_writable("go-w"), None);
+    assert_eq!(world_writable("/etc/motd"), None);
+}
+
+#[test]
+fn audit_kickstart() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-audit-kickstart");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "rootpw --allow-ssh hunter2\n\
+         user --name=john --iscrypted --password=$1$salt$hash\n\
+         user --name=jane --iscrypted --password=$6$salt$hash\n\
+         selinux --permissive\n\
+         firewall --disabled\n\
+         %post\n\
+         # chmod 777 /etc\n\
+         chmod -R 0777 /srv/share\n\
+         mkdir -m 1777 /scratch\n\
+         echo 'PermitRootLogin yes' >> /etc/ssh/sshd_config\n\
+         %end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+
+    assert_eq!(
+        audit(&kickstart, Version::LATEST)
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.severity, diagnostic.line))
+            .collect::<Vec<_>>(),
+        vec![
+            ("world-writable", Severity::Warning, Some(8)),
+            ("world-writable", Severity::Note, Some(9)),
+            ("root-ssh-password", Severity::Error, Some(10)),
+            ("password-plaintext", Severity::Error, Some(1)),
+            ("root-ssh-password", Severity::Error, Some(1)),
+            ("password-weak-hash", Severity::Warning, Some(2)),
+            ("selinux", Severity::Warning, Some(4)),
+            ("firewall-disabled", Severity::Warning, Some(5)),
+        ]
+    );
+
+    fs::write(dir.join("old.ks"), "rootpw --iscrypted $6$salt$hash\n").unwrap();
+
+    let codes = |version: Version| {
+        let kickstart = Kickstart::from_path(&dir.join("old.ks"), &includes, version).unwrap();
+
+        audit(&kickstart, version)
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(codes(Version::Rhel(8)), vec!["root-ssh-password"]);
+    assert!(codes(Version::LATEST).is_empty());
+}
@@ -0,0 +1,182 @@
+//! Converting many kickstarts at once, all of those in a directory or those matching a pattern
+//! such as `hosts/*.ks`. Each is written into the output directory under its own name, and one
+//! that fails doesn't stop the others.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::exit;
+
+/// The extensions of the kickstarts that are found in a directory.
+const EXTENSIONS: &[&str] = &["ks", "cfg"];
+
+/// Whether `name` matches `pattern`, where `*` is any number of characters and `?` is one.
+fn wildcard(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, _) => name.is_empty(),
+        (Some(('*', rest)), _) => {
+            wildcard(rest, name) || (!name.is_empty() && wildcard(pattern, &name[1..]))
+        }
+        (Some(('?', rest)), Some((_, name))) => wildcard(rest, name),
+        (Some((expected, rest)), Some((found, name))) => expected == found && wildcard(rest, name),
+        (Some(_), None) => false,
+    }
+}
+
+/// The kickstarts of `src`: the files ending in `.ks` or `.cfg` of a directory, or the files
+/// matching a pattern in its last component. They're sorted by path.
+pub fn kickstarts(src: &str) -> io::Result<Vec<PathBuf>> {
+    let path = Path::new(src);
+
+    let (dir, pattern) = match path.file_name().map(|name| name.to_string_lossy()) {
+        _ if path.is_dir() => (path, None),
+        Some(name) if name.contains(['*', '?']) => (
+            path.parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new(".")),
+            Some(name.chars().collect::<Vec<_>>()),
+        ),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a directory or a pattern",
+            ))
+        }
+    };
+
+    let mut kickstarts = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if !path.is_file() {
+            continue;
+        }
+
+        let found = match (&pattern, path.file_name()) {
+            (Some(pattern), Some(name)) => {
+                wildcard(pattern, &name.to_string_lossy().chars().collect::<Vec<_>>())
+            }
+            (None, _) => path
+                .extension()
+                .is_some_and(|extension| EXTENSIONS.iter().any(|known| extension == *known)),
+            _ => false,
+        };
+
+        if found {
+            kickstarts.push(path);
+        }
+    }
+
+    kickstarts.sort();
+
+    Ok(kickstarts)
+}
+
+/// The extension of what's written with `--output-format`.
+pub fn extension(output_format: &str) -> &'static str {
+    match output_format {
+        "yaml" | "otk" | "cloud-init" => "yaml",
+        "blueprint" => "toml",
+        "ignition" => "ign",
+        _ => "json",
+    }
+}
+
+/// Where what's made of the kickstart at `src` is written, in `out_dir` and named after it.
+pub fn output(src: &Path, out_dir: &Path, extension: &str) -> PathBuf {
+    let stem = src.file_stem().unwrap_or_default().to_string_lossy();
+
+    out_dir.join(format!("{}.{}", stem, extension))
+}
+
+/// Which kickstarts were converted and why the others weren't.
+#[derive(Debug, Default)]
+pub struct Summary {
+    kickstarts: Vec<(PathBuf, Result<PathBuf, exit::Code>)>,
+}
+
+impl Summary {
+    pub fn add(&mut self, src: &Path, converted: Result<PathBuf, exit::Code>) {
+        self.kickstarts.push((src.to_path_buf(), converted));
+    }
+
+    /// The code of the first kickstart that failed.
+    pub fn code(&self) -> Option<exit::Code> {
+        self.kickstarts
+            .iter()
+            .find_map(|(_, converted)| converted.as_ref().err().copied())
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut failed = 0;
+
+        for (src, converted) in &self.kickstarts {
+            match converted {
+                Ok(dst) => writeln!(f, "converted {} -> {}", src.display(), dst.display())?,
+                Err(code) => {
+                    writeln!(
+                        f,
+                        "failed    {} (exit code {})",
+                        src.display(),
+                        *code as i32
+                    )?;
+                    failed += 1;
+                }
+            }
+        }
+
+        write!(
+            f,
+            "{} converted, {} failed",
+            self.kickstarts.len() - failed,
+            failed
+        )
+    }
+}
+
+#[test]
+fn batch_kickstarts() {
+    let dir = std::env::temp_dir().join("osbuild-ks-batch-kickstarts");
+    fs::create_dir_all(dir.join("nested.ks")).unwrap();
+
+    for name in ["web.ks", "db.cfg", "notes.txt", "web-old.ks"] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+
+    let names = |src: &Path| -> Vec<String> {
+        kickstarts(&src.display().to_string())
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    };
+
+    assert_eq!(names(&dir), ["db.cfg", "web-old.ks", "web.ks"]);
+    assert_eq!(names(&dir.join("web*.ks")), ["web-old.ks", "web.ks"]);
+    assert_eq!(names(&dir.join("??.cfg")), ["db.cfg"]);
+    assert!(kickstarts(&dir.join("web.ks").display().to_string()).is_err());
+
+    assert_eq!(
+        output(
+            &dir.join("web.ks"),
+            Path::new("out"),
+            extension("blueprint")
+        ),
+        Path::new("out/web.toml")
+    );
+
+    let mut summary = Summary::default();
+    summary.add(Path::new("web.ks"), Ok(PathBuf::from("out/web.json")));
+    summary.add(Path::new("db.cfg"), Err(exit::Code::Unsupported));
+
+    assert_eq!(summary.code(), Some(exit::Code::Unsupported));
+    assert_eq!(
+        summary.to_string(),
+        "converted web.ks -> out/web.json\nfailed    db.cfg (exit code 5)\n1 converted, 1 failed"
+    );
+}
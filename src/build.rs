@@ -0,0 +1,81 @@
+//! Building hands the manifest to a locally installed osbuild, for trying out a kickstart without
+//! a separate step. osbuild prints its progress as it goes.
+
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// The pipeline that makes the artifact of `names`, the last one except for the kickstart that's
+/// passed through. Version 1 manifests name it `assembler`.
+pub fn artifact<'a>(names: &[&'a str], manifest_version: u32) -> Option<&'a str> {
+    if manifest_version == 1 {
+        return Some("assembler");
+    }
+
+    names
+        .iter()
+        .rev()
+        .find(|name| **name != "kickstart")
+        .copied()
+}
+
+/// Build `manifest` with osbuild, which caches in `store` and writes the trees of `exports` to
+/// `output`.
+pub fn build(
+    manifest: &str,
+    store: &Path,
+    output: &Path,
+    exports: &[&str],
+    checkpoints: &[&str],
+) -> Result<(), String> {
+    let mut command = Command::new("osbuild");
+    command
+        .arg("--store")
+        .arg(store)
+        .arg("--output-directory")
+        .arg(output);
+
+    for export in exports {
+        command.arg("--export").arg(export);
+    }
+
+    for checkpoint in checkpoints {
+        command.arg("--checkpoint").arg(checkpoint);
+    }
+
+    // The manifest is read from stdin, it's JSON whatever was written.
+    let mut child = command
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound => "osbuild isn't installed".to_string(),
+            _ => format!("failed to run osbuild: {}", err),
+        })?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(manifest.as_bytes())
+            .map_err(|err| format!("failed to pass the manifest to osbuild: {}", err))?;
+    }
+
+    let status = child
+        .wait()
+        .map_err(|err| format!("failed to run osbuild: {}", err))?;
+
+    if !status.success() {
+        return Err(format!("osbuild failed, {}", status));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn build_artifact() {
+    assert_eq!(
+        artifact(&["build", "os", "image", "qcow2", "kickstart"], 2),
+        Some("qcow2")
+    );
+    assert_eq!(artifact(&["build", "os"], 1), Some("assembler"));
+    assert_eq!(artifact(&[], 2), None);
+}
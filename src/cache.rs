@@ -0,0 +1,89 @@
+//! An on-disk cache of what was fetched, so converting a kickstart again doesn't download what
+//! didn't change. Entries are kept along with the `ETag` and `Last-Modified` the server sent and
+//! are only used when the server answers a conditional request with `304 Not Modified`.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Where the cache is kept unless another directory is given, `osbuild-ks` in `$XDG_CACHE_HOME`
+/// or in `~/.cache`.
+pub fn default_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("osbuild-ks"))
+}
+
+/// What was fetched from `url`, with what tells whether it changed since.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub data: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Entries are named after the digest of their URL.
+    fn path(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:x}.json", Sha256::digest(url)))
+    }
+
+    /// The entry for `url`, if there is one that can be read.
+    pub fn get(&self, url: &str) -> Option<Entry> {
+        fs::read_to_string(self.path(url))
+            .ok()
+            .and_then(|data| serde_json::from_str::<Entry>(&data).ok())
+            .filter(|entry| entry.url == url)
+    }
+
+    /// Keep `entry`, replacing the one for its URL. It's written next to where it goes first so
+    /// a conversion running alongside never reads half of it.
+    pub fn put(&self, entry: &Entry) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        let path = self.path(&entry.url);
+        let partial = path.with_extension(format!("{}.partial", std::process::id()));
+
+        fs::write(&partial, serde_json::to_string(entry)?)?;
+        fs::rename(&partial, &path)
+    }
+}
+
+#[test]
+fn cache_entries() {
+    let dir = env::temp_dir().join("osbuild-ks-cache-entries");
+    let _ = fs::remove_dir_all(&dir);
+
+    let cache = Cache::new(dir.join("nested"));
+    let entry = Entry {
+        url: "https://example.com/a.ks".to_string(),
+        etag: Some("\"1\"".to_string()),
+        last_modified: None,
+        data: "lang en_US\n".to_string(),
+    };
+
+    assert_eq!(cache.get(&entry.url), None);
+
+    cache.put(&entry).unwrap();
+    assert_eq!(cache.get(&entry.url), Some(entry.clone()));
+    assert_eq!(cache.get("https://example.com/b.ks"), None);
+
+    fs::write(cache.path(&entry.url), "{").unwrap();
+    assert_eq!(cache.get(&entry.url), None);
+}
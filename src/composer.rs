@@ -0,0 +1,123 @@
+//! A client for the Cloud API of osbuild-composer, which builds images from compose requests on
+//! its own workers. Requests go through the proxy set in the environment, like those of
+//! [`fetch`](crate::fetch).
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the Cloud API is below the URL of osbuild-composer.
+const API: &str = "api/image-builder-composer/v2";
+
+/// What osbuild-composer says about a compose.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct ComposeStatus {
+    /// `pending`, `success` or `failure`.
+    pub status: String,
+    /// The status of the image, such as `building` or `uploading`.
+    #[serde(default)]
+    pub image_status: Value,
+}
+
+#[derive(Deserialize)]
+struct ComposeId {
+    id: String,
+}
+
+pub struct Composer {
+    url: String,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl Composer {
+    /// A client for the osbuild-composer at `url`, which is authenticated with `token` if it's
+    /// given.
+    pub fn new(url: &str, token: Option<&str>) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            token: token.map(str::to_string),
+            agent: ureq::AgentBuilder::new()
+                .timeout(TIMEOUT)
+                .try_proxy_from_env(true)
+                .build(),
+        }
+    }
+
+    /// Submit the compose `request`, returning its id.
+    pub fn compose(&self, request: &Value) -> Result<String, String> {
+        let response = self
+            .request("POST", "compose")
+            .set("Content-Type", "application/json")
+            .send_string(&request.to_string());
+        let compose: ComposeId = parse(response)?;
+
+        Ok(compose.id)
+    }
+
+    /// The status of the compose with `id`.
+    pub fn status(&self, id: &str) -> Result<ComposeStatus, String> {
+        parse(self.request("GET", &format!("composes/{}", id)).call())
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}/{}/{}", self.url, API, path));
+
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+/// The body of `response`, errors of the API carry a `reason`.
+fn parse<T: for<'de> Deserialize<'de>>(
+    response: Result<ureq::Response, ureq::Error>,
+) -> Result<T, String> {
+    let body = match response {
+        Ok(response) => response.into_string().map_err(|err| err.to_string())?,
+        Err(ureq::Error::Status(code, response)) => {
+            let body = response.into_string().unwrap_or_default();
+            let reason = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|error| error["reason"].as_str().map(str::to_string))
+                .unwrap_or(body);
+
+            return Err(format!("osbuild-composer answered {}: {}", code, reason));
+        }
+        Err(err) => return Err(err.to_string()),
+    };
+
+    serde_json::from_str(&body).map_err(|err| format!("unexpected answer: {}", err))
+}
+
+#[test]
+fn composer_parse() {
+    let response = |status: u16, body: &str| ureq::Response::new(status, "", body).unwrap();
+
+    let status: ComposeStatus = parse(Ok(response(
+        200,
+        r#"{"status": "pending", "image_status": {"status": "building"}}"#,
+    )))
+    .unwrap();
+    assert_eq!(status.status, "pending");
+    assert_eq!(status.image_status["status"], "building");
+
+    let error = parse::<ComposeId>(Err(ureq::Error::Status(
+        400,
+        response(
+            400,
+            r#"{"id": "13", "reason": "Compose request is invalid"}"#,
+        ),
+    )));
+    assert_eq!(
+        error.err().unwrap(),
+        "osbuild-composer answered 400: Compose request is invalid"
+    );
+    assert!(parse::<ComposeId>(Ok(response(201, "{}"))).is_err());
+}
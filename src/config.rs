@@ -0,0 +1,155 @@
+//! Defaults for the command line, from `config.toml` in the user's `~/.config/osbuild-ks` and the
+//! `.osbuild-ks.toml` of the project, in the working directory or above it. Keys are named after
+//! the options they give defaults for, what the project says wins over what the user says and
+//! options on the command line win over both.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::toml;
+
+/// The config file of a project.
+const PROJECT: &str = ".osbuild-ks.toml";
+
+/// The options that can be given defaults, and whether their values are paths. Paths are
+/// relative to the directory of the config file they're in.
+const KEYS: &[(&str, bool)] = &[
+    ("distro", false),
+    ("arch", false),
+    ("include", true),
+    ("define", false),
+    ("proxy", false),
+    ("ca-cert", true),
+    ("cache-dir", true),
+    ("output-format", false),
+    ("warning", false),
+    ("warning-config", true),
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    defaults: BTreeMap<&'static str, Vec<String>>,
+}
+
+impl Config {
+    /// The user's config and then the project's, if they exist.
+    pub fn load() -> Result<Self, String> {
+        let user = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("osbuild-ks").join("config.toml"));
+
+        let project = env::current_dir().ok().and_then(|cwd| {
+            cwd.ancestors()
+                .map(|dir| dir.join(PROJECT))
+                .find(|path| path.is_file())
+        });
+
+        let mut config = Self::default();
+
+        for path in [user, project].into_iter().flatten() {
+            if path.is_file() {
+                config = config.file(&path)?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Take the defaults in the config file at `path` over those that are already set.
+    pub fn file(mut self, path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read '{}': {}", path.display(), err))?;
+        let table = toml::from_str(&data)
+            .map_err(|err| format!("failed to parse '{}': {}", path.display(), err))?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        for (key, value) in table {
+            let (key, is_path) = KEYS
+                .iter()
+                .find(|(known, _)| *known == key)
+                .ok_or_else(|| format!("'{}': '{}' is not an option", path.display(), key))?;
+
+            let values = match value {
+                Value::String(value) => vec![value],
+                Value::Array(values) => values
+                    .into_iter()
+                    .map(|value| match value {
+                        Value::String(value) => Ok(value),
+                        _ => Err(format!("'{}': '{}' isn't a string", path.display(), key)),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(format!("'{}': '{}' isn't a string", path.display(), key)),
+            };
+
+            let values = match is_path {
+                true => values
+                    .iter()
+                    .map(|value| dir.join(value).display().to_string())
+                    .collect(),
+                false => values,
+            };
+
+            self.defaults.insert(key, values);
+        }
+
+        Ok(self)
+    }
+
+    /// The options that are given defaults, with their values.
+    pub fn defaults(&self) -> impl Iterator<Item = (&'static str, &[String])> {
+        self.defaults
+            .iter()
+            .map(|(key, values)| (*key, values.as_slice()))
+    }
+}
+
+#[test]
+fn config_file() {
+    let dir = std::env::temp_dir().join("osbuild-ks-config-file");
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(
+        dir.join("user.toml"),
+        "distro = \"centos\"\narch = \"aarch64\"\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join(PROJECT),
+        "distro = \"rhel\"\ninclude = [\"includes\", \"/srv/ks\"]\n\
+         warning = [\"error=deprecated-command\", \"no-untranslated\"]\n",
+    )
+    .unwrap();
+    fs::write(dir.join("bad.toml"), "format = \"qcow2\"\n").unwrap();
+
+    let config = Config::default()
+        .file(&dir.join("user.toml"))
+        .unwrap()
+        .file(&dir.join(PROJECT))
+        .unwrap();
+
+    let includes = dir.join("includes").display().to_string();
+
+    assert_eq!(
+        config.defaults().collect::<Vec<_>>(),
+        [
+            ("arch", &["aarch64".to_string()][..]),
+            ("distro", &["rhel".to_string()][..]),
+            ("include", &[includes, "/srv/ks".to_string()][..]),
+            (
+                "warning",
+                &[
+                    "error=deprecated-command".to_string(),
+                    "no-untranslated".to_string()
+                ][..]
+            ),
+        ]
+    );
+    assert!(Config::default().file(&dir.join("bad.toml")).is_err());
+}
@@ -0,0 +1,669 @@
+//! Diagnostics are the errors and warnings found while converting a kickstart. They are printed
+//! for people by default, or as structured records so CI systems don't have to scrape stderr.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::kickstart::{KickstartError, Span};
+use crate::translate::{Entry, Outcome, TranslateError};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    /// Worth knowing about, but not a problem by itself.
+    Note,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diagnostic {
+    /// What kind of problem this is, stable so it can be matched on.
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+    /// What can be done about the problem, if anything obvious.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<String>,
+}
+
+/// How diagnostics are printed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Errors and warnings on stderr with the lines they are about, in color if `color` is set.
+    Text { color: bool },
+    /// A JSON array of diagnostics on stdout.
+    Json,
+    /// A SARIF log on stdout, for code scanning UIs.
+    Sarif,
+}
+
+pub const FORMATS: &[&str] = &["text", "json", "sarif"];
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text { color: false }),
+            "json" => Ok(Format::Json),
+            "sarif" => Ok(Format::Sarif),
+            _ => Err(format!("'{}' is not a diagnostics format", s)),
+        }
+    }
+}
+
+/// How seriously problems are taken, the same for reading, validating and translating.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Warnings are errors too.
+    Strict,
+    Default,
+    /// Problems reading the kickstart are warnings, what could be read is used.
+    Permissive,
+}
+
+/// The codes of the problems found while reading a kickstart, [`Mode::Permissive`] goes on
+/// after them.
+const READING: &[&str] = &[
+    "read",
+    "fetch",
+    "parse",
+    "missing-end",
+    "encoding",
+    "include-missing",
+    "include-network",
+    "include-cycle",
+    "include-depth",
+    "undefined",
+    "undefined-env",
+    "invalid",
+];
+
+impl Mode {
+    /// `diagnostics` with the severity this mode gives them.
+    pub fn apply(self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .map(|mut diagnostic| {
+                match (self, diagnostic.severity) {
+                    (Mode::Strict, Severity::Warning) => diagnostic.severity = Severity::Error,
+                    (Mode::Permissive, Severity::Error) if READING.contains(&diagnostic.code) => {
+                        diagnostic.severity = Severity::Warning
+                    }
+                    _ => {}
+                }
+
+                diagnostic
+            })
+            .collect()
+    }
+}
+
+impl Diagnostic {
+    pub fn new(code: &'static str, severity: Severity, message: String) -> Self {
+        Self {
+            code,
+            severity,
+            message,
+            file: None,
+            line: None,
+            column: None,
+            fix: None,
+        }
+    }
+
+    pub fn at(mut self, span: Option<&Span>) -> Self {
+        if let Some(span) = span {
+            self.file = Some(span.path.clone());
+            self.line = Some(span.line);
+            self.column = Some(span.column);
+        }
+
+        self
+    }
+
+    pub fn fix(mut self, fix: String) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+
+    /// The diagnostic for people, with the line of `source` it's at and the word it starts at
+    /// underlined, like rustc does.
+    fn render(&self, source: Option<&str>, color: bool) -> String {
+        let (severity, style) = match self.severity {
+            Severity::Error => ("error", RED),
+            Severity::Warning => ("warning", YELLOW),
+            Severity::Note => ("note", GREEN),
+        };
+
+        let mut rendered = format!(
+            "{}{}\n",
+            paint(&format!("{}[{}]", severity, self.code), style, color),
+            paint(&format!(": {}", self.message), BOLD, color)
+        );
+
+        let gutter = " ".repeat(self.line.map_or(0, |line| line.to_string().len()));
+
+        if let Some(file) = &self.file {
+            let at = match (self.line, self.column) {
+                (Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+                _ => file.clone(),
+            };
+
+            rendered += &format!("{}{} {}\n", gutter, paint("-->", BLUE, color), at);
+        }
+
+        let text = match (source, self.line) {
+            (Some(source), Some(line)) => source.lines().nth(line.saturating_sub(1)),
+            _ => None,
+        };
+
+        if let (Some(text), Some(line)) = (text, self.line) {
+            let column = self.column.unwrap_or(1).saturating_sub(1);
+
+            // Tabs are kept so the carets line up with what's above them.
+            let indent: String = text
+                .chars()
+                .take(column)
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+            let width = text
+                .chars()
+                .skip(column)
+                .take_while(|c| !c.is_whitespace())
+                .count();
+
+            rendered += &format!("{} {}\n", gutter, paint("|", BLUE, color));
+            rendered += &format!(
+                "{}\n",
+                paint(&format!("{} |", line), BLUE, color) + " " + text
+            );
+            rendered += &format!(
+                "{} {} {}{}\n",
+                gutter,
+                paint("|", BLUE, color),
+                indent,
+                paint(&"^".repeat(width.max(1)), style, color)
+            );
+        }
+
+        if let Some(fix) = &self.fix {
+            rendered += &format!("{} {} help: {}\n", gutter, paint("=", BLUE, color), fix);
+        }
+
+        rendered
+    }
+
+    /// The errors in reading a kickstart, one for each problem that was found.
+    pub fn from_kickstart(err: &KickstartError) -> Vec<Self> {
+        let (span, err) = match err {
+            KickstartError::Invalid(errors) => {
+                return errors.iter().flat_map(Self::from_kickstart).collect()
+            }
+            KickstartError::At(span, err) => (Some(span), err.as_ref()),
+            err => (None, err),
+        };
+
+        let (code, fix) = match err {
+            KickstartError::IO(..) => ("read", None),
+            KickstartError::Fetch(..) => ("fetch", None),
+            KickstartError::Parse(_) => ("parse", None),
+            KickstartError::MissingEnd(_) => ("missing-end", None),
+            KickstartError::Encoding(..) => {
+                ("encoding", Some("save the file as UTF-8".to_string()))
+            }
+            KickstartError::Missing(target, _) => (
+                "include-missing",
+                Some(format!(
+                    "add the directory that has '{}' to the include path with -I",
+                    target
+                )),
+            ),
+            KickstartError::Network(_) => (
+                "include-network",
+                Some("pass --allow-network to fetch it".to_string()),
+            ),
+            KickstartError::Undefined(key) => (
+                "undefined",
+                Some(format!(
+                    "give it a value with --define {}=<value> or a default with ${{{}:-<default>}}",
+                    key, key
+                )),
+            ),
+            KickstartError::Environment(name) => (
+                "undefined-env",
+                Some(format!(
+                    "set {} or give a default with ${{env:{}:-<default>}}",
+                    name, name
+                )),
+            ),
+            KickstartError::IncludeCycle(_) => ("include-cycle", None),
+            KickstartError::IncludeDepth(_) => ("include-depth", None),
+            KickstartError::Invalid(_) | KickstartError::At(..) => ("invalid", None),
+        };
+
+        let diagnostic = Self::new(code, Severity::Error, err.to_string()).at(span);
+
+        vec![match fix {
+            Some(fix) => diagnostic.fix(fix),
+            None => diagnostic,
+        }]
+    }
+
+    /// The errors that stopped a translation, one for each problem with the storage plan.
+    pub fn from_translate(err: &TranslateError) -> Vec<Self> {
+        if let TranslateError::StoragePlan(problems) = err {
+            return problems
+                .iter()
+                .map(|problem| {
+                    Self::new(problem.code, Severity::Error, problem.message.clone())
+                        .at(problem.span.as_ref())
+                })
+                .collect();
+        }
+
+        let code = match err {
+            TranslateError::Fetch(..) => "fetch",
+            TranslateError::Strict(_) => "strict",
+            TranslateError::Storage(_) | TranslateError::StoragePlan(_) => "storage",
+            TranslateError::Bootloader(_) => "bootloader",
+            TranslateError::Unsupported(_) => "unsupported",
+            TranslateError::Removed(_) => "removed-command",
+            TranslateError::UnknownPipeline(_) => "unknown-pipeline",
+            TranslateError::ManifestVersion(_) => "manifest-version",
+            TranslateError::Network(_) => "gpgkey-network",
+        };
+
+        let diagnostic = Self::new(code, Severity::Error, err.to_string());
+
+        vec![match err {
+            TranslateError::Strict(_) => {
+                diagnostic.fix("leave out --strict to skip it".to_string())
+            }
+            TranslateError::Network(_) => {
+                diagnostic.fix("pass --allow-network to fetch it".to_string())
+            }
+            _ => diagnostic,
+        }]
+    }
+
+    /// The warnings for an element of the report, elements that were translated only have those
+    /// for being deprecated.
+    pub fn from_entry(entry: &Entry) -> Vec<Self> {
+        let mut diagnostics: Vec<Self> = entry
+            .deprecated
+            .iter()
+            .map(|deprecated| {
+                Self::new("deprecated-command", Severity::Warning, deprecated.clone())
+                    .at(entry.span.as_ref())
+            })
+            .collect();
+
+        let (code, message) = match &entry.outcome {
+            Outcome::Translated => return diagnostics,
+            Outcome::Skipped(reason) => (
+                "skipped",
+                format!("skipped '{}': {}", entry.element, reason),
+            ),
+            Outcome::Untranslated(reason) => (
+                "untranslated",
+                format!("not translated '{}': {}", entry.element, reason),
+            ),
+        };
+
+        diagnostics.push(Self::new(code, Severity::Warning, message).at(entry.span.as_ref()));
+        diagnostics
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let (Some(file), Some(line), Some(column)) = (&self.file, self.line, self.column) {
+            write!(f, "{}:{}:{}: ", file, line, column)?;
+        }
+
+        write!(f, "{}", self.message)?;
+
+        if let Some(fix) = &self.fix {
+            write!(f, ", {}", fix)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Print `diagnostics` in `format`.
+pub fn emit(format: Format, diagnostics: &[Diagnostic]) {
+    match format {
+        Format::Text { color } => {
+            let mut sources = HashMap::new();
+
+            for diagnostic in diagnostics {
+                let level = match diagnostic.severity {
+                    Severity::Error => Level::Error,
+                    Severity::Warning => Level::Warn,
+                    Severity::Note => Level::Info,
+                };
+
+                if !log_enabled!(level) {
+                    continue;
+                }
+
+                // Files that can't be read again, such as standard input, are shown without
+                // their lines.
+                let source = diagnostic.file.as_ref().and_then(|file| {
+                    sources
+                        .entry(file.clone())
+                        .or_insert_with(|| fs::read_to_string(file).ok())
+                        .clone()
+                });
+
+                eprintln!("{}", diagnostic.render(source.as_deref(), color));
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(diagnostics).unwrap()),
+        Format::Sarif => println!(
+            "{}",
+            serde_json::to_string_pretty(&sarif(diagnostics)).unwrap()
+        ),
+    }
+}
+
+/// Prints diagnostics in a format, or only those that changed since the last time for `--watch`.
+#[derive(Clone, Debug)]
+pub struct Emitter {
+    format: Format,
+    last: Option<Vec<Diagnostic>>,
+}
+
+impl Emitter {
+    pub fn new(format: Format) -> Self {
+        Self { format, last: None }
+    }
+
+    /// Only print the diagnostics that weren't printed the last time.
+    pub fn changes(mut self) -> Self {
+        self.last = Some(Vec::new());
+        self
+    }
+
+    pub fn emit(&mut self, diagnostics: &[Diagnostic]) {
+        let last = match &mut self.last {
+            Some(last) => last,
+            None => return emit(self.format, diagnostics),
+        };
+
+        let gone = last
+            .iter()
+            .filter(|diagnostic| !diagnostics.contains(diagnostic))
+            .count();
+        let new: Vec<_> = diagnostics
+            .iter()
+            .filter(|diagnostic| !last.contains(diagnostic))
+            .cloned()
+            .collect();
+
+        if gone > 0 {
+            info!("{} errors and warnings are gone", gone);
+        }
+
+        emit(self.format, &new);
+
+        *last = diagnostics.to_vec();
+    }
+}
+
+const BOLD: &str = "1";
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const GREEN: &str = "1;32";
+const BLUE: &str = "1;34";
+
+/// `text` in the ANSI `style` when `color` is set.
+fn paint(text: &str, style: &str, color: bool) -> String {
+    match color {
+        true => format!("\x1b[{}m{}\x1b[0m", style, text),
+        false => text.to_string(),
+    }
+}
+
+/// A SARIF 2.1.0 log with a single run that has `diagnostics` as its results.
+pub fn sarif(diagnostics: &[Diagnostic]) -> Value {
+    let mut rules: Vec<&str> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.code)
+        .collect();
+    rules.sort_unstable();
+    rules.dedup();
+
+    let results: Vec<Value> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let mut message = diagnostic.message.clone();
+
+            if let Some(fix) = &diagnostic.fix {
+                message = format!("{}, {}", message, fix);
+            }
+
+            let mut result = json!({
+                "ruleId": diagnostic.code,
+                "level": match diagnostic.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                    Severity::Note => "note",
+                },
+                "message": { "text": message },
+            });
+
+            if let Some(file) = &diagnostic.file {
+                let mut region = json!({});
+
+                if let Some(line) = diagnostic.line {
+                    region["startLine"] = json!(line);
+                }
+
+                if let Some(column) = diagnostic.column {
+                    region["startColumn"] = json!(column);
+                }
+
+                result["locations"] = json!([{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": uri(file) },
+                        "region": region,
+                    }
+                }]);
+            }
+
+            result
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/supakeen/osbuild-ks",
+                    "rules": rules
+                        .iter()
+                        .map(|rule| json!({ "id": rule }))
+                        .collect::<Vec<_>>(),
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+/// The URI of `file` in a SARIF log. Files below the working directory are relative to it, which
+/// is where code scanning expects the checkout to be, other files are absolute and URLs are kept.
+fn uri(file: &str) -> String {
+    if file.contains("://") {
+        return file.to_string();
+    }
+
+    let relative = std::env::current_dir().ok().and_then(|cwd| {
+        Path::new(file)
+            .strip_prefix(cwd)
+            .ok()
+            .map(Path::to_path_buf)
+    });
+
+    let uri = match relative {
+        Some(relative) => relative.display().to_string(),
+        None => format!("file://{}", file),
+    };
+
+    uri.replace('%', "%25").replace(' ', "%20")
+}
+
+#[test]
+fn diagnostics_from_kickstart() {
+    let span = Span {
+        path: "/ks/main.ks".to_string(),
+        line: 3,
+        column: 1,
+    };
+
+    let diagnostics = Diagnostic::from_kickstart(&KickstartError::Invalid(vec![
+        KickstartError::At(
+            span,
+            Box::new(KickstartError::Network(
+                "https://example.com/a.ks".to_string(),
+            )),
+        ),
+        KickstartError::Parse("'%end' without a section to end".to_string()),
+    ]));
+
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].code, "include-network");
+    assert_eq!(diagnostics[0].line, Some(3));
+    assert!(diagnostics[0].to_string().starts_with("/ks/main.ks:3:1: "));
+    assert_eq!(diagnostics[1].file, None);
+
+    assert_eq!(
+        serde_json::to_value(&diagnostics[1]).unwrap(),
+        serde_json::json!({
+            "code": "parse",
+            "severity": "error",
+            "message": "failed to parse kickstart: '%end' without a section to end",
+        })
+    );
+}
+
+#[test]
+fn diagnostics_sarif() {
+    let cwd = std::env::current_dir().unwrap();
+
+    let diagnostics = vec![
+        Diagnostic::new("parse", Severity::Error, "no %end".to_string()).at(Some(&Span {
+            path: cwd.join("ks/main ks.ks").display().to_string(),
+            line: 3,
+            column: 2,
+        })),
+        Diagnostic::new("skipped", Severity::Warning, "skipped 'reboot'".to_string()).at(Some(
+            &Span {
+                path: "/elsewhere/main.ks".to_string(),
+                line: 1,
+                column: 1,
+            },
+        )),
+        Diagnostic::new("parse", Severity::Error, "no section".to_string()),
+    ];
+
+    let log = sarif(&diagnostics);
+    let run = &log["runs"][0];
+
+    assert_eq!(log["version"], "2.1.0");
+    assert_eq!(
+        run["tool"]["driver"]["rules"],
+        json!([{ "id": "parse" }, { "id": "skipped" }])
+    );
+
+    let results = run["results"].as_array().unwrap();
+    let location = &results[0]["locations"][0]["physicalLocation"];
+
+    assert_eq!(results[0]["level"], "error");
+    assert_eq!(location["artifactLocation"]["uri"], "ks/main%20ks.ks");
+    assert_eq!(
+        location["region"],
+        json!({ "startLine": 3, "startColumn": 2 })
+    );
+    assert_eq!(
+        results[1]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+        "file:///elsewhere/main.ks"
+    );
+    assert!(results[2].get("locations").is_none());
+}
+
+#[test]
+fn diagnostics_render() {
+    let diagnostic = Diagnostic::new("skipped", Severity::Warning, "skipped 'reboot'".to_string())
+        .at(Some(&Span {
+            path: "/ks/main.ks".to_string(),
+            line: 2,
+            column: 2,
+        }))
+        .fix("remove it".to_string());
+
+    assert_eq!(
+        diagnostic.render(Some("lang en_US\n\treboot --eject\n"), false),
+        "warning[skipped]: skipped 'reboot'\n --> /ks/main.ks:2:2\n  |\n2 | \treboot --eject\n  \
+         | \t^^^^^^\n  = help: remove it\n"
+    );
+    assert_eq!(
+        diagnostic.render(None, false),
+        "warning[skipped]: skipped 'reboot'\n --> /ks/main.ks:2:2\n  = help: remove it\n"
+    );
+    assert!(diagnostic
+        .render(None, true)
+        .starts_with("\x1b[1;33mwarning[skipped]\x1b[0m"));
+}
+
+#[test]
+fn diagnostics_render_token() {
+    use crate::kickstart::{Includes, Kickstart};
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-diagnostics-render-token");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+    let source = "%include missing.ks\npart / --size=${size}\n";
+
+    fs::write(dir.join("main.ks"), source).unwrap();
+
+    let err = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap_err();
+    let carets: Vec<String> = Diagnostic::from_kickstart(&err)
+        .iter()
+        .map(|diagnostic| {
+            diagnostic
+                .render(Some(source), false)
+                .lines()
+                .nth(4)
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+
+    assert_eq!(
+        carets,
+        ["  |          ^^^^^^^^^^", "  |               ^^^^^^^"]
+    );
+}
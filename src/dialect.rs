@@ -0,0 +1,253 @@
+//! The dialects of kickstart. Every release adds commands and options, and the installer of a
+//! release only knows those that were added in it or before it. Removed commands are in
+//! [`deprecated`](crate::translate::deprecated) instead.
+
+use crate::version::Version;
+
+/// The commands of the latest kickstart syntax, with those that were deprecated but not removed.
+pub const COMMANDS: &[&str] = &[
+    "auth",
+    "authconfig",
+    "authselect",
+    "autopart",
+    "bootc",
+    "bootloader",
+    "btrfs",
+    "cdrom",
+    "clearpart",
+    "cmdline",
+    "device",
+    "driverdisk",
+    "eula",
+    "fcoe",
+    "firewall",
+    "firstboot",
+    "graphical",
+    "group",
+    "halt",
+    "harddrive",
+    "hmc",
+    "ignoredisk",
+    "install",
+    "iscsi",
+    "iscsiname",
+    "keyboard",
+    "lang",
+    "liveimg",
+    "logging",
+    "logvol",
+    "mediacheck",
+    "module",
+    "mount",
+    "network",
+    "nfs",
+    "nvdimm",
+    "ostreecontainer",
+    "ostreesetup",
+    "part",
+    "partition",
+    "poweroff",
+    "raid",
+    "realm",
+    "reboot",
+    "repo",
+    "reqpart",
+    "rescue",
+    "rhsm",
+    "rootpw",
+    "selinux",
+    "services",
+    "shutdown",
+    "skipx",
+    "snapshot",
+    "sshkey",
+    "sshpw",
+    "syspurpose",
+    "text",
+    "timesource",
+    "timezone",
+    "updates",
+    "upgrade",
+    "url",
+    "user",
+    "vnc",
+    "volgroup",
+    "xconfig",
+    "zerombr",
+    "zfcp",
+    "zipl",
+];
+
+/// A command, or option of a command, and the release that added it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Added {
+    pub command: &'static str,
+    /// The option that was added, the whole command when there is none.
+    pub option: Option<&'static str>,
+    pub added: &'static str,
+}
+
+/// Commands and options that were added after the first releases, as pykickstart has them.
+pub const ADDED: &[Added] = &[
+    Added {
+        command: "liveimg",
+        option: None,
+        added: "F19",
+    },
+    Added {
+        command: "realm",
+        option: None,
+        added: "F19",
+    },
+    Added {
+        command: "eula",
+        option: None,
+        added: "F20",
+    },
+    Added {
+        command: "sshkey",
+        option: None,
+        added: "F22",
+    },
+    Added {
+        command: "reqpart",
+        option: None,
+        added: "F23",
+    },
+    Added {
+        command: "snapshot",
+        option: None,
+        added: "F26",
+    },
+    Added {
+        command: "mount",
+        option: None,
+        added: "F27",
+    },
+    Added {
+        command: "authselect",
+        option: None,
+        added: "F28",
+    },
+    Added {
+        command: "hmc",
+        option: None,
+        added: "F28",
+    },
+    Added {
+        command: "nvdimm",
+        option: None,
+        added: "F28",
+    },
+    Added {
+        command: "firewall",
+        option: Some("--use-system-defaults"),
+        added: "F28",
+    },
+    Added {
+        command: "module",
+        option: None,
+        added: "F29",
+    },
+    Added {
+        command: "rhsm",
+        option: None,
+        added: "F29",
+    },
+    Added {
+        command: "syspurpose",
+        option: None,
+        added: "F29",
+    },
+    Added {
+        command: "timesource",
+        option: None,
+        added: "F33",
+    },
+    Added {
+        command: "zipl",
+        option: None,
+        added: "F34",
+    },
+    Added {
+        command: "rootpw",
+        option: Some("--allow-ssh"),
+        added: "F37",
+    },
+    Added {
+        command: "ostreecontainer",
+        option: None,
+        added: "F38",
+    },
+    Added {
+        command: "bootc",
+        option: None,
+        added: "F42",
+    },
+];
+
+/// Whether `command` is a kickstart command in any release.
+pub fn is_command(command: &str) -> bool {
+    COMMANDS.contains(&command)
+}
+
+/// Check that `command` and the options in `args` exist in kickstarts for `version`, the error
+/// names the first one that was added later.
+pub fn check(command: &str, args: &[String], version: Version) -> Result<(), String> {
+    let missing = ADDED.iter().find(|entry| {
+        entry.command == command
+            && entry
+                .option
+                .is_none_or(|option| args.iter().any(|arg| is_option(arg, option)))
+            && !reached(entry.added, version)
+    });
+
+    match missing {
+        Some(entry) => Err(format!(
+            "'{}' was added in {} and isn't in {} kickstarts",
+            match entry.option {
+                Some(option) => format!("{} {}", entry.command, option),
+                None => entry.command.to_string(),
+            },
+            entry.added,
+            version
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Whether `arg` is `option`, on its own or with a value.
+pub fn is_option(arg: &str, option: &str) -> bool {
+    arg == option
+        || arg
+            .strip_prefix(option)
+            .is_some_and(|value| value.starts_with('='))
+}
+
+/// Whether `version` is `release` or a later one.
+pub fn reached(release: &str, version: Version) -> bool {
+    release
+        .parse::<Version>()
+        .is_ok_and(|release| version.fedora() >= release.fedora())
+}
+
+#[test]
+fn dialect_check() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert!(check(
+        "timesource",
+        &args("--ntp-server=ntp.example"),
+        Version::Fedora(33)
+    )
+    .is_ok());
+    assert_eq!(
+        check("timesource", &[], Version::Fedora(32)),
+        Err("'timesource' was added in F33 and isn't in F32 kickstarts".to_string())
+    );
+    assert!(check("rootpw", &args("--allow-ssh secret"), Version::Rhel(8)).is_err());
+    assert!(check("rootpw", &args("secret"), Version::Rhel(8)).is_ok());
+    assert!(check("part", &args("/"), Version::Fedora(6)).is_ok());
+    assert!(is_command("zipl"));
+    assert!(!is_command("bogus"));
+}
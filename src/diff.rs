@@ -0,0 +1,555 @@
+//! What differs between two kickstarts, by what they do rather than by their text. Packages are
+//! compared one by one, commands by what they're about, such as the user they add or the mount
+//! point of a partition, with their options in any order, and scripts by their header.
+//!
+//! Manifests are compared the same way, by their pipelines, the options of the stages in them
+//! and the sources they fetch.
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::kickstart::{Kickstart, SectionKind};
+use crate::translate::catalog;
+use crate::translate::options::Options;
+use crate::translate::packages::Packages;
+use crate::translate::words;
+
+/// The parts of a kickstart differences are grouped by, in the order they're printed.
+const AREAS: &[&str] = &["packages", "partitioning", "users", "commands", "scripts"];
+
+const PARTITIONING: &[&str] = &[
+    "autopart",
+    "btrfs",
+    "clearpart",
+    "ignoredisk",
+    "logvol",
+    "part",
+    "partition",
+    "raid",
+    "reqpart",
+    "volgroup",
+    "zerombr",
+];
+
+const USERS: &[&str] = &["group", "rootpw", "sshkey", "user"];
+
+/// Something that was added, removed or changed between two kickstarts, as it's written in them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub area: &'static str,
+    /// What the change is about, such as `user alice` or `part /`.
+    pub key: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// A part of a kickstart that can be added, removed or changed.
+struct Part {
+    area: &'static str,
+    key: String,
+    /// What's compared, the same for parts that do the same.
+    canonical: String,
+    text: String,
+}
+
+/// What a command is about, commands that can be given once are about themselves.
+fn key(name: &str, options: &Options) -> String {
+    let about = match name {
+        "group" | "repo" | "user" => options.value("--name"),
+        "sshkey" => options.value("--username"),
+        "network" => options.value("--device"),
+        "btrfs" | "logvol" | "part" | "partition" | "raid" | "volgroup" => {
+            options.positional().first().map(String::as_str)
+        }
+        _ => None,
+    };
+
+    match about {
+        Some(about) => format!("{} {}", name, about),
+        None => name.to_string(),
+    }
+}
+
+fn command(line: &str) -> Option<Part> {
+    let words = words::split(line).ok()?;
+    let (name, args) = words.split_first()?;
+
+    let area = match name.as_str() {
+        name if PARTITIONING.contains(&name) => "partitioning",
+        name if USERS.contains(&name) => "users",
+        _ => "commands",
+    };
+
+    let values = catalog::lookup(name)
+        .map(|command| command.values)
+        .unwrap_or_default();
+
+    let (key, canonical) = match Options::parse(args, values) {
+        Ok(options) => {
+            let mut named: Vec<_> = options
+                .iter()
+                .map(|(option, value)| match value {
+                    Some(value) => format!("{}={}", option, value),
+                    None => option.to_string(),
+                })
+                .collect();
+            named.sort();
+
+            let mut canonical = vec![name.clone()];
+            canonical.extend(options.positional().iter().cloned());
+            canonical.extend(named);
+
+            (key(name, &options), canonical.join(" "))
+        }
+        Err(_) => (name.clone(), words.join(" ")),
+    };
+
+    Some(Part {
+        area,
+        key,
+        canonical,
+        text: line.trim().to_string(),
+    })
+}
+
+fn parts(kickstart: &Kickstart) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut packages = Packages::default();
+
+    for section in kickstart.sections() {
+        match section.kind() {
+            SectionKind::Command => {
+                parts.extend(section.lines().filter_map(|(line, _)| command(line)))
+            }
+            SectionKind::Packages => packages.parse(section.args(), section.data()),
+            _ => {
+                let mut header = vec![section.name().to_string()];
+                header.extend(section.args().iter().cloned());
+                let header = header.join(" ");
+
+                parts.push(Part {
+                    area: "scripts",
+                    key: header.clone(),
+                    canonical: section.data().to_string(),
+                    text: format!("{}\n{}", header, section.data().trim_end()),
+                });
+            }
+        }
+    }
+
+    let excluded = packages
+        .exclude
+        .iter()
+        .map(|package| format!("-{}", package));
+
+    for package in packages
+        .include
+        .iter()
+        .cloned()
+        .chain(packages.groups.iter().cloned())
+        .chain(excluded)
+    {
+        parts.push(Part {
+            area: "packages",
+            key: package.clone(),
+            canonical: package.clone(),
+            text: package,
+        });
+    }
+
+    if !packages.args.is_empty() {
+        let args = format!("%packages {}", packages.args.join(" "));
+
+        parts.push(Part {
+            area: "packages",
+            key: "%packages".to_string(),
+            canonical: args.clone(),
+            text: args,
+        });
+    }
+
+    // What's given more than once with the same key is told apart by the order it's given in.
+    let mut seen: Vec<(&str, String)> = Vec::new();
+
+    for part in &mut parts {
+        let count = seen
+            .iter()
+            .filter(|(area, key)| *area == part.area && *key == part.key)
+            .count();
+        seen.push((part.area, part.key.clone()));
+
+        if count > 0 {
+            part.key = format!("{} ({})", part.key, count + 1);
+        }
+    }
+
+    parts
+}
+
+/// The part of `parts` that is about the same as `part`.
+fn find<'a>(parts: &'a [Part], part: &Part) -> Option<&'a Part> {
+    parts
+        .iter()
+        .find(|other| other.area == part.area && other.key == part.key)
+}
+
+/// What changed from `old` to `new`, grouped by area.
+pub fn diff(old: &Kickstart, new: &Kickstart) -> Vec<Change> {
+    let old = parts(old);
+    let new = parts(new);
+
+    let mut changes = Vec::new();
+
+    for area in AREAS {
+        for part in old.iter().filter(|part| part.area == *area) {
+            let change = match find(&new, part) {
+                None => Some(None),
+                Some(other) if other.canonical != part.canonical => Some(Some(&other.text)),
+                Some(_) => None,
+            };
+
+            if let Some(new) = change {
+                changes.push(Change {
+                    area,
+                    key: part.key.clone(),
+                    old: Some(part.text.clone()),
+                    new: new.cloned(),
+                });
+            }
+        }
+
+        for part in new.iter().filter(|part| part.area == *area) {
+            if find(&old, part).is_none() {
+                changes.push(Change {
+                    area,
+                    key: part.key.clone(),
+                    old: None,
+                    new: Some(part.text.clone()),
+                });
+            }
+        }
+    }
+
+    changes
+}
+
+/// The stages of `pipeline`, the assembler of a version 1 manifest is the last one.
+fn pipeline(pipeline: &Value) -> Vec<&Value> {
+    pipeline["stages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .chain(pipeline.get("assembler"))
+        .collect()
+}
+
+/// The stages of each pipeline of `manifest`, version 1 manifests have a `build` and a `pipeline`
+/// pipeline.
+fn pipelines(manifest: &Value) -> Result<Vec<(String, Vec<&Value>)>, String> {
+    if let Some(pipeline) = manifest.get("pipeline") {
+        let mut pipelines = Vec::new();
+
+        if let Some(build) = pipeline["build"].get("pipeline") {
+            pipelines.push(("build".to_string(), self::pipeline(build)));
+        }
+
+        pipelines.push(("pipeline".to_string(), self::pipeline(pipeline)));
+
+        return Ok(pipelines);
+    }
+
+    Ok(manifest["pipelines"]
+        .as_array()
+        .ok_or("the manifest has no pipelines")?
+        .iter()
+        .map(|pipeline| {
+            let name = pipeline["name"].as_str().unwrap_or_default().to_string();
+            (name, self::pipeline(pipeline))
+        })
+        .collect())
+}
+
+/// `stages` keyed by their pipeline and type, stages of a type given more than once are told
+/// apart by their order.
+fn stages<'a>(pipeline: &str, stages: &[&'a Value]) -> Vec<(String, &'a Value)> {
+    let mut kinds = Vec::new();
+
+    stages
+        .iter()
+        .map(|stage| {
+            let kind = stage["type"]
+                .as_str()
+                .or_else(|| stage["name"].as_str())
+                .unwrap_or_default();
+            let count = kinds.iter().filter(|other| **other == kind).count();
+            kinds.push(kind);
+
+            let key = match count {
+                0 => format!("{}: {}", pipeline, kind),
+                count => format!("{}: {} ({})", pipeline, kind, count + 1),
+            };
+
+            (key, *stage)
+        })
+        .collect()
+}
+
+/// `value` as JSON on a single line, cut short when it's long.
+fn compact(value: &Value) -> String {
+    let json = value.to_string();
+
+    match json.char_indices().nth(80) {
+        Some((end, _)) => format!("{}...", &json[..end]),
+        None => json,
+    }
+}
+
+/// The options, inputs, devices and mounts that differ between two stages with the same `key`.
+fn stage(key: &str, old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    let mut change = |name: &str, old: Option<&Value>, new: Option<&Value>| {
+        if old != new {
+            changes.push(Change {
+                area: "stages",
+                key: key.to_string(),
+                old: old.map(|old| format!("{} {}={}", key, name, compact(old))),
+                new: new.map(|new| format!("{} {}={}", key, name, compact(new))),
+            });
+        }
+    };
+
+    for field in ["options", "inputs", "devices", "mounts"] {
+        let (old, new) = (old.get(field), new.get(field));
+
+        match (
+            old.and_then(Value::as_object),
+            new.and_then(Value::as_object),
+        ) {
+            (Some(old), Some(new)) => {
+                let names = old
+                    .keys()
+                    .chain(new.keys().filter(|name| !old.contains_key(*name)));
+
+                for name in names {
+                    let qualified = match field {
+                        "options" => name.to_string(),
+                        _ => format!("{}.{}", field, name),
+                    };
+
+                    change(&qualified, old.get(name), new.get(name));
+                }
+            }
+            _ => change(field, old, new),
+        }
+    }
+
+    changes
+}
+
+/// The sources of `manifest` by their type and checksum, with their URL if they have one.
+fn sources(manifest: &Value) -> Vec<(String, String)> {
+    let mut sources = Vec::new();
+
+    for (kind, source) in manifest["sources"].as_object().into_iter().flatten() {
+        let items = source.get("items").or_else(|| source.get("urls"));
+
+        for (checksum, item) in items.and_then(Value::as_object).into_iter().flatten() {
+            let text = match item.as_str().or_else(|| item["url"].as_str()) {
+                Some(url) => format!("{} {}", checksum, url),
+                None => checksum.to_string(),
+            };
+
+            sources.push((format!("{} {}", kind, checksum), text));
+        }
+    }
+
+    sources
+}
+
+/// What was added, removed and changed from `old` to `new`, which are keyed texts.
+fn texts(area: &'static str, old: &[(String, String)], new: &[(String, String)]) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (key, text) in old {
+        match new.iter().find(|(other, _)| other == key) {
+            Some((_, other)) if other == text => {}
+            other => changes.push(Change {
+                area,
+                key: key.clone(),
+                old: Some(text.clone()),
+                new: other.map(|(_, other)| other.clone()),
+            }),
+        }
+    }
+
+    for (key, text) in new {
+        if !old.iter().any(|(other, _)| other == key) {
+            changes.push(Change {
+                area,
+                key: key.clone(),
+                old: None,
+                new: Some(text.clone()),
+            });
+        }
+    }
+
+    changes
+}
+
+/// What changed from the manifest `old` to `new`: the pipelines, the stages of the pipelines
+/// that are in both and the sources.
+pub fn manifests(old: &Value, new: &Value) -> Result<Vec<Change>, String> {
+    let old_pipelines = pipelines(old)?;
+    let new_pipelines = pipelines(new)?;
+
+    let names = |pipelines: &[(String, Vec<&Value>)]| -> Vec<(String, String)> {
+        pipelines
+            .iter()
+            .map(|(name, _)| (name.clone(), name.clone()))
+            .collect()
+    };
+
+    let mut changes = texts("pipelines", &names(&old_pipelines), &names(&new_pipelines));
+
+    for (name, old_stages) in &old_pipelines {
+        let new_stages = match new_pipelines.iter().find(|(other, _)| other == name) {
+            Some((_, new_stages)) => stages(name, new_stages),
+            None => continue,
+        };
+        let old_stages = stages(name, old_stages);
+
+        let keys = |stages: &[(String, &Value)]| -> Vec<(String, String)> {
+            stages
+                .iter()
+                .map(|(key, _)| (key.clone(), key.clone()))
+                .collect()
+        };
+
+        changes.extend(texts("stages", &keys(&old_stages), &keys(&new_stages)));
+
+        for (key, old_stage) in &old_stages {
+            if let Some((_, new_stage)) = new_stages.iter().find(|(other, _)| other == key) {
+                changes.extend(stage(key, old_stage, new_stage));
+            }
+        }
+    }
+
+    changes.extend(texts("sources", &sources(old), &sources(new)));
+
+    Ok(changes)
+}
+
+/// `changes` under the area they're in, with `-` for what was there and `+` for what is.
+pub struct Outline<'a>(pub &'a [Change]);
+
+impl fmt::Display for Outline<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut area = None;
+
+        for change in self.0 {
+            if area != Some(change.area) {
+                writeln!(f, "{}", change.area)?;
+                area = Some(change.area);
+            }
+
+            for (sign, text) in [("-", &change.old), ("+", &change.new)] {
+                if let Some(text) = text {
+                    let mut lines = text.lines();
+
+                    writeln!(f, "    {} {}", sign, lines.next().unwrap_or_default())?;
+
+                    for line in lines {
+                        writeln!(f, "          {}", line)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn diff_kickstarts() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-diff-kickstarts");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("old.ks"),
+        "part / --size=1000 --fstype=xfs\npart /boot --size=500\nuser --name=alice\n\
+         timezone UTC\n%packages\nvim\nnano\n%end\n%post\ntrue\n%end\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("new.ks"),
+        "part / --fstype=xfs  --size=1000\npart /boot --size=1000\nuser --name=alice --groups=wheel\n\
+         user --name=bob\ntimezone UTC\n%packages\nvim\n-nano\n@core\n%end\n%post\ntrue\n%end\n",
+    )
+    .unwrap();
+
+    let read =
+        |name: &str| Kickstart::from_path(&dir.join(name), &includes, Version::LATEST).unwrap();
+    let changes = diff(&read("old.ks"), &read("new.ks"));
+
+    assert_eq!(
+        Outline(&changes).to_string(),
+        "packages\n    - nano\n    + @core\n    + -nano\n\
+         partitioning\n    - part /boot --size=500\n    + part /boot --size=1000\n\
+         users\n    - user --name=alice\n    + user --name=alice --groups=wheel\n    + user --name=bob\n"
+    );
+    assert_eq!(changes[3].key, "part /boot");
+}
+
+#[test]
+fn diff_manifests() {
+    use serde_json::json;
+
+    let old = json!({
+        "version": "2",
+        "pipelines": [
+            { "name": "build", "stages": [{ "type": "org.osbuild.rpm" }] },
+            { "name": "os", "stages": [
+                { "type": "org.osbuild.timezone", "options": { "zone": "UTC" } },
+                { "type": "org.osbuild.users", "options": { "users": { "alice": {} } } },
+            ] },
+        ],
+        "sources": { "org.osbuild.curl": { "items": {
+            "sha256:aa": { "url": "https://example.com/vim-9.0-1.x86_64.rpm" },
+        } } },
+    });
+    let new = json!({
+        "version": "2",
+        "pipelines": [
+            { "name": "build", "stages": [{ "type": "org.osbuild.rpm" }] },
+            { "name": "os", "stages": [
+                { "type": "org.osbuild.timezone", "options": { "zone": "UTC" } },
+                { "type": "org.osbuild.users", "options": { "users": { "bob": {} } } },
+                { "type": "org.osbuild.chrony", "options": { "servers": [] } },
+            ] },
+            { "name": "image", "stages": [] },
+        ],
+        "sources": { "org.osbuild.curl": { "items": {
+            "sha256:bb": "https://example.com/nano-7.2-1.x86_64.rpm",
+        } } },
+    });
+
+    assert_eq!(
+        Outline(&manifests(&old, &new).unwrap()).to_string(),
+        "pipelines\n    + image\n\
+         stages\n    + os: org.osbuild.chrony\n    \
+         - os: org.osbuild.users users={\"alice\":{}}\n    \
+         + os: org.osbuild.users users={\"bob\":{}}\n\
+         sources\n    - sha256:aa https://example.com/vim-9.0-1.x86_64.rpm\n    \
+         + sha256:bb https://example.com/nano-7.2-1.x86_64.rpm\n"
+    );
+    assert!(manifests(&json!({}), &new).is_err());
+}
@@ -0,0 +1,234 @@
+//! Distributions that kickstarts are written for. The syntax of a kickstart doesn't say which
+//! distribution it installs, but the groups it can install, where the bootloader goes and which
+//! commands make sense all depend on it.
+//!
+//! What is known about a distribution is in a JSON definition file, those in `definitions/` are
+//! built in and a directory given with `--definitions-dir` can replace them or add new ones.
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::translate::packages::Repo;
+use crate::version::Version;
+
+/// The definitions that are built in.
+const BUILTIN: &[&str] = &[
+    include_str!("../definitions/fedora.json"),
+    include_str!("../definitions/rhel.json"),
+    include_str!("../definitions/centos.json"),
+];
+
+/// What differs between distributions.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Distro {
+    /// The name given to `--distro`.
+    pub name: String,
+    /// The name of the distribution in messages.
+    pub title: String,
+    /// The kickstart syntax version used when none is given.
+    pub version: Version,
+    /// The directory of the EFI system partition the EFI binaries are in.
+    pub efi_vendor: String,
+    /// The environments `%packages` can install with `@^`.
+    #[serde(default)]
+    pub environments: Vec<String>,
+    /// Whether systems are registered with Red Hat Subscription Management.
+    #[serde(default)]
+    pub subscription: bool,
+    /// The distribution osbuild-composer knows it as, composes need it.
+    #[serde(default)]
+    pub composer: Option<String>,
+    /// The release and module platform packages are depsolved for, such as `42` and
+    /// `platform:f42`.
+    #[serde(default)]
+    pub releasever: Option<String>,
+    #[serde(default)]
+    pub module_platform_id: Option<String>,
+    /// The osbuild runner of the build root.
+    #[serde(default)]
+    pub runner: Option<String>,
+    /// Packages of the build root the stages run in, there is no build pipeline without them.
+    /// The tools of the stages are added to them.
+    #[serde(default)]
+    pub buildroot: Vec<String>,
+    /// Repositories the packages of the image come from.
+    #[serde(default)]
+    pub repositories: Vec<Repo>,
+    /// Arguments that are on the kernel command line of every image.
+    #[serde(default)]
+    pub kernel_opts: Vec<String>,
+    /// The authselect profile of systems that don't select one, `auth` and `authconfig` enable
+    /// features in it.
+    #[serde(default)]
+    pub authselect_profile: Option<String>,
+    /// The datastream of the SCAP Security Guide in the tree, for the OpenSCAP addon.
+    #[serde(default)]
+    pub oscap_datastream: Option<String>,
+}
+
+/// All distributions that have a definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Definitions {
+    distros: Vec<Distro>,
+}
+
+impl Default for Definitions {
+    fn default() -> Self {
+        Self {
+            distros: BUILTIN
+                .iter()
+                .map(|data| serde_json::from_str(data).unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl Definitions {
+    /// Add the definitions in the `.json` files in `dir`, they replace those with the same name.
+    pub fn dir(mut self, dir: &Path) -> Result<Self, String> {
+        let entries = fs::read_dir(dir)
+            .map_err(|err| format!("failed to read '{}': {}", dir.display(), err))?;
+
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect();
+
+        // The order files are listed in differs between filesystems.
+        paths.sort();
+
+        for path in paths {
+            let data = fs::read_to_string(&path)
+                .map_err(|err| format!("failed to read '{}': {}", path.display(), err))?;
+            let distro: Distro = serde_json::from_str(&data)
+                .map_err(|err| format!("failed to parse '{}': {}", path.display(), err))?;
+
+            self.distros.retain(|known| known.name != distro.name);
+            self.distros.push(distro);
+        }
+
+        Ok(self)
+    }
+
+    /// The names of all distributions.
+    pub fn names(&self) -> Vec<&str> {
+        self.distros
+            .iter()
+            .map(|distro| distro.name.as_str())
+            .collect()
+    }
+
+    /// The distribution named `name`.
+    pub fn lookup(&self, name: &str) -> Option<&Distro> {
+        self.distros.iter().find(|distro| distro.name == name)
+    }
+}
+
+impl Default for Distro {
+    fn default() -> Self {
+        Definitions::default().lookup("fedora").unwrap().clone()
+    }
+}
+
+impl Distro {
+    /// Why `command` doesn't apply to this distribution, if it doesn't.
+    pub fn unsupported(&self, command: &str) -> Option<String> {
+        match command {
+            "rhsm" | "syspurpose" if !self.subscription => Some(format!(
+                "{} systems aren't registered with Red Hat Subscription Management",
+                self.title
+            )),
+            _ => None,
+        }
+    }
+
+    /// Why the group `group` from `%packages` can't be installed, if it's an environment this
+    /// distribution doesn't have.
+    pub fn unknown_group(&self, group: &str) -> Option<String> {
+        let environment = group.strip_prefix("@^")?;
+
+        if self.environments.iter().any(|known| known == environment) {
+            return None;
+        }
+
+        Some(format!(
+            "'{}' is not an environment of {}",
+            environment, self.title
+        ))
+    }
+}
+
+#[test]
+fn distro_lookup() {
+    let definitions = Definitions::default();
+    let rhel = definitions.lookup("rhel").unwrap();
+
+    assert_eq!(definitions.names(), vec!["fedora", "rhel", "centos"]);
+    assert_eq!(rhel.efi_vendor, "redhat");
+    assert_eq!(rhel.version, Version::Rhel(10));
+    assert_eq!(
+        rhel.oscap_datastream.as_deref(),
+        Some("/usr/share/xml/scap/ssg/content/ssg-rhel10-ds.xml")
+    );
+    assert!(rhel.unsupported("rhsm").is_none());
+    assert!(definitions
+        .lookup("centos")
+        .unwrap()
+        .unsupported("syspurpose")
+        .is_some());
+    assert!(definitions.lookup("bogus").is_none());
+
+    let fedora = Distro::default();
+
+    assert_eq!(fedora.name, "fedora");
+    assert!(fedora.unknown_group("@^kde-desktop-environment").is_none());
+    assert!(fedora.unknown_group("@core").is_none());
+    assert_eq!(
+        rhel.unknown_group("@^kde-desktop-environment"),
+        Some("'kde-desktop-environment' is not an environment of RHEL".to_string())
+    );
+}
+
+#[test]
+fn distro_definitions_dir() {
+    let dir = std::env::temp_dir().join("osbuild-ks-distro-definitions-dir");
+    let bogus = dir.join("bogus");
+    fs::create_dir_all(&bogus).unwrap();
+
+    fs::write(
+        dir.join("fedora.json"),
+        r#"{"name": "fedora", "title": "Fedora", "version": "F43", "efi_vendor": "fedora"}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("alma.json"),
+        r#"{"name": "alma", "title": "AlmaLinux", "version": "RHEL9", "efi_vendor": "almalinux",
+            "kernel_opts": ["console=ttyS0"]}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("README"), "not a definition").unwrap();
+    fs::write(bogus.join("bogus.json"), r#"{"name": "bogus"}"#).unwrap();
+
+    let definitions = Definitions::default().dir(&dir).unwrap();
+
+    assert_eq!(
+        definitions.names(),
+        vec!["rhel", "centos", "alma", "fedora"]
+    );
+    assert_eq!(
+        definitions.lookup("fedora").unwrap().version,
+        Version::Fedora(43)
+    );
+    assert_eq!(
+        definitions.lookup("alma").unwrap().kernel_opts,
+        vec!["console=ttyS0"]
+    );
+    assert!(Definitions::default().dir(&bogus).is_err());
+    assert!(Definitions::default().dir(&dir.join("missing")).is_err());
+}
@@ -0,0 +1,62 @@
+//! The exit codes of `osbuild-ks`, so scripts can tell why it failed without reading what it
+//! printed.
+
+use std::process;
+
+/// Why `osbuild-ks` failed, which is also its exit code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    /// Anything that has no code of its own, such as osbuild failing to build the manifest.
+    Failure = 1,
+    /// The command line is wrong, clap exits with this as well.
+    Usage = 2,
+    /// A kickstart, blueprint or manifest can't be parsed.
+    Parse = 3,
+    /// What was read isn't valid, such as problems found by `lint` and `validate` or a disk that
+    /// can't be partitioned the way the kickstart says.
+    Invalid = 4,
+    /// Something isn't translated while it has to be, with `--unsupported=fail` or `--strict`, or
+    /// can't be done in an image at all.
+    Unsupported = 5,
+    /// A file can't be read or written, or a URL can't be fetched.
+    IO = 6,
+    /// The depsolved packages given can't be used.
+    Depsolve = 7,
+}
+
+impl Code {
+    pub fn exit(self) -> ! {
+        process::exit(self as i32)
+    }
+}
+
+#[test]
+fn exit_codes() {
+    use crate::kickstart::{KickstartError, Span};
+    use crate::translate::TranslateError;
+
+    let span = Span {
+        path: "/ks/main.ks".to_string(),
+        line: 1,
+        column: 1,
+    };
+
+    let err = KickstartError::Invalid(vec![KickstartError::At(
+        span,
+        Box::new(KickstartError::Missing(
+            "a.ks".to_string(),
+            "/ks".to_string(),
+        )),
+    )]);
+
+    assert_eq!(err.code(), Code::IO);
+    assert_eq!(
+        KickstartError::Parse("no section".to_string()).code(),
+        Code::Parse
+    );
+    assert_eq!(
+        TranslateError::Strict("skipped".to_string()).code(),
+        Code::Unsupported
+    );
+    assert_eq!(Code::Depsolve as i32, 7);
+}
@@ -0,0 +1,328 @@
+//! Retrieval of remote resources referenced by a kickstart, such as GPG keys and included files.
+//! Requests go through the proxy set in the environment with `http_proxy`, `https_proxy` or
+//! `all_proxy` unless another is given, HTTPS certificates are verified against the bundled web
+//! roots and the certificate authorities that are given.
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::*;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+
+use crate::cache::{Cache, Entry};
+
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum FetchError {
+    IO(io::Error),
+    Http(String),
+    Tls(String),
+}
+
+impl From<io::Error> for FetchError {
+    fn from(err: io::Error) -> FetchError {
+        FetchError::IO(err)
+    }
+}
+
+impl From<ureq::Error> for FetchError {
+    fn from(err: ureq::Error) -> FetchError {
+        FetchError::Http(err.to_string())
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FetchError::IO(err) => write!(f, "{}", err),
+            FetchError::Http(err) => write!(f, "{}", err),
+            FetchError::Tls(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// How remote resources are retrieved.
+#[derive(Clone, Debug)]
+pub struct Options {
+    timeout: Duration,
+    proxy: Option<String>,
+    ca_certs: Vec<PathBuf>,
+    insecure: bool,
+    /// Where what's fetched is kept to ask the server whether it changed, if anywhere.
+    cache: Option<Cache>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            timeout: TIMEOUT,
+            proxy: None,
+            ca_certs: Vec::new(),
+            insecure: false,
+            cache: None,
+        }
+    }
+}
+
+impl Options {
+    /// How long to wait for a connection and for each read.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The proxy to use instead of the one set in the environment.
+    pub fn proxy(mut self, proxy: Option<String>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Also trust the certificate authorities in these PEM files.
+    pub fn ca_certs(mut self, ca_certs: Vec<PathBuf>) -> Self {
+        self.ca_certs = ca_certs;
+        self
+    }
+
+    /// Accept any certificate an HTTPS server presents.
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    /// Keep what's fetched in `cache` and only download it again when it changed.
+    pub fn cache(mut self, cache: Option<Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    fn agent(&self) -> Result<ureq::Agent, FetchError> {
+        let agent = ureq::AgentBuilder::new().timeout(self.timeout);
+
+        let agent = match &self.proxy {
+            Some(proxy) => agent.proxy(ureq::Proxy::new(proxy)?),
+            None => agent.try_proxy_from_env(true),
+        };
+
+        // The default configuration of ureq is kept unless something about TLS was asked for.
+        let agent = match self.insecure || !self.ca_certs.is_empty() {
+            true => agent.tls_config(Arc::new(self.tls()?)),
+            false => agent,
+        };
+
+        Ok(agent.build())
+    }
+
+    fn tls(&self) -> Result<rustls::ClientConfig, FetchError> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = rustls::ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| FetchError::Tls(err.to_string()))?;
+
+        if self.insecure {
+            warn!("fetch: not verifying the certificates of HTTPS servers");
+
+            return Ok(config
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(Insecure(provider)))
+                .with_no_client_auth());
+        }
+
+        let mut roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+
+        for path in &self.ca_certs {
+            let invalid =
+                |err: &dyn fmt::Display| FetchError::Tls(format!("'{}': {}", path.display(), err));
+
+            let certs = CertificateDer::pem_file_iter(path)
+                .map_err(|err| invalid(&err))?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| invalid(&err))?;
+
+            if certs.is_empty() {
+                return Err(invalid(&"no certificates in PEM file"));
+            }
+
+            for cert in certs {
+                roots.add(cert).map_err(|err| invalid(&err))?;
+            }
+        }
+
+        Ok(config.with_root_certificates(roots).with_no_client_auth())
+    }
+}
+
+/// Takes any certificate for the server it's from, for `--insecure`. Signatures are still checked
+/// as the handshake depends on them.
+#[derive(Debug)]
+struct Insecure(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for Insecure {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Whether a string looks like something we know how to fetch.
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+/// Fetch the contents of `url` as a string.
+pub fn fetch(url: &str, options: &Options) -> Result<String, FetchError> {
+    debug!("fetch: retrieving '{}'", url);
+
+    let cached = options.cache.as_ref().and_then(|cache| cache.get(url));
+    let mut request = options.agent()?.get(url);
+
+    if let Some(cached) = &cached {
+        if let Some(etag) = &cached.etag {
+            request = request.set("If-None-Match", etag);
+        }
+
+        if let Some(last_modified) = &cached.last_modified {
+            request = request.set("If-Modified-Since", last_modified);
+        }
+    }
+
+    let response = request.call()?;
+
+    if let (304, Some(cached)) = (response.status(), cached) {
+        debug!("fetch: '{}' is unchanged, using the cached copy", url);
+        return Ok(cached.data);
+    }
+
+    let etag = response.header("ETag").map(str::to_string);
+    let last_modified = response.header("Last-Modified").map(str::to_string);
+    let data = response.into_string()?;
+
+    trace!("fetch: retrieved {} bytes from '{}'", data.len(), url);
+
+    // Without either header there's no asking whether it changed, so it isn't kept.
+    if let (Some(cache), true) = (&options.cache, etag.is_some() || last_modified.is_some()) {
+        let entry = Entry {
+            url: url.to_string(),
+            etag,
+            last_modified,
+            data,
+        };
+
+        if let Err(err) = cache.put(&entry) {
+            warn!("fetch: failed to cache '{}': {}", url, err);
+        }
+
+        return Ok(entry.data);
+    }
+
+    Ok(data)
+}
+
+#[test]
+fn fetch_tls() {
+    let dir = std::env::temp_dir().join("osbuild-ks-fetch-tls");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("empty.pem"), "not a certificate\n").unwrap();
+
+    assert!(Options::default().insecure(true).tls().is_ok());
+    assert!(Options::default().tls().is_ok());
+
+    assert!(matches!(
+        Options::default().ca_certs(vec![dir.join("empty.pem")]).tls(),
+        Err(FetchError::Tls(err)) if err.contains("no certificates")
+    ));
+    assert!(matches!(
+        Options::default()
+            .ca_certs(vec![dir.join("missing.pem")])
+            .tls(),
+        Err(FetchError::Tls(_))
+    ));
+}
+
+#[test]
+fn fetch_cache() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let dir = std::env::temp_dir().join("osbuild-ks-fetch-cache");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/a.ks", listener.local_addr().unwrap());
+
+    // Answers the first request with the kickstart and those asking whether it changed with 304.
+    let server = std::thread::spawn(move || {
+        let mut conditional = Vec::new();
+
+        for stream in listener.incoming().take(2) {
+            let mut stream = stream.unwrap();
+            let mut lines = BufReader::new(&stream).lines().map(Result::unwrap);
+            let headers: Vec<String> = lines.by_ref().take_while(|line| !line.is_empty()).collect();
+            let unchanged = headers
+                .iter()
+                .any(|header| header == "If-None-Match: \"1\"");
+
+            let response = match unchanged {
+                true => "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+                false => "HTTP/1.1 200 OK\r\nETag: \"1\"\r\nContent-Length: 11\r\nConnection: close\r\n\r\nlang en_US\n",
+            };
+
+            stream.write_all(response.as_bytes()).unwrap();
+            conditional.push(unchanged);
+        }
+
+        conditional
+    });
+
+    let options = Options::default().cache(Some(Cache::new(dir)));
+
+    assert_eq!(fetch(&url, &options).unwrap(), "lang en_US\n");
+    assert_eq!(fetch(&url, &options).unwrap(), "lang en_US\n");
+    assert_eq!(server.join().unwrap(), [false, true]);
+}
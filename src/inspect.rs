@@ -0,0 +1,201 @@
+//! What a kickstart is parsed into: its sections, the commands of the command section with their
+//! options and arguments, and where each of them comes from. `osbuild-ks inspect` prints it to
+//! see why a translation came out the way it did.
+
+use serde_json::{json, Value};
+
+use crate::kickstart::{Kickstart, Section, SectionKind, Span};
+use crate::translate::catalog;
+use crate::translate::options::Options;
+use crate::translate::words;
+
+/// The options and arguments of the command or section `name`. Options take a value when the
+/// catalog says they do, others only when it's given with `=`.
+fn options(name: &str, args: &[String]) -> Result<Options, String> {
+    let values = catalog::lookup(name)
+        .map(|command| command.values)
+        .unwrap_or_default();
+
+    Options::parse(args, values)
+}
+
+fn location(span: Option<&Span>) -> Value {
+    match span {
+        Some(span) => json!({ "file": span.path, "line": span.line, "column": span.column }),
+        None => Value::Null,
+    }
+}
+
+/// `name` with the options and arguments of `args`, or why they can't be parsed.
+fn typed(name: &str, args: &[String], span: Option<&Span>) -> Value {
+    let mut typed = json!({ "name": name, "location": location(span) });
+
+    match options(name, args) {
+        Ok(options) => {
+            let named: Vec<_> = options
+                .iter()
+                .map(|(option, value)| json!({ "name": option, "value": value }))
+                .collect();
+
+            typed["options"] = json!(named);
+            typed["arguments"] = json!(options.positional());
+        }
+        Err(err) => typed["error"] = json!(err),
+    }
+
+    typed
+}
+
+fn command(line: &str, span: Option<&Span>) -> Value {
+    match words::split(line) {
+        Ok(words) => match words.split_first() {
+            Some((name, args)) => typed(name, args, span),
+            None => json!({ "location": location(span) }),
+        },
+        Err(err) => json!({ "text": line, "location": location(span), "error": err }),
+    }
+}
+
+fn section(section: &Section) -> Value {
+    let mut value = typed(section.name(), section.args(), Some(section.span()));
+
+    if section.kind() == SectionKind::Command {
+        let commands: Vec<_> = section
+            .lines()
+            .map(|(line, span)| command(line, span))
+            .collect();
+
+        value["commands"] = json!(commands);
+    } else {
+        let lines: Vec<_> = section
+            .lines()
+            .map(|(line, span)| json!({ "text": line, "location": location(span) }))
+            .collect();
+
+        value["lines"] = json!(lines);
+    }
+
+    value
+}
+
+/// The sections of `kickstart` in the order they're translated, the command section is last.
+pub fn to_json(kickstart: &Kickstart) -> Value {
+    let sections: Vec<_> = kickstart.sections().iter().map(section).collect();
+
+    json!({ "sections": sections })
+}
+
+/// What [`to_json`] has as an indented outline, one line for each section, command, option,
+/// argument and line of a section.
+pub fn outline(kickstart: &Kickstart) -> String {
+    let json = to_json(kickstart);
+    let mut outline = String::new();
+
+    let at = |value: &Value| match value["location"].as_object() {
+        Some(location) => format!(
+            "  ({}:{}:{})",
+            location["file"].as_str().unwrap_or_default(),
+            location["line"],
+            location["column"]
+        ),
+        None => String::new(),
+    };
+
+    let typed = |outline: &mut String, value: &Value, indent: &str| {
+        if let Some(err) = value["error"].as_str() {
+            *outline += &format!("{}error {}\n", indent, err);
+        }
+
+        for option in value["options"].as_array().into_iter().flatten() {
+            let name = option["name"].as_str().unwrap_or_default();
+
+            match option["value"].as_str() {
+                Some(value) => *outline += &format!("{}option {}={}\n", indent, name, value),
+                None => *outline += &format!("{}option {}\n", indent, name),
+            }
+        }
+
+        for argument in value["arguments"].as_array().into_iter().flatten() {
+            *outline += &format!("{}argument {}\n", indent, argument.as_str().unwrap());
+        }
+    };
+
+    for section in json["sections"].as_array().unwrap() {
+        outline += &format!("{}{}\n", section["name"].as_str().unwrap(), at(section));
+        typed(&mut outline, section, "    ");
+
+        for command in section["commands"].as_array().into_iter().flatten() {
+            let name = command["name"]
+                .as_str()
+                .or_else(|| command["text"].as_str())
+                .unwrap_or_default();
+
+            outline += &format!("    command {}{}\n", name, at(command));
+            typed(&mut outline, command, "        ");
+        }
+
+        for line in section["lines"].as_array().into_iter().flatten() {
+            outline += &format!("    line {}{}\n", line["text"].as_str().unwrap(), at(line));
+        }
+    }
+
+    outline
+}
+
+#[test]
+fn inspect_kickstart() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-inspect-kickstart");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "timezone UTC --ntpservers ntp.example --utc\n%post --nochroot\ntrue\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let path = dir.join("main.ks").canonicalize().unwrap();
+    let path = path.display();
+    let location = |line| json!({ "file": path.to_string(), "line": line, "column": 1 });
+
+    let json = to_json(&kickstart);
+
+    assert_eq!(
+        json["sections"][0],
+        json!({
+            "name": "%post",
+            "options": [{ "name": "--nochroot", "value": null }],
+            "arguments": [],
+            "location": location(2),
+            "lines": [{ "text": "true", "location": location(3) }],
+        })
+    );
+    assert_eq!(
+        json["sections"][1]["commands"][0],
+        json!({
+            "name": "timezone",
+            "options": [
+                { "name": "--ntpservers", "value": "ntp.example" },
+                { "name": "--utc", "value": null },
+            ],
+            "arguments": ["UTC"],
+            "location": location(1),
+        })
+    );
+
+    assert_eq!(
+        outline(&kickstart),
+        format!(
+            "%post  ({path}:2:1)\n    option --nochroot\n    line true  ({path}:3:1)\n\
+             command  ({path}:1:1)\n    command timezone  ({path}:1:1)\n        \
+             option --ntpservers=ntp.example\n        option --utc\n        argument UTC\n",
+            path = path
+        )
+    );
+}
@@ -0,0 +1,1691 @@
+use std::borrow::Cow;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use log::*;
+use thiserror::Error;
+
+use crate::exit;
+use crate::fetch::{self, FetchError};
+use crate::version::Version;
+
+#[derive(Clone, Debug)]
+pub struct Kickstart {
+    file: File,
+    tree: Tree,
+}
+
+#[derive(Clone, Debug)]
+pub struct File {
+    path: Box<PathBuf>,
+    data: String,
+    /// Where each line of `data` comes from, includes keep the spans of the included file.
+    spans: Vec<Span>,
+}
+
+/// Where something in a kickstart comes from: the file it's in, the line and the column its first
+/// word starts at, or the word a problem is with, both counting from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Section {
+    name: String,
+    data: String,
+    args: Vec<String>,
+    /// Where the header is, for the command section the start of the kickstart.
+    span: Span,
+    /// Where each line of `data` comes from.
+    spans: Vec<Span>,
+}
+
+/// The kinds of sections we know about, anything else is kept as `Unknown`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SectionKind {
+    Command,
+    Packages,
+    Pre,
+    PreInstall,
+    Post,
+    Addon,
+    Anaconda,
+    OnError,
+    Traceback,
+    Certificate,
+    Unknown,
+}
+
+/// Where the files a kickstart includes or appends come from, paths are searched in order, and
+/// what their placeholders are replaced with.
+#[derive(Clone, Debug)]
+pub struct Includes {
+    paths: Vec<PathBuf>,
+    network: bool,
+    /// Comments outside of scripts are kept, only for flattening as they aren't parsed.
+    comments: bool,
+    /// The values of `${key}` placeholders, given with `--define key=value`.
+    defines: Vec<(String, String)>,
+    /// `${env:NAME}` placeholders are replaced with the variable `NAME` of the environment.
+    environment: bool,
+    /// How kickstarts given by URL are retrieved.
+    fetch: fetch::Options,
+}
+
+impl SectionKind {
+    /// The kind of section named `name`, including its leading `%`.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "command" => SectionKind::Command,
+            "%packages" => SectionKind::Packages,
+            "%pre" => SectionKind::Pre,
+            "%pre-install" => SectionKind::PreInstall,
+            "%post" => SectionKind::Post,
+            "%addon" => SectionKind::Addon,
+            "%anaconda" => SectionKind::Anaconda,
+            "%onerror" => SectionKind::OnError,
+            "%traceback" => SectionKind::Traceback,
+            "%certificate" => SectionKind::Certificate,
+            _ => SectionKind::Unknown,
+        }
+    }
+
+    /// Whether the section is a script, its lines are passed to an interpreter untouched.
+    pub fn is_script(self) -> bool {
+        matches!(
+            self,
+            SectionKind::Pre
+                | SectionKind::PreInstall
+                | SectionKind::Post
+                | SectionKind::OnError
+                | SectionKind::Traceback
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Tree {
+    file: File,
+    sections: Vec<Section>,
+}
+
+/// How deep includes may nest before we give up.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The path that reads the kickstart from standard input, what it includes is looked for in the
+/// include paths like for any other kickstart.
+pub const STDIN: &str = "-";
+
+/// What a kickstart read from standard input is called where it's pointed at.
+const STDIN_NAME: &str = "<stdin>";
+
+#[derive(Debug, Error)]
+pub enum KickstartError {
+    /// A file couldn't be read, with its path.
+    #[error("failed to read '{0}': {1}")]
+    IO(String, io::Error),
+    #[error("failed to fetch '{0}': {1}")]
+    Fetch(String, FetchError),
+    #[error("failed to parse kickstart: {0}")]
+    Parse(String),
+    /// A section isn't closed with `%end` where the kickstart version requires it.
+    #[error("failed to parse kickstart: {0}")]
+    MissingEnd(String),
+    /// A file isn't valid UTF-8, with the offset of the first invalid byte.
+    #[error("'{0}' is not valid UTF-8, the first invalid byte is at offset {1}")]
+    Encoding(String, usize),
+    /// A file to include isn't in any of the include paths, which are given as well.
+    #[error("'{0}' isn't in the include path '{1}'")]
+    Missing(String, String),
+    /// A file wants to include a URL while network access isn't allowed.
+    #[error("'{0}' is remote and network access isn't allowed, see --allow-network")]
+    Network(String),
+    /// A placeholder outside of a script has no value and no default.
+    #[error("'${{{0}}}' isn't defined and has no default")]
+    Undefined(String),
+    /// A variable of the environment isn't set, with `--env-substitution`.
+    #[error("'${{env:{0}}}' isn't set in the environment and has no default")]
+    Environment(String),
+    /// A file includes itself, with the chain of includes that led back to it.
+    #[error("include cycle: {}", .0.join(" -> "))]
+    IncludeCycle(Vec<String>),
+    /// Includes nest deeper than [`MAX_INCLUDE_DEPTH`], with the chain of includes.
+    #[error("includes nest deeper than {}: {}", MAX_INCLUDE_DEPTH, .0.join(" -> "))]
+    IncludeDepth(Vec<String>),
+    /// All problems found in a kickstart, parsing goes on after a problem to find the others.
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<KickstartError>),
+    /// A problem with where in the kickstart, or in a file it includes, it is.
+    #[error("{0}: {1}")]
+    At(Span, Box<KickstartError>),
+}
+
+impl KickstartError {
+    /// This problem located at `span`.
+    fn at(self, span: &Span) -> KickstartError {
+        KickstartError::At(span.clone(), Box::new(self))
+    }
+
+    /// The exit code for this problem, the first one of several.
+    pub fn code(&self) -> exit::Code {
+        match self {
+            KickstartError::IO(..) | KickstartError::Fetch(..) | KickstartError::Missing(..) => {
+                exit::Code::IO
+            }
+            KickstartError::Network(_) => exit::Code::Usage,
+            KickstartError::Invalid(errors) => errors
+                .first()
+                .map_or(exit::Code::Parse, KickstartError::code),
+            KickstartError::At(_, err) => err.code(),
+            _ => exit::Code::Parse,
+        }
+    }
+
+    /// Failing to read `path`.
+    fn io(path: &Path) -> impl FnOnce(io::Error) -> KickstartError + '_ {
+        move |err| KickstartError::IO(path.display().to_string(), err)
+    }
+}
+
+impl Includes {
+    /// Look for includes in `paths`, the first path containing a file wins, without network
+    /// access.
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> Result<Self, KickstartError> {
+        Ok(Self {
+            paths: paths
+                .iter()
+                .map(|path| {
+                    let path = path.as_ref();
+                    path.canonicalize().map_err(KickstartError::io(path))
+                })
+                .collect::<Result<_, _>>()?,
+            network: false,
+            comments: false,
+            defines: Vec::new(),
+            environment: false,
+            fetch: fetch::Options::default(),
+        })
+    }
+
+    /// Allow including and appending files from HTTP and HTTPS URLs.
+    pub fn network(mut self, network: bool) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Keep comments outside of scripts in what is read, to flatten a kickstart with them.
+    pub fn comments(mut self, comments: bool) -> Self {
+        self.comments = comments;
+        self
+    }
+
+    /// Replace `${key}` placeholders with `value`, the last value given for a key wins.
+    pub fn defines(mut self, defines: Vec<(String, String)>) -> Self {
+        self.defines = defines;
+        self
+    }
+
+    /// Replace `${env:NAME}` placeholders with the variables of the environment, in scripts as
+    /// well. Variables that aren't set are an error unless the placeholder has a default.
+    pub fn environment(mut self, environment: bool) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// Retrieve the kickstart and the files it includes from URLs with `fetch`.
+    pub fn fetch(mut self, fetch: fetch::Options) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    /// `line` with its `${key}` and `${key:-default}` placeholders replaced. Lines of scripts and
+    /// comments only have the keys that are defined replaced, the others are shell variables.
+    fn expand<'a>(&self, line: &'a str, lenient: bool) -> Result<Cow<'a, str>, KickstartError> {
+        if !line.contains("${") {
+            return Ok(Cow::Borrowed(line));
+        }
+
+        let mut expanded = String::new();
+        let mut rest = line;
+
+        while let Some(start) = rest.find("${") {
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => break,
+            };
+
+            let placeholder = &rest[start + 2..end];
+            let (key, default) = match placeholder.split_once(":-") {
+                Some((key, default)) => (key, Some(default)),
+                None => (placeholder, None),
+            };
+
+            let defined = match key.strip_prefix("env:") {
+                Some(name) if self.environment => match (env::var(name), default) {
+                    (Ok(value), _) => Some(value),
+                    (Err(_), Some(default)) => Some(default.to_string()),
+                    (Err(_), None) => return Err(KickstartError::Environment(name.to_string())),
+                },
+                _ => self
+                    .defines
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value.clone()),
+            };
+
+            let is_key = !key.is_empty()
+                && key
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c));
+
+            let value = match (defined, default) {
+                (Some(value), _) => Some(value),
+                _ if lenient || !is_key => None,
+                (None, Some(default)) => Some(default.to_string()),
+                (None, None) => return Err(KickstartError::Undefined(key.to_string())),
+            };
+
+            expanded += &rest[..start];
+            expanded += value.as_deref().unwrap_or(&rest[start..=end]);
+            rest = &rest[end + 1..];
+        }
+
+        expanded += rest;
+
+        Ok(Cow::Owned(expanded))
+    }
+
+    /// The file to include for `target`, a URL if network access is allowed or otherwise the
+    /// first include path that has it.
+    fn open(&self, target: &str) -> Result<File, KickstartError> {
+        if fetch::is_url(target) {
+            if !self.network {
+                return Err(KickstartError::Network(target.to_string()));
+            }
+
+            return File::fetch(target, &self.fetch);
+        }
+
+        self.paths
+            .iter()
+            .map(|path| path.join(target))
+            .find(|path| path.exists())
+            .ok_or_else(|| KickstartError::Missing(target.to_string(), self.to_string()))
+            .and_then(|path| File::load(&path))
+    }
+}
+
+impl fmt::Display for Includes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let paths: Vec<_> = self
+            .paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+
+        write!(f, "{}", paths.join(":"))
+    }
+}
+
+impl Span {
+    /// This span moved to the first `token` in `line`, the line it's of, after byte `from`. It
+    /// stays where it is when `token` isn't there.
+    fn token(&self, line: &str, from: usize, token: &str) -> Span {
+        let mut span = self.clone();
+
+        if let Some(index) = line.get(from..).and_then(|rest| rest.find(token)) {
+            span.column = line[..from + index].chars().count() + 1;
+        }
+
+        span
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.column)
+    }
+}
+
+impl Kickstart {
+    #[cfg(test)]
+    pub fn from_path(src: &Path, inc: &Includes, version: Version) -> Result<Self, KickstartError> {
+        Self::from_layers(&[src], inc, version)
+    }
+
+    /// Read several kickstarts as one, each appended after those before it as if by
+    /// `%ksappend` so later layers add to and override earlier ones.
+    pub fn from_layers(
+        srcs: &[&Path],
+        inc: &Includes,
+        version: Version,
+    ) -> Result<Self, KickstartError> {
+        let (kickstart, errors) = Self::read_layers(srcs, inc, version)?;
+
+        if !errors.is_empty() {
+            return Err(KickstartError::Invalid(errors));
+        }
+
+        Ok(kickstart)
+    }
+
+    /// Read a kickstart like [`Kickstart::from_path`], but return the problems found along with
+    /// everything that could still be parsed. Only failing to read the kickstart itself is an
+    /// error.
+    pub fn read(
+        src: &Path,
+        inc: &Includes,
+        version: Version,
+    ) -> Result<(Self, Vec<KickstartError>), KickstartError> {
+        Self::read_layers(&[src], inc, version)
+    }
+
+    /// Read layered kickstarts like [`Kickstart::from_layers`], returning the problems found
+    /// like [`Kickstart::read`].
+    pub fn read_layers(
+        srcs: &[&Path],
+        inc: &Includes,
+        version: Version,
+    ) -> Result<(Self, Vec<KickstartError>), KickstartError> {
+        let mut errors = Vec::new();
+        let mut file: Option<File> = None;
+
+        for src in srcs {
+            let src = &match *src == Path::new(STDIN) || fetch::is_url(&src.to_string_lossy()) {
+                true => src.to_path_buf(),
+                false => src.canonicalize().map_err(KickstartError::io(src))?,
+            };
+
+            info!(
+                "Creating Kickstart from path '{}' with include path '{}'",
+                src.display(),
+                inc
+            );
+
+            let layer = File::from_path(src, inc, &mut errors)?;
+
+            file = match file {
+                Some(mut file) => {
+                    file.append(layer);
+                    Some(file)
+                }
+                None => Some(layer),
+            };
+        }
+
+        let file = match file {
+            Some(file) => file,
+            None => {
+                return Err(KickstartError::io(Path::new(STDIN))(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "no kickstart given",
+                )))
+            }
+        };
+
+        let tree = Tree::from_file(file.clone())?.parse(version, &mut errors); // TODO: no clone
+
+        Ok((Self { file, tree }, errors))
+    }
+
+    /// The flattened file this kickstart was parsed from.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    /// All sections of this kickstart, the command section is always the last one.
+    pub fn sections(&self) -> &[Section] {
+        &self.tree.sections
+    }
+}
+
+impl File {
+    /// Read a kickstart and everything it includes, files given to `%ksappend` anywhere in it
+    /// are appended after it. A `src` that is an HTTP or HTTPS URL is retrieved. Problems in
+    /// what it includes are added to `errors`, only failing to read the kickstart itself is an
+    /// error.
+    pub fn from_path(
+        src: &Path,
+        inc: &Includes,
+        errors: &mut Vec<KickstartError>,
+    ) -> Result<Self, KickstartError> {
+        let mut instance = match src.to_string_lossy() {
+            _ if src == Path::new(STDIN) => Self::stdin()?,
+            url if fetch::is_url(&url) => Self::fetch(&url, &inc.fetch)?,
+            _ => Self::load(src)?,
+        };
+
+        let chain = vec![instance.path.display().to_string()];
+
+        for appended in instance.resolve(inc, None, &chain, errors) {
+            instance.append(appended);
+        }
+
+        Ok(instance)
+    }
+
+    /// Add the lines of `other` after those of this file.
+    fn append(&mut self, other: File) {
+        self.data += &other.data;
+        self.spans.extend(other.spans);
+    }
+
+    /// The files on disk this was read from, the kickstart first and then what it includes in
+    /// the order they're first used.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.path.to_path_buf()];
+
+        for span in &self.spans {
+            let path = Path::new(&span.path);
+
+            if !paths.iter().any(|known| known == path) {
+                paths.push(path.to_path_buf());
+            }
+        }
+
+        paths.retain(|path| path.is_file());
+        paths
+    }
+
+    /// The kickstart as a single file, with a `%end` after every section that doesn't have one
+    /// so each section ends where it did in the file it comes from.
+    pub fn flatten(&self) -> String {
+        let mut flattened = String::new();
+        let mut current: Option<SectionKind> = None;
+
+        for line in self.data.lines() {
+            let script = current.is_some_and(SectionKind::is_script);
+
+            match header(line, script).as_deref() {
+                Some(["%end", ..]) => current = None,
+                Some(words) => {
+                    if current.is_some() {
+                        flattened += "%end\n";
+                    }
+
+                    current = Some(SectionKind::from_name(words[0]));
+                }
+                None => {}
+            }
+
+            flattened = flattened + line + "\n";
+        }
+
+        if current.is_some() {
+            flattened += "%end\n";
+        }
+
+        flattened
+    }
+
+    /// Read a single kickstart file without resolving anything.
+    fn load(src: &Path) -> Result<Self, KickstartError> {
+        let mut file = fs::File::open(src).map_err(KickstartError::io(src))?;
+        let mut buffer = Vec::new();
+
+        file.read_to_end(&mut buffer)
+            .map_err(KickstartError::io(src))?;
+
+        let path = src.canonicalize().map_err(KickstartError::io(src))?;
+        let name = path.display().to_string();
+        let data = decode(&name, buffer)?;
+
+        Ok(Self {
+            path: Box::new(path),
+            spans: spans(&name, &data),
+            data,
+        })
+    }
+
+    /// Read a kickstart from standard input without resolving anything.
+    fn stdin() -> Result<Self, KickstartError> {
+        let mut buffer = Vec::new();
+
+        io::stdin()
+            .read_to_end(&mut buffer)
+            .map_err(KickstartError::io(Path::new(STDIN)))?;
+
+        let data = decode(STDIN_NAME, buffer)?;
+
+        Ok(Self {
+            path: Box::new(PathBuf::from(STDIN_NAME)),
+            spans: spans(STDIN_NAME, &data),
+            data,
+        })
+    }
+
+    /// Retrieve a kickstart file from a URL without resolving anything.
+    fn fetch(url: &str, options: &fetch::Options) -> Result<Self, KickstartError> {
+        let data = fetch::fetch(url, options)
+            .map_err(|err| KickstartError::Fetch(url.to_string(), err))?;
+        let data = decode(url, data.into_bytes())?;
+
+        Ok(Self {
+            path: Box::new(PathBuf::from(url)),
+            spans: spans(url, &data),
+            data,
+        })
+    }
+
+    /// Resolve all includes in a kickstart file to flatten it into a single string. The files
+    /// to append, from `%ksappend` here or in any included file, are returned in order. Comments
+    /// are removed unless `inc` keeps them, except in scripts where they belong to the script.
+    ///
+    /// `section` is the section this file is included in, its contents become part of that
+    /// section so it can't start or end sections itself. `chain` are the files that led to this
+    /// one, starting with the kickstart itself and ending with this file. Problems are added to
+    /// `errors` at the line they are on, which is left out so everything else is still resolved.
+    fn resolve(
+        &mut self,
+        inc: &Includes,
+        section: Option<&str>,
+        chain: &[String],
+        errors: &mut Vec<KickstartError>,
+    ) -> Vec<File> {
+        let mut data = String::new();
+        let mut spans = Vec::new();
+        let mut appended = Vec::new();
+        let mut current = section.map(str::to_string);
+
+        for (line, span) in self.data.lines().zip(&self.spans) {
+            let script = current
+                .as_deref()
+                .is_some_and(|name| SectionKind::from_name(name).is_script());
+
+            let comment = line.trim_start().starts_with('#');
+
+            if !script && !inc.comments && comment {
+                continue;
+            }
+
+            // What can't be expanded is kept as it is, so the rest of it is still read.
+            let line = match inc.expand(line, script || comment) {
+                Ok(line) => line,
+                Err(err) => {
+                    let at = match &err {
+                        KickstartError::Undefined(key) => {
+                            span.token(line, 0, &format!("${{{}", key))
+                        }
+                        KickstartError::Environment(name) => {
+                            span.token(line, 0, &format!("${{env:{}", name))
+                        }
+                        _ => span.clone(),
+                    };
+
+                    errors.push(err.at(&at));
+                    Cow::Borrowed(line)
+                }
+            };
+            let line = line.as_ref();
+            let words = header(line, script);
+
+            match words.as_deref() {
+                Some(words @ ["%include", ..]) => {
+                    trace!(
+                        "File.resolve: '{}' wants '{}' in '{}'",
+                        self.path.display(),
+                        line,
+                        current.as_deref().unwrap_or("command")
+                    );
+
+                    let (target, span) = match directive(line, words, span) {
+                        Ok(directive) => directive,
+                        Err(err) => {
+                            errors.push(err.at(span));
+                            continue;
+                        }
+                    };
+
+                    let mut included = match inc.open(target) {
+                        Ok(included) => included,
+                        Err(err) => {
+                            errors.push(err.at(&span));
+                            continue;
+                        }
+                    };
+
+                    let chain = match descend(chain, &included) {
+                        Ok(chain) => chain,
+                        Err(err) => {
+                            errors.push(err.at(&span));
+                            continue;
+                        }
+                    };
+
+                    appended.extend(included.resolve(inc, current.as_deref(), &chain, errors));
+
+                    debug!(
+                        "File.resolve: '{}' has included '{}'",
+                        self.path.display(),
+                        included.path.display()
+                    );
+
+                    data = data + &included.data;
+                    spans.append(&mut included.spans);
+                }
+                Some(words @ ["%ksappend", ..]) => {
+                    if let Some(current) = &current {
+                        errors.push(
+                            KickstartError::Parse(format!(
+                                "'{}' is appended after the kickstart, not inside '{}'",
+                                line, current
+                            ))
+                            .at(span),
+                        );
+                        continue;
+                    }
+
+                    trace!("File.resolve: '{}' wants '{}'", self.path.display(), line);
+
+                    let (target, span) = match directive(line, words, span) {
+                        Ok(directive) => directive,
+                        Err(err) => {
+                            errors.push(err.at(span));
+                            continue;
+                        }
+                    };
+
+                    let mut file = match inc.open(target) {
+                        Ok(file) => file,
+                        Err(err) => {
+                            errors.push(err.at(&span));
+                            continue;
+                        }
+                    };
+
+                    let chain = match descend(chain, &file) {
+                        Ok(chain) => chain,
+                        Err(err) => {
+                            errors.push(err.at(&span));
+                            continue;
+                        }
+                    };
+
+                    let nested = file.resolve(inc, None, &chain, errors);
+
+                    appended.push(file);
+                    appended.extend(nested);
+                }
+                _ => {
+                    if let Some(words) = &words {
+                        if let Some(section) = section {
+                            errors.push(
+                                KickstartError::Parse(format!(
+                                    "'{}' is included in '{}' and can't contain '{}'",
+                                    self.path.display(),
+                                    section,
+                                    line
+                                ))
+                                .at(span),
+                            );
+                            continue;
+                        }
+
+                        current = match words[0] {
+                            "%end" => None,
+                            name => Some(name.to_string()),
+                        };
+                    }
+
+                    data = data + line + "\n";
+                    spans.push(span.clone());
+                }
+            }
+        }
+
+        self.data = data;
+        self.spans = spans;
+
+        appended
+    }
+}
+
+/// The path or URL of an `%include` or `%ksappend` line at `span`, split into its `words` by
+/// [`header`], with the span of the path or URL.
+fn directive<'a>(
+    line: &str,
+    words: &[&'a str],
+    span: &Span,
+) -> Result<(&'a str, Span), KickstartError> {
+    match words {
+        [name, target] => {
+            let from = line.len() - line.trim_start().len() + name.len();
+            Ok((target, span.token(line, from, target)))
+        }
+        _ => Err(KickstartError::Parse(format!(
+            "'{}' needs a single path or URL",
+            line
+        ))),
+    }
+}
+
+/// The text of a kickstart named `name`. Kickstarts written on Windows or exported by provisioning
+/// tools can start with a byte order mark and end their lines with CRLF, both are normalized away.
+fn decode(name: &str, bytes: Vec<u8>) -> Result<String, KickstartError> {
+    let data = String::from_utf8(bytes).map_err(|err| {
+        KickstartError::Encoding(name.to_string(), err.utf8_error().valid_up_to())
+    })?;
+
+    let data = data.strip_prefix('\u{feff}').unwrap_or(&data);
+
+    Ok(data.replace("\r\n", "\n"))
+}
+
+/// The span of every line in `data`, read from `name`.
+fn spans(name: &str, data: &str) -> Vec<Span> {
+    data.lines()
+        .enumerate()
+        .map(|(index, line)| Span {
+            path: name.to_string(),
+            line: index + 1,
+            column: line.chars().take_while(|c| c.is_whitespace()).count() + 1,
+        })
+        .collect()
+}
+
+/// The words of a line that starts or ends a section, without any comment after them. In a
+/// `script` only `%end`, `%include` and the sections pykickstart knows are headers, other lines
+/// starting with `%` belong to the script.
+fn header(line: &str, script: bool) -> Option<Vec<&str>> {
+    let words: Vec<&str> = line
+        .split_whitespace()
+        .take_while(|word| !word.starts_with('#'))
+        .collect();
+
+    match words.first() {
+        Some(word) if script && !is_header(word) => None,
+        Some(word) if word.starts_with('%') => Some(words),
+        _ => None,
+    }
+}
+
+/// Whether `word` starts or ends a section, or includes or appends a file.
+fn is_header(word: &str) -> bool {
+    match word {
+        "%end" | "%include" | "%ksappend" => true,
+        name => name.starts_with('%') && SectionKind::from_name(name) != SectionKind::Unknown,
+    }
+}
+
+/// The chain of includes once `file` is included at the end of `chain`.
+fn descend(chain: &[String], file: &File) -> Result<Vec<String>, KickstartError> {
+    let mut descended = chain.to_vec();
+    descended.push(file.path.display().to_string());
+
+    if chain.contains(descended.last().unwrap()) {
+        return Err(KickstartError::IncludeCycle(descended));
+    }
+
+    if chain.len() > MAX_INCLUDE_DEPTH {
+        return Err(KickstartError::IncludeDepth(descended));
+    }
+
+    Ok(descended)
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.data)
+    }
+}
+
+impl Section {
+    /// An empty section from the words of its header, such as `%post --nochroot`, at `span`.
+    fn from_header(words: &[&str], span: Span) -> Self {
+        Section {
+            name: words[0].to_string(),
+            data: String::new(),
+            args: words[1..].iter().map(|word| word.to_string()).collect(),
+            span,
+            spans: Vec::new(),
+        }
+    }
+
+    /// Add a line of data from `span`.
+    fn push(&mut self, line: &str, span: Span) {
+        self.data = self.data.clone() + line + "\n";
+        self.spans.push(span);
+    }
+
+    /// The name of the section including its leading `%`, or `command` for the command section.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> SectionKind {
+        SectionKind::from_name(&self.name)
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Where the header of the section is, for the command section the start of the kickstart.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// The lines of data with where they come from, lines of replaced data don't have a span.
+    pub fn lines(&self) -> impl Iterator<Item = (&str, Option<&Span>)> {
+        self.data
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (line, self.spans.get(index)))
+    }
+
+    /// A copy of this section with its data replaced.
+    pub fn with_data(&self, data: String) -> Section {
+        Section {
+            name: self.name.clone(),
+            data,
+            args: self.args.clone(),
+            span: self.span.clone(),
+            spans: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    /// Render the section back into kickstart syntax, the command section is rendered as bare
+    /// commands.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.name == "command" {
+            return write!(f, "{}", self.data);
+        }
+
+        write!(f, "{}", self.name)?;
+
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+
+        writeln!(f)?;
+        write!(f, "{}", self.data)?;
+        writeln!(f, "%end")
+    }
+}
+
+impl Tree {
+    pub fn from_file(file: File) -> Result<Self, KickstartError> {
+        Ok(Self {
+            file,
+            sections: Vec::new(),
+        })
+    }
+
+    /// Split the file into its sections. Kickstarts older than [`Version::requires_end`] may
+    /// leave out `%end`, a section then ends where the next one starts or at the end of the file.
+    /// For later kickstarts a missing `%end` is added to `errors` and the section is ended there
+    /// anyway.
+    pub fn parse(mut self, version: Version, errors: &mut Vec<KickstartError>) -> Self {
+        let mut in_section = false;
+
+        let start = self.file.spans.first().cloned().unwrap_or_else(|| Span {
+            path: self.file.path.display().to_string(),
+            line: 1,
+            column: 1,
+        });
+
+        // The command section is all data that is not in any of the other sections.
+        let mut command_section = Section::from_header(&["command"], start.clone());
+        let mut section = Section::from_header(&[""], start);
+
+        // A command that ends in a backslash continues on the next line, it's where its first
+        // line is.
+        let mut continued: Option<(String, Span)> = None;
+
+        for (line, span) in self.file.data.lines().zip(&self.file.spans) {
+            let joined;
+            let (line, span) = match continued.take() {
+                Some((start, span)) => {
+                    joined = start + line;
+                    (joined.as_str(), span)
+                }
+                None => (line, span.clone()),
+            };
+
+            let words = header(line, in_section && section.kind().is_script());
+
+            if in_section {
+                match words.as_deref() {
+                    Some(["%end", ..]) => {
+                        in_section = false;
+                        self.sections.push(section.clone());
+                        debug!("Tree.parse: end section '{}'", section.name);
+                    }
+                    Some(words) => {
+                        if version.requires_end() {
+                            errors.push(
+                                KickstartError::MissingEnd(format!(
+                                    "'{}' starts while '{}' is still open, '{}' needs a %end \
+                                     before it",
+                                    words[0], section.name, section.name
+                                ))
+                                .at(&span),
+                            );
+                        }
+
+                        debug!("Tree.parse: end section '{}' without %end", section.name);
+                        self.sections.push(section);
+
+                        section = Section::from_header(words, span);
+                        debug!("Tree.parse: new section '{}'", section.name);
+                    }
+                    None => {
+                        trace!("Tree.parse: '{}'", line);
+
+                        section.push(line, span);
+                    }
+                }
+            } else if let Some(words) = words {
+                if words[0] == "%end" {
+                    errors.push(
+                        KickstartError::Parse("'%end' without a section to end".to_string())
+                            .at(&span),
+                    );
+                } else {
+                    // We're starting a new section
+                    in_section = true;
+                    section = Section::from_header(&words, span);
+
+                    debug!("Tree.parse: new section '{}'", section.name);
+                }
+            } else if let Some(start) = line.strip_suffix('\\') {
+                continued = Some((start.to_string(), span));
+            } else {
+                // TODO: Skip empty lines, is this correct, empty lines might carry
+                // significance in sections but do they carry it outside of %post/%pre?
+                if !line.is_empty() {
+                    command_section.push(line, span);
+                }
+            }
+        }
+
+        if let Some((start, span)) = continued {
+            command_section.push(&start, span);
+        }
+
+        if in_section {
+            if version.requires_end() {
+                errors.push(
+                    KickstartError::MissingEnd(format!(
+                        "'{}' doesn't end with %end, only kickstarts before {} may leave it out",
+                        section.name,
+                        Version::END_REQUIRED
+                    ))
+                    .at(&section.span),
+                );
+            }
+
+            debug!("Tree.parse: end section '{}' at end of file", section.name);
+            self.sections.push(section);
+        }
+
+        self.sections.push(command_section);
+        self.merge(errors)
+    }
+
+    /// After parsing there can be duplicate sections, we merge these down to single sections
+    /// where that is what the installer does. All `%packages` sections become one at the place
+    /// of the first, without repeated lines and with the arguments of all of them. Scripts run
+    /// one after the other so they stay separate, in order.
+    fn merge(mut self, errors: &mut Vec<KickstartError>) -> Self {
+        let mut sections: Vec<Section> = Vec::new();
+
+        for section in self.sections.drain(..) {
+            if section.kind() != SectionKind::Packages {
+                sections.push(section);
+                continue;
+            }
+
+            let merged = match sections
+                .iter_mut()
+                .find(|merged| merged.kind() == SectionKind::Packages)
+            {
+                Some(merged) => {
+                    debug!("Tree.merge: merging '{}'", section.name);
+
+                    for conflict in merge_args(&mut merged.args, &section.args) {
+                        errors.push(
+                            KickstartError::Parse(format!(
+                                "conflicting {}: {}",
+                                section.name, conflict
+                            ))
+                            .at(&section.span),
+                        );
+                    }
+
+                    merged
+                }
+                None => {
+                    sections.push(section.with_data(String::new()));
+                    sections.last_mut().unwrap()
+                }
+            };
+
+            for (line, span) in section.data.lines().zip(&section.spans) {
+                let line = line.trim();
+
+                if !line.is_empty() && !merged.data.lines().any(|merged| merged == line) {
+                    merged.push(line, span.clone());
+                }
+            }
+        }
+
+        self.sections = sections;
+
+        self
+    }
+}
+
+/// The options in section arguments, with their value if they have one. A value is given either
+/// as `--option=value` or as the next argument.
+fn section_options(args: &[String]) -> Vec<(&str, Option<&str>)> {
+    let mut options = Vec::new();
+    let mut args = args.iter().peekable();
+
+    while let Some(arg) = args.next() {
+        match arg.split_once('=') {
+            Some((option, value)) => options.push((option, Some(value))),
+            None => {
+                let value = args.next_if(|value| !value.starts_with('-'));
+                options.push((arg.as_str(), value.map(String::as_str)));
+            }
+        }
+    }
+
+    options
+}
+
+/// Add the section arguments in `args` to those in `merged`, options that are already there must
+/// have the same value. Returns the options that don't, these keep the value in `merged`.
+fn merge_args(merged: &mut Vec<String>, args: &[String]) -> Vec<String> {
+    let mut added = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for (option, value) in section_options(args) {
+        match section_options(merged)
+            .into_iter()
+            .find(|(known, _)| *known == option)
+        {
+            Some((_, known)) if known != value => {
+                conflicts.push(format!(
+                    "'{}' is given as '{}' and as '{}'",
+                    option,
+                    known.unwrap_or(""),
+                    value.unwrap_or("")
+                ));
+            }
+            Some(_) => {}
+            None => added.push(match value {
+                Some(value) => format!("{}={}", option, value),
+                None => option.to_string(),
+            }),
+        }
+    }
+
+    merged.extend(added);
+
+    conflicts
+}
+
+/// The problems in `errors` without where they are.
+#[cfg(test)]
+fn problems(errors: &[KickstartError]) -> Vec<&KickstartError> {
+    errors
+        .iter()
+        .map(|err| match err {
+            KickstartError::At(_, err) => err,
+            err => err,
+        })
+        .collect()
+}
+
+#[test]
+fn kickstart_ksappend() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-ksappend");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%ksappend appended.ks\n%include included.ks\nlang en_US\n",
+    )
+    .unwrap();
+    fs::write(dir.join("included.ks"), "keyboard us\n%ksappend last.ks\n").unwrap();
+    fs::write(dir.join("appended.ks"), "timezone UTC\n").unwrap();
+    fs::write(dir.join("last.ks"), "reboot\n").unwrap();
+
+    let mut errors = Vec::new();
+    let file = File::from_path(&dir.join("main.ks"), &includes, &mut errors).unwrap();
+
+    assert!(errors.is_empty());
+
+    assert_eq!(
+        file.to_string(),
+        "keyboard us\nlang en_US\ntimezone UTC\nreboot\n"
+    );
+}
+
+#[test]
+fn kickstart_include_in_section() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-include-in-section");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%packages\n%include packages.ks\n%end\n%post\n%include post.ks\n%end\n",
+    )
+    .unwrap();
+    fs::write(dir.join("packages.ks"), "vim\n-nano\n").unwrap();
+    fs::write(dir.join("post.ks"), "echo hello\n").unwrap();
+    fs::write(dir.join("section.ks"), "vim\n%end\n").unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+
+    assert_eq!(kickstart.sections()[0].data(), "vim\n-nano\n");
+    assert_eq!(kickstart.sections()[1].data(), "echo hello\n");
+
+    // The included `%end` would end `%packages` early.
+    fs::write(dir.join("bad.ks"), "%packages\n%include section.ks\n%end\n").unwrap();
+    let mut errors = Vec::new();
+    File::from_path(&dir.join("bad.ks"), &includes, &mut errors).unwrap();
+    assert!(matches!(problems(&errors)[..], [KickstartError::Parse(_)]));
+}
+
+#[test]
+fn kickstart_include_cycle() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-include-cycle");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(dir.join("a.ks"), "%include b.ks\n").unwrap();
+    fs::write(dir.join("b.ks"), "%ksappend a.ks\n").unwrap();
+
+    let mut errors = Vec::new();
+    File::from_path(&dir.join("a.ks"), &includes, &mut errors).unwrap();
+
+    match &problems(&errors)[..] {
+        [KickstartError::IncludeCycle(chain)] => {
+            let names: Vec<_> = chain
+                .iter()
+                .map(|path| path.rsplit('/').next().unwrap())
+                .collect();
+
+            assert_eq!(names, vec!["a.ks", "b.ks", "a.ks"]);
+        }
+        errors => panic!("expected an include cycle, got {:?}", errors),
+    }
+
+    for depth in 0..=MAX_INCLUDE_DEPTH + 1 {
+        fs::write(
+            dir.join(format!("{}.ks", depth)),
+            format!("%include {}.ks\n", depth + 1),
+        )
+        .unwrap();
+    }
+
+    let mut errors = Vec::new();
+    File::from_path(&dir.join("0.ks"), &includes, &mut errors).unwrap();
+    assert!(matches!(
+        problems(&errors)[..],
+        [KickstartError::IncludeDepth(_)]
+    ));
+}
+
+#[test]
+fn kickstart_include_network() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-include-network");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("include.ks"),
+        "%include https://example.com/a.ks\n",
+    )
+    .unwrap();
+    fs::write(dir.join("append.ks"), "%ksappend http://example.com/b.ks\n").unwrap();
+
+    for name in ["include.ks", "append.ks"] {
+        let mut errors = Vec::new();
+        File::from_path(&dir.join(name), &includes, &mut errors).unwrap();
+        assert!(matches!(
+            problems(&errors)[..],
+            [KickstartError::Network(_)]
+        ));
+    }
+}
+
+#[test]
+fn kickstart_include_paths() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-include-paths");
+    let (host, shared) = (dir.join("host"), dir.join("shared"));
+    fs::create_dir_all(&host).unwrap();
+    fs::create_dir_all(&shared).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%include network.ks\n%include lang.ks\n",
+    )
+    .unwrap();
+    fs::write(host.join("network.ks"), "network --hostname=host\n").unwrap();
+    fs::write(shared.join("network.ks"), "network --bootproto=dhcp\n").unwrap();
+    fs::write(shared.join("lang.ks"), "lang en_US\n").unwrap();
+
+    let includes = Includes::new(&[&host, &shared]).unwrap();
+
+    assert_eq!(
+        File::from_path(&dir.join("main.ks"), &includes, &mut Vec::new())
+            .unwrap()
+            .to_string(),
+        "network --hostname=host\nlang en_US\n"
+    );
+}
+
+#[test]
+fn kickstart_defines() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-defines");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap().defines(vec![
+        ("host".to_string(), "web".to_string()),
+        ("site".to_string(), "ams".to_string()),
+        ("site".to_string(), "fra".to_string()),
+    ]);
+
+    fs::write(
+        dir.join("main.ks"),
+        "network --hostname=${host}.${site}.example\n%include ${site}.ks\n\
+         part / --size=${size:-4096}\n%post\necho ${HOME} ${host} ${tmp:-/tmp}\n%end\n",
+    )
+    .unwrap();
+    fs::write(dir.join("fra.ks"), "timezone Europe/Paris\n").unwrap();
+    fs::write(dir.join("bad.ks"), "lang ${lang}\n").unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+
+    assert_eq!(
+        kickstart.sections()[0].data(),
+        "echo ${HOME} web ${tmp:-/tmp}\n"
+    );
+    assert_eq!(
+        kickstart.sections()[1].data(),
+        "network --hostname=web.fra.example\ntimezone Europe/Paris\npart / --size=4096\n"
+    );
+
+    let err = Kickstart::from_path(&dir.join("bad.ks"), &includes, Version::LATEST).unwrap_err();
+    assert!(err
+        .to_string()
+        .ends_with("'${lang}' isn't defined and has no default"));
+
+    env::set_var("OSBUILD_KS_KICKSTART_DEFINES", "admin");
+    fs::write(
+        dir.join("env.ks"),
+        "user --name=${env:OSBUILD_KS_KICKSTART_DEFINES} --gecos=${env:OSBUILD_KS_UNSET:-none}\n\
+         %post\necho ${env:OSBUILD_KS_UNSET}\n%end\n",
+    )
+    .unwrap();
+
+    let read = |environment| {
+        Kickstart::from_path(
+            &dir.join("env.ks"),
+            &includes.clone().environment(environment),
+            Version::LATEST,
+        )
+    };
+
+    assert_eq!(
+        read(false).unwrap().sections()[1].data(),
+        "user --name=${env:OSBUILD_KS_KICKSTART_DEFINES} --gecos=${env:OSBUILD_KS_UNSET:-none}\n"
+    );
+    assert!(read(true)
+        .unwrap_err()
+        .to_string()
+        .ends_with("'${env:OSBUILD_KS_UNSET}' isn't set in the environment and has no default"));
+}
+
+#[test]
+fn kickstart_comments() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-comments");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "# a kickstart\nlang en_US\n%packages\n  # editors\nvim\n%end\n%post\n%include post.ks\n%end\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("post.ks"),
+        "#!/bin/sh\n%wheel ALL=(ALL) ALL\n# say hello\necho hello\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+
+    assert_eq!(kickstart.sections()[0].data(), "vim\n");
+    assert_eq!(
+        kickstart.sections()[1].data(),
+        "#!/bin/sh\n%wheel ALL=(ALL) ALL\n# say hello\necho hello\n"
+    );
+    assert_eq!(kickstart.sections()[2].data(), "lang en_US\n");
+}
+
+#[test]
+fn kickstart_merge() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-merge");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%packages --nocore\nvim\n%end\n%post\necho one\n%end\n\
+         %packages --excludedocs --nocore\nvim\n-nano\n%end\n%post\necho two\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let sections = kickstart.sections();
+
+    assert_eq!(sections.len(), 4);
+    assert_eq!(sections[0].args(), ["--nocore", "--excludedocs"]);
+    assert_eq!(sections[0].data(), "vim\n-nano\n");
+    assert_eq!(sections[1].data(), "echo one\n");
+    assert_eq!(sections[2].data(), "echo two\n");
+
+    fs::write(
+        dir.join("conflict.ks"),
+        "%packages --inst-langs=en\n%end\n%packages --inst-langs de\n%end\n",
+    )
+    .unwrap();
+
+    assert!(matches!(
+        Kickstart::from_path(&dir.join("conflict.ks"), &includes, Version::LATEST),
+        Err(KickstartError::Invalid(errors))
+            if matches!(problems(&errors)[..], [KickstartError::Parse(_)])
+    ));
+}
+
+#[test]
+fn kickstart_layers() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-layers");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(dir.join("base.ks"), "lang en_US\n%packages\nvim\n%end\n").unwrap();
+    fs::write(dir.join("site.ks"), "%packages\n-nano\n%end\n").unwrap();
+    fs::write(dir.join("host.ks"), "timezone UTC\n").unwrap();
+
+    let layers = ["base.ks", "site.ks", "host.ks"].map(|name| dir.join(name));
+    let layers = layers.iter().map(|path| path.as_path()).collect::<Vec<_>>();
+
+    let kickstart = Kickstart::from_layers(&layers, &includes, Version::LATEST).unwrap();
+    let sections = kickstart.sections();
+
+    assert_eq!(sections.len(), 2);
+    assert_eq!(sections[0].data(), "vim\n-nano\n");
+    assert_eq!(sections[1].data(), "lang en_US\ntimezone UTC\n");
+    assert_eq!(kickstart.file().paths(), layers);
+}
+
+#[test]
+fn kickstart_legacy_sections() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-legacy-sections");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "lang en_US\n%packages\nvim\n%post\necho hello\n",
+    )
+    .unwrap();
+
+    let kickstart =
+        Kickstart::from_path(&dir.join("main.ks"), &includes, Version::Fedora(7)).unwrap();
+    let sections = kickstart.sections();
+
+    assert_eq!(sections[0].name(), "%packages");
+    assert_eq!(sections[0].data(), "vim\n");
+    assert_eq!(sections[1].name(), "%post");
+    assert_eq!(sections[1].data(), "echo hello\n");
+
+    assert!(matches!(
+        Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST),
+        Err(KickstartError::Invalid(_))
+    ));
+    assert!(Kickstart::from_path(&dir.join("main.ks"), &includes, Version::Rhel(5)).is_ok());
+}
+
+#[test]
+fn kickstart_section_headers() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-section-headers");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%post\t--nochroot   --log=/tmp/post.log # log it\necho hello\n%end  # done\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let sections = kickstart.sections();
+
+    assert_eq!(sections[0].name(), "%post");
+    assert_eq!(sections[0].args(), ["--nochroot", "--log=/tmp/post.log"]);
+    assert_eq!(sections[0].data(), "echo hello\n");
+
+    fs::write(dir.join("open.ks"), "%packages\nvim\n%post\n%end\n").unwrap();
+
+    match Kickstart::from_path(&dir.join("open.ks"), &includes, Version::LATEST) {
+        Err(KickstartError::Invalid(errors)) => assert!(matches!(
+            problems(&errors)[..],
+            [KickstartError::MissingEnd(err)] if err ==
+            "'%post' starts while '%packages' is still open, '%packages' needs a %end before it"
+        )),
+        result => panic!("expected a parse error, got {:?}", result),
+    }
+
+    fs::write(
+        dir.join("sudo.ks"),
+        "%post\ncat > /etc/sudoers.d/wheel <<EOF\n%wheel ALL=(ALL) NOPASSWD: ALL\nEOF\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("sudo.ks"), &includes, Version::LATEST).unwrap();
+    let sections = kickstart.sections();
+
+    assert_eq!(sections.len(), 2);
+    assert_eq!(sections[0].name(), "%post");
+    assert_eq!(
+        sections[0].data(),
+        "cat > /etc/sudoers.d/wheel <<EOF\n%wheel ALL=(ALL) NOPASSWD: ALL\nEOF\n"
+    );
+
+    fs::write(dir.join("part.ks"), "lang en_US\n").unwrap();
+    fs::write(dir.join("last.ks"), "reboot\n").unwrap();
+    fs::write(
+        dir.join("directives.ks"),
+        "  %include part.ks # shared\n%ksappend\tlast.ks  # at the end\n%includefoo part.ks\n",
+    )
+    .unwrap();
+
+    let mut errors = Vec::new();
+    let file = File::from_path(&dir.join("directives.ks"), &includes, &mut errors).unwrap();
+
+    assert!(errors.is_empty());
+    assert_eq!(
+        file.to_string(),
+        "lang en_US\n%includefoo part.ks\nreboot\n"
+    );
+}
+
+#[test]
+fn kickstart_errors() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-errors");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%include missing.ks\nlang en_US\n%end\n%packages\nvim\n%post\n%end\n",
+    )
+    .unwrap();
+
+    match Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST) {
+        Err(KickstartError::Invalid(errors)) => {
+            assert!(matches!(
+                problems(&errors)[..],
+                [
+                    KickstartError::Missing(..),
+                    KickstartError::Parse(_),
+                    KickstartError::MissingEnd(_)
+                ]
+            ));
+        }
+        result => panic!("expected every problem, got {:?}", result),
+    }
+
+    match Kickstart::from_path(&dir.join("gone.ks"), &includes, Version::LATEST) {
+        Err(KickstartError::IO(path, _)) => assert!(path.ends_with("gone.ks")),
+        result => panic!("expected a read error, got {:?}", result),
+    }
+}
+
+#[test]
+fn kickstart_continuation() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-continuation");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "network --bootproto=static \\\n  --ip=10.0.0.2\n%post\necho a \\\n  b\n%end\nreboot \\\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let sections = kickstart.sections();
+
+    assert_eq!(sections[0].data(), "echo a \\\n  b\n");
+    assert_eq!(
+        sections[1].data(),
+        "network --bootproto=static   --ip=10.0.0.2\nreboot \n"
+    );
+}
+
+#[test]
+fn kickstart_decode() {
+    assert_eq!(
+        decode("ks", b"\xef\xbb\xbflang en_US\r\nkeyboard us\r\n".to_vec()).unwrap(),
+        "lang en_US\nkeyboard us\n"
+    );
+
+    match decode("ks", b"lang en_US\n\xff\n".to_vec()) {
+        Err(KickstartError::Encoding(_, offset)) => assert_eq!(offset, 11),
+        result => panic!("expected an encoding error, got {:?}", result),
+    }
+}
+
+#[test]
+fn kickstart_spans() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-spans");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "# a kickstart\nlang en_US\n%packages\n%include packages.ks\n%end\n%ksappend last.ks\n",
+    )
+    .unwrap();
+    fs::write(dir.join("packages.ks"), "vim\n  -nano\n").unwrap();
+    fs::write(
+        dir.join("last.ks"),
+        "network \\\n  --bootproto=dhcp\n%end\n",
+    )
+    .unwrap();
+
+    let location = |span: &Span| {
+        (
+            span.path.rsplit('/').next().unwrap().to_string(),
+            span.line,
+            span.column,
+        )
+    };
+
+    let mut errors = Vec::new();
+    let file = File::from_path(&dir.join("main.ks"), &includes, &mut errors).unwrap();
+    let tree = Tree::from_file(file)
+        .unwrap()
+        .parse(Version::LATEST, &mut errors);
+    let sections = &tree.sections;
+
+    assert_eq!(location(sections[0].span()), ("main.ks".to_string(), 3, 1));
+    assert_eq!(
+        sections[0]
+            .lines()
+            .map(|(_, span)| location(span.unwrap()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("packages.ks".to_string(), 1, 1),
+            ("packages.ks".to_string(), 2, 3)
+        ]
+    );
+    assert_eq!(
+        sections[1]
+            .lines()
+            .map(|(line, span)| (line, location(span.unwrap())))
+            .collect::<Vec<_>>(),
+        vec![
+            ("lang en_US", ("main.ks".to_string(), 2, 1)),
+            ("network   --bootproto=dhcp", ("last.ks".to_string(), 1, 1))
+        ]
+    );
+
+    match &errors[..] {
+        [KickstartError::At(span, _)] => assert_eq!(location(span), ("last.ks".to_string(), 3, 1)),
+        errors => panic!("expected a located error, got {:?}", errors),
+    }
+}
+
+#[test]
+fn kickstart_flatten() {
+    let dir = std::env::temp_dir().join("osbuild-ks-kickstart-flatten");
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "# main\nlang en_US\n%include packages.ks\n%post\n# script\n%wheel\n%pre\necho\n",
+    )
+    .unwrap();
+    fs::write(dir.join("packages.ks"), "%packages\n# editor\nvim\n%end\n").unwrap();
+
+    let flatten = |includes: Includes| {
+        let mut errors = Vec::new();
+        let file = File::from_path(&dir.join("main.ks"), &includes, &mut errors).unwrap();
+        assert!(errors.is_empty());
+        file.flatten()
+    };
+
+    assert_eq!(
+        flatten(Includes::new(&[&dir]).unwrap()),
+        "lang en_US\n%packages\nvim\n%end\n%post\n# script\n%wheel\n%end\n%pre\necho\n%end\n"
+    );
+    assert_eq!(
+        flatten(Includes::new(&[&dir]).unwrap().comments(true)),
+        "# main\nlang en_US\n%packages\n# editor\nvim\n%end\n%post\n# script\n%wheel\n%end\n\
+         %pre\necho\n%end\n"
+    );
+}
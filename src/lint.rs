@@ -0,0 +1,336 @@
+//! The linter looks for mistakes in a kickstart that don't stop it from being translated, such as
+//! passwords in plain text. Every rule can be turned off, on the command line or in a config file
+//! that is shared between the kickstarts of a project.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::kickstart::{Kickstart, KickstartError, SectionKind, Span};
+use crate::translate::deprecated;
+use crate::translate::options::Options;
+use crate::translate::storage::VOLUME_VALUES;
+use crate::translate::words;
+use crate::version::Version;
+
+/// A rule of the linter, its name is the code of the diagnostics it finds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub description: &'static str,
+}
+
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "plaintext-password",
+        description: "passwords of `rootpw` and `user` that aren't hashed",
+    },
+    Rule {
+        name: "missing-end",
+        description: "sections that aren't closed with %end",
+    },
+    Rule {
+        name: "duplicate-mountpoint",
+        description: "partitions and logical volumes mounted at the same place",
+    },
+    Rule {
+        name: "deprecated-command",
+        description: "commands that were deprecated or removed",
+    },
+    Rule {
+        name: "unreachable-include",
+        description: "files to include or append that can't be found or fetched",
+    },
+];
+
+/// The names of all rules.
+pub fn names() -> Vec<&'static str> {
+    RULES.iter().map(|rule| rule.name).collect()
+}
+
+/// The options of `user` that take a value.
+const USER_VALUES: &[&str] = &[
+    "--name",
+    "--password",
+    "--groups",
+    "--homedir",
+    "--shell",
+    "--uid",
+    "--gid",
+    "--gecos",
+];
+
+/// The config file, rules are turned on or off by name: `{"rules": {"missing-end": false}}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    rules: BTreeMap<String, bool>,
+}
+
+/// The rules to check, all of them unless told otherwise.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    enabled: Vec<&'static str>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self { enabled: names() }
+    }
+}
+
+impl Rules {
+    /// Turn rules on or off as the config file at `path` says.
+    pub fn config(mut self, path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read '{}': {}", path.display(), err))?;
+        let config: Config = serde_json::from_str(&data)
+            .map_err(|err| format!("failed to parse '{}': {}", path.display(), err))?;
+
+        for (name, enabled) in &config.rules {
+            self = self.set(name, *enabled)?;
+        }
+
+        Ok(self)
+    }
+
+    /// Turn the rule named `name` on or off.
+    pub fn set(mut self, name: &str, enabled: bool) -> Result<Self, String> {
+        let rule = RULES
+            .iter()
+            .find(|rule| rule.name == name)
+            .ok_or_else(|| format!("'{}' is not a lint rule", name))?;
+
+        self.enabled.retain(|enabled| *enabled != rule.name);
+
+        if enabled {
+            self.enabled.push(rule.name);
+        }
+
+        Ok(self)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled.contains(&name)
+    }
+}
+
+/// The rule that covers a problem found while reading a kickstart, problems that aren't covered
+/// by a rule are always reported. Covered problems are reported with the name of their rule.
+fn rule_of(code: &str) -> Option<&'static str> {
+    match code {
+        "missing-end" => Some("missing-end"),
+        "include-missing" | "include-network" | "fetch" => Some("unreachable-include"),
+        _ => None,
+    }
+}
+
+/// Check `kickstart` against `rules`, `problems` are those found while reading it. What is
+/// deprecated depends on `version`.
+pub fn lint(
+    kickstart: &Kickstart,
+    problems: &[KickstartError],
+    rules: &Rules,
+    version: Version,
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = problems
+        .iter()
+        .flat_map(Diagnostic::from_kickstart)
+        .filter_map(|mut diagnostic| match rule_of(diagnostic.code) {
+            Some(rule) if !rules.is_enabled(rule) => None,
+            Some(rule) => {
+                diagnostic.code = rule;
+                Some(diagnostic)
+            }
+            None => Some(diagnostic),
+        })
+        .collect();
+
+    let mut mountpoints: Vec<(String, Option<&Span>)> = Vec::new();
+
+    for section in kickstart.sections() {
+        if section.kind() != SectionKind::Command {
+            continue;
+        }
+
+        for (line, span) in section.lines() {
+            let words = match words::split(line) {
+                Ok(words) => words,
+                Err(_) => continue,
+            };
+
+            let (name, args) = match words.split_first() {
+                Some((name, args)) => (name.as_str(), args),
+                None => continue,
+            };
+
+            let mut found = Vec::new();
+
+            match name {
+                "rootpw" => found.extend(plaintext_password(name, args, &[])),
+                "user" => found.extend(plaintext_password(name, args, USER_VALUES)),
+                "part" | "partition" | "logvol" => {
+                    // `swap`, `pv.01` and the like aren't mounted and can be given more than once.
+                    let mountpoint = Options::parse(args, VOLUME_VALUES)
+                        .ok()
+                        .and_then(|options| options.positional().first().cloned())
+                        .filter(|mountpoint| mountpoint.starts_with('/'));
+
+                    if let Some(mountpoint) = mountpoint {
+                        match mountpoints.iter().find(|(known, _)| *known == mountpoint) {
+                            Some((_, first)) => found.push(Diagnostic::new(
+                                "duplicate-mountpoint",
+                                Severity::Warning,
+                                match first {
+                                    Some(first) => format!(
+                                        "'{}' is mounted more than once, first at {}",
+                                        mountpoint, first
+                                    ),
+                                    None => format!("'{}' is mounted more than once", mountpoint),
+                                },
+                            )),
+                            None => mountpoints.push((mountpoint, span)),
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            for entry in deprecated::find(name, args) {
+                if entry.is_deprecated(version) {
+                    found.push(Diagnostic::new(
+                        "deprecated-command",
+                        Severity::Warning,
+                        format!("'{}' is {}", entry.subject(), entry.diagnostic()),
+                    ));
+                }
+            }
+
+            diagnostics.extend(
+                found
+                    .into_iter()
+                    .filter(|diagnostic| rules.is_enabled(diagnostic.code))
+                    .map(|diagnostic| diagnostic.at(span)),
+            );
+        }
+    }
+
+    diagnostics
+}
+
+/// The warning for a password of `rootpw` or `user` that isn't hashed, options in `values` take
+/// a value.
+fn plaintext_password(name: &str, args: &[String], values: &[&str]) -> Option<Diagnostic> {
+    let options = Options::parse(args, values).ok()?;
+
+    let password = match name {
+        "user" => options.value("--password").is_some(),
+        _ => !options.positional().is_empty(),
+    };
+
+    if !password || (options.flag("--iscrypted") && !options.flag("--plaintext")) {
+        return None;
+    }
+
+    Some(
+        Diagnostic::new(
+            "plaintext-password",
+            Severity::Warning,
+            format!("'{}' has a password in plain text", name),
+        )
+        .fix("hash it with `openssl passwd -6` and add --iscrypted".to_string()),
+    )
+}
+
+#[test]
+fn lint_rules() {
+    let rules = Rules::default().set("missing-end", false).unwrap();
+
+    assert!(!rules.is_enabled("missing-end"));
+    assert!(rules.is_enabled("plaintext-password"));
+    assert!(rules.clone().set("bogus", true).is_err());
+    assert!(rules
+        .set("missing-end", true)
+        .unwrap()
+        .is_enabled("missing-end"));
+
+    let dir = std::env::temp_dir().join("osbuild-ks-lint-rules");
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(
+        dir.join("lint.json"),
+        r#"{"rules": {"deprecated-command": false}}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("bogus.json"), r#"{"rule": {}}"#).unwrap();
+
+    let rules = Rules::default().config(&dir.join("lint.json")).unwrap();
+    assert!(!rules.is_enabled("deprecated-command"));
+    assert!(Rules::default().config(&dir.join("bogus.json")).is_err());
+}
+
+#[test]
+fn lint_kickstart() {
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-lint-kickstart");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%include missing.ks\n\
+         rootpw hunter2\n\
+         rootpw --iscrypted $6$salt$hash\n\
+         user --name=john --password=secret\n\
+         part / --size 1024\n\
+         part swap --size 512\n\
+         part swap --size 512\n\
+         logvol / --vgname=vg --name=root\n\
+         autostep\n\
+         %post\n\
+         echo hello\n",
+    )
+    .unwrap();
+
+    let (kickstart, problems) =
+        Kickstart::read(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+
+    let codes = |rules: &Rules| {
+        lint(&kickstart, &problems, rules, Version::LATEST)
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.line))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        codes(&Rules::default()),
+        vec![
+            ("unreachable-include", Some(1)),
+            ("missing-end", Some(10)),
+            ("plaintext-password", Some(2)),
+            ("plaintext-password", Some(4)),
+            ("duplicate-mountpoint", Some(8)),
+            ("deprecated-command", Some(9)),
+        ]
+    );
+
+    let rules = Rules::default()
+        .set("unreachable-include", false)
+        .and_then(|rules| rules.set("missing-end", false))
+        .and_then(|rules| rules.set("plaintext-password", false))
+        .unwrap();
+
+    assert_eq!(
+        codes(&rules),
+        vec![
+            ("duplicate-mountpoint", Some(8)),
+            ("deprecated-command", Some(9))
+        ]
+    );
+}
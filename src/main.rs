@@ -13,309 +13,2020 @@
 /// filed. The specification and abilities of Kickstart files were found on this
 /// [Fedora Documentation](https://docs.fedoraproject.org/en-US/fedora/latest/install-guide/appendixes/Kickstart_Syntax_Reference/)
 /// page.
-use std::path::Path;
-use std::process::exit;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 
-use clap;
 use log::*;
 
-mod kickstart {
-    use std::fs;
-    use std::io;
-    use std::io::prelude::*;
-    use std::path::{Path, PathBuf};
-    use std::process::exit;
+mod audit;
+mod batch;
+mod build;
+mod cache;
+mod composer;
+mod config;
+mod diagnostics;
+mod dialect;
+mod diff;
+mod distro;
+mod exit;
+mod fetch;
+mod inspect;
+mod kickstart;
+mod lint;
+mod man;
+mod osbuild;
+mod reverse;
+mod toml;
+mod translate;
+mod validate;
+mod verify;
+mod version;
+mod warnings;
+mod watch;
+mod yaml;
 
-    use log::*;
+fn make_cli<'a>() -> clap::Command<'a> {
+    clap::command!()
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .arg(
+            clap::arg!(-I --include <path> "include path for kickstart files, searched in the order given")
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true)
+                .default_value(".")
+                .value_hint(clap::ValueHint::DirPath),
+        )
+        .arg(
+            clap::arg!(-D --define <"key=value"> "replace ${key} in the kickstart with value, ${key:-default} is replaced with default when key isn't defined")
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::arg!(--"ks-version" <version> "kickstart syntax version, such as F38 or RHEL9")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            clap::arg!(--distro <name> "distribution the kickstart installs, such as fedora, rhel or centos")
+                .required(false)
+                .global(true)
+                .default_value("fedora"),
+        )
+        .arg(
+            clap::arg!(--"definitions-dir" <path> "directory with JSON distribution definitions")
+                .required(false)
+                .global(true)
+                .value_hint(clap::ValueHint::DirPath),
+        )
+        .arg(
+            clap::arg!(--"diagnostics-format" <fmt> "print errors and warnings as text, json or sarif")
+                .required(false)
+                .global(true)
+                .possible_values(diagnostics::FORMATS)
+                .hide_possible_values(true)
+                .default_value("text"),
+        )
+        .arg(
+            clap::arg!(--color <when> "color errors and warnings: auto, always or never")
+                .required(false)
+                .global(true)
+                .possible_values(["auto", "always", "never"])
+                .hide_possible_values(true)
+                .default_value("auto"),
+        )
+        .arg(clap::arg!(--strict "treat warnings as errors and fail on commands that have no image equivalent").global(true))
+        .arg(
+            clap::arg!(--permissive "go on past problems in the kickstart and commands that can't be built")
+                .global(true)
+                .conflicts_with("strict"),
+        )
+        .arg(
+            clap::arg!(-W --warning <control> "make warnings errors with error=<code> or silence them with no-<code>")
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::arg!(--"warning-config" <path> "JSON file with what to do with warnings")
+                .required(false)
+                .global(true),
+        )
+        .arg(clap::arg!(--"allow-network" "allow `%include`, `%ksappend` and `rpm --import` of HTTP and HTTPS URLs").global(true))
+        .arg(
+            clap::arg!(--proxy <url> "proxy for HTTP and HTTPS URLs, instead of the one in https_proxy, http_proxy or all_proxy")
+                .required(false)
+                .global(true),
+        )
+        .arg(
+            clap::arg!(--"fetch-timeout" <seconds> "how long to wait for a server to connect and to answer")
+                .required(false)
+                .global(true)
+                .default_value("30"),
+        )
+        .arg(
+            clap::arg!(--"ca-cert" <file> "also trust the certificate authorities in this PEM file for HTTPS URLs")
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true)
+                .value_hint(clap::ValueHint::FilePath),
+        )
+        .arg(clap::arg!(--insecure "don't verify the certificates of HTTPS servers").global(true))
+        .arg(
+            clap::arg!(--"cache-dir" <dir> "where what's fetched is kept to download it again only when it changed, osbuild-ks in $XDG_CACHE_HOME by default")
+                .required(false)
+                .global(true)
+                .value_hint(clap::ValueHint::DirPath),
+        )
+        .arg(
+            clap::arg!(--"no-cache" "download what's fetched every time")
+                .global(true)
+                .conflicts_with("cache-dir"),
+        )
+        .arg(clap::arg!(--"env-substitution" "replace ${env:NAME} in the kickstart with the environment variable NAME").global(true))
+        .arg(
+            clap::arg!(-v --verbose "log more, -vv and -vvv log what's done in detail")
+                .global(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            clap::arg!(-q --quiet "only log errors")
+                .global(true)
+                .conflicts_with("verbose"),
+        )
+        .arg(
+            clap::arg!(--"log-level" <level> "what to log: error, warn, info, debug or trace")
+                .required(false)
+                .global(true)
+                .possible_values(LOG_LEVELS)
+                .hide_possible_values(true)
+                .conflicts_with_all(&["verbose", "quiet"]),
+        )
+        .subcommand(
+            clap::Command::new("convert")
+                .about("Convert a kickstart into an osbuild manifest")
+                .arg(
+                    clap::arg!(<src> "Kickstart input file, - reads it from standard input, or a directory or pattern such as 'hosts/*.ks' with --out-dir")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!([dst] "osbuild manifest output file, standard output if it's - or not given, or a kickstart layered over <src> with -o")
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!([layers] ... "more kickstarts layered over <src> and <dst> in order, with -o")
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(-o --output <dst> "osbuild manifest output file, every other path given is a kickstart that's layered over those before it")
+                        .required(false)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(--"out-dir" <dir> "convert each kickstart of a <src> directory or pattern into this directory")
+                        .required(false)
+                        .conflicts_with_all(&["dst", "layers", "output", "report", "split-sources", "build"])
+                        .value_hint(clap::ValueHint::DirPath),
+                )
+                .arg(
+                    clap::arg!(--"eula-marker" <path> "file marking the EULA as accepted by `eula --agreed`")
+                        .required(false)
+                        .default_value(translate::DEFAULT_EULA_MARKER),
+                )
+                .arg(
+                    clap::arg!(--arch <arch> "architecture of the image: x86_64, aarch64, ppc64le or s390x")
+                        .required(false)
+                        .possible_values(translate::ARCHES)
+                        .hide_possible_values(true),
+                )
+                .arg(
+                    clap::arg!(--format <format> "artifact to build: raw, qcow2, vmdk, vhd, ami, tar, oci (container) or iso")
+                        .required(false)
+                        .possible_values(translate::IMAGE_FORMATS)
+                        .hide_possible_values(true)
+                        .default_value("raw"),
+                )
+                .arg(
+                    clap::arg!(--"manifest-version" <version> "version of the manifest format, 1 for older osbuild")
+                        .required(false)
+                        .possible_values(["1", "2"])
+                        .default_value("2"),
+                )
+                .arg(
+                    clap::arg!(--"output-format" <fmt> "write the manifest as json or yaml, an image-builder blueprint as blueprint, an otk omnifest as otk, an osbuild-mpp manifest as mpp, or an Ignition or cloud-init config as ignition or cloud-init")
+                        .required(false)
+                        .possible_values(["json", "yaml", "blueprint", "otk", "mpp", "ignition", "cloud-init"])
+                        .hide_possible_values(true)
+                        .default_value("json"),
+                )
+                .arg(clap::arg!(--pretty "indent the JSON manifest, which is the default"))
+                .arg(
+                    clap::arg!(--compact "write the JSON manifest on a single line")
+                        .conflicts_with("pretty"),
+                )
+                .arg(
+                    clap::arg!(--"split-sources" <file> "write the sources of the manifest to this JSON file, the manifest refers to it in its metadata")
+                        .required(false)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(--report <file> "write what each command and section was translated into to a JSON file")
+                        .required(false)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(--kernel <version> "version of the kernel in the image, zipl and ISOs need it")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--depsolved <file> "packages to install, as depsolved by osbuild-depsolve-dnf")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"depsolved-build" <file> "packages of the build root, as depsolved by osbuild-depsolve-dnf")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--export <pipeline> "pipeline osbuild should export, recorded in the manifest and used by --build")
+                        .required(false)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    clap::arg!(--checkpoint <pipeline> "pipeline osbuild should checkpoint, recorded in the manifest and used by --build")
+                        .required(false)
+                        .multiple_occurrences(true),
+                )
+                .arg(clap::arg!(--timestamp "record when the manifest was made, it's then no longer reproducible"))
+                .arg(clap::arg!(--provenance "record the flattened kickstart and the command line in the metadata of the manifest"))
+                .arg(
+                    clap::arg!(--seed <seed> "what UUIDs are derived from instead of the kickstart")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"image-size" <MiB> "size of the disk image, growing partitions fill it")
+                        .required(false),
+                )
+                .arg(clap::arg!(--"remote-logging" "forward logs of the image to the `logging --host`"))
+                .arg(clap::arg!(--"embed-secrets" "embed credentials such as activation keys in the image"))
+                .arg(clap::arg!(--"driverdisk-repos" "use driver disks served over HTTP as package repositories"))
+                .arg(clap::arg!(--"display-target" "let `graphical` and `text` pick the default target"))
+                .arg(
+                    clap::arg!(--unsupported <mode> "what happens to commands and sections that aren't translated: fail, warn or passthrough")
+                        .required(false)
+                        .possible_values(["fail", "warn", "passthrough"])
+                        .hide_possible_values(true)
+                        .default_value("warn"),
+                )
+                .arg(
+                    clap::arg!(--passthrough "pass untranslated content on in a kickstart pipeline, like --unsupported=passthrough")
+                        .conflicts_with("unsupported"),
+                )
+                .arg(clap::arg!(--build "build the manifest with osbuild when it's installed"))
+                .arg(
+                    clap::arg!(--store <dir> "where osbuild caches sources and trees with --build")
+                        .required(false)
+                        .default_value("osbuild-store"),
+                )
+                .arg(
+                    clap::arg!(--"output-directory" <dir> "where osbuild writes what it exports with --build")
+                        .required(false)
+                        .default_value("osbuild-output"),
+                )
+                .arg(clap::arg!(--verify "check the manifest with `osbuild --inspect` when osbuild is installed"))
+                .arg(clap::arg!(--audit "point out what in the kickstart weakens the security of the image"))
+                .arg(
+                    clap::arg!(--watch "convert the kickstart again whenever it or what it includes changes")
+                        .conflicts_with_all(&["out-dir", "build"]),
+                )
+        )
+        .subcommand(
+            clap::Command::new("lint")
+                .about("Check a kickstart for common mistakes")
+                .arg(
+                    clap::arg!(<src> "Kickstart input file, - reads it from standard input")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(--enable <rule> "enable a rule, after the config file")
+                        .required(false)
+                        .multiple_occurrences(true)
+                        .possible_values(lint::names()),
+                )
+                .arg(
+                    clap::arg!(--disable <rule> "disable a rule, after the config file")
+                        .required(false)
+                        .multiple_occurrences(true)
+                        .possible_values(lint::names()),
+                )
+                .arg(
+                    clap::arg!(--config <path> "JSON file with the rules to enable and disable")
+                        .required(false)
+                        .value_hint(clap::ValueHint::FilePath),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("validate")
+                .about("Check a kickstart against its syntax version, like ksvalidator")
+                .arg(
+                    clap::arg!(<src> "Kickstart input file, - reads it from standard input")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("flatten")
+                .about("Write a kickstart with its includes resolved as a single file")
+                .arg(
+                    clap::arg!(<src> "Kickstart input file, - reads it from standard input")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!([dst] "Kickstart output file, standard output if it's - or not given")
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(clap::arg!(--comments "keep comments outside of scripts")),
+        )
+        .subcommand(
+            clap::Command::new("inspect")
+                .about("Print what a kickstart is parsed into and where each part comes from")
+                .arg(
+                    clap::arg!(<src> "Kickstart input file, - reads it from standard input")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(clap::arg!(--json "print the parsed kickstart as JSON instead of an outline")),
+        )
+        .subcommand(
+            clap::Command::new("commands")
+                .about("List the kickstart commands that are translated, with their options and stages")
+                .arg(clap::arg!(--json "print the list as JSON")),
+        )
+        .subcommand(
+            clap::Command::new("from-blueprint")
+                .about("Convert an image-builder blueprint into a kickstart")
+                .arg(
+                    clap::arg!(<src> "Blueprint input file")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(<dst> "Kickstart output file")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("reverse")
+                .about("Reconstruct a kickstart approximating an osbuild manifest")
+                .arg(
+                    clap::arg!(<src> "osbuild manifest input file")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(<dst> "Kickstart output file")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("compose")
+                .about("Submit a kickstart to osbuild-composer as image customizations")
+                .arg(
+                    clap::arg!(<src> "Kickstart input file, - reads it from standard input")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(--url <url> "URL of osbuild-composer")
+                        .required(false)
+                        .default_value("http://localhost:8700"),
+                )
+                .arg(
+                    clap::arg!(--token <token> "bearer token for osbuild-composer, COMPOSER_TOKEN otherwise")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--arch <arch> "architecture of the image: x86_64, aarch64, ppc64le or s390x")
+                        .required(false)
+                        .possible_values(translate::ARCHES)
+                        .hide_possible_values(true),
+                )
+                .arg(
+                    clap::arg!(--format <format> "artifact to build: raw, qcow2, vmdk, vhd, ami or iso")
+                        .required(false)
+                        .possible_values(translate::COMPOSE_FORMATS)
+                        .hide_possible_values(true)
+                        .default_value("qcow2"),
+                )
+                .arg(
+                    clap::arg!(--"image-size" <MiB> "size of the disk image")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--interval <seconds> "how often to ask for the status of the compose")
+                        .required(false)
+                        .default_value("30"),
+                )
+                .arg(clap::arg!(--"dry-run" "print the compose request instead of submitting it")),
+        )
+        .subcommand(
+            clap::Command::new("diff")
+                .about("Print what differs between two kickstarts, such as packages, partitions and users")
+                .arg(
+                    clap::arg!(<old> "Kickstart to compare against")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(<new> "Kickstart to compare")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(clap::arg!(--json "print the differences as JSON")),
+        )
+        .subcommand(
+            clap::Command::new("manifest-diff")
+                .about("Print what differs between two osbuild manifests: pipelines, stages and sources")
+                .arg(
+                    clap::arg!(<old> "osbuild manifest to compare against")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(
+                    clap::arg!(<new> "osbuild manifest to compare")
+                        .required(true)
+                        .value_hint(clap::ValueHint::FilePath),
+                )
+                .arg(clap::arg!(--json "print the differences as JSON")),
+        )
+        .subcommand(
+            clap::Command::new("completions")
+                .about("Print completions for bash, zsh or fish")
+                .arg(
+                    clap::arg!(<shell> "shell to complete in: bash, zsh or fish")
+                        .required(true)
+                        .possible_values(["bash", "zsh", "fish"])
+                        .hide_possible_values(true),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("mangen")
+                .about("Write man pages for osbuild-ks and its subcommands")
+                .arg(
+                    clap::arg!([dir] "directory to write the man pages to")
+                        .default_value("."),
+                ),
+        )
+}
 
-    #[derive(Clone, Debug)]
-    pub struct Kickstart {
-        file: File,
-        tree: Tree,
+#[test]
+fn verify_cli() {
+    make_cli().debug_assert();
+}
+
+/// `cli` with the defaults of `config` for the options they're given for, or the value that
+/// isn't one of an option.
+fn configured<'a>(
+    mut cli: clap::Command<'a>,
+    config: &'a config::Config,
+) -> Result<clap::Command<'a>, String> {
+    for (key, values) in config.defaults() {
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        let default = |arg: clap::Arg<'a>| arg.default_values(&values);
+
+        // Whether `cli` has the option, values it doesn't take are errors.
+        let has = |cli: &clap::Command| -> Result<bool, String> {
+            let possible = match cli.get_arguments().find(|arg| arg.get_id() == key) {
+                Some(arg) => arg.get_possible_values().unwrap_or_default(),
+                None => return Ok(false),
+            };
+
+            match values.iter().find(|value| {
+                !possible.is_empty() && !possible.iter().any(|p| p.matches(value, false))
+            }) {
+                Some(value) => Err(format!("'{}' isn't a value of --{}", value, key)),
+                None => Ok(true),
+            }
+        };
+
+        if has(&cli)? {
+            cli = cli.mut_arg(key, default);
+        }
+
+        for subcommand in cli.get_subcommands_mut() {
+            if has(subcommand)? {
+                *subcommand = std::mem::take(subcommand).mut_arg(key, default);
+            }
+        }
     }
 
-    #[derive(Clone, Debug)]
-    pub struct File {
-        path: Box<PathBuf>,
-        data: String,
+    Ok(cli)
+}
+
+fn main() {
+    // Problems with the config files are logged once there is a logger.
+    let config = config::Config::load();
+    let cli = config
+        .as_ref()
+        .map_err(Clone::clone)
+        .and_then(|config| configured(make_cli(), config));
+
+    let matches = match &cli {
+        Ok(cli) => cli.clone().get_matches(),
+        Err(_) => make_cli().get_matches(),
+    };
+
+    // Global options given after the subcommand are only in its matches.
+    let globals = match matches.subcommand() {
+        Some((_, matches)) => matches,
+        None => &matches,
+    };
+
+    stderrlog::new()
+        .module(module_path!())
+        .verbosity(verbosity(globals))
+        .color(match color(globals) {
+            true => stderrlog::ColorChoice::Always,
+            false => stderrlog::ColorChoice::Never,
+        })
+        .init()
+        .unwrap();
+
+    if let Err(err) = &cli {
+        error!("{}", err);
+        exit::Code::Parse.exit();
     }
 
-    #[derive(Clone, Debug)]
-    pub struct Section {
-        name: String,
-        data: String,
-        args: Vec<String>,
+    match matches.subcommand() {
+        Some(("convert", matches)) => convert(matches),
+        Some(("validate", matches)) => validate(matches),
+        Some(("flatten", matches)) => flatten(matches),
+        Some(("inspect", matches)) => inspect(matches),
+        Some(("lint", matches)) => lint(matches),
+        Some(("compose", matches)) => compose(matches),
+        Some(("commands", matches)) => commands(matches),
+        Some(("from-blueprint", matches)) => from_blueprint(matches),
+        Some(("reverse", matches)) => reverse_manifest(matches),
+        Some(("diff", matches)) => diff_kickstarts(matches),
+        Some(("manifest-diff", matches)) => diff_manifests(matches),
+        Some(("completions", matches)) => completions(matches),
+        Some(("mangen", matches)) => mangen(matches),
+        _ => unreachable!(),
     }
+}
 
-    #[derive(Clone, Debug)]
-    pub struct Tree {
-        file: File,
-        sections: Vec<Section>,
+/// What happens to commands and sections that aren't translated, `--passthrough` is short for
+/// passing them on.
+fn unsupported(matches: &clap::ArgMatches) -> &str {
+    match matches.is_present("passthrough") {
+        true => "passthrough",
+        false => matches.value_of("unsupported").unwrap(),
     }
+}
+
+/// The diagnostics of what became of the elements of `report`, those that aren't translated are
+/// errors with `--unsupported=fail`.
+fn outcomes(
+    matches: &clap::ArgMatches,
+    report: &translate::Report,
+) -> Vec<diagnostics::Diagnostic> {
+    let fail = unsupported(matches) == "fail";
 
-    #[derive(Debug)]
-    pub enum KickstartError {
-        IO(io::Error),
-        Parse,
+    let outcomes = report
+        .entries()
+        .iter()
+        .flat_map(diagnostics::Diagnostic::from_entry)
+        .map(|mut diagnostic| {
+            if fail && diagnostic.code == "untranslated" {
+                diagnostic.severity = diagnostics::Severity::Error;
+                diagnostic.fix("use --unsupported=passthrough to pass it on".to_string())
+            } else {
+                diagnostic
+            }
+        })
+        .collect();
+
+    controlled(matches, outcomes)
+}
+
+/// Why nothing is written, if it isn't: `outcomes` has errors, or with `--strict` anything else
+/// that was found has.
+fn failed(
+    matches: &clap::ArgMatches,
+    outcomes: &[diagnostics::Diagnostic],
+    findings: &[diagnostics::Diagnostic],
+) -> Option<exit::Code> {
+    let errors = |diagnostics: &[diagnostics::Diagnostic]| {
+        diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == diagnostics::Severity::Error)
+    };
+
+    match mode(matches) {
+        _ if errors(outcomes) => Some(exit::Code::Unsupported),
+        diagnostics::Mode::Strict if errors(findings) => Some(exit::Code::Invalid),
+        _ => None,
+    }
+}
+
+/// How seriously problems are taken, `--strict` and `--permissive` apply to every subcommand.
+fn mode(matches: &clap::ArgMatches) -> diagnostics::Mode {
+    match (
+        matches.is_present("strict"),
+        matches.is_present("permissive"),
+    ) {
+        (true, _) => diagnostics::Mode::Strict,
+        (_, true) => diagnostics::Mode::Permissive,
+        _ => diagnostics::Mode::Default,
+    }
+}
+
+/// What `-W` and `--warning-config` do to warnings.
+fn warnings(matches: &clap::ArgMatches) -> warnings::Controls {
+    let mut controls = warnings::Controls::default();
+
+    if let Some(config) = matches.value_of("warning-config") {
+        controls = match controls.config(Path::new(config)) {
+            Ok(controls) => controls,
+            Err(err) => {
+                error!("{}", err);
+                exit::Code::IO.exit();
+            }
+        };
     }
 
-    impl From<io::Error> for KickstartError {
-        fn from(err: io::Error) -> KickstartError {
-            KickstartError::IO(err)
+    for control in matches.values_of("warning").into_iter().flatten() {
+        controls = match controls.add(control) {
+            Ok(controls) => controls,
+            Err(err) => {
+                error!("{}", err);
+                exit::Code::Usage.exit();
+            }
+        };
+    }
+
+    controls
+}
+
+/// `diagnostics` with the severity `-W`, `--warning-config`, `--strict` and `--permissive` give
+/// them, silenced warnings are left out.
+fn controlled(
+    matches: &clap::ArgMatches,
+    diagnostics: Vec<diagnostics::Diagnostic>,
+) -> Vec<diagnostics::Diagnostic> {
+    mode(matches).apply(warnings(matches).apply(diagnostics))
+}
+
+/// The kickstart at `src_paths`, layered in order, exiting when it can't be read. Problems in it
+/// are errors unless `--permissive` is given, they're returned as warnings then and the rest of
+/// it is used.
+fn read_kickstart(
+    matches: &clap::ArgMatches,
+    src_paths: &[&Path],
+    includes: &kickstart::Includes,
+    version: version::Version,
+    emitter: &mut diagnostics::Emitter,
+) -> Result<(kickstart::Kickstart, Vec<diagnostics::Diagnostic>), exit::Code> {
+    let read = match mode(matches) {
+        diagnostics::Mode::Permissive => {
+            kickstart::Kickstart::read_layers(src_paths, includes, version)
+        }
+        _ => kickstart::Kickstart::from_layers(src_paths, includes, version)
+            .map(|kickstart| (kickstart, Vec::new())),
+    };
+
+    match read {
+        Ok((kickstart, problems)) => {
+            let problems = kickstart::KickstartError::Invalid(problems);
+            let problems = diagnostics::Diagnostic::from_kickstart(&problems);
+
+            Ok((kickstart, controlled(matches, problems)))
+        }
+        Err(err) => {
+            emitter.emit(&diagnostics::Diagnostic::from_kickstart(&err));
+            Err(err.code())
         }
     }
+}
 
-    impl Kickstart {
-        pub fn from_path<'a>(src: &Path, inc: &Path) -> Result<Self, KickstartError> {
-            let src = &src.canonicalize()?;
-            let inc = &inc.canonicalize()?;
+/// The levels of `--log-level`, from the least to the most that's logged.
+const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
 
-            info!(
-                "Creating Kickstart from path '{}' with include path '{}'",
-                src.display(),
-                inc.display()
+/// How much is logged: errors and warnings, unless `-v`, `-q` or `--log-level` ask for more or
+/// less.
+fn verbosity(matches: &clap::ArgMatches) -> usize {
+    match matches.value_of("log-level") {
+        Some(level) => LOG_LEVELS.iter().position(|known| *known == level).unwrap(),
+        None if matches.is_present("quiet") => 0,
+        None => 1 + matches.occurrences_of("verbose") as usize,
+    }
+}
+
+/// The `dst` that is written to standard output, which is also where output goes without one.
+const STDOUT: &str = "-";
+
+/// The manifest at `path`, exiting when it can't be read, with its sources if they were written
+/// on their own.
+fn read_manifest(path: &Path) -> serde_json::Value {
+    let read = |path: &Path, what: &str| -> serde_json::Value {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) => {
+                error!("Failed to read {} '{}': {}", what, path.display(), err);
+                exit::Code::IO.exit();
+            }
+        };
+
+        match serde_json::from_str(&data) {
+            Ok(value) => value,
+            Err(err) => {
+                error!("Failed to parse {} '{}': {}", what, path.display(), err);
+                exit::Code::Parse.exit();
+            }
+        }
+    };
+
+    let mut manifest = read(path, "manifest");
+
+    // Sources written on their own with `--split-sources` are put back where they belong.
+    if let Some(sources) = manifest["metadata"]["sources"]["path"].as_str() {
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        manifest["sources"] = read(&dir.join(sources), "sources");
+    }
+
+    manifest
+}
+
+/// Write `data` to `dst`, or to standard output if it's `-`.
+fn write(dst: &str, data: &str) -> io::Result<()> {
+    match dst {
+        STDOUT => io::stdout().write_all(data.as_bytes()),
+        dst => fs::write(dst, data),
+    }
+}
+
+/// The path given for `src` of a kickstart, see [`kickstart_path`].
+fn kickstart_source(matches: &clap::ArgMatches) -> &Path {
+    kickstart_path(matches.value_of("src").unwrap())
+}
+
+/// The path of a kickstart given on the command line. It's read from standard input if it's `-`
+/// and retrieved if it's an HTTP or HTTPS URL, otherwise it has to be a file, which can be given
+/// as a `file://` URL as well.
+fn kickstart_path(src: &str) -> &Path {
+    if src == kickstart::STDIN || fetch::is_url(src) {
+        return Path::new(src);
+    }
+
+    let src_path = Path::new(src.strip_prefix("file://").unwrap_or(src));
+
+    if !src_path.exists() {
+        error!("The path given for a kickstart does not exist: '{}'", src);
+        exit::Code::IO.exit();
+    }
+
+    if !src_path.is_file() {
+        error!("The path given for a kickstart is not a file: '{}'", src);
+        exit::Code::IO.exit();
+    }
+
+    src_path
+}
+
+/// The kickstarts `convert` layers and where it writes the result. With `-o` every path given is
+/// a kickstart, otherwise there's only `src`.
+fn layers(matches: &clap::ArgMatches) -> (Vec<&Path>, &str) {
+    let mut src_paths = vec![kickstart_source(matches)];
+
+    let dst = match matches.value_of("output") {
+        Some(output) => {
+            let layers = matches.values_of("layers").into_iter().flatten();
+
+            for layer in matches.value_of("dst").into_iter().chain(layers) {
+                src_paths.push(kickstart_path(layer));
+            }
+
+            output
+        }
+        None if matches.is_present("layers") => {
+            error!("Give the manifest to write with -o to layer kickstarts");
+            exit::Code::Usage.exit();
+        }
+        None => matches.value_of("dst").unwrap_or(STDOUT),
+    };
+
+    (src_paths, dst)
+}
+
+/// The path given for `src`, which has to be a file.
+fn source(matches: &clap::ArgMatches) -> &Path {
+    let src = matches.value_of("src").unwrap();
+    let src_path = Path::new(src);
+
+    if !src_path.exists() {
+        error!("The path given for `src` does not exist: '{}'", src);
+        exit::Code::IO.exit();
+    }
+
+    if !src_path.is_file() {
+        error!("The path given for `src` is not a file: '{}'", src);
+        exit::Code::IO.exit();
+    }
+
+    src_path
+}
+
+/// The include paths and whether includes may be fetched.
+fn includes(matches: &clap::ArgMatches) -> kickstart::Includes {
+    let inc: Vec<&str> = matches.values_of("include").unwrap().collect();
+
+    for inc in &inc {
+        let inc_path = Path::new(inc);
+
+        if !inc_path.exists() {
+            error!("The path given for `include` does not exist: '{}'", inc);
+            exit::Code::IO.exit();
+        }
+
+        if !inc_path.is_dir() {
+            error!("The path given for `include` is not a directory: '{}'", inc);
+            exit::Code::IO.exit();
+        }
+    }
+
+    let defines = matches
+        .values_of("define")
+        .into_iter()
+        .flatten()
+        .map(|define| match define.split_once('=') {
+            Some((key, value)) => (key.to_string(), value.to_string()),
+            None => {
+                error!("--define needs a key=value, not '{}'", define);
+                exit::Code::Usage.exit();
+            }
+        })
+        .collect();
+
+    match kickstart::Includes::new(&inc) {
+        Ok(includes) => includes
+            .network(matches.is_present("allow-network"))
+            .environment(matches.is_present("env-substitution"))
+            .defines(defines)
+            .fetch(fetch_options(matches)),
+        Err(err) => {
+            error!("Failed to use include path '{}': {}", inc.join(":"), err);
+            err.code().exit();
+        }
+    }
+}
+
+/// How URLs are retrieved, with `--proxy`, `--fetch-timeout`, `--ca-cert`, `--insecure` and
+/// the cache.
+fn fetch_options(matches: &clap::ArgMatches) -> fetch::Options {
+    let timeout = match matches.value_of("fetch-timeout").unwrap().parse() {
+        Ok(timeout) => std::time::Duration::from_secs(timeout),
+        Err(_) => {
+            error!("The fetch timeout is not a number of seconds");
+            exit::Code::Usage.exit();
+        }
+    };
+
+    let cache = match matches.value_of("cache-dir") {
+        _ if matches.is_present("no-cache") => None,
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => cache::default_dir(),
+    };
+
+    let ca_certs = matches
+        .values_of("ca-cert")
+        .map(|ca_certs| ca_certs.map(PathBuf::from).collect())
+        .unwrap_or_default();
+
+    fetch::Options::default()
+        .timeout(timeout)
+        .proxy(matches.value_of("proxy").map(str::to_string))
+        .ca_certs(ca_certs)
+        .insecure(matches.is_present("insecure"))
+        .cache(cache.map(cache::Cache::new))
+}
+
+/// The kickstart syntax version, the latest one of the distribution unless it's given.
+fn version(matches: &clap::ArgMatches) -> version::Version {
+    match matches.value_of("ks-version").map(str::parse) {
+        Some(Ok(version)) => version,
+        Some(Err(err)) => {
+            error!("{}", err);
+            exit::Code::Usage.exit();
+        }
+        None => distro(matches).version,
+    }
+}
+
+/// The packages of the depsolve result given with the option `name`, if it's given.
+fn depsolved(matches: &clap::ArgMatches, name: &str) -> Vec<translate::packages::Rpm> {
+    match matches.value_of(name).map(Path::new) {
+        Some(path) => match translate::packages::depsolved(path) {
+            Ok(depsolved) => depsolved,
+            Err(err) => {
+                error!("{}", err);
+                exit::Code::Depsolve.exit();
+            }
+        },
+        None => Vec::new(),
+    }
+}
+
+/// The built-in distribution definitions and those in `--definitions-dir`.
+fn definitions(matches: &clap::ArgMatches) -> distro::Definitions {
+    let definitions = distro::Definitions::default();
+
+    match matches.value_of("definitions-dir") {
+        Some(dir) => match definitions.dir(Path::new(dir)) {
+            Ok(definitions) => definitions,
+            Err(err) => {
+                error!("{}", err);
+                exit::Code::IO.exit();
+            }
+        },
+        None => definitions,
+    }
+}
+
+/// The distribution, from the built in definitions and those in `--definitions-dir`.
+fn distro(matches: &clap::ArgMatches) -> distro::Distro {
+    let definitions = definitions(matches);
+
+    let name = matches.value_of("distro").unwrap();
+
+    match definitions.lookup(name) {
+        Some(distro) => distro.clone(),
+        None => {
+            error!(
+                "'{}' is not a distribution, known are {}",
+                name,
+                definitions.names().join(", ")
             );
+            exit::Code::Usage.exit();
+        }
+    }
+}
 
-            let file = File::from_path(src, inc)?;
-            let tree = Tree::from_file(file.clone())?.parse(); // TODO: no clone
+/// The size of the disk image in MiB, if it's given.
+fn image_size(matches: &clap::ArgMatches) -> Option<u64> {
+    match matches.value_of("image-size").map(str::parse) {
+        Some(Ok(size)) => Some(size),
+        Some(Err(_)) => {
+            error!("The image size is not a number of MiB");
+            exit::Code::Usage.exit();
+        }
+        None => None,
+    }
+}
 
-            Ok(Self {
-                file: file,
-                tree: tree,
-            })
+fn format(matches: &clap::ArgMatches) -> diagnostics::Format {
+    match matches.value_of("diagnostics-format").unwrap().parse() {
+        Ok(diagnostics::Format::Text { .. }) => diagnostics::Format::Text {
+            color: color(matches),
+        },
+        format => format.unwrap(),
+    }
+}
+
+/// Whether what's printed on standard error is colored, by default when it's a terminal.
+fn color(matches: &clap::ArgMatches) -> bool {
+    match matches.value_of("color").unwrap() {
+        "always" => true,
+        "never" => false,
+        _ => io::stderr().is_terminal(),
+    }
+}
+
+/// `osbuild-ks commands` prints the commands and sections that are translated, with the options
+/// they're understood with and the stages they can end up in.
+fn commands(matches: &clap::ArgMatches) {
+    let commands = translate::catalog::COMMANDS;
+
+    if matches.is_present("json") {
+        let commands: Vec<_> = commands.iter().map(|command| command.to_json()).collect();
+        println!("{}", serde_json::to_string_pretty(&commands).unwrap());
+        return;
+    }
+
+    for command in commands {
+        let mut options: Vec<String> = command
+            .values
+            .iter()
+            .map(|option| format!("{}=<value>", option))
+            .collect();
+        options.extend(command.flags.iter().map(|option| option.to_string()));
+
+        println!("{}", command.name);
+
+        if !options.is_empty() {
+            println!("    options: {}", options.join(" "));
         }
+
+        println!(
+            "    stages: {}",
+            command.stages().collect::<Vec<_>>().join(" ")
+        );
     }
+}
 
-    impl File {
-        pub fn from_path(src: &Path, inc: &Path) -> Result<Self, KickstartError> {
-            let mut file = fs::File::open(src)?;
-            let mut buffer = String::new();
+/// `osbuild-ks lint <src>` prints what the enabled rules find, failing if they find anything.
+fn lint(matches: &clap::ArgMatches) {
+    let src_path = kickstart_source(matches);
+    let includes = includes(matches);
+    let format = format(matches);
+    let version = version(matches);
 
-            file.read_to_string(&mut buffer)?;
+    let mut rules = lint::Rules::default();
 
-            let mut instance = Self {
-                path: Box::new(src.canonicalize()?),
-                data: buffer,
-            };
+    if let Some(config) = matches.value_of("config") {
+        rules = match rules.config(Path::new(config)) {
+            Ok(rules) => rules,
+            Err(err) => {
+                error!("{}", err);
+                exit::Code::IO.exit();
+            }
+        };
+    }
 
-            instance.clean();
-            instance.resolve(&inc)?;
+    for (option, enabled) in [("enable", true), ("disable", false)] {
+        for rule in matches.values_of(option).into_iter().flatten() {
+            rules = rules.set(rule, enabled).unwrap();
+        }
+    }
 
-            Ok(instance)
+    let (kickstart, problems) = match kickstart::Kickstart::read(src_path, &includes, version) {
+        Ok(read) => read,
+        Err(err) => {
+            diagnostics::emit(format, &diagnostics::Diagnostic::from_kickstart(&err));
+            err.code().exit();
         }
+    };
 
-        /// Remove all comments from a kickstart file.
-        fn clean(&mut self) -> Result<(), KickstartError> {
-            let mut buf = String::new();
+    let diagnostics = controlled(matches, lint::lint(&kickstart, &problems, &rules, version));
 
-            for line in self.data.lines() {
-                if !line.starts_with("#") {
-                    buf = buf + line + "\n";
-                }
+    diagnostics::emit(format, &diagnostics);
+
+    info!("Linted kickstart, found {} problems", diagnostics.len());
+
+    if !diagnostics.is_empty() {
+        exit::Code::Invalid.exit();
+    }
+}
+
+/// `osbuild-ks validate <src>` prints the problems with the kickstart for its syntax version,
+/// failing if any of them is an error.
+fn validate(matches: &clap::ArgMatches) {
+    let src_path = kickstart_source(matches);
+    let includes = includes(matches);
+    let version = version(matches);
+    let format = format(matches);
+
+    let (kickstart, problems) = match kickstart::Kickstart::read(src_path, &includes, version) {
+        Ok(read) => read,
+        Err(err) => {
+            diagnostics::emit(format, &diagnostics::Diagnostic::from_kickstart(&err));
+            err.code().exit();
+        }
+    };
+
+    let diagnostics = controlled(
+        matches,
+        validate::validate(&kickstart, &problems, version, &distro(matches)),
+    );
+
+    diagnostics::emit(format, &diagnostics);
+
+    let errors = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == diagnostics::Severity::Error)
+        .count();
+
+    info!(
+        "Validated kickstart against {}, found {} errors and {} warnings",
+        version,
+        errors,
+        diagnostics.len() - errors
+    );
+
+    if errors > 0 {
+        exit::Code::Invalid.exit();
+    }
+}
+
+/// `osbuild-ks flatten <src> [dst]` writes the kickstart with what it includes and appends in
+/// place of the commands that include them, like pykickstart's `ksflatten`.
+fn flatten(matches: &clap::ArgMatches) {
+    let src_path = kickstart_source(matches);
+    let includes = includes(matches).comments(matches.is_present("comments"));
+    let format = format(matches);
+
+    let mut errors = Vec::new();
+
+    let file = match kickstart::File::from_path(src_path, &includes, &mut errors) {
+        Ok(file) if errors.is_empty() => file,
+        Ok(_) => {
+            let err = kickstart::KickstartError::Invalid(errors);
+            diagnostics::emit(format, &diagnostics::Diagnostic::from_kickstart(&err));
+            err.code().exit();
+        }
+        Err(err) => {
+            diagnostics::emit(format, &diagnostics::Diagnostic::from_kickstart(&err));
+            err.code().exit();
+        }
+    };
+
+    let flattened = file.flatten();
+
+    let dst = matches.value_of("dst").unwrap_or(STDOUT);
+
+    if let Err(err) = write(dst, &flattened) {
+        error!("Failed to write kickstart '{}': {}", dst, err);
+        exit::Code::IO.exit();
+    }
+}
+
+/// `osbuild-ks inspect <src>` prints the sections of the kickstart as they're parsed, with the
+/// options and arguments of its commands and where each of them comes from.
+fn inspect(matches: &clap::ArgMatches) {
+    let src_path = kickstart_source(matches);
+    let includes = includes(matches);
+    let format = format(matches);
+
+    // What can't be parsed is printed, along with everything that could be.
+    let (kickstart, problems) =
+        match kickstart::Kickstart::read(src_path, &includes, version(matches)) {
+            Ok(read) => read,
+            Err(err) => {
+                diagnostics::emit(format, &diagnostics::Diagnostic::from_kickstart(&err));
+                err.code().exit();
             }
+        };
+
+    if !problems.is_empty() {
+        let err = kickstart::KickstartError::Invalid(problems);
+        diagnostics::emit(format, &diagnostics::Diagnostic::from_kickstart(&err));
+    }
+
+    if matches.is_present("json") {
+        let json = inspect::to_json(&kickstart);
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    } else {
+        print!("{}", inspect::outline(&kickstart));
+    }
+}
+
+/// `osbuild-ks compose <src>` submits the kickstart to osbuild-composer and follows the compose
+/// until it's done, failing if the compose fails.
+fn compose(matches: &clap::ArgMatches) {
+    let src_path = kickstart_source(matches);
+    let includes = includes(matches);
+    let version = version(matches);
+    let format = format(matches);
 
-            self.data = buf;
+    let (kickstart, problems) = match read_kickstart(
+        matches,
+        &[src_path],
+        &includes,
+        version,
+        &mut diagnostics::Emitter::new(format),
+    ) {
+        Ok(read) => read,
+        Err(code) => code.exit(),
+    };
 
-            Ok(())
+    let interval = match matches.value_of("interval").unwrap().parse() {
+        Ok(interval) => std::time::Duration::from_secs(interval),
+        Err(_) => {
+            error!("The interval is not a number of seconds");
+            exit::Code::Usage.exit();
         }
+    };
 
-        /// Resolve all includes in a kickstart file to flatten it into a single string.
-        fn resolve(&mut self, inc: &Path) -> Result<(), KickstartError> {
-            let mut data = String::new();
+    let (request, report) = match translate::Translator::new()
+        .image_size(image_size(matches))
+        .arch(matches.value_of("arch").unwrap_or(std::env::consts::ARCH))
+        .format(matches.value_of("format").unwrap().parse().unwrap())
+        .strict(mode(matches) == diagnostics::Mode::Strict)
+        .permissive(mode(matches) == diagnostics::Mode::Permissive)
+        .version(version)
+        .distro(&distro(matches))
+        .network(matches.is_present("allow-network"))
+        .fetch(fetch_options(matches))
+        .compose(&kickstart)
+    {
+        Ok(request) => request,
+        Err(err) => {
+            diagnostics::emit(format, &diagnostics::Diagnostic::from_translate(&err));
+            err.code().exit();
+        }
+    };
 
-            for line in self.data.lines() {
-                if line.starts_with("%include") {
-                    // TODO: handle ksappend as well and check order
-                    let parts: Vec<&str> = line.split_whitespace().collect();
+    let mut warnings = problems;
+    warnings.extend(controlled(
+        matches,
+        report
+            .entries()
+            .iter()
+            .flat_map(diagnostics::Diagnostic::from_entry)
+            .collect(),
+    ));
 
-                    if parts.len() != 2 {
-                        eprintln!("ErroR!");
-                        exit(1);
-                    }
+    diagnostics::emit(format, &warnings);
 
-                    trace!(
-                        "File.resolve: '{}' wants to include '{}'",
-                        self.path.display(),
-                        parts[1]
-                    );
+    if let Some(code) = failed(matches, &warnings, &[]) {
+        code.exit();
+    }
 
-                    let path = Path::join(inc, Path::new(parts[1]));
+    if matches.is_present("dry-run") {
+        println!("{}", serde_json::to_string_pretty(&request).unwrap());
+        return;
+    }
 
-                    if !path.exists() {
-                        eprintln!("Error!");
-                        exit(2);
-                    }
+    // Tokens are kept out of the command line, and the process list, through the environment.
+    let token = matches
+        .value_of("token")
+        .map(str::to_string)
+        .or_else(|| std::env::var("COMPOSER_TOKEN").ok());
+    let composer = composer::Composer::new(matches.value_of("url").unwrap(), token.as_deref());
 
-                    let string = File::from_path(&path, &inc)?.to_string();
+    let id = match composer.compose(&request) {
+        Ok(id) => id,
+        Err(err) => {
+            error!("Failed to submit the compose: {}", err);
+            exit::Code::IO.exit();
+        }
+    };
 
-                    debug!(
-                        "File.resolve: '{}' has included '{}'",
-                        self.path.display(),
-                        path.display()
-                    );
+    info!("Submitted compose {}", id);
 
-                    data = data + &string;
-                } else {
-                    data = data + line + "\n";
-                }
+    let mut last = None;
+
+    loop {
+        let status = match composer.status(&id) {
+            Ok(status) => status,
+            Err(err) => {
+                error!("Failed to get the status of compose {}: {}", id, err);
+                exit::Code::IO.exit();
             }
+        };
 
-            self.data = data;
+        if last.as_ref() != Some(&status) {
+            info!(
+                "Compose {} is {}: {}",
+                id, status.status, status.image_status
+            );
+        }
+
+        match status.status.as_str() {
+            "success" => return,
+            "failure" => exit::Code::Failure.exit(),
+            _ => {}
+        }
+
+        last = Some(status);
+        std::thread::sleep(interval);
+    }
+}
 
-            Ok(())
+/// `osbuild-ks from-blueprint <src> <dst>` writes the kickstart that installs what the blueprint
+/// describes.
+fn from_blueprint(matches: &clap::ArgMatches) {
+    let src_path = source(matches);
+    let dst = matches.value_of("dst").unwrap();
+
+    let blueprint = match fs::read_to_string(src_path)
+        .map_err(|err| err.to_string())
+        .and_then(|data| toml::from_str(&data))
+    {
+        Ok(blueprint) => blueprint,
+        Err(err) => {
+            error!("Failed to read blueprint '{}': {}", src_path.display(), err);
+            exit::Code::Parse.exit();
+        }
+    };
+
+    let kickstart = match translate::blueprint::to_kickstart(&blueprint) {
+        Ok(kickstart) => kickstart,
+        Err(err) => {
+            error!(
+                "Failed to translate blueprint '{}': {}",
+                src_path.display(),
+                err
+            );
+            exit::Code::Unsupported.exit();
         }
+    };
+
+    if let Err(err) = fs::write(dst, kickstart) {
+        error!("Failed to write kickstart '{}': {}", dst, err);
+        exit::Code::IO.exit();
+    }
+
+    info!("Translated blueprint into kickstart '{}'", dst);
+}
+
+/// `osbuild-ks diff <old> <new>` prints what was added, removed and changed from one kickstart
+/// to the other.
+fn diff_kickstarts(matches: &clap::ArgMatches) {
+    let includes = includes(matches);
+    let version = version(matches);
+    let mut emitter = diagnostics::Emitter::new(format(matches));
 
-        pub fn to_string(&mut self) -> String {
-            self.data.clone()
+    let mut read = |name: &str| {
+        let src_path = kickstart_path(matches.value_of(name).unwrap());
+
+        match read_kickstart(matches, &[src_path], &includes, version, &mut emitter) {
+            Ok((kickstart, problems)) => {
+                emitter.emit(&problems);
+                kickstart
+            }
+            Err(code) => code.exit(),
         }
+    };
+
+    let old = read("old");
+    let new = read("new");
+
+    let changes = diff::diff(&old, &new);
+
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+    } else {
+        print!("{}", diff::Outline(&changes));
     }
+}
+
+/// `osbuild-ks manifest-diff <old> <new>` prints the pipelines, stages and sources that were
+/// added, removed and changed from one manifest to the other.
+fn diff_manifests(matches: &clap::ArgMatches) {
+    let old = read_manifest(Path::new(matches.value_of("old").unwrap()));
+    let new = read_manifest(Path::new(matches.value_of("new").unwrap()));
 
-    impl Tree {
-        pub fn from_file(file: File) -> Result<Self, KickstartError> {
-            Ok(Self {
-                file: file,
-                sections: Vec::new(),
-            })
+    let changes = match diff::manifests(&old, &new) {
+        Ok(changes) => changes,
+        Err(err) => {
+            error!("Failed to compare manifests: {}", err);
+            exit::Code::Invalid.exit();
         }
+    };
 
-        pub fn parse(mut self) -> Self {
-            let mut in_section = false;
+    if matches.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+    } else {
+        print!("{}", diff::Outline(&changes));
+    }
+}
 
-            // The command section is all data that is not in any of the other sections.
-            let mut command_section = Section {
-                name: "command".to_string(),
-                data: String::new(),
-                args: Vec::new(),
-            };
+/// `osbuild-ks completions <shell>` prints the completions for `shell`, with the distributions
+/// that are known when they're made.
+fn completions(matches: &clap::ArgMatches) {
+    let shell: clap_complete::Shell = matches.value_of("shell").unwrap().parse().unwrap();
 
-            let mut section = Section {
-                name: String::new(),
-                data: String::new(),
-                args: Vec::new(),
-            };
+    let definitions = definitions(matches);
+    let mut cli = make_cli().mut_arg("distro", |arg| arg.possible_values(definitions.names()));
+    let name = cli.get_name().to_string();
+
+    clap_complete::generate(shell, &mut cli, name, &mut io::stdout());
+}
+
+/// `osbuild-ks mangen [dir]` writes `osbuild-ks.1` and a page for each subcommand into `dir`.
+fn mangen(matches: &clap::ArgMatches) {
+    let dir = Path::new(matches.value_of("dir").unwrap());
+
+    let pages = match man::pages(make_cli()) {
+        Ok(pages) => pages,
+        Err(err) => {
+            error!("Failed to render man pages: {}", err);
+            exit::Code::Failure.exit();
+        }
+    };
+
+    for (name, data) in pages {
+        let path = dir.join(name);
+
+        if let Err(err) = fs::write(&path, data) {
+            error!("Failed to write '{}': {}", path.display(), err);
+            exit::Code::IO.exit();
+        }
+
+        info!("Wrote man page '{}'", path.display());
+    }
+}
+
+/// `osbuild-ks reverse <src> <dst>` writes a kickstart approximating the manifest, with a warning
+/// for each stage that it can't say.
+fn reverse_manifest(matches: &clap::ArgMatches) {
+    let src_path = source(matches);
+    let dst = matches.value_of("dst").unwrap();
+    let format = format(matches);
+
+    let manifest = read_manifest(src_path);
+
+    let (kickstart, diagnostics) = match reverse::reverse(&manifest) {
+        Ok(reversed) => reversed,
+        Err(err) => {
+            error!(
+                "Failed to reconstruct manifest '{}': {}",
+                src_path.display(),
+                err
+            );
+            exit::Code::Unsupported.exit();
+        }
+    };
+
+    diagnostics::emit(format, &diagnostics);
+
+    if let Err(err) = fs::write(dst, kickstart) {
+        error!("Failed to write kickstart '{}': {}", dst, err);
+        exit::Code::IO.exit();
+    }
+
+    info!(
+        "Reconstructed kickstart '{}', {} stages left out",
+        dst,
+        diagnostics.len()
+    );
+}
+
+/// `osbuild-ks convert <src> [dst]` translates the kickstart into a manifest.
+fn convert(matches: &clap::ArgMatches) {
+    let out_dir = match matches.value_of("out-dir") {
+        Some(out_dir) => Path::new(out_dir),
+        None if matches.is_present("watch") => return watch(matches),
+        None => {
+            let (src_paths, dst) = layers(matches);
+
+            let mut emitter = diagnostics::Emitter::new(format(matches));
+
+            if let Err(code) = convert_kickstart(matches, &src_paths, dst, &mut emitter) {
+                code.exit();
+            }
+
+            return;
+        }
+    };
+
+    let src = matches.value_of("src").unwrap();
+
+    let kickstarts = match batch::kickstarts(src) {
+        Ok(kickstarts) => kickstarts,
+        Err(err) => {
+            error!("Failed to find kickstarts in '{}': {}", src, err);
+            exit::Code::IO.exit();
+        }
+    };
+
+    if let Err(err) = fs::create_dir_all(out_dir) {
+        error!("Failed to create '{}': {}", out_dir.display(), err);
+        exit::Code::IO.exit();
+    }
+
+    let extension = batch::extension(matches.value_of("output-format").unwrap());
+    let mut summary = batch::Summary::default();
+    let mut emitter = diagnostics::Emitter::new(format(matches));
+
+    for src_path in kickstarts {
+        let dst = batch::output(&src_path, out_dir, extension);
+
+        info!("Converting '{}'", src_path.display());
+
+        match convert_kickstart(
+            matches,
+            &[&src_path],
+            &dst.display().to_string(),
+            &mut emitter,
+        ) {
+            // What's wrong with the command line is wrong for every kickstart.
+            Err(exit::Code::Usage) => exit::Code::Usage.exit(),
+            converted => summary.add(&src_path, converted.map(|_| dst)),
+        }
+    }
+
+    eprintln!("{}", summary);
+
+    if let Some(code) = summary.code() {
+        code.exit();
+    }
+}
+
+/// `osbuild-ks convert <src> [dst] --watch` converts the kickstart again whenever it or what it
+/// includes changes, printing only the errors and warnings that weren't printed before.
+fn watch(matches: &clap::ArgMatches) {
+    let (src_paths, dst) = layers(matches);
+
+    if src_paths.contains(&Path::new(kickstart::STDIN)) {
+        error!("--watch can't watch standard input");
+        exit::Code::Usage.exit();
+    }
+
+    if let Some(url) = src_paths
+        .iter()
+        .find(|src_path| fetch::is_url(&src_path.to_string_lossy()))
+    {
+        error!("--watch can't watch '{}', it's a URL", url.display());
+        exit::Code::Usage.exit();
+    }
+
+    let src_path = src_paths[0];
+    let includes = includes(matches);
+    let version = version(matches);
+    let mut emitter = diagnostics::Emitter::new(format(matches)).changes();
+
+    loop {
+        // What's included can change along with the kickstart, so it's looked at every time and
+        // before converting so no change is missed.
+        let paths = match kickstart::Kickstart::read_layers(&src_paths, &includes, version) {
+            Ok((kickstart, _)) => kickstart.file().paths(),
+            Err(_) => src_paths.iter().map(|path| path.to_path_buf()).collect(),
+        };
+        let files = watch::Files::new(paths);
+
+        match convert_kickstart(matches, &src_paths, dst, &mut emitter) {
+            Ok(()) => info!("Converted '{}' into '{}'", src_path.display(), dst),
+            Err(exit::Code::Usage) => exit::Code::Usage.exit(),
+            Err(code) => error!(
+                "Failed to convert '{}', exit code {}",
+                src_path.display(),
+                code as i32
+            ),
+        }
+
+        for path in files.wait() {
+            info!("'{}' changed", path.display());
+        }
+    }
+}
+
+/// Convert the kickstart at `src_paths`, layered in order, into what `--output-format` says and
+/// write it to `dst`.
+fn convert_kickstart(
+    matches: &clap::ArgMatches,
+    src_paths: &[&Path],
+    dst: &str,
+    emitter: &mut diagnostics::Emitter,
+) -> Result<(), exit::Code> {
+    let includes = includes(matches);
+    let version = version(matches);
+    let format = format(matches);
+
+    // Diagnostics other than text are printed on standard output, they'd end up in the manifest.
+    if dst == STDOUT && !matches!(format, diagnostics::Format::Text { .. }) {
+        error!(
+            "--diagnostics-format={} prints on standard output, give a <dst> to write to",
+            matches.value_of("diagnostics-format").unwrap()
+        );
+        return Err(exit::Code::Usage);
+    }
+
+    // Problems in the kickstart and findings of the audit are printed along with the warnings of
+    // the translation.
+    let (kickstart, mut findings) =
+        read_kickstart(matches, src_paths, &includes, version, emitter)?;
+
+    trace!("Flattened kickstart:\n{}", kickstart.file());
+
+    if matches.is_present("audit") {
+        let audit = audit::audit(&kickstart, version);
+
+        info!("Audited kickstart, {} findings", audit.len());
+
+        findings.extend(controlled(matches, audit));
+    }
+
+    let values = |name: &str| -> Vec<&str> {
+        matches
+            .values_of(name)
+            .map(|values| values.collect())
+            .unwrap_or_default()
+    };
 
-            for line in self.file.to_string().lines() {
-                if in_section {
-                    if line.starts_with('%') {
-                        if line == "%end" {
-                            in_section = false;
-                            self.sections.push(section.clone());
-                            debug!("Tree.parse: end section '{}'", section.name);
-                        } else {
-                            // This is an error as we're encountering a new section while still being
-                            // inside a section.
-                            eprintln!("encountered new section while still inside section");
-                        }
-                    } else {
-                        trace!("Tree.parse: '{}'", line);
-
-                        section.data = section.data + line + "\n";
-                    }
-                } else {
-                    if line.starts_with('%') {
-                        if line == "%end" {
-                            // This is an error as we're encountering '%end' while not being in a
-                            // section
-                            eprintln!("encountered %end section while not inside section");
-                        } else {
-                            // We're starting a new section
-                            in_section = true;
-
-                            let mut parts: Vec<String> =
-                                line.split_whitespace().map(str::to_string).collect();
-
-                            let args = parts.split_off(1);
-
-                            section = Section {
-                                name: parts[0].clone(),
-                                data: String::new(),
-                                args: args,
-                            };
-
-                            debug!("Tree.parse: new section '{}'", section.name);
-                        }
-                    } else {
-                        // TODO: Skip empty lines, is this correct, empty lines might carry
-                        // significance in sections but do they carry it outside of %post/%pre?
-                        if line != "" {
-                            command_section.data = command_section.data + line + "\n";
-                        }
-                    }
+    // The time is left out unless asked for, it's the one thing that differs between runs.
+    let timestamp = matches.is_present("timestamp").then(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    });
+
+    let manifest_version = matches
+        .value_of("manifest-version")
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let translator = translate::Translator::new()
+        .passthrough(unsupported(matches) == "passthrough")
+        .strict(mode(matches) == diagnostics::Mode::Strict)
+        .permissive(mode(matches) == diagnostics::Mode::Permissive)
+        .remote_logging(matches.is_present("remote-logging"))
+        .embed_secrets(matches.is_present("embed-secrets"))
+        .driverdisk_repos(matches.is_present("driverdisk-repos"))
+        .network(matches.is_present("allow-network"))
+        .fetch(fetch_options(matches))
+        .display_target(matches.is_present("display-target"))
+        .eula_marker(matches.value_of("eula-marker").unwrap())
+        .image_size(image_size(matches))
+        .seed(matches.value_of("seed"))
+        .exports(&values("export"))
+        .checkpoints(&values("checkpoint"))
+        .timestamp(timestamp)
+        .provenance(matches.is_present("provenance").then(|| {
+            std::env::args_os()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        }))
+        .depsolved(depsolved(matches, "depsolved"))
+        .depsolved_build(depsolved(matches, "depsolved-build"))
+        .arch(matches.value_of("arch").unwrap_or(std::env::consts::ARCH))
+        .format(matches.value_of("format").unwrap().parse().unwrap())
+        .kernel(matches.value_of("kernel"))
+        .manifest_version(manifest_version)
+        .version(version)
+        .distro(&distro(matches));
+
+    match matches.value_of("output-format") {
+        Some("blueprint") => {
+            return blueprint(
+                matches,
+                translator,
+                &kickstart,
+                findings,
+                src_paths[0],
+                dst,
+                emitter,
+            )
+        }
+        Some("otk" | "mpp") => {
+            return undepsolved(matches, translator, &kickstart, findings, dst, emitter)
+        }
+        Some("ignition" | "cloud-init") => {
+            return provisioning(matches, translator, &kickstart, findings, dst, emitter)
+        }
+        _ => {}
+    }
+
+    let (manifest, report) = match translator.translate(&kickstart) {
+        Ok(manifest) => manifest,
+        Err(err) => {
+            findings.extend(diagnostics::Diagnostic::from_translate(&err));
+            emitter.emit(&findings);
+            return Err(err.code());
+        }
+    };
+
+    let outcomes = outcomes(matches, &report);
+    findings.extend(outcomes.iter().cloned());
+
+    // What osbuild finds is printed along with the rest, the manifest isn't written then.
+    let mut failure = failed(matches, &outcomes, &findings);
+
+    if matches.is_present("verify") {
+        match verify::verify(&manifest.to_json(), &kickstart) {
+            Ok(errors) => {
+                if !errors.is_empty() {
+                    failure = failure.or(Some(exit::Code::Invalid));
                 }
+
+                findings.extend(errors);
             }
+            Err(err) => warn!("Not verifying the manifest: {}", err),
+        }
+    }
+
+    emitter.emit(&findings);
+
+    info!("Translated kickstart, {}", report.summary());
+
+    if let Some(code) = failure {
+        return Err(code);
+    }
+
+    // What's built still has its sources, only what's written goes without.
+    let written = match matches.value_of("split-sources") {
+        Some(path) => Cow::Owned(split_sources(matches, &manifest, path, dst)?),
+        None => Cow::Borrowed(&manifest),
+    };
 
-            self.sections.push(command_section);
-            self.merge()
+    let output = match matches.value_of("output-format").unwrap() {
+        "yaml" => written.to_yaml(),
+        _ if matches.is_present("compact") => written.to_compact_json(),
+        _ => written.to_json(),
+    };
+
+    if let Err(err) = write(dst, &output) {
+        error!("Failed to write manifest '{}': {}", dst, err);
+        return Err(exit::Code::IO);
+    }
+
+    if let Some(path) = matches.value_of("report") {
+        let report = serde_json::to_string_pretty(&report.to_json(&manifest.kinds())).unwrap();
+
+        if let Err(err) = fs::write(path, report) {
+            error!("Failed to write report '{}': {}", path, err);
+            return Err(exit::Code::IO);
+        }
+    }
+
+    if matches.is_present("build") {
+        let mut exports = values("export");
+
+        if exports.is_empty() {
+            exports.extend(build::artifact(&manifest.names(), manifest_version));
         }
 
-        /// After parsing there can be duplicate sections, we merge these down to single sections.
-        fn merge(self) -> Self {
-            self
+        info!(
+            "Building the manifest with osbuild, exporting {}",
+            exports.join(", ")
+        );
+
+        if let Err(err) = build::build(
+            &manifest.to_json(),
+            Path::new(matches.value_of("store").unwrap()),
+            Path::new(matches.value_of("output-directory").unwrap()),
+            &exports,
+            &values("checkpoint"),
+        ) {
+            error!("Failed to build the manifest: {}", err);
+            return Err(exit::Code::Failure);
         }
     }
+
+    Ok(())
 }
 
-fn make_cli() -> clap::Command<'static> {
-    clap::command!()
-        .arg(
-            clap::arg!(<src> "Kickstart input file")
-                .required(true)
-                .value_hint(clap::ValueHint::FilePath),
-        )
-        .arg(
-            clap::arg!(<dst> "osbuild manifest output file")
-                .required(true)
-                .value_hint(clap::ValueHint::FilePath),
-        )
-        .arg(
-            clap::arg!(-I --include "include path for kickstart files")
-                .default_value(".")
-                .value_hint(clap::ValueHint::DirPath),
-        )
+/// `manifest` without its sources, which are written to `path` instead. Its metadata says where
+/// they are from the directory of `dst` and what their checksum is.
+fn split_sources(
+    matches: &clap::ArgMatches,
+    manifest: &osbuild::Manifest,
+    path: &str,
+    dst: &str,
+) -> Result<osbuild::Manifest, exit::Code> {
+    if matches.value_of("output-format") != Some("json")
+        || matches.value_of("manifest-version") != Some("2")
+    {
+        error!("--split-sources needs a JSON manifest of version 2");
+        return Err(exit::Code::Usage);
+    }
+
+    let mut manifest = manifest.clone();
+    let sources = manifest.take_sources();
+
+    let data = match matches.is_present("compact") {
+        true => serde_json::to_string(&sources),
+        false => serde_json::to_string_pretty(&sources),
+    }
+    .unwrap();
+
+    if let Err(err) = fs::write(path, &data) {
+        error!("Failed to write sources '{}': {}", path, err);
+        return Err(exit::Code::IO);
+    }
+
+    // The manifest is read from where it's written, the sources are found from there.
+    let sources_path = Path::new(path);
+    let reference = match (dst, sources_path.canonicalize()) {
+        (STDOUT, _) | (_, Err(_)) => sources_path.to_path_buf(),
+        (dst, Ok(sources_path)) => {
+            let dir = Path::new(dst)
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+
+            match dir.canonicalize() {
+                Ok(dir) => match sources_path.strip_prefix(&dir) {
+                    Ok(relative) => relative.to_path_buf(),
+                    Err(_) => sources_path.clone(),
+                },
+                Err(_) => sources_path,
+            }
+        }
+    };
+
+    manifest.metadata(
+        "sources",
+        serde_json::json!({
+            "path": reference.display().to_string(),
+            "checksum": osbuild::checksum(data.as_bytes()),
+        }),
+    );
+
+    info!("Wrote sources '{}'", path);
+
+    Ok(manifest)
 }
 
-#[test]
-fn verify_cli() {
-    make_cli().debug_assert();
+/// Write the blueprint `translator` makes of `kickstart` to `dst`, there's no manifest to verify
+/// or build.
+fn blueprint(
+    matches: &clap::ArgMatches,
+    translator: translate::Translator,
+    kickstart: &kickstart::Kickstart,
+    mut findings: Vec<diagnostics::Diagnostic>,
+    src_path: &Path,
+    dst: &str,
+    emitter: &mut diagnostics::Emitter,
+) -> Result<(), exit::Code> {
+    for option in ["verify", "build", "report", "split-sources"] {
+        if matches.is_present(option) {
+            error!("--{} needs a manifest, not a blueprint", option);
+            return Err(exit::Code::Usage);
+        }
+    }
+
+    // Blueprints are named after the kickstart they're made from.
+    let name = match src_path {
+        path if path == Path::new(kickstart::STDIN) => "kickstart".to_string(),
+        path => path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    };
+
+    let (blueprint, report) = match translator.blueprint(kickstart, &name) {
+        Ok(blueprint) => blueprint,
+        Err(err) => {
+            findings.extend(diagnostics::Diagnostic::from_translate(&err));
+            emitter.emit(&findings);
+            return Err(err.code());
+        }
+    };
+
+    let outcomes = outcomes(matches, &report);
+    findings.extend(outcomes.iter().cloned());
+
+    emitter.emit(&findings);
+
+    info!(
+        "Translated kickstart into blueprint '{}', {} customizations, {}",
+        name,
+        blueprint
+            .get("customizations")
+            .and_then(|customizations| customizations.as_object())
+            .map(|customizations| customizations.len())
+            .unwrap_or_default(),
+        report.summary()
+    );
+
+    if let Some(code) = failed(matches, &outcomes, &findings) {
+        return Err(code);
+    }
+
+    if let Err(err) = write(dst, &toml::to_string(&blueprint)) {
+        error!("Failed to write blueprint '{}': {}", dst, err);
+        return Err(exit::Code::IO);
+    }
+
+    Ok(())
 }
 
-fn main() {
-    stderrlog::new()
-        .module(module_path!())
-        .verbosity(10)
-        .init()
-        .unwrap();
+/// Write the config for the first boot that `translator` makes of `kickstart` to `dst`, for
+/// Ignition or cloud-init. There's no manifest to verify or build.
+fn provisioning(
+    matches: &clap::ArgMatches,
+    translator: translate::Translator,
+    kickstart: &kickstart::Kickstart,
+    mut findings: Vec<diagnostics::Diagnostic>,
+    dst: &str,
+    emitter: &mut diagnostics::Emitter,
+) -> Result<(), exit::Code> {
+    let output_format = matches.value_of("output-format").unwrap();
 
-    let matches = make_cli().get_matches();
+    let what = match output_format {
+        "ignition" => "an Ignition config",
+        _ => "a cloud config",
+    };
 
-    let src = matches.value_of("src").unwrap();
-    // let dst = matches.value_of("dst").unwrap();
-    let inc = matches.value_of("include").unwrap();
+    for option in ["verify", "build", "report", "split-sources"] {
+        if matches.is_present(option) {
+            error!("--{} needs a manifest, not {}", option, what);
+            return Err(exit::Code::Usage);
+        }
+    }
 
-    // Let's verify some of these paths.
-    let src_path = Path::new(src);
-    // let dst_path = Path::new(dst);
-    let inc_path = Path::new(inc);
+    let translated = match output_format {
+        "ignition" => translator.ignition(kickstart),
+        _ => translator.cloud_init(kickstart),
+    };
 
-    if !src_path.exists() {
-        eprintln!("The path given for `src` does not exist: '{}'", src);
-        exit(1);
+    let (config, report) = match translated {
+        Ok(config) => config,
+        Err(err) => {
+            findings.extend(diagnostics::Diagnostic::from_translate(&err));
+            emitter.emit(&findings);
+            return Err(err.code());
+        }
+    };
+
+    let outcomes = outcomes(matches, &report);
+    findings.extend(outcomes.iter().cloned());
+
+    emitter.emit(&findings);
+
+    info!("Translated kickstart into {}, {}", what, report.summary());
+
+    if let Some(code) = failed(matches, &outcomes, &findings) {
+        return Err(code);
     }
 
-    if !src_path.is_file() {
-        eprintln!("The path given for `src` is not a file: '{}'", src);
-        exit(1);
+    let output = match output_format {
+        "ignition" if matches.is_present("compact") => serde_json::to_string(&config).unwrap(),
+        "ignition" => serde_json::to_string_pretty(&config).unwrap(),
+        _ => format!("#cloud-config\n{}", yaml::to_string(&config)),
+    };
+
+    if let Err(err) = write(dst, &output) {
+        error!("Failed to write {} to '{}': {}", what, dst, err);
+        return Err(exit::Code::IO);
+    }
+
+    Ok(())
+}
+
+/// Write what `translator` makes of `kickstart` for otk or osbuild-mpp to `dst`, it's a manifest
+/// once they have depsolved its packages.
+fn undepsolved(
+    matches: &clap::ArgMatches,
+    translator: translate::Translator,
+    kickstart: &kickstart::Kickstart,
+    mut findings: Vec<diagnostics::Diagnostic>,
+    dst: &str,
+    emitter: &mut diagnostics::Emitter,
+) -> Result<(), exit::Code> {
+    let output_format = matches.value_of("output-format").unwrap();
+
+    let tool = match output_format {
+        "otk" => "otk",
+        _ => "osbuild-mpp",
+    };
+
+    for option in ["verify", "build", "report", "split-sources"] {
+        if matches.is_present(option) {
+            error!("--{} needs a manifest that {} has depsolved", option, tool);
+            return Err(exit::Code::Usage);
+        }
+    }
+
+    for option in ["depsolved", "depsolved-build"] {
+        if matches.is_present(option) {
+            error!(
+                "--{} can't be used, {} depsolves the packages",
+                option, tool
+            );
+            return Err(exit::Code::Usage);
+        }
     }
 
-    if !inc_path.exists() {
-        eprintln!("The path given for `include` does not exist: '{}'", inc);
-        exit(1);
+    let translated = match output_format {
+        "otk" => translator.otk(kickstart),
+        _ => translator.mpp(kickstart),
+    };
+
+    let (output, report) = match translated {
+        Ok(translated) => translated,
+        Err(err) => {
+            findings.extend(diagnostics::Diagnostic::from_translate(&err));
+            emitter.emit(&findings);
+            return Err(err.code());
+        }
+    };
+
+    let outcomes = outcomes(matches, &report);
+    findings.extend(outcomes.iter().cloned());
+
+    emitter.emit(&findings);
+
+    info!(
+        "Translated kickstart, {} depsolves its packages, {}",
+        tool,
+        report.summary()
+    );
+
+    if let Some(code) = failed(matches, &outcomes, &findings) {
+        return Err(code);
     }
 
-    if !inc_path.is_dir() {
-        eprintln!("The path given for `include` is not a directory: '{}'", inc);
-        exit(1);
+    let output = match output_format {
+        "otk" => yaml::to_string(&output),
+        _ if matches.is_present("compact") => serde_json::to_string(&output).unwrap(),
+        _ => serde_json::to_string_pretty(&output).unwrap(),
+    };
+
+    if let Err(err) = write(dst, &output) {
+        error!("Failed to write '{}': {}", dst, err);
+        return Err(exit::Code::IO);
     }
 
-    let _kickstart = kickstart::Kickstart::from_path(&src_path, &inc_path);
+    Ok(())
 }
@@ -0,0 +1,108 @@
+//! Man pages made from the definition of the command line, so distributions can package them
+//! with the binary. The page of `osbuild-ks` itself also lists the kickstart commands that are
+//! translated, from the catalog.
+
+use std::io;
+
+use roff::{bold, italic, line_break, roman, Roff};
+
+use crate::translate::catalog;
+
+/// The pages of `cli` and each of its subcommands but `help`, named like `osbuild-ks-convert.1`.
+pub fn pages(mut cli: clap::Command) -> io::Result<Vec<(String, Vec<u8>)>> {
+    cli.build();
+
+    let mut pages = vec![(format!("{}.1", cli.get_name()), page(&cli)?)];
+
+    for subcommand in cli.get_subcommands() {
+        if subcommand.get_name() == "help" {
+            continue;
+        }
+
+        let name = format!("{}-{}", cli.get_name(), subcommand.get_name());
+        let mut subcommand = subcommand.clone().name(&name);
+        let mut data = Vec::new();
+
+        if let Some(version) = cli.get_version() {
+            subcommand = subcommand.version(version);
+        }
+
+        clap_mangen::Man::new(subcommand).render(&mut data)?;
+        pages.push((format!("{}.1", name), data));
+    }
+
+    Ok(pages)
+}
+
+/// The page of `cli` with the kickstart commands after its subcommands.
+fn page(cli: &clap::Command) -> io::Result<Vec<u8>> {
+    let man = clap_mangen::Man::new(cli.clone());
+    let mut data = Vec::new();
+
+    man.render_title(&mut data)?;
+    man.render_name_section(&mut data)?;
+    man.render_synopsis_section(&mut data)?;
+    man.render_description_section(&mut data)?;
+    man.render_options_section(&mut data)?;
+    man.render_subcommands_section(&mut data)?;
+    commands().to_writer(&mut data)?;
+
+    if cli.get_version().is_some() {
+        man.render_version_section(&mut data)?;
+    }
+
+    if cli.get_author().is_some() {
+        man.render_authors_section(&mut data)?;
+    }
+
+    Ok(data)
+}
+
+/// The section with the commands and sections of the catalog.
+fn commands() -> Roff {
+    let mut roff = Roff::new();
+
+    roff.control("SH", ["KICKSTART COMMANDS"]);
+    roff.text([roman(
+        "The commands and sections that are translated, with the options they're understood \
+         with and the stages they can end up in. Others are left out with a warning.",
+    )]);
+
+    for command in catalog::COMMANDS {
+        roff.control("TP", []);
+        roff.text([bold(command.name)]);
+
+        let mut text = Vec::new();
+
+        for (what, options) in [("Values", command.values), ("Flags", command.flags)] {
+            if !options.is_empty() {
+                text.push(italic(format!("{}: ", what)));
+                text.push(roman(options.join(", ")));
+                text.push(line_break());
+            }
+        }
+
+        text.push(italic("Stages: "));
+        text.push(roman(command.stages().collect::<Vec<_>>().join(", ")));
+
+        roff.text(text);
+    }
+
+    roff
+}
+
+#[test]
+fn man_pages() {
+    let cli = clap::Command::new("osbuild-ks")
+        .about("Convert kickstarts")
+        .subcommand(clap::Command::new("convert").about("Convert a kickstart"));
+
+    let pages = pages(cli).unwrap();
+    let names: Vec<_> = pages.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["osbuild-ks.1", "osbuild-ks-convert.1"]);
+
+    let page = String::from_utf8(pages[0].1.clone()).unwrap();
+    assert!(page.contains(".SH \"KICKSTART COMMANDS\""));
+    assert!(page.contains("\\fBtimezone\\fR"));
+    assert!(page.contains("org.osbuild.timezone"));
+}
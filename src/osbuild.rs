@@ -0,0 +1,457 @@
+//! Types describing an osbuild manifest, these serialize to the version 2 manifest format as
+//! described in the [osbuild documentation](https://www.osbuild.org/guides/developer-guide/manifest-v2.html).
+//! Older osbuild only takes version 1 manifests, with a single pipeline whose tree is turned into
+//! the artifact by an assembler.
+//!
+//! The fields of the types are serialized in a fixed order and options are kept in maps that
+//! sort their keys, the same manifest is always written the same way.
+
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+use crate::yaml;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Manifest {
+    version: String,
+    pipelines: Vec<Pipeline>,
+    sources: Map<String, Value>,
+    /// Where the manifest comes from and how to build it, osbuild doesn't act on it.
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    metadata: Map<String, Value>,
+    /// The manifest in the version 1 format, written instead when it's there.
+    #[serde(skip)]
+    v1: Option<Value>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Pipeline {
+    name: String,
+    /// The pipeline whose tree the stages run in, the host when there is none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<String>,
+    /// The osbuild runner that sets up the build root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runner: Option<String>,
+    stages: Vec<Stage>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Stage {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    inputs: Map<String, Value>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    options: Map<String, Value>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    devices: Map<String, Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    mounts: Vec<Value>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self {
+            version: "2".to_string(),
+            pipelines: Vec::new(),
+            sources: Map::new(),
+            metadata: Map::new(),
+            v1: None,
+        }
+    }
+
+    pub fn push(&mut self, pipeline: Pipeline) {
+        self.pipelines.push(pipeline);
+    }
+
+    /// The types of the stages in all pipelines, each once.
+    pub fn kinds(&self) -> Vec<&str> {
+        let mut kinds: Vec<&str> = Vec::new();
+
+        for stage in self.pipelines.iter().flat_map(|pipeline| &pipeline.stages) {
+            if !kinds.contains(&stage.kind()) {
+                kinds.push(stage.kind());
+            }
+        }
+
+        kinds
+    }
+
+    /// The names of all pipelines.
+    pub fn names(&self) -> Vec<&str> {
+        self.pipelines
+            .iter()
+            .map(|pipeline| pipeline.name.as_str())
+            .collect()
+    }
+
+    pub fn metadata(&mut self, key: &str, value: Value) {
+        self.metadata.insert(key.to_string(), value);
+    }
+
+    /// Remove the sources to write them on their own, the manifest is left with none.
+    pub fn take_sources(&mut self) -> Map<String, Value> {
+        std::mem::take(&mut self.sources)
+    }
+
+    /// Embed `data` in the manifest as an `org.osbuild.inline` source, returning the checksum
+    /// that stages can use to reference it.
+    pub fn inline(&mut self, data: &[u8]) -> String {
+        let id = checksum(data);
+
+        let source = self
+            .sources
+            .entry("org.osbuild.inline")
+            .or_insert_with(|| json!({ "items": {} }));
+
+        source["items"][&id] = json!({
+            "encoding": "base64",
+            "data": base64::engine::general_purpose::STANDARD.encode(data),
+        });
+
+        id
+    }
+
+    /// Download the file with `checksum` from `url` with an `org.osbuild.curl` source, returning
+    /// the checksum that stages can use to reference it.
+    pub fn curl(&mut self, checksum: &str, url: &str) -> String {
+        let source = self
+            .sources
+            .entry("org.osbuild.curl")
+            .or_insert_with(|| json!({ "items": {} }));
+
+        source["items"][checksum] = json!({ "url": url });
+
+        checksum.to_string()
+    }
+
+    /// Run the stages of all pipelines in the tree of `build`, which goes first.
+    pub fn build(&mut self, build: Pipeline) {
+        let name = format!("name:{}", build.name);
+
+        for pipeline in &mut self.pipelines {
+            pipeline.build = Some(name.clone());
+        }
+
+        self.pipelines.insert(0, build);
+    }
+
+    /// Write the manifest in the version 1 format, with the stages of the pipeline named `tree`
+    /// run in the build pipeline if there is one. The other pipelines are replaced by
+    /// `assembler`.
+    pub fn v1(&mut self, tree: &str, assembler: Option<&Stage>) -> Result<(), String> {
+        let stages = |name: &str| -> Result<Option<Vec<Value>>, String> {
+            match self.pipelines.iter().find(|pipeline| pipeline.name == name) {
+                Some(pipeline) => pipeline
+                    .stages
+                    .iter()
+                    .map(Stage::v1)
+                    .collect::<Result<_, _>>()
+                    .map(Some),
+                None => Ok(None),
+            }
+        };
+
+        let mut pipeline = json!({ "stages": stages(tree)?.unwrap_or_default() });
+
+        if let Some(build) = stages("build")? {
+            pipeline["build"] = json!({
+                "pipeline": { "stages": build },
+                "runner": "org.osbuild.linux",
+            });
+        }
+
+        if let Some(assembler) = assembler {
+            pipeline["assembler"] = json!({ "name": assembler.kind, "options": assembler.options });
+        }
+
+        // Version 1 downloads files with the `org.osbuild.files` source.
+        let mut sources = self.sources.clone();
+
+        if let Some(curl) = sources.remove("org.osbuild.curl") {
+            let urls: Map<String, Value> = curl["items"]
+                .as_object()
+                .into_iter()
+                .flatten()
+                .map(|(checksum, item)| (checksum.clone(), item["url"].clone()))
+                .collect();
+
+            sources.insert("org.osbuild.files".to_string(), json!({ "urls": urls }));
+        }
+
+        self.v1 = Some(json!({ "pipeline": pipeline, "sources": sources }));
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        match &self.v1 {
+            Some(v1) => serde_json::to_string_pretty(v1).unwrap(),
+            None => serde_json::to_string_pretty(self).unwrap(),
+        }
+    }
+
+    /// The JSON on a single line, for manifests that are only read by osbuild.
+    pub fn to_compact_json(&self) -> String {
+        match &self.v1 {
+            Some(v1) => serde_json::to_string(v1).unwrap(),
+            None => serde_json::to_string(self).unwrap(),
+        }
+    }
+
+    pub fn to_yaml(&self) -> String {
+        match &self.v1 {
+            Some(v1) => yaml::to_string(v1),
+            None => yaml::to_string(&serde_json::to_value(self).unwrap()),
+        }
+    }
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            build: None,
+            runner: None,
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, stage: Stage) {
+        self.stages.push(stage);
+    }
+
+    pub fn runner(&mut self, runner: &str) {
+        self.runner = Some(runner.to_string());
+    }
+
+    /// Reorder the stages so that every stage comes after those that `before` says have to run
+    /// before it. Stages keep the order they were pushed in where `before` doesn't say.
+    pub fn order(&mut self, before: impl Fn(&Stage, &Stage) -> bool) {
+        let mut left: Vec<Stage> = self.stages.drain(..).collect();
+
+        while !left.is_empty() {
+            // When `before` has a cycle none of the stages is ready, they then stay in order.
+            let ready = (0..left.len())
+                .find(|&i| {
+                    !left
+                        .iter()
+                        .enumerate()
+                        .any(|(j, other)| j != i && before(other, &left[i]))
+                })
+                .unwrap_or(0);
+
+            self.stages.push(left.remove(ready));
+        }
+    }
+}
+
+impl Stage {
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_string(),
+            inputs: Map::new(),
+            options: Map::new(),
+            devices: Map::new(),
+            mounts: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn input(mut self, name: &str, value: Value) -> Self {
+        self.inputs.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn option(mut self, name: &str, value: Value) -> Self {
+        self.options.insert(name.to_string(), value);
+        self
+    }
+
+    pub fn device(mut self, name: &str, value: Value) -> Self {
+        self.devices.insert(name.to_string(), value);
+        self
+    }
+
+    /// Mounts are applied in order, so parents have to come before their children.
+    pub fn mount(mut self, value: Value) -> Self {
+        self.mounts.push(value);
+        self
+    }
+
+    /// The stage in the version 1 format, which has no devices or mounts. The only inputs it has
+    /// are the packages of the rpm stage, which are an option.
+    fn v1(&self) -> Result<Value, String> {
+        if !self.devices.is_empty() || !self.mounts.is_empty() {
+            return Err(format!(
+                "'{}' needs devices, which version 1 manifests don't have",
+                self.kind
+            ));
+        }
+
+        let mut options = self.options.clone();
+
+        for (name, input) in &self.inputs {
+            if self.kind != "org.osbuild.rpm" || name != "packages" {
+                return Err(format!(
+                    "'{}' takes inputs, which version 1 manifests don't have",
+                    self.kind
+                ));
+            }
+
+            let packages: Vec<&String> = input["references"]
+                .as_object()
+                .map(|references| references.keys().collect())
+                .unwrap_or_default();
+
+            options.insert("packages".to_string(), json!(packages));
+        }
+
+        Ok(json!({ "name": self.kind, "options": options }))
+    }
+
+    /// Set all options from a JSON object at once.
+    pub fn options(mut self, options: &Value) -> Self {
+        if let Some(options) = options.as_object() {
+            self.options.extend(options.clone());
+        }
+
+        self
+    }
+}
+
+/// The checksum osbuild uses to identify source items.
+pub fn checksum(data: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(data))
+}
+
+#[test]
+fn manifest_inline() {
+    let mut manifest = Manifest::new();
+    let id = manifest.inline(b"hello");
+
+    assert_eq!(
+        id,
+        "sha256:2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+    );
+    assert_eq!(
+        manifest.sources["org.osbuild.inline"]["items"][&id]["data"],
+        "aGVsbG8="
+    );
+}
+
+#[test]
+fn manifest_take_sources() {
+    let mut manifest = Manifest::new();
+    let id = manifest.inline(b"hello");
+
+    let sources = manifest.take_sources();
+    let json: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+
+    assert_eq!(
+        sources["org.osbuild.inline"]["items"][&id]["data"],
+        "aGVsbG8="
+    );
+    assert_eq!(json["sources"], json!({}));
+}
+
+#[test]
+fn manifest_compact_json() {
+    let mut os = Pipeline::new("os");
+    os.push(
+        Stage::new("org.osbuild.timezone")
+            .option("zone", json!("UTC"))
+            .option("config", json!({ "utc": true, "adjtime": false })),
+    );
+
+    let mut manifest = Manifest::new();
+    manifest.push(os);
+
+    assert_eq!(
+        manifest.to_compact_json(),
+        "{\"version\":\"2\",\"pipelines\":[{\"name\":\"os\",\"stages\":[{\"type\":\
+         \"org.osbuild.timezone\",\"options\":{\"config\":{\"adjtime\":false,\"utc\":true},\
+         \"zone\":\"UTC\"}}]}],\"sources\":{}}"
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(&manifest.to_json()).unwrap(),
+        serde_json::from_str::<Value>(&manifest.to_compact_json()).unwrap()
+    );
+}
+
+#[test]
+fn manifest_build() {
+    let mut manifest = Manifest::new();
+    manifest.push(Pipeline::new("os"));
+    manifest.build(Pipeline::new("build"));
+
+    let json: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+
+    assert_eq!(
+        json["pipelines"][0],
+        json!({ "name": "build", "stages": [] })
+    );
+    assert_eq!(json["pipelines"][1]["build"], "name:build");
+}
+
+#[test]
+fn manifest_v1() {
+    let mut os = Pipeline::new("os");
+    os.push(Stage::new("org.osbuild.rpm").input(
+        "packages",
+        json!({ "type": "org.osbuild.files", "references": { "sha256:01": {} } }),
+    ));
+    os.push(Stage::new("org.osbuild.timezone").option("zone", json!("UTC")));
+
+    let mut manifest = Manifest::new();
+    manifest.push(os);
+    manifest.build(Pipeline::new("build"));
+    manifest.curl("sha256:01", "https://example.com/vim.rpm");
+    assert_eq!(
+        manifest.sources["org.osbuild.curl"]["items"]["sha256:01"]["url"],
+        "https://example.com/vim.rpm"
+    );
+    manifest
+        .v1(
+            "os",
+            Some(&Stage::new("org.osbuild.tar").option("filename", json!("root.tar"))),
+        )
+        .unwrap();
+
+    let json: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+
+    assert!(json.get("version").is_none());
+    assert_eq!(
+        json["pipeline"]["stages"],
+        json!([
+            { "name": "org.osbuild.rpm", "options": { "packages": ["sha256:01"] } },
+            { "name": "org.osbuild.timezone", "options": { "zone": "UTC" } },
+        ])
+    );
+    assert_eq!(json["pipeline"]["build"]["runner"], "org.osbuild.linux");
+    assert_eq!(json["pipeline"]["assembler"]["name"], "org.osbuild.tar");
+    assert_eq!(
+        json["sources"],
+        json!({ "org.osbuild.files": { "urls": { "sha256:01": "https://example.com/vim.rpm" } } })
+    );
+
+    let mut image = Pipeline::new("image");
+    image.push(Stage::new("org.osbuild.copy").device("disk", json!({})));
+
+    let mut manifest = Manifest::new();
+    manifest.push(image);
+
+    assert!(manifest.v1("image", None).is_err());
+}
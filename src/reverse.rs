@@ -0,0 +1,698 @@
+//! Reversing a manifest reconstructs a kickstart that comes close to it, for moving images
+//! that were built from manifests to kickstarts. Stages are read back into the commands they're
+//! made from, the disk into partitions and the packages from the names of their RPM files.
+//!
+//! It's a best effort: options that don't survive the translation, such as whether a partition
+//! grows, can't be recovered and stages that no command makes are pointed out.
+
+use base64::Engine;
+use serde_json::Value;
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::translate::words::quote;
+
+/// Stages that come with every image, or with its disk, rather than from a command.
+const IMPLIED: &[&str] = &[
+    "org.osbuild.copy",
+    "org.osbuild.fstab",
+    "org.osbuild.selinux",
+    "org.osbuild.truncate",
+    "org.osbuild.sfdisk",
+    "org.osbuild.mkswap",
+    "org.osbuild.lvm2.create",
+    "org.osbuild.lvm2.metadata",
+    "org.osbuild.grub2",
+    "org.osbuild.grub2.inst",
+    "org.osbuild.zipl",
+    "org.osbuild.zipl.inst",
+    "org.osbuild.fix-bls",
+    "org.osbuild.qemu",
+    "org.osbuild.tar",
+    "org.osbuild.oci-archive",
+];
+
+/// GPT and dos partition types of partitions the platform needs, which `reqpart` adds.
+const REQUIRED_PARTITIONS: &[&str] = &[
+    "21686148-6449-6E6F-744E-656564454649",
+    "9E1A2D38-C612-4316-AA26-8B49521E5A8B",
+    "41",
+];
+
+/// The kickstart for `manifest`, with the stages it has no command for.
+pub fn reverse(manifest: &Value) -> Result<(String, Vec<Diagnostic>), String> {
+    let stages = stages(manifest)?;
+
+    let mut lines = vec!["# Reconstructed from an osbuild manifest".to_string()];
+    let mut packages = Vec::new();
+    let mut passthrough = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    for (pipeline, stage) in &stages {
+        let kind = stage["type"]
+            .as_str()
+            .or_else(|| stage["name"].as_str())
+            .unwrap_or_default();
+        let options = &stage["options"];
+
+        match kind {
+            "org.osbuild.rpm" => packages.extend(rpms(manifest, stage)),
+            "org.osbuild.kernel-cmdline" => {
+                if let Some(append) = options["kernel_opts"].as_str() {
+                    lines.push(format!("bootloader --append={}", quote(append)));
+                }
+            }
+            "org.osbuild.timezone" => {
+                if let Some(zone) = options["zone"].as_str() {
+                    lines.push(format!("timezone --utc {}", quote(zone)));
+                }
+            }
+            "org.osbuild.chrony" => {
+                for server in options["timeservers"].as_array().into_iter().flatten() {
+                    if let Some(server) = server.as_str() {
+                        lines.push(format!("timesource --ntp-server={}", quote(server)));
+                    }
+                }
+            }
+            "org.osbuild.locale" => {
+                if let Some(language) = options["language"].as_str() {
+                    lines.push(format!("lang {}", quote(language)));
+                }
+            }
+            "org.osbuild.keymap" => {
+                if let Some(keymap) = options["keymap"].as_str() {
+                    lines.push(format!("keyboard --vckeymap={}", quote(keymap)));
+                }
+            }
+            "org.osbuild.hostname" => {
+                if let Some(hostname) = options["hostname"].as_str() {
+                    lines.push(format!("network --hostname={}", quote(hostname)));
+                }
+            }
+            "org.osbuild.groups" => {
+                for (name, group) in options["groups"].as_object().into_iter().flatten() {
+                    let mut line = format!("group --name={}", quote(name));
+
+                    if let Some(gid) = group["gid"].as_u64() {
+                        line.push_str(&format!(" --gid={}", gid));
+                    }
+
+                    lines.push(line);
+                }
+            }
+            "org.osbuild.users" => {
+                for (name, user) in options["users"].as_object().into_iter().flatten() {
+                    lines.extend(user_lines(name, user));
+                }
+            }
+            "org.osbuild.systemd" => {
+                let mut line = "services".to_string();
+
+                for (option, state) in [
+                    ("enabled_services", "enabled"),
+                    ("disabled_services", "disabled"),
+                ] {
+                    let services: Vec<_> = options[option]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_str)
+                        .collect();
+
+                    if !services.is_empty() {
+                        line.push_str(&format!(" --{}={}", state, quote(&services.join(","))));
+                    }
+                }
+
+                if line != "services" {
+                    lines.push(line);
+                }
+
+                match options["default_target"].as_str() {
+                    Some("graphical.target") => lines.push("graphical".to_string()),
+                    Some("multi-user.target") => lines.push("text".to_string()),
+                    _ => {}
+                }
+            }
+            "org.osbuild.firewall" => {
+                let mut line = "firewall --enabled".to_string();
+
+                for (option, flag) in [
+                    ("ports", "--port"),
+                    ("enabled_services", "--service"),
+                    ("disabled_services", "--remove-service"),
+                ] {
+                    let values: Vec<_> = options[option]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter_map(Value::as_str)
+                        .collect();
+
+                    if !values.is_empty() {
+                        line.push_str(&format!(" {}={}", flag, quote(&values.join(","))));
+                    }
+                }
+
+                lines.push(line);
+            }
+            "org.osbuild.authselect" => {
+                if let Some(profile) = options["profile"].as_str() {
+                    let mut line = format!("authselect select {}", quote(profile));
+
+                    for feature in options["features"].as_array().into_iter().flatten() {
+                        if let Some(feature) = feature.as_str() {
+                            line.push_str(&format!(" {}", quote(feature)));
+                        }
+                    }
+
+                    lines.push(line);
+                }
+            }
+            // What was passed through goes back in as it was.
+            "org.osbuild.copy" if pipeline == "kickstart" => {
+                passthrough.extend(inline_files(manifest, stage));
+            }
+            kind if IMPLIED.contains(&kind) || kind.starts_with("org.osbuild.mkfs.") => {}
+            kind => diagnostics.push(Diagnostic::new(
+                "no-equivalent",
+                Severity::Warning,
+                format!(
+                    "{}: {} has no kickstart equivalent, leaving it out",
+                    pipeline, kind
+                ),
+            )),
+        }
+    }
+
+    lines.extend(storage(manifest, &stages));
+
+    if !packages.is_empty() {
+        lines.push(String::new());
+        lines.push("%packages".to_string());
+        lines.extend(packages);
+        lines.push("%end".to_string());
+    }
+
+    for kickstart in passthrough {
+        lines.push(String::new());
+        lines.push(kickstart.trim_end().to_string());
+    }
+
+    lines.push(String::new());
+
+    Ok((lines.join("\n"), diagnostics))
+}
+
+/// The stages of the pipelines of `manifest` with the name of their pipeline, the build
+/// pipelines are left out. Version 1 manifests have one pipeline.
+fn stages(manifest: &Value) -> Result<Vec<(String, &Value)>, String> {
+    if let Some(pipeline) = manifest.get("pipeline") {
+        return Ok(pipeline["stages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|stage| ("pipeline".to_string(), stage))
+            .collect());
+    }
+
+    let pipelines = manifest["pipelines"]
+        .as_array()
+        .ok_or("the manifest has no pipelines")?;
+
+    let builds: Vec<_> = pipelines
+        .iter()
+        .filter_map(|pipeline| pipeline["build"].as_str())
+        .filter_map(|build| build.strip_prefix("name:"))
+        .collect();
+
+    Ok(pipelines
+        .iter()
+        .filter(|pipeline| !builds.contains(&pipeline["name"].as_str().unwrap_or_default()))
+        .flat_map(|pipeline| {
+            let name = pipeline["name"].as_str().unwrap_or_default().to_string();
+
+            pipeline["stages"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(move |stage| (name.clone(), stage))
+        })
+        .collect())
+}
+
+/// The names of the packages the rpm `stage` installs, from the URLs of their RPM files.
+fn rpms(manifest: &Value, stage: &Value) -> Vec<String> {
+    let references = &stage["inputs"]["packages"]["references"];
+
+    let checksums: Vec<&str> = match references {
+        Value::Object(references) => references.keys().map(String::as_str).collect(),
+        Value::Array(references) => references
+            .iter()
+            .filter_map(|reference| reference.as_str().or_else(|| reference["id"].as_str()))
+            .collect(),
+        // Version 1 manifests list them in the options.
+        _ => stage["options"]["packages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect(),
+    };
+
+    let sources = &manifest["sources"];
+
+    checksums
+        .iter()
+        .filter_map(|checksum| {
+            let curl = &sources["org.osbuild.curl"]["items"][checksum];
+            let url = curl
+                .as_str()
+                .or_else(|| curl["url"].as_str())
+                .or_else(|| sources["org.osbuild.files"]["urls"][checksum].as_str())?;
+
+            package_name(url.rsplit('/').next()?)
+        })
+        .collect()
+}
+
+/// The text of the inline sources the copy `stage` puts in its tree.
+fn inline_files(manifest: &Value, stage: &Value) -> Vec<String> {
+    let items = &manifest["sources"]["org.osbuild.inline"]["items"];
+
+    stage["inputs"]["files"]["references"]
+        .as_object()
+        .into_iter()
+        .flat_map(|references| references.keys())
+        .filter_map(|id| {
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(items[id]["data"].as_str()?)
+                .ok()?;
+
+            String::from_utf8(data).ok()
+        })
+        .collect()
+}
+
+/// The name of the package in the RPM file `filename`, which is called
+/// `<name>-<version>-<release>.<arch>.rpm`.
+fn package_name(filename: &str) -> Option<String> {
+    let nevr = filename.strip_suffix(".rpm")?.rsplit_once('.')?.0;
+    let (nv, _release) = nevr.rsplit_once('-')?;
+    let (name, _version) = nv.rsplit_once('-')?;
+
+    Some(name.to_string())
+}
+
+/// `rootpw` for root, `user` for the others and `sshkey` for their keys.
+fn user_lines(name: &str, user: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+    let password = user["password"].as_str();
+
+    if name == "root" {
+        lines.push(match password {
+            Some(password) => format!("rootpw --iscrypted {}", quote(password)),
+            None => "rootpw --lock".to_string(),
+        });
+    } else {
+        let mut line = format!("user --name={}", quote(name));
+
+        // The users stage only takes hashed passwords.
+        if let Some(password) = password {
+            line.push_str(&format!(" --password={} --iscrypted", quote(password)));
+        }
+
+        let groups: Vec<_> = user["groups"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+
+        if !groups.is_empty() {
+            line.push_str(&format!(" --groups={}", quote(&groups.join(","))));
+        }
+
+        for (key, option) in [
+            ("home", "--homedir"),
+            ("shell", "--shell"),
+            ("description", "--gecos"),
+        ] {
+            if let Some(value) = user[key].as_str() {
+                line.push_str(&format!(" {}={}", option, quote(value)));
+            }
+        }
+
+        for (key, option) in [("uid", "--uid"), ("gid", "--gid")] {
+            if let Some(id) = user[key].as_u64() {
+                line.push_str(&format!(" {}={}", option, id));
+            }
+        }
+
+        lines.push(line);
+    }
+
+    if let Some(key) = user["key"].as_str() {
+        lines.push(format!("sshkey --username={} {}", quote(name), quote(key)));
+    }
+
+    lines
+}
+
+/// A volume group on a partition of `size` bytes.
+struct VolumeGroup {
+    name: String,
+    size: u64,
+    /// The names and sizes in bytes of its logical volumes.
+    volumes: Vec<(String, u64)>,
+}
+
+/// The storage commands for the disk of `manifest`, none when it makes no disk.
+fn storage(manifest: &Value, stages: &[(String, &Value)]) -> Vec<String> {
+    let kind = |stage: &Value| -> String {
+        stage["type"]
+            .as_str()
+            .or_else(|| stage["name"].as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    // Version 1 manifests have their partitions in the assembler.
+    if let Some(partitions) = manifest["pipeline"]["assembler"]["options"]["partitions"].as_array()
+    {
+        let mut lines = vec!["clearpart --all --initlabel".to_string()];
+
+        for partition in partitions {
+            let filesystem = &partition["filesystem"];
+            let size = mib(partition["size"].as_u64().unwrap_or_default() * 512);
+
+            match filesystem["mountpoint"].as_str() {
+                Some(mountpoint) => lines.push(format!(
+                    "part {} --fstype={} --size={}",
+                    quote(mountpoint),
+                    fstype(mountpoint, filesystem["type"].as_str().unwrap_or_default()),
+                    size
+                )),
+                None if is_required(&partition["type"]) => lines.push("reqpart".to_string()),
+                None => {}
+            }
+        }
+
+        return lines;
+    }
+
+    let fstab = stages
+        .iter()
+        .find(|(_, stage)| kind(stage) == "org.osbuild.fstab")
+        .and_then(|(_, stage)| stage["options"]["filesystems"].as_array());
+
+    let fstab = match fstab {
+        Some(fstab) => fstab,
+        None => return Vec::new(),
+    };
+
+    let sfdisk = stages
+        .iter()
+        .find(|(_, stage)| kind(stage) == "org.osbuild.sfdisk")
+        .map(|(_, stage)| &stage["options"]);
+
+    let mut lines = vec![match sfdisk.and_then(|sfdisk| sfdisk["label"].as_str()) {
+        Some(label) => format!("clearpart --all --initlabel --disklabel={}", label),
+        None => "clearpart --all --initlabel".to_string(),
+    }];
+
+    if let Some(sfdisk) = sfdisk {
+        if sfdisk["partitions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .any(|partition| is_required(&partition["type"]))
+        {
+            lines.push("reqpart".to_string());
+        }
+    }
+
+    // The stages of a volume group share the device of the partition it's on.
+    let mut volgroups = Vec::new();
+
+    for (_, create) in stages
+        .iter()
+        .filter(|(_, stage)| kind(stage) == "org.osbuild.lvm2.create")
+    {
+        let device = &create["devices"]["device"]["options"];
+
+        let name = stages
+            .iter()
+            .filter(|(_, stage)| kind(stage) == "org.osbuild.lvm2.metadata")
+            .find(|(_, stage)| stage["devices"]["device"]["options"]["start"] == device["start"])
+            .and_then(|(_, stage)| stage["options"]["vg_name"].as_str())
+            .unwrap_or("osbuild");
+
+        let volumes = create["options"]["volumes"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|volume| {
+                let size = volume["size"]
+                    .as_str()
+                    .and_then(|size| size.trim_end_matches('B').parse().ok())
+                    .unwrap_or_default();
+
+                (
+                    volume["name"].as_str().unwrap_or_default().to_string(),
+                    size,
+                )
+            })
+            .collect();
+
+        volgroups.push(VolumeGroup {
+            name: name.to_string(),
+            size: device["size"].as_u64().unwrap_or_default() * 512,
+            volumes,
+        });
+    }
+
+    for (index, volgroup) in volgroups.iter().enumerate() {
+        lines.push(format!(
+            "part pv.{:02} --size={}",
+            index + 1,
+            mib(volgroup.size)
+        ));
+        lines.push(format!(
+            "volgroup {} pv.{:02}",
+            quote(&volgroup.name),
+            index + 1
+        ));
+    }
+
+    for entry in fstab {
+        let uuid = entry["uuid"].as_str().unwrap_or_default();
+        let path = entry["path"].as_str().unwrap_or_default();
+        let vfs_type = entry["vfs_type"].as_str().unwrap_or_default();
+
+        let mountpoint = match vfs_type {
+            "swap" => "swap",
+            _ => path,
+        };
+
+        // The stage that made the filesystem, FAT filesystems go by their volume id.
+        let mkfs = stages.iter().map(|(_, stage)| *stage).find(|stage| {
+            let kind = kind(stage);
+            let options = &stage["options"];
+
+            (kind.starts_with("org.osbuild.mkfs.") || kind == "org.osbuild.mkswap")
+                && (options["uuid"] == uuid
+                    || options["volid"]
+                        .as_str()
+                        .is_some_and(|volid| volid.eq_ignore_ascii_case(&uuid.replace('-', ""))))
+        });
+
+        let device = mkfs.map(|stage| &stage["devices"]["device"]);
+        let fstype = fstype(mountpoint, vfs_type);
+
+        match device.and_then(|device| device["options"]["volume"].as_str()) {
+            Some(volume) => {
+                let (vgname, size) = volgroups
+                    .iter()
+                    .find_map(|volgroup| {
+                        volgroup
+                            .volumes
+                            .iter()
+                            .find(|(name, _)| name == volume)
+                            .map(|(_, size)| (volgroup.name.as_str(), *size))
+                    })
+                    .unwrap_or(("osbuild", 0));
+
+                lines.push(format!(
+                    "logvol {} --vgname={} --name={} --fstype={} --size={}",
+                    quote(mountpoint),
+                    quote(vgname),
+                    quote(volume),
+                    fstype,
+                    mib(size)
+                ));
+            }
+            None => {
+                let size = device
+                    .and_then(|device| device["options"]["size"].as_u64())
+                    .unwrap_or_default()
+                    * 512;
+
+                lines.push(format!(
+                    "part {} --fstype={} --size={}",
+                    quote(mountpoint),
+                    fstype,
+                    mib(size)
+                ));
+            }
+        }
+    }
+
+    lines
+}
+
+/// Whether a partition of `type` is one `reqpart` adds.
+fn is_required(kind: &Value) -> bool {
+    kind.as_str().is_some_and(|kind| {
+        REQUIRED_PARTITIONS
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(kind))
+    })
+}
+
+/// The kickstart filesystem type of `vfs_type` mounted on `mountpoint`.
+fn fstype<'a>(mountpoint: &str, vfs_type: &'a str) -> &'a str {
+    match vfs_type {
+        "vfat" if mountpoint == "/boot/efi" => "efi",
+        vfs_type => vfs_type,
+    }
+}
+
+/// `bytes` in MiB, rounded up and at least 1.
+fn mib(bytes: u64) -> u64 {
+    bytes.div_ceil(1024 * 1024).max(1)
+}
+
+#[test]
+fn reverse_manifest() {
+    use std::fs;
+
+    use crate::kickstart::{Includes, Kickstart};
+    use crate::translate::packages::Rpm;
+    use crate::translate::Translator;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-reverse-manifest");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "timezone Europe/Amsterdam --ntpservers=ntp.example\n\
+         bootloader --append=\"quiet\"\n\
+         firstboot --enable\n\
+         part biosboot --size=1\n\
+         part /boot --size=512 --fstype=ext4\n\
+         part pv.01 --size=4096\n\
+         volgroup system pv.01\n\
+         logvol / --vgname=system --name=root --size=3072\n\
+         logvol swap --vgname=system --name=swap --size=512\n\
+         eula --agreed\n\
+         %packages\nvim-enhanced\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (manifest, _) = Translator::new()
+        .arch("x86_64")
+        .depsolved(vec![Rpm {
+            name: "vim-enhanced".to_string(),
+            checksum: format!("sha256:{}", "0".repeat(64)),
+            remote_location: "https://example.com/vim-enhanced-9.1.1-1.fc42.x86_64.rpm".to_string(),
+        }])
+        .translate(&kickstart)
+        .unwrap();
+
+    let manifest: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let (reversed, diagnostics) = reverse(&manifest).unwrap();
+
+    assert_eq!(
+        reversed,
+        "# Reconstructed from an osbuild manifest\n\
+         bootloader --append='console=ttyS0,115200n8 quiet'\n\
+         timezone --utc Europe/Amsterdam\n\
+         timesource --ntp-server=ntp.example\n\
+         services --enabled=initial-setup.service\n\
+         clearpart --all --initlabel --disklabel=gpt\n\
+         reqpart\n\
+         part pv.01 --size=4096\n\
+         volgroup system pv.01\n\
+         logvol / --vgname=system --name=root --fstype=xfs --size=3072\n\
+         logvol swap --vgname=system --name=swap --fstype=swap --size=512\n\
+         part /boot --fstype=ext4 --size=512\n\
+         \n\
+         %packages\n\
+         vim-enhanced\n\
+         %end\n"
+    );
+
+    // The EULA marker is written by a stage no command has.
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, "no-equivalent");
+    assert!(diagnostics[0].message.starts_with("os: org.osbuild."));
+
+    assert_eq!(
+        package_name("python3-libs-3.13.0-1.fc42.x86_64.rpm"),
+        Some("python3-libs".to_string())
+    );
+    assert!(reverse(&serde_json::json!({ "version": "2" })).is_err());
+}
+
+#[test]
+fn reverse_passthrough() {
+    use std::fs;
+
+    use crate::kickstart::{Includes, Kickstart};
+    use crate::translate::Translator;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-reverse-passthrough");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "timezone UTC\n%pre\necho hello\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (manifest, _) = Translator::new()
+        .arch("x86_64")
+        .passthrough(true)
+        .translate(&kickstart)
+        .unwrap();
+
+    let manifest: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let pipeline = manifest["pipelines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|pipeline| pipeline["name"] == "kickstart")
+        .unwrap();
+    let kinds: Vec<_> = pipeline["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| stage["type"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(kinds, ["org.osbuild.mkdir", "org.osbuild.copy"]);
+    assert_eq!(
+        pipeline["stages"][1]["options"]["paths"][0]["to"],
+        "tree:///usr/share/anaconda/interactive-defaults.ks"
+    );
+
+    let (reversed, _) = reverse(&manifest).unwrap();
+
+    assert!(reversed.ends_with("\n%pre\necho hello\n%end\n"));
+}
@@ -0,0 +1,709 @@
+//! TOML is written for blueprints, which image-builder and osbuild-composer read. Only what a
+//! blueprint needs is written: tables and arrays of tables under their own headers, everything
+//! else inline. TOML has no null, keys without a value are left out.
+//!
+//! Blueprints are read back with all of TOML, except that dates and times are read as strings.
+
+use serde_json::{json, Map, Value};
+
+/// The TOML document of the table `value`.
+pub fn to_string(value: &Map<String, Value>) -> String {
+    let mut out = String::new();
+    table(&mut out, "", value);
+    out
+}
+
+fn table(out: &mut String, path: &str, map: &Map<String, Value>) {
+    for (k, value) in map.iter().filter(|(_, value)| is_inline(value)) {
+        out.push_str(&format!("{} = {}\n", key(k), inline(value)));
+    }
+
+    for (k, value) in map {
+        let path = if path.is_empty() {
+            key(k)
+        } else {
+            format!("{}.{}", path, key(k))
+        };
+
+        match value {
+            Value::Object(map) => {
+                // Tables that only hold tables are implied by their headers.
+                if map.is_empty() || map.values().any(is_inline) {
+                    header(out, &format!("[{}]", path));
+                }
+
+                table(out, &path, map);
+            }
+            Value::Array(items) if is_table_array(value) => {
+                for item in items {
+                    header(out, &format!("[[{}]]", path));
+                    table(out, &path, item.as_object().unwrap());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Start a table with `header`, separated from what comes before it by an empty line.
+fn header(out: &mut String, header: &str) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    out.push_str(header);
+    out.push('\n');
+}
+
+/// Whether `value` is written on the line of its key, rather than under a header.
+fn is_inline(value: &Value) -> bool {
+    !value.is_null() && !value.is_object() && !is_table_array(value)
+}
+
+/// Whether `value` is written as an array of tables, which empty arrays aren't.
+fn is_table_array(value: &Value) -> bool {
+    matches!(value, Value::Array(items) if !items.is_empty() && items.iter().all(Value::is_object))
+}
+
+/// `value` on the line of its key. Basic strings take the escapes JSON strings have.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Array(items) => {
+            let items: Vec<_> = items
+                .iter()
+                .filter(|item| !item.is_null())
+                .map(inline)
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let entries: Vec<_> = map
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(k, value)| format!("{} = {}", key(k), inline(value)))
+                .collect();
+
+            if entries.is_empty() {
+                "{}".to_string()
+            } else {
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+        value => value.to_string(),
+    }
+}
+
+/// `k` bare when it can be, quoted otherwise.
+fn key(k: &str) -> String {
+    if !k.is_empty()
+        && k.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        k.to_string()
+    } else {
+        serde_json::to_string(k).unwrap()
+    }
+}
+
+/// The table the TOML document `s` holds.
+pub fn from_str(s: &str) -> Result<Map<String, Value>, String> {
+    let mut parser = Parser {
+        chars: s.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+
+    parser
+        .document()
+        .map_err(|err| format!("line {}: {}", parser.line, err))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+
+        if c == '\n' {
+            self.line += 1;
+        }
+
+        Some(c)
+    }
+
+    /// Skip `n` characters that aren't newlines.
+    fn skip(&mut self, n: usize) {
+        self.pos += n;
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}', found '{}'", expected, c)),
+            None => Err(format!("expected '{}' at the end", expected)),
+        }
+    }
+
+    /// Skip spaces and a comment up to the end of the line.
+    fn space(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+    }
+
+    /// Skip spaces, comments and empty lines.
+    fn blank(&mut self) {
+        loop {
+            self.space();
+
+            match self.peek() {
+                Some('\n' | '\r') => self.next(),
+                _ => return,
+            };
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.space();
+
+        if self.starts_with("\r\n") {
+            self.next();
+        }
+
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(format!("expected the end of the line, found '{}'", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Map<String, Value>, String> {
+        let mut root = Map::new();
+        let mut current = Vec::new();
+
+        loop {
+            self.blank();
+
+            if self.peek().is_none() {
+                return Ok(root);
+            }
+
+            if self.starts_with("[[") {
+                self.skip(2);
+                current = self.key()?;
+                self.expect(']')?;
+                self.expect(']')?;
+
+                let (last, parent) = current.split_last().unwrap();
+                let tables = table_mut(&mut root, parent)?
+                    .entry(last.clone())
+                    .or_insert_with(|| Value::Array(Vec::new()));
+
+                match tables {
+                    Value::Array(items) => items.push(Value::Object(Map::new())),
+                    _ => return Err(format!("'{}' is not an array of tables", last)),
+                }
+            } else if self.peek() == Some('[') {
+                self.next();
+                current = self.key()?;
+                self.expect(']')?;
+                table_mut(&mut root, &current)?;
+            } else {
+                let key = self.key()?;
+                self.expect('=')?;
+                self.space();
+                let value = self.value()?;
+
+                let path: Vec<_> = current.iter().chain(&key).cloned().collect();
+                insert(&mut root, &path, value)?;
+            }
+
+            self.end_of_line()?;
+        }
+    }
+
+    /// A dotted key, its parts can be bare or quoted.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut key = Vec::new();
+
+        loop {
+            self.space();
+
+            let part = match self.peek() {
+                Some('"') => self.basic()?,
+                Some('\'') => self.literal()?,
+                _ => {
+                    let mut part = String::new();
+
+                    while let Some(c) = self
+                        .peek()
+                        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+                    {
+                        part.push(c);
+                        self.next();
+                    }
+
+                    if part.is_empty() {
+                        return Err("expected a key".to_string());
+                    }
+
+                    part
+                }
+            };
+
+            key.push(part);
+            self.space();
+
+            if self.peek() != Some('.') {
+                return Ok(key);
+            }
+
+            self.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        if self.starts_with("\"\"\"") {
+            return self.multiline("\"\"\"", true).map(Value::String);
+        }
+
+        if self.starts_with("'''") {
+            return self.multiline("'''", false).map(Value::String);
+        }
+
+        match self.peek() {
+            Some('"') => self.basic().map(Value::String),
+            Some('\'') => self.literal().map(Value::String),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) => self.scalar(),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+
+        loop {
+            self.blank();
+
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(items));
+            }
+
+            items.push(self.value()?);
+            self.blank();
+
+            match self.next() {
+                Some(',') => {}
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err("expected ',' or ']' in an array".to_string()),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut map = Map::new();
+
+        self.space();
+
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::Object(map));
+        }
+
+        loop {
+            let key = self.key()?;
+            self.expect('=')?;
+            self.space();
+            let value = self.value()?;
+
+            insert(&mut map, &key, value)?;
+            self.space();
+
+            match self.next() {
+                Some(',') => {}
+                Some('}') => return Ok(Value::Object(map)),
+                _ => return Err("expected ',' or '}' in an inline table".to_string()),
+            }
+        }
+    }
+
+    /// Booleans, numbers and dates, which are kept as strings.
+    fn scalar(&mut self) -> Result<Value, String> {
+        let mut token = String::new();
+
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || "_+-.:".contains(*c))
+        {
+            token.push(c);
+            self.next();
+        }
+
+        // A date and a time may be separated by a space.
+        if is_date(&token)
+            && self.peek() == Some(' ')
+            && self
+                .chars
+                .get(self.pos + 1)
+                .is_some_and(char::is_ascii_digit)
+        {
+            self.next();
+            token.push(' ');
+
+            while let Some(c) = self
+                .peek()
+                .filter(|c| c.is_ascii_alphanumeric() || "+-.:".contains(*c))
+            {
+                token.push(c);
+                self.next();
+            }
+        }
+
+        if token == "true" || token == "false" {
+            return Ok(json!(token == "true"));
+        }
+
+        if is_date(&token) || token.contains(':') {
+            return Ok(json!(token));
+        }
+
+        let digits = token.replace('_', "");
+        let (negative, unsigned) = match digits.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, digits.strip_prefix('+').unwrap_or(&digits)),
+        };
+
+        let radix = [("0x", 16), ("0o", 8), ("0b", 2)]
+            .iter()
+            .find_map(|(prefix, radix)| unsigned.strip_prefix(prefix).map(|n| (n, *radix)));
+
+        let value = match radix {
+            Some((number, radix)) => i64::from_str_radix(number, radix)
+                .ok()
+                .map(|n| json!(if negative { -n } else { n })),
+            None => match digits.parse::<i64>() {
+                Ok(n) => Some(json!(n)),
+                Err(_) if unsigned.starts_with(|c: char| c.is_ascii_digit()) => {
+                    digits.parse::<f64>().ok().map(|n| json!(n))
+                }
+                Err(_) => None,
+            },
+        };
+
+        value.ok_or_else(|| format!("'{}' is not a value", token))
+    }
+
+    fn basic(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+
+        loop {
+            match self.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.escape()?),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let mut s = String::new();
+
+        loop {
+            match self.next() {
+                Some('\'') => return Ok(s),
+                Some('\n') | None => return Err("unterminated string".to_string()),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// A multi-line string between `quotes`, escapes are only taken in basic strings. A newline
+    /// right after the opening quotes isn't part of it.
+    fn multiline(&mut self, quotes: &str, basic: bool) -> Result<String, String> {
+        self.skip(3);
+
+        if self.starts_with("\r\n") {
+            self.next();
+        }
+
+        if self.peek() == Some('\n') {
+            self.next();
+        }
+
+        let mut s = String::new();
+
+        loop {
+            // Up to two quotes may come right before the closing ones.
+            if self.starts_with(quotes) && !self.starts_with(&format!("{}{}", quotes, &quotes[..1]))
+            {
+                self.skip(3);
+                return Ok(s);
+            }
+
+            match self.next() {
+                // A backslash at the end of a line trims the whitespace up to the next text.
+                Some('\\') if basic && matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) => {
+                    while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+                        self.next();
+                    }
+                }
+                Some('\\') if basic => s.push(self.escape()?),
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.next() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('e') => '\u{1b}',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(c @ ('u' | 'U')) => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.next()).collect();
+
+                return u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("'\\{}{}' is not a character", c, hex));
+            }
+            Some(c) => return Err(format!("'\\{}' is not an escape", c)),
+            None => return Err("unterminated string".to_string()),
+        };
+
+        Ok(c)
+    }
+}
+
+/// Whether `token` starts with a date, like `1979-05-27`.
+fn is_date(token: &str) -> bool {
+    let bytes = token.as_bytes();
+
+    bytes.len() >= 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && bytes[..10]
+            .iter()
+            .enumerate()
+            .all(|(i, b)| i == 4 || i == 7 || b.is_ascii_digit())
+}
+
+/// The table at `path` below `root`, tables along the way are added. An array of tables stands
+/// for its last table.
+fn table_mut<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+) -> Result<&'a mut Map<String, Value>, String> {
+    let mut map = root;
+
+    for key in path {
+        let mut value = map
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+
+        if let Value::Array(items) = value {
+            value = items
+                .last_mut()
+                .ok_or_else(|| format!("'{}' is not a table", key))?;
+        }
+
+        map = value
+            .as_object_mut()
+            .ok_or_else(|| format!("'{}' is not a table", key))?;
+    }
+
+    Ok(map)
+}
+
+/// Set the dotted key `path` below `root` to `value`, keys can't be given twice.
+fn insert(root: &mut Map<String, Value>, path: &[String], value: Value) -> Result<(), String> {
+    let (last, parent) = path.split_last().unwrap();
+
+    match table_mut(root, parent)?.insert(last.clone(), value) {
+        Some(_) => Err(format!("'{}' is given twice", last)),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn toml_to_string() {
+    use serde_json::json;
+
+    let value = json!({
+        "name": "web",
+        "version": "0.0.1",
+        "packages": [{ "name": "vim", "version": "*" }, { "name": "nginx", "version": "*" }],
+        "groups": [],
+        "customizations": {
+            "hostname": "web.example",
+            "description": null,
+            "kernel": { "append": "console=ttyS0 \"quoted\"" },
+            "user": [{ "name": "admin", "groups": ["wheel"], "uid": 1000 }],
+            "firewall": { "ports": ["22:tcp"], "services": { "enabled": ["http"] } },
+            "locale": { "keyboard": { "layout": "us" } },
+            "dotted.key": [{ "a": 1 }, { "b": [1, { "c": true }] }],
+        },
+    });
+
+    // Keys are sorted by serde_json.
+    assert_eq!(
+        to_string(value.as_object().unwrap()),
+        "groups = []\n\
+         name = \"web\"\n\
+         version = \"0.0.1\"\n\
+         \n\
+         [customizations]\n\
+         hostname = \"web.example\"\n\
+         \n\
+         [[customizations.\"dotted.key\"]]\n\
+         a = 1\n\
+         \n\
+         [[customizations.\"dotted.key\"]]\n\
+         b = [1, { c = true }]\n\
+         \n\
+         [customizations.firewall]\n\
+         ports = [\"22:tcp\"]\n\
+         \n\
+         [customizations.firewall.services]\n\
+         enabled = [\"http\"]\n\
+         \n\
+         [customizations.kernel]\n\
+         append = \"console=ttyS0 \\\"quoted\\\"\"\n\
+         \n\
+         [customizations.locale.keyboard]\n\
+         layout = \"us\"\n\
+         \n\
+         [[customizations.user]]\n\
+         groups = [\"wheel\"]\n\
+         name = \"admin\"\n\
+         uid = 1000\n\
+         \n\
+         [[packages]]\n\
+         name = \"vim\"\n\
+         version = \"*\"\n\
+         \n\
+         [[packages]]\n\
+         name = \"nginx\"\n\
+         version = \"*\"\n"
+    );
+}
+
+#[test]
+fn toml_from_str() {
+    let document = r#"
+# A blueprint.
+name = "web"   # named after the kickstart
+"description" = 'C:\path'
+version = "0.0.1"
+distro.name = "fedora-42"
+numbers = [1, -2, +3, 0x1f, 1_000, 1.5, 5e2, true, 1979-05-27, 1979-05-27 07:32:00Z]
+multi = """
+line \
+  joined\tand "quoted" \u00e9
+"""
+raw = '''
+C:\path'''
+
+[[packages]]
+name = "vim"
+
+[[packages]]
+name = "nginx"
+version = "*"
+
+[customizations.kernel]
+append = "console=ttyS0"
+
+[[customizations.user]]
+name = "admin"
+groups = [
+    "wheel",  # trailing comma
+]
+extra = { home = "/home/admin", ids.uid = 1000 }
+
+[customizations]
+hostname = "web.example"
+"#;
+
+    assert_eq!(
+        Value::Object(from_str(document).unwrap()),
+        json!({
+            "name": "web",
+            "description": "C:\\path",
+            "version": "0.0.1",
+            "distro": { "name": "fedora-42" },
+            "numbers": [
+                1, -2, 3, 31, 1000, 1.5, 500.0, true, "1979-05-27", "1979-05-27 07:32:00Z",
+            ],
+            "multi": "line joined\tand \"quoted\" \u{e9}\n",
+            "raw": "C:\\path",
+            "packages": [{ "name": "vim" }, { "name": "nginx", "version": "*" }],
+            "customizations": {
+                "hostname": "web.example",
+                "kernel": { "append": "console=ttyS0" },
+                "user": [{
+                    "name": "admin",
+                    "groups": ["wheel"],
+                    "extra": { "home": "/home/admin", "ids": { "uid": 1000 } },
+                }],
+            },
+        })
+    );
+
+    // What is written reads back the same.
+    let written = json!({
+        "name": "web",
+        "packages": [{ "name": "vim", "version": "*" }],
+        "customizations": {
+            "user": [{ "name": "a", "password": "$6$\"x\\" }],
+            "locale": { "keyboard": { "layout": "us" } },
+        },
+    });
+    let written = written.as_object().unwrap();
+    assert_eq!(&from_str(&to_string(written)).unwrap(), written);
+
+    assert_eq!(
+        from_str("a = 1\na = 2\n"),
+        Err("line 2: 'a' is given twice".to_string())
+    );
+    assert_eq!(
+        from_str("a = \"open\n"),
+        Err("line 2: unterminated string".to_string())
+    );
+    assert!(from_str("a = 1 b = 2").is_err());
+    assert!(from_str("a = nope").is_err());
+}
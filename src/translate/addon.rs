@@ -0,0 +1,135 @@
+//! Parsing of `%addon` sections. Addons are named by their first argument and carry their own
+//! options, the body of the section is addon specific.
+
+use super::unquote;
+
+/// The `com_redhat_kdump` addon configures the crash kernel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kdump {
+    pub enabled: bool,
+    /// Amount of memory to reserve in MiB, or `auto`.
+    pub reserve_mb: String,
+}
+
+impl Kdump {
+    pub fn from_args(args: &[String]) -> Self {
+        let mut kdump = Self {
+            enabled: false,
+            reserve_mb: "auto".to_string(),
+        };
+
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match arg.split_once('=') {
+                Some(("--reserve-mb", value)) => kdump.reserve_mb = unquote(value),
+                _ => match arg.as_str() {
+                    "--enable" | "--enablefadump" => kdump.enabled = true,
+                    "--disable" => kdump.enabled = false,
+                    "--reserve-mb" => {
+                        if let Some(value) = args.next() {
+                            kdump.reserve_mb = unquote(value);
+                        }
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        kdump
+    }
+
+    /// The `crashkernel` kernel argument for the reservation. Kernels no longer take
+    /// `crashkernel=auto`, `auto` is whatever the distribution reserves by default and has none.
+    pub fn crashkernel(&self) -> Option<String> {
+        match self.reserve_mb.as_str() {
+            "auto" => None,
+            reserve_mb => Some(format!("crashkernel={}M", reserve_mb)),
+        }
+    }
+}
+
+/// The `org_fedora_oscap` addon, also known as `com_redhat_oscap`, applies a security profile.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Oscap {
+    pub content_type: String,
+    pub content_url: Option<String>,
+    pub content_path: Option<String>,
+    pub xccdf_id: Option<String>,
+    pub tailoring_path: Option<String>,
+    pub profile: Option<String>,
+}
+
+impl Oscap {
+    /// Parse the `key = value` lines of the section body.
+    pub fn from_data(data: &str) -> Self {
+        let mut oscap = Self::default();
+
+        for line in data.lines() {
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+                None => continue,
+            };
+
+            match key {
+                "content-type" => oscap.content_type = value,
+                "content-url" => oscap.content_url = Some(value),
+                "content-path" => oscap.content_path = Some(value),
+                "xccdf-id" => oscap.xccdf_id = Some(value),
+                "tailoring-path" => oscap.tailoring_path = Some(value),
+                "profile" => oscap.profile = Some(value),
+                _ => {}
+            }
+        }
+
+        oscap
+    }
+
+    /// The path to the datastream inside the tree, if it is known without fetching content. The
+    /// SCAP Security Guide has a datastream for each distribution, `guide` is that of the one
+    /// being built.
+    pub fn datastream(&self, guide: Option<&str>) -> Option<String> {
+        match self.content_type.as_str() {
+            "scap-security-guide" => guide.map(str::to_string),
+            "datastream" if self.content_url.is_none() => self.content_path.clone(),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn addon_kdump() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    let kdump = Kdump::from_args(&args("com_redhat_kdump --enable --reserve-mb='auto'"));
+    assert!(kdump.enabled);
+    assert_eq!(kdump.crashkernel(), None);
+
+    let kdump = Kdump::from_args(&args("com_redhat_kdump --enable --reserve-mb 256"));
+    assert_eq!(kdump.crashkernel().as_deref(), Some("crashkernel=256M"));
+
+    assert!(!Kdump::from_args(&args("com_redhat_kdump --disable")).enabled);
+}
+
+#[test]
+fn addon_oscap() {
+    let oscap = Oscap::from_data(
+        "    content-type = scap-security-guide\n    profile = xccdf_org.ssgproject.content_profile_cis\n",
+    );
+
+    assert_eq!(
+        oscap.profile.as_deref(),
+        Some("xccdf_org.ssgproject.content_profile_cis")
+    );
+    assert_eq!(
+        oscap
+            .datastream(Some("/usr/share/xml/scap/ssg/content/ssg-rhel10-ds.xml"))
+            .as_deref(),
+        Some("/usr/share/xml/scap/ssg/content/ssg-rhel10-ds.xml")
+    );
+    assert_eq!(oscap.datastream(None), None);
+
+    let oscap =
+        Oscap::from_data("content-type = datastream\ncontent-url = https://example.com/ds.xml\n");
+    assert_eq!(oscap.datastream(None), None);
+}
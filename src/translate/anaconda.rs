@@ -0,0 +1,107 @@
+//! The `%anaconda` section configures the installer itself. The only command it knows about is
+//! `pwpolicy`, which decides what passwords the installer accepts. None of it has an equivalent
+//! in an image, but we do validate it so mistakes don't go unnoticed.
+
+/// A password policy as set by `pwpolicy <name> [options]`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PwPolicy {
+    pub name: String,
+    pub options: Vec<String>,
+}
+
+const PWPOLICY_NAMES: &[&str] = &["root", "user", "luks"];
+const PWPOLICY_FLAGS: &[&str] = &[
+    "--strict",
+    "--notstrict",
+    "--emptyok",
+    "--notempty",
+    "--changesok",
+    "--nochanges",
+];
+const PWPOLICY_VALUES: &[&str] = &["--minlen", "--minquality"];
+
+/// A command inside of `%anaconda`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    PwPolicy(PwPolicy),
+    Unknown(String),
+}
+
+/// Parse the body of an `%anaconda` section into its commands.
+pub fn parse(data: &str) -> Vec<Command> {
+    data.lines()
+        .map(str::split_whitespace)
+        .filter_map(|mut parts| {
+            let name = parts.next()?;
+
+            Some(match name {
+                "pwpolicy" => Command::PwPolicy(PwPolicy {
+                    name: parts.next().unwrap_or_default().to_string(),
+                    options: parts.map(str::to_string).collect(),
+                }),
+                _ => Command::Unknown(name.to_string()),
+            })
+        })
+        .collect()
+}
+
+impl PwPolicy {
+    /// Problems with this policy, an empty list means the policy is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if !PWPOLICY_NAMES.contains(&self.name.as_str()) {
+            problems.push(format!(
+                "pwpolicy name '{}' is not one of {}",
+                self.name,
+                PWPOLICY_NAMES.join(", ")
+            ));
+        }
+
+        let mut options = self.options.iter();
+
+        while let Some(option) = options.next() {
+            let (option, value) = match option.split_once('=') {
+                Some((option, value)) => (option, Some(value.to_string())),
+                None => (option.as_str(), None),
+            };
+
+            if PWPOLICY_FLAGS.contains(&option) {
+                continue;
+            }
+
+            if !PWPOLICY_VALUES.contains(&option) {
+                problems.push(format!("pwpolicy option '{}' is not known", option));
+                continue;
+            }
+
+            match value.or_else(|| options.next().cloned()) {
+                Some(value) if value.parse::<u32>().is_ok() => {}
+                Some(value) => problems.push(format!(
+                    "pwpolicy option '{}' needs a number, not '{}'",
+                    option, value
+                )),
+                None => problems.push(format!("pwpolicy option '{}' needs a value", option)),
+            }
+        }
+
+        problems
+    }
+}
+
+#[test]
+fn anaconda_pwpolicy() {
+    let commands = parse("pwpolicy root --minlen=6 --strict\npwpolicy admin --minquality x\nfoo\n");
+
+    let policies: Vec<&PwPolicy> = commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::PwPolicy(policy) => Some(policy),
+            Command::Unknown(_) => None,
+        })
+        .collect();
+
+    assert!(policies[0].validate().is_empty());
+    assert_eq!(policies[1].validate().len(), 2);
+    assert_eq!(commands[2], Command::Unknown("foo".to_string()));
+}
@@ -0,0 +1,142 @@
+//! Commands that attach storage to the installer: iSCSI, FCoE, zFCP and NVDIMM devices. The
+//! installer uses them to reach the disks it installs to, an image is built without any of those
+//! so these are parsed to tell what they were for and then left out.
+
+use super::options::Options;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Attach {
+    /// `iscsi --ipaddr=<address> [--port=<port>] [--target=<iqn>]`
+    Iscsi {
+        address: String,
+        port: Option<String>,
+        target: Option<String>,
+    },
+    /// `iscsiname <iqn>`
+    IscsiName(String),
+    /// `fcoe --nic=<nic> [--dcb] [--autovlan]`
+    Fcoe(String),
+    /// `zfcp --devnum=<devnum> [--wwpn=<wwpn> --fcplun=<lun>]`
+    Zfcp { devnum: String, lun: Option<String> },
+    /// `nvdimm reconfigure|use [--namespace=<ns>] [--blockdevs=<devs>]`
+    Nvdimm {
+        action: String,
+        namespace: Option<String>,
+    },
+}
+
+impl Attach {
+    /// Parse one of the attach commands, `name` is the command.
+    pub fn from_args(name: &str, args: &[String]) -> Result<Self, String> {
+        let (values, flags): (&[&str], &[&str]) = match name {
+            "iscsi" => (
+                &[
+                    "--ipaddr",
+                    "--port",
+                    "--target",
+                    "--iface",
+                    "--user",
+                    "--password",
+                    "--reverse-user",
+                    "--reverse-password",
+                ],
+                &[],
+            ),
+            "iscsiname" => (&[], &[]),
+            "fcoe" => (&["--nic"], &["--dcb", "--autovlan"]),
+            "zfcp" => (&["--devnum", "--wwpn", "--fcplun"], &[]),
+            "nvdimm" => (
+                &["--namespace", "--mode", "--sectorsize", "--blockdevs"],
+                &[],
+            ),
+            name => return Err(format!("'{}' doesn't attach storage", name)),
+        };
+
+        let options = Options::parse_known(args, values, flags)?;
+        let value = |option| options.value(option).map(str::to_string);
+
+        match name {
+            "iscsi" => Ok(Attach::Iscsi {
+                address: value("--ipaddr").ok_or("needs --ipaddr")?,
+                port: value("--port"),
+                target: value("--target"),
+            }),
+            "iscsiname" => match options.positional() {
+                [iqn] => Ok(Attach::IscsiName(iqn.clone())),
+                _ => Err("needs a single iSCSI name".to_string()),
+            },
+            "fcoe" => Ok(Attach::Fcoe(value("--nic").ok_or("needs --nic")?)),
+            "zfcp" => {
+                let devnum = value("--devnum").ok_or("needs --devnum")?;
+
+                let lun = match (value("--wwpn"), value("--fcplun")) {
+                    (Some(wwpn), Some(lun)) => Some(format!("{}:{}", wwpn, lun)),
+                    (None, None) => None,
+                    _ => return Err("--wwpn and --fcplun go together".to_string()),
+                };
+
+                Ok(Attach::Zfcp { devnum, lun })
+            }
+            _ => match options.positional().first().map(String::as_str) {
+                Some(action @ ("reconfigure" | "use")) => Ok(Attach::Nvdimm {
+                    action: action.to_string(),
+                    namespace: value("--namespace"),
+                }),
+                Some(action) => Err(format!("unknown action '{}'", action)),
+                None => Err("needs an action".to_string()),
+            },
+        }
+    }
+
+    /// What is attached, for the report.
+    pub fn describe(&self) -> String {
+        match self {
+            Attach::Iscsi {
+                address,
+                port,
+                target,
+            } => format!(
+                "iSCSI target {} at {}:{}",
+                target.as_deref().unwrap_or("(any)"),
+                address,
+                port.as_deref().unwrap_or("3260")
+            ),
+            Attach::IscsiName(iqn) => format!("iSCSI initiator name {}", iqn),
+            Attach::Fcoe(nic) => format!("FCoE on {}", nic),
+            Attach::Zfcp { devnum, lun } => match lun {
+                Some(lun) => format!("zFCP LUN {} on {}", lun, devnum),
+                None => format!("zFCP device {}", devnum),
+            },
+            Attach::Nvdimm { action, namespace } => format!(
+                "NVDIMM {} of namespace {}",
+                action,
+                namespace.as_deref().unwrap_or("(any)")
+            ),
+        }
+    }
+}
+
+#[test]
+fn attach_parse() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert_eq!(
+        Attach::from_args(
+            "iscsi",
+            &args("--ipaddr=10.0.0.1 --target iqn.2024-01.example:disk")
+        )
+        .unwrap()
+        .describe(),
+        "iSCSI target iqn.2024-01.example:disk at 10.0.0.1:3260"
+    );
+    assert_eq!(
+        Attach::from_args("zfcp", &args("--devnum=0.0.4000")).unwrap(),
+        Attach::Zfcp {
+            devnum: "0.0.4000".to_string(),
+            lun: None
+        }
+    );
+    assert!(Attach::from_args("zfcp", &args("--devnum=0.0.4000 --wwpn=0x5005")).is_err());
+    assert!(Attach::from_args("iscsi", &args("--port=3260")).is_err());
+    assert!(Attach::from_args("nvdimm", &args("destroy")).is_err());
+}
@@ -0,0 +1,137 @@
+//! Authentication configuration through `authselect`, and the legacy `auth`/`authconfig`
+//! commands it replaced. The legacy commands took a long list of toggles; only the ones that
+//! have an `authselect` profile or feature equivalent can be mapped.
+
+/// An `authselect` profile with its enabled features.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Authselect {
+    pub profile: String,
+    pub features: Vec<String>,
+}
+
+impl Authselect {
+    /// Parse the arguments to `authselect`, which are passed on to the `authselect` tool. We can
+    /// only translate `authselect select <profile> [features]`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter().filter(|arg| !arg.starts_with('-'));
+
+        match args.next().map(String::as_str) {
+            Some("select") => {}
+            Some(command) => return Err(format!("authselect '{}' is not translated", command)),
+            None => return Err("authselect needs a command".to_string()),
+        }
+
+        let profile = match args.next() {
+            Some(profile) => profile.to_string(),
+            None => return Err("authselect select needs a profile".to_string()),
+        };
+
+        Ok(Self {
+            profile,
+            features: args.cloned().collect(),
+        })
+    }
+
+    /// Map the options of the legacy `auth` and `authconfig` commands onto a profile, options
+    /// that only enable features enable them in the `default` profile of the distribution.
+    /// Returns `None` if the options only describe the default configuration, besides any
+    /// options that could not be mapped. Without a `default` the features can't be enabled in
+    /// anything, their options are left unmapped.
+    pub fn from_authconfig(args: &[String], default: Option<&str>) -> (Option<Self>, Vec<String>) {
+        let mut profile = None;
+        let mut features = Vec::new();
+        let mut unmapped = Vec::new();
+
+        for arg in args {
+            let (option, value) = match arg.split_once('=') {
+                Some((option, value)) => (option, Some(value)),
+                None => (arg.as_str(), None),
+            };
+
+            match option {
+                "--enablesssd" | "--enablesssdauth" | "--enableldap" | "--enableldapauth"
+                | "--enablekrb5" => profile = Some("sssd"),
+                "--enablewinbind" | "--enablewinbindauth" => profile = Some("winbind"),
+                "--enablemkhomedir" => features.push(("with-mkhomedir", arg)),
+                "--enablefaillock" => features.push(("with-faillock", arg)),
+                "--enablefingerprint" => features.push(("with-fingerprint", arg)),
+                "--enablesmartcard" => features.push(("with-smartcard", arg)),
+                // Shadow passwords hashed with SHA-512 are what every profile does.
+                "--enableshadow" | "--useshadow" => {}
+                "--passalgo" if value == Some("sha512") => {}
+                _ => unmapped.push(arg.clone()),
+            }
+        }
+
+        if profile.is_none() && features.is_empty() {
+            return (None, unmapped);
+        }
+
+        let profile = match profile.or(default) {
+            Some(profile) => profile,
+            None => {
+                unmapped.extend(features.into_iter().map(|(_, arg)| arg.clone()));
+                return (None, unmapped);
+            }
+        };
+
+        let authselect = Self {
+            profile: profile.to_string(),
+            features: features
+                .into_iter()
+                .map(|(feature, _)| feature.to_string())
+                .collect(),
+        };
+
+        (Some(authselect), unmapped)
+    }
+}
+
+#[test]
+fn auth_authselect() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert_eq!(
+        Authselect::from_args(&args("select sssd with-mkhomedir --force")),
+        Ok(Authselect {
+            profile: "sssd".to_string(),
+            features: vec!["with-mkhomedir".to_string()],
+        })
+    );
+    assert!(Authselect::from_args(&args("enable-feature with-sudo")).is_err());
+}
+
+#[test]
+fn auth_authconfig() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert_eq!(
+        Authselect::from_authconfig(&args("--enableshadow --passalgo=sha512"), Some("local")),
+        (None, vec![])
+    );
+
+    let (authselect, unmapped) = Authselect::from_authconfig(
+        &args("--enableldap --enablemkhomedir --ldapserver=ldap://x"),
+        Some("local"),
+    );
+    assert_eq!(
+        authselect,
+        Some(Authselect {
+            profile: "sssd".to_string(),
+            features: vec!["with-mkhomedir".to_string()],
+        })
+    );
+    assert_eq!(unmapped, vec!["--ldapserver=ldap://x".to_string()]);
+
+    assert_eq!(
+        Authselect::from_authconfig(&args("--enablefaillock"), Some("local")).0,
+        Some(Authselect {
+            profile: "local".to_string(),
+            features: vec!["with-faillock".to_string()],
+        })
+    );
+    assert_eq!(
+        Authselect::from_authconfig(&args("--enablefaillock"), None),
+        (None, vec!["--enablefaillock".to_string()])
+    );
+}
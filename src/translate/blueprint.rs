@@ -0,0 +1,952 @@
+//! Blueprints describe an image to image-builder and osbuild-composer as packages and
+//! customizations of an image type. The commands they have customizations for, such as `user` or
+//! `firewall`, are only translated for blueprints, manifests have no stages for them yet.
+//!
+//! Blueprints are also translated back into kickstarts, for moving between the two.
+
+use log::*;
+use serde_json::{json, Map, Value};
+
+use super::options::Options;
+use super::report::Outcome;
+use super::words::quote;
+use super::{Report, TranslateError, Translator};
+use crate::kickstart::Kickstart;
+
+/// The version blueprints are given, image-builder bumps it when a blueprint is changed.
+const VERSION: &str = "0.0.1";
+
+/// What the commands that are only translated for blueprints customize.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Customizations {
+    /// The users in the form of the blueprint, with their `password` and SSH `key`.
+    pub users: Vec<Map<String, Value>>,
+    pub groups: Vec<Value>,
+    pub enabled_services: Vec<String>,
+    pub disabled_services: Vec<String>,
+    pub ports: Vec<String>,
+    pub enabled_firewall: Vec<String>,
+    pub disabled_firewall: Vec<String>,
+    pub languages: Vec<String>,
+    pub keyboard: Option<String>,
+    pub hostname: Option<String>,
+}
+
+impl Customizations {
+    /// Take in the command `name`, none if it isn't one blueprints customize.
+    pub fn command(&mut self, name: &str, args: &[String]) -> Option<Outcome> {
+        let result = match name {
+            "user" => self.user(args),
+            "rootpw" => self.rootpw(args),
+            "group" => self.group(args),
+            "sshkey" => self.sshkey(args),
+            "services" => self.services(args),
+            "firewall" => self.firewall(args),
+            "lang" => self.lang(args),
+            "keyboard" => self.keyboard(args),
+            "network" => self.network(args),
+            _ => return None,
+        };
+
+        Some(match result {
+            Ok(()) => Outcome::Translated,
+            Err(err) => Outcome::Untranslated(err),
+        })
+    }
+
+    /// The user called `name`, which is added if there isn't one yet.
+    fn named(&mut self, name: &str) -> &mut Map<String, Value> {
+        let index = match self.users.iter().position(|user| user["name"] == name) {
+            Some(index) => index,
+            None => {
+                let mut user = Map::new();
+                user.insert("name".to_string(), json!(name));
+                self.users.push(user);
+                self.users.len() - 1
+            }
+        };
+
+        &mut self.users[index]
+    }
+
+    /// `user --name=<name> [--password=<pw> [--iscrypted]] [--groups=<a,b>] [--homedir=<dir>]
+    /// [--shell=<shell>] [--uid=<uid>] [--gid=<gid>] [--gecos=<gecos>]`
+    fn user(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(
+            args,
+            &[
+                "--name",
+                "--password",
+                "--groups",
+                "--homedir",
+                "--shell",
+                "--uid",
+                "--gid",
+                "--gecos",
+            ],
+            &["--iscrypted", "--plaintext", "--lock"],
+        )?;
+
+        let name = options.value("--name").ok_or("needs --name")?;
+        let uid = number(&options, "--uid")?;
+        let gid = number(&options, "--gid")?;
+        let user = self.named(name);
+
+        // Blueprints hash passwords that aren't, locked users have none.
+        if let Some(password) = options
+            .value("--password")
+            .filter(|_| !options.flag("--lock"))
+        {
+            user.insert("password".to_string(), json!(password));
+        }
+
+        if let Some(groups) = options.value("--groups") {
+            user.insert("groups".to_string(), json!(list(groups)));
+        }
+
+        for (option, key) in [
+            ("--homedir", "home"),
+            ("--shell", "shell"),
+            ("--gecos", "description"),
+        ] {
+            if let Some(value) = options.value(option) {
+                user.insert(key.to_string(), json!(value));
+            }
+        }
+
+        if let Some(uid) = uid {
+            user.insert("uid".to_string(), json!(uid));
+        }
+
+        if let Some(gid) = gid {
+            user.insert("gid".to_string(), json!(gid));
+        }
+
+        Ok(())
+    }
+
+    /// `rootpw [--iscrypted|--plaintext] [--lock] [--allow-ssh] <password>`
+    fn rootpw(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(
+            args,
+            &[],
+            &["--iscrypted", "--plaintext", "--lock", "--allow-ssh"],
+        )?;
+
+        if options.flag("--lock") {
+            return Ok(());
+        }
+
+        let password = options.positional().first().ok_or("needs a password")?;
+        self.named("root")
+            .insert("password".to_string(), json!(password));
+
+        Ok(())
+    }
+
+    /// `group --name=<name> [--gid=<gid>]`
+    fn group(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(args, &["--name", "--gid"], &[])?;
+        let name = options.value("--name").ok_or("needs --name")?;
+        let mut group = json!({ "name": name });
+
+        if let Some(gid) = number(&options, "--gid")? {
+            group["gid"] = json!(gid);
+        }
+
+        self.groups.push(group);
+
+        Ok(())
+    }
+
+    /// `sshkey --username=<name> <key>`
+    fn sshkey(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(args, &["--username"], &[])?;
+        let name = options.value("--username").ok_or("needs --username")?;
+        let key = options.positional().first().ok_or("needs a key")?;
+
+        self.named(name).insert("key".to_string(), json!(key));
+
+        Ok(())
+    }
+
+    /// `services [--enabled=<a,b>] [--disabled=<a,b>]`
+    fn services(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(args, &["--enabled", "--disabled"], &[])?;
+
+        for enabled in options.values("--enabled") {
+            self.enabled_services.extend(list(enabled));
+        }
+
+        for disabled in options.values("--disabled") {
+            self.disabled_services.extend(list(disabled));
+        }
+
+        Ok(())
+    }
+
+    /// `firewall [--enabled] [--port=<port:proto,..>] [--service=<a,b>] [--remove-service=<a>]`,
+    /// blueprints can't turn the firewall off.
+    fn firewall(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(
+            args,
+            &["--port", "--service", "--remove-service"],
+            &["--enabled", "--enable", "--ssh", "--use-system-defaults"],
+        )?;
+
+        for port in options.values("--port") {
+            self.ports.extend(list(port));
+        }
+
+        for service in options.values("--service") {
+            self.enabled_firewall.extend(list(service));
+        }
+
+        if options.flag("--ssh") {
+            self.enabled_firewall.push("ssh".to_string());
+        }
+
+        for service in options.values("--remove-service") {
+            self.disabled_firewall.extend(list(service));
+        }
+
+        Ok(())
+    }
+
+    /// `lang <lang> [--addsupport=<a,b>]`
+    fn lang(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(args, &["--addsupport"], &[])?;
+        let lang = options.positional().first().ok_or("needs a language")?;
+
+        self.languages.push(lang.clone());
+
+        for support in options.values("--addsupport") {
+            self.languages.extend(list(support));
+        }
+
+        Ok(())
+    }
+
+    /// `keyboard [--vckeymap=<map>] [--xlayouts=<a,b>] [<map>]`, the console keymap is what a
+    /// blueprint sets.
+    fn keyboard(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(args, &["--vckeymap", "--xlayouts", "--switch"], &[])?;
+
+        let keymap = options
+            .value("--vckeymap")
+            .map(str::to_string)
+            .or_else(|| options.positional().first().cloned())
+            .or_else(|| {
+                options
+                    .value("--xlayouts")
+                    .and_then(|x| list(x).into_iter().next())
+            })
+            .ok_or("needs a keymap")?;
+
+        self.keyboard = Some(keymap);
+
+        Ok(())
+    }
+
+    /// `network --hostname=<name>`, the rest of the network is configured when the image boots.
+    fn network(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse(args, &["--hostname"])?;
+
+        match options.value("--hostname") {
+            Some(hostname) if options.names().count() == 1 => {
+                self.hostname = Some(hostname.to_string());
+                Ok(())
+            }
+            _ => Err("only the hostname of `network` is a customization".to_string()),
+        }
+    }
+}
+
+impl Translator {
+    /// The blueprint called `name` for `kickstart`.
+    pub fn blueprint(
+        mut self,
+        kickstart: &Kickstart,
+        name: &str,
+    ) -> Result<(Map<String, Value>, Report), TranslateError> {
+        self.customizations = Some(Customizations::default());
+
+        let layout = self.read(kickstart)?;
+        let customized = self.customizations.take().unwrap_or_default();
+
+        let mut blueprint = Map::new();
+        blueprint.insert("name".to_string(), json!(name));
+        blueprint.insert(
+            "description".to_string(),
+            json!(format!("Translated from {}", name)),
+        );
+        blueprint.insert("version".to_string(), json!(VERSION));
+
+        let packages: Vec<_> = self
+            .packages
+            .include
+            .iter()
+            .map(|package| json!({ "name": package, "version": "*" }))
+            .collect();
+        blueprint.insert("packages".to_string(), json!(packages));
+
+        let mut groups = Vec::new();
+
+        for group in &self.packages.groups {
+            match group.strip_prefix('@') {
+                Some(environment) if environment.starts_with('^') => {
+                    warn!(
+                        "blueprint: environments aren't groups, leaving out '{}'",
+                        group
+                    )
+                }
+                Some(group) => groups.push(json!({ "name": group })),
+                None => {}
+            }
+        }
+
+        blueprint.insert("groups".to_string(), json!(groups));
+
+        let mut customizations = Map::new();
+
+        if let Some(hostname) = &customized.hostname {
+            customizations.insert("hostname".to_string(), json!(hostname));
+        }
+
+        if !self.kernel_opts.is_empty() {
+            customizations.insert(
+                "kernel".to_string(),
+                json!({ "append": self.kernel_opts.join(" ") }),
+            );
+        }
+
+        if !customized.users.is_empty() {
+            customizations.insert("user".to_string(), json!(customized.users));
+        }
+
+        if !customized.groups.is_empty() {
+            customizations.insert("group".to_string(), json!(customized.groups));
+        }
+
+        let mut timezone = Map::new();
+
+        if let Some(zone) = &self.time.zone {
+            timezone.insert("timezone".to_string(), json!(zone));
+        }
+
+        let ntpservers = self.time.timeservers();
+
+        if !ntpservers.is_empty() && !self.time.ntp_disabled {
+            timezone.insert("ntpservers".to_string(), json!(ntpservers));
+        }
+
+        if !timezone.is_empty() {
+            customizations.insert("timezone".to_string(), Value::Object(timezone));
+        }
+
+        let mut locale = Map::new();
+
+        if !customized.languages.is_empty() {
+            locale.insert("languages".to_string(), json!(customized.languages));
+        }
+
+        if let Some(keyboard) = &customized.keyboard {
+            locale.insert("keyboard".to_string(), json!(keyboard));
+        }
+
+        if !locale.is_empty() {
+            customizations.insert("locale".to_string(), Value::Object(locale));
+        }
+
+        let mut firewall = Map::new();
+
+        if !customized.ports.is_empty() {
+            firewall.insert("ports".to_string(), json!(customized.ports));
+        }
+
+        if let Some(services) =
+            services(&customized.enabled_firewall, &customized.disabled_firewall)
+        {
+            firewall.insert("services".to_string(), services);
+        }
+
+        if !firewall.is_empty() {
+            customizations.insert("firewall".to_string(), Value::Object(firewall));
+        }
+
+        let mut enabled = self.enabled_services.clone();
+        enabled.extend(customized.enabled_services.iter().cloned());
+        let mut disabled = self.disabled_services.clone();
+        disabled.extend(customized.disabled_services.iter().cloned());
+
+        if let Some(services) = services(&enabled, &disabled) {
+            customizations.insert("services".to_string(), services);
+        }
+
+        // The image type lays out the disk, only the sizes of the mountpoints can be asked for.
+        if let Some(layout) = &layout {
+            let filesystems: Vec<_> = layout
+                .filesystems
+                .iter()
+                .filter(|filesystem| filesystem.volume.is_mounted())
+                .map(|filesystem| {
+                    json!({
+                        "mountpoint": filesystem.volume.mountpoint,
+                        "minsize": filesystem.volume.size * 1024 * 1024,
+                    })
+                })
+                .collect();
+
+            if !filesystems.is_empty() {
+                customizations.insert("filesystem".to_string(), json!(filesystems));
+            }
+        }
+
+        if !self.packages.exclude.is_empty() {
+            warn!("blueprint: packages can't be excluded, leaving out the excludes");
+        }
+
+        if !customizations.is_empty() {
+            blueprint.insert("customizations".to_string(), Value::Object(customizations));
+        }
+
+        Ok((blueprint, self.report))
+    }
+}
+
+/// The kickstart that installs what `blueprint` describes, what it has no commands for is left
+/// out with a warning.
+pub fn to_kickstart(blueprint: &Map<String, Value>) -> Result<String, String> {
+    let mut lines = Vec::new();
+
+    if let Some(name) = blueprint.get("name").and_then(Value::as_str) {
+        lines.push(format!("# Translated from the blueprint '{}'", name));
+    }
+
+    let customizations = match blueprint.get("customizations") {
+        Some(Value::Object(customizations)) => customizations.clone(),
+        Some(_) => return Err("customizations is not a table".to_string()),
+        None => Map::new(),
+    };
+
+    for (key, value) in &customizations {
+        let found = match key.as_str() {
+            "hostname" => value
+                .as_str()
+                .map(|hostname| vec![format!("network --hostname={}", quote(hostname))]),
+            "kernel" => value["append"]
+                .as_str()
+                .map(|append| vec![format!("bootloader --append={}", quote(append))]),
+            "user" => users(value),
+            "group" => tables(value, |group| {
+                let mut line = format!("group --name={}", quote(string(group, "name")?));
+
+                if let Some(gid) = group.get("gid").and_then(Value::as_u64) {
+                    line.push_str(&format!(" --gid={}", gid));
+                }
+
+                Some(vec![line])
+            }),
+            "sshkey" => tables(value, |sshkey| {
+                Some(vec![format!(
+                    "sshkey --username={} {}",
+                    quote(string(sshkey, "user")?),
+                    quote(string(sshkey, "key")?)
+                )])
+            }),
+            "timezone" => Some(timezone(value)),
+            "locale" => Some(locale(value)),
+            "firewall" => Some(firewall(value)),
+            "services" => {
+                let mut line = "services".to_string();
+
+                for state in ["enabled", "disabled"] {
+                    if let Some(services) = strings(&value[state]).filter(|s| !s.is_empty()) {
+                        line.push_str(&format!(" --{}={}", state, quote(&services.join(","))));
+                    }
+                }
+
+                Some(vec![line])
+            }
+            "filesystem" => filesystems(value)?,
+            _ => None,
+        };
+
+        match found {
+            Some(found) => lines.extend(found),
+            None => warn!(
+                "blueprint: customizations.{} has no kickstart equivalent, leaving it out",
+                key
+            ),
+        }
+    }
+
+    // Image types lay out the disk themselves when it isn't customized.
+    if !customizations.contains_key("filesystem") {
+        lines.push("autopart".to_string());
+    }
+
+    let mut packages = Vec::new();
+
+    for (key, prefix) in [("packages", ""), ("modules", ""), ("groups", "@")] {
+        let entries = match blueprint.get(key) {
+            Some(Value::Array(entries)) => entries,
+            Some(_) => return Err(format!("{} is not an array of tables", key)),
+            None => continue,
+        };
+
+        for entry in entries {
+            let name = entry["name"]
+                .as_str()
+                .ok_or_else(|| format!("{} without a name", key))?;
+
+            match entry["version"].as_str() {
+                Some(version) if version != "*" && !version.is_empty() => {
+                    packages.push(format!("{}{}-{}", prefix, name, version))
+                }
+                _ => packages.push(format!("{}{}", prefix, name)),
+            }
+        }
+    }
+
+    if !packages.is_empty() {
+        lines.push(String::new());
+        lines.push("%packages".to_string());
+        lines.extend(packages);
+        lines.push("%end".to_string());
+    }
+
+    lines.push(String::new());
+
+    Ok(lines.join("\n"))
+}
+
+/// The string at `key` of `table`.
+fn string<'a>(table: &'a Value, key: &str) -> Option<&'a str> {
+    table.get(key).and_then(Value::as_str)
+}
+
+/// `value` as strings, if it's an array of them.
+fn strings(value: &Value) -> Option<Vec<&str>> {
+    value.as_array()?.iter().map(Value::as_str).collect()
+}
+
+/// The lines for each table of the array `value`.
+fn tables(value: &Value, line: impl Fn(&Value) -> Option<Vec<String>>) -> Option<Vec<String>> {
+    let mut lines = Vec::new();
+
+    for table in value.as_array()? {
+        lines.extend(line(table)?);
+    }
+
+    Some(lines)
+}
+
+/// `user` and `sshkey` for the users, root has `rootpw` instead.
+fn users(value: &Value) -> Option<Vec<String>> {
+    tables(value, |user| {
+        let name = string(user, "name")?;
+        let mut lines = Vec::new();
+
+        // Blueprints take hashed and plain passwords alike, hashes start with their algorithm.
+        let password = string(user, "password").map(|password| {
+            let crypted = if password.starts_with('$') {
+                " --iscrypted"
+            } else {
+                " --plaintext"
+            };
+
+            (quote(password), crypted)
+        });
+
+        if name == "root" {
+            match &password {
+                Some((password, crypted)) => lines.push(format!("rootpw{} {}", crypted, password)),
+                None => lines.push("rootpw --lock".to_string()),
+            }
+        } else {
+            let mut line = format!("user --name={}", quote(name));
+
+            if let Some((password, crypted)) = &password {
+                line.push_str(&format!(" --password={}{}", password, crypted));
+            }
+
+            if let Some(groups) = strings(&user["groups"]).filter(|groups| !groups.is_empty()) {
+                line.push_str(&format!(" --groups={}", quote(&groups.join(","))));
+            }
+
+            for (key, option) in [
+                ("home", "--homedir"),
+                ("shell", "--shell"),
+                ("description", "--gecos"),
+            ] {
+                if let Some(value) = string(user, key) {
+                    line.push_str(&format!(" {}={}", option, quote(value)));
+                }
+            }
+
+            for (key, option) in [("uid", "--uid"), ("gid", "--gid")] {
+                if let Some(id) = user.get(key).and_then(Value::as_u64) {
+                    line.push_str(&format!(" {}={}", option, id));
+                }
+            }
+
+            lines.push(line);
+        }
+
+        if let Some(key) = string(user, "key") {
+            lines.push(format!("sshkey --username={} {}", quote(name), quote(key)));
+        }
+
+        Some(lines)
+    })
+}
+
+/// `timezone` for the zone and `timesource` for each NTP server.
+fn timezone(value: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if let Some(zone) = string(value, "timezone") {
+        lines.push(format!("timezone --utc {}", quote(zone)));
+    }
+
+    for server in strings(&value["ntpservers"]).unwrap_or_default() {
+        lines.push(format!("timesource --ntp-server={}", quote(server)));
+    }
+
+    lines
+}
+
+/// `lang` with the first language, which the others are added to, and `keyboard`.
+fn locale(value: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    if let Some((lang, support)) = strings(&value["languages"])
+        .unwrap_or_default()
+        .split_first()
+    {
+        let mut line = format!("lang {}", quote(lang));
+
+        if !support.is_empty() {
+            line.push_str(&format!(" --addsupport={}", quote(&support.join(","))));
+        }
+
+        lines.push(line);
+    }
+
+    if let Some(keyboard) = string(value, "keyboard") {
+        lines.push(format!("keyboard --vckeymap={}", quote(keyboard)));
+    }
+
+    lines
+}
+
+/// `firewall` with the ports and services to open and the services to close.
+fn firewall(value: &Value) -> Vec<String> {
+    let mut line = "firewall --enabled".to_string();
+
+    for (values, option) in [
+        (&value["ports"], "--port"),
+        (&value["services"]["enabled"], "--service"),
+        (&value["services"]["disabled"], "--remove-service"),
+    ] {
+        if let Some(values) = strings(values).filter(|values| !values.is_empty()) {
+            line.push_str(&format!(" {}={}", option, quote(&values.join(","))));
+        }
+    }
+
+    vec![line]
+}
+
+/// A partition for each mountpoint, at least as large as asked for. The disk is cleared first and
+/// the partitions the platform needs are added, `/` takes up what's left.
+fn filesystems(value: &Value) -> Result<Option<Vec<String>>, String> {
+    let filesystems = match value.as_array() {
+        Some(filesystems) => filesystems,
+        None => return Ok(None),
+    };
+
+    let mut lines = vec![
+        "clearpart --all --initlabel".to_string(),
+        "reqpart".to_string(),
+    ];
+
+    for filesystem in filesystems {
+        let mountpoint = string(filesystem, "mountpoint")
+            .ok_or("customizations.filesystem without a mountpoint")?;
+        let minsize = filesystem
+            .get("minsize")
+            .or_else(|| filesystem.get("min_size"))
+            .map(size)
+            .transpose()?
+            .unwrap_or_default();
+
+        // Kickstarts size partitions in MiB, rounding up keeps them large enough.
+        let mut line = format!(
+            "part {} --size={}",
+            quote(mountpoint),
+            minsize.div_ceil(1024 * 1024).max(1)
+        );
+
+        if mountpoint == "/" {
+            line.push_str(" --grow");
+        }
+
+        lines.push(line);
+    }
+
+    Ok(Some(lines))
+}
+
+/// A size in bytes, given as a number of bytes or as a string with a unit such as `20 GiB`.
+fn size(value: &Value) -> Result<u64, String> {
+    const UNITS: &[(&str, u64)] = &[
+        ("TiB", 1 << 40),
+        ("GiB", 1 << 30),
+        ("MiB", 1 << 20),
+        ("KiB", 1 << 10),
+        ("TB", 1_000_000_000_000),
+        ("GB", 1_000_000_000),
+        ("MB", 1_000_000),
+        ("kB", 1_000),
+        ("B", 1),
+    ];
+
+    if let Some(bytes) = value.as_u64() {
+        return Ok(bytes);
+    }
+
+    let s = value.as_str().unwrap_or_default().trim();
+
+    for (unit, factor) in UNITS {
+        if let Some(number) = s.strip_suffix(unit) {
+            if let Ok(number) = number.trim().parse::<u64>() {
+                return Ok(number * factor);
+            }
+        }
+    }
+
+    s.parse()
+        .map_err(|_| format!("'{}' is not a size", value.as_str().unwrap_or_default()))
+}
+
+/// The `enabled` and `disabled` table of services, none if both are empty.
+fn services(enabled: &[String], disabled: &[String]) -> Option<Value> {
+    let mut services = Map::new();
+
+    if !enabled.is_empty() {
+        services.insert("enabled".to_string(), json!(enabled));
+    }
+
+    if !disabled.is_empty() {
+        services.insert("disabled".to_string(), json!(disabled));
+    }
+
+    (!services.is_empty()).then_some(Value::Object(services))
+}
+
+/// The items of a comma separated list.
+fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The value of `option` as a number, if it's given.
+fn number(options: &Options, option: &str) -> Result<Option<u64>, String> {
+    options
+        .value(option)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| format!("{} '{}' is not a number", option, value))
+        })
+        .transpose()
+}
+
+#[test]
+fn blueprint_customizations() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-blueprint-customizations");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "lang en_US.UTF-8 --addsupport=nl_NL.UTF-8\n\
+         keyboard --vckeymap=us --xlayouts=us\n\
+         network --hostname=web.example\n\
+         network --bootproto=dhcp\n\
+         timezone Europe/Amsterdam\n\
+         rootpw --lock\n\
+         user --name=admin --groups=wheel --password=$6$x --iscrypted --uid=1000\n\
+         sshkey --username=admin \"ssh-ed25519 AAAA admin\"\n\
+         group --name=web --gid=990\n\
+         services --enabled=nginx --disabled=cups\n\
+         firewall --enabled --ssh --port=8080:tcp --service=http\n\
+         firstboot --disable\n\
+         bootloader --append=\"console=ttyS0\"\n\
+         part / --size=4096\n\
+         %packages\n@core\n@^server-product-environment\nnginx\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (blueprint, report) = Translator::new().blueprint(&kickstart, "web").unwrap();
+
+    assert_eq!(blueprint["name"], "web");
+    assert_eq!(
+        blueprint["packages"],
+        json!([{ "name": "nginx", "version": "*" }])
+    );
+    assert_eq!(blueprint["groups"], json!([{ "name": "core" }]));
+
+    let customizations = &blueprint["customizations"];
+    assert_eq!(customizations["hostname"], "web.example");
+    assert_eq!(customizations["kernel"]["append"], "console=ttyS0");
+    assert_eq!(
+        customizations["user"],
+        json!([{
+            "name": "admin",
+            "password": "$6$x",
+            "groups": ["wheel"],
+            "uid": 1000,
+            "key": "ssh-ed25519 AAAA admin",
+        }])
+    );
+    assert_eq!(
+        customizations["group"],
+        json!([{ "name": "web", "gid": 990 }])
+    );
+    assert_eq!(
+        customizations["locale"],
+        json!({ "languages": ["en_US.UTF-8", "nl_NL.UTF-8"], "keyboard": "us" })
+    );
+    assert_eq!(
+        customizations["firewall"],
+        json!({ "ports": ["8080:tcp"], "services": { "enabled": ["http", "ssh"] } })
+    );
+    assert_eq!(
+        customizations["services"],
+        json!({ "enabled": ["nginx"], "disabled": ["initial-setup.service", "cups"] })
+    );
+    assert_eq!(
+        customizations["filesystem"],
+        json!([{ "mountpoint": "/", "minsize": 4096_u64 * 1024 * 1024 }])
+    );
+
+    let untranslated: Vec<_> = report
+        .entries()
+        .iter()
+        .filter(|entry| matches!(entry.outcome, Outcome::Untranslated(_)))
+        .map(|entry| entry.element.as_str())
+        .collect();
+    assert_eq!(untranslated, vec!["network --bootproto=dhcp"]);
+}
+
+#[test]
+fn blueprint_to_kickstart() {
+    let blueprint = crate::toml::from_str(
+        r#"
+name = "web"
+version = "0.0.1"
+
+[[packages]]
+name = "nginx"
+version = "*"
+
+[[packages]]
+name = "vim"
+version = "9.1.0"
+
+[[groups]]
+name = "core"
+
+[customizations]
+hostname = "web.example"
+
+[customizations.kernel]
+append = "console=ttyS0 quiet"
+
+[[customizations.user]]
+name = "root"
+
+[[customizations.user]]
+name = "admin"
+password = "$6$hash"
+key = "ssh-ed25519 AAAA admin"
+groups = ["wheel"]
+uid = 1000
+
+[[customizations.group]]
+name = "web"
+gid = 990
+
+[customizations.timezone]
+timezone = "Europe/Amsterdam"
+ntpservers = ["ntp.example"]
+
+[customizations.locale]
+languages = ["en_US.UTF-8", "nl_NL.UTF-8"]
+keyboard = "us"
+
+[customizations.firewall]
+ports = ["8080:tcp"]
+
+[customizations.firewall.services]
+enabled = ["http"]
+
+[customizations.services]
+enabled = ["nginx"]
+
+[[customizations.filesystem]]
+mountpoint = "/"
+minsize = "10 GiB"
+
+[[customizations.filesystem]]
+mountpoint = "/var/log"
+minsize = 1500000000
+
+[customizations.fips]
+enabled = true
+"#,
+    )
+    .unwrap();
+
+    // Customizations come in the order of their names.
+    assert_eq!(
+        to_kickstart(&blueprint).unwrap(),
+        "# Translated from the blueprint 'web'\n\
+         clearpart --all --initlabel\n\
+         reqpart\n\
+         part / --size=10240 --grow\n\
+         part /var/log --size=1431\n\
+         firewall --enabled --port=8080:tcp --service=http\n\
+         group --name=web --gid=990\n\
+         network --hostname=web.example\n\
+         bootloader --append='console=ttyS0 quiet'\n\
+         lang en_US.UTF-8 --addsupport=nl_NL.UTF-8\n\
+         keyboard --vckeymap=us\n\
+         services --enabled=nginx\n\
+         timezone --utc Europe/Amsterdam\n\
+         timesource --ntp-server=ntp.example\n\
+         rootpw --lock\n\
+         user --name=admin --password=$6$hash --iscrypted --groups=wheel --uid=1000\n\
+         sshkey --username=admin 'ssh-ed25519 AAAA admin'\n\
+         \n\
+         %packages\n\
+         nginx\n\
+         vim-9.1.0\n\
+         @core\n\
+         %end\n"
+    );
+
+    assert!(to_kickstart(&crate::toml::from_str("customizations = 1").unwrap()).is_err());
+}
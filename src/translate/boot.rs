@@ -0,0 +1,353 @@
+//! The bootloader, configured by `bootloader` and on s390x by `zipl`. Which bootloader stages are
+//! used follows from the architecture, where the bootloader goes follows from the disk layout.
+
+use log::*;
+use serde_json::json;
+
+use super::image;
+use super::options::Options;
+use super::storage::{Layout, Location};
+use crate::osbuild::Stage;
+
+/// The architectures images can be built for.
+pub const ARCHES: &[&str] = &["x86_64", "aarch64", "ppc64le", "s390x"];
+
+/// The options of `bootloader` that take a value.
+pub const VALUES: &[&str] = &[
+    "--append",
+    "--location",
+    "--timeout",
+    "--password",
+    "--boot-drive",
+    "--driveorder",
+    "--default",
+];
+
+/// The options of `bootloader` that are flags.
+pub const FLAGS: &[&str] = &[
+    "--disabled",
+    "--iscrypted",
+    "--md5pass",
+    "--extlinux",
+    "--sdboot",
+    "--leavebootorder",
+    "--nombr",
+    "--lba32",
+    "--linear",
+    "--nolinear",
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bootloader {
+    /// No bootloader is installed, from `--location=none` or `--disabled`.
+    pub disabled: bool,
+    pub timeout: Option<u64>,
+    /// The version of the installed kernel, zipl needs it to find the kernel in `/boot`.
+    pub kernel: Option<String>,
+}
+
+/// The secure boot mode `zipl` asks for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SecureBoot {
+    Auto,
+    Enabled,
+    Disabled,
+}
+
+impl Bootloader {
+    /// `bootloader [--location=mbr|partition|none] [--timeout=<seconds>] [--disabled]`, the
+    /// kernel arguments of `--append` are left to the caller. Returns the options that are
+    /// understood but can't be expressed.
+    pub fn parse(&mut self, options: &Options) -> Result<Vec<String>, String> {
+        let unsupported = options
+            .names()
+            .filter(|option| {
+                [
+                    "--password",
+                    "--iscrypted",
+                    "--md5pass",
+                    "--extlinux",
+                    "--sdboot",
+                ]
+                .contains(option)
+            })
+            .map(str::to_string)
+            .collect();
+
+        if options.flag("--disabled") {
+            self.disabled = true;
+        }
+
+        match options.value("--location") {
+            Some("none") => self.disabled = true,
+            Some("mbr" | "partition" | "boot") | None => {}
+            Some(location) => return Err(format!("unknown location '{}'", location)),
+        }
+
+        if let Some(timeout) = options.value("--timeout") {
+            self.timeout =
+                Some(timeout.parse().map_err(|_| {
+                    format!("--timeout needs a number of seconds, not '{}'", timeout)
+                })?);
+        }
+
+        Ok(unsupported)
+    }
+
+    /// The stage of the os pipeline that writes the bootloader configuration, EFI binaries are
+    /// in the `vendor` directory of the EFI system partition.
+    pub fn config_stage(
+        &self,
+        arch: &str,
+        vendor: &str,
+        layout: &Layout,
+        kernel_opts: &[String],
+    ) -> Result<Option<Stage>, String> {
+        if self.disabled {
+            return Ok(None);
+        }
+
+        let root = layout
+            .filesystem("/")
+            .ok_or("there is no root filesystem")?;
+
+        if arch == "s390x" {
+            zipl_boot(layout)?;
+
+            let mut zipl = Stage::new("org.osbuild.zipl");
+
+            if let Some(timeout) = self.timeout {
+                zipl = zipl.option("timeout", json!(timeout));
+            }
+
+            return Ok(Some(zipl));
+        }
+
+        let mut grub2 = Stage::new("org.osbuild.grub2")
+            .option("root_fs_uuid", json!(root.uuid))
+            .option("kernel_opts", json!(kernel_opts.join(" ")));
+
+        if let Some(boot) = layout.filesystem("/boot") {
+            grub2 = grub2.option("boot_fs_uuid", json!(boot.uuid));
+        }
+
+        let legacy = legacy_platform(arch, layout);
+        let uefi = layout.filesystem("/boot/efi").is_some();
+
+        if let Some(platform) = legacy {
+            grub2 = grub2.option("legacy", json!(platform));
+        }
+
+        if uefi {
+            grub2 = grub2.option("uefi", json!({ "vendor": vendor }));
+        }
+
+        if legacy.is_none() && !uefi {
+            let needs = match arch {
+                "x86_64" => "a biosboot or /boot/efi partition",
+                "ppc64le" => "a prepboot partition",
+                _ => "a /boot/efi partition",
+            };
+
+            return Err(format!("booting on {} needs {}", arch, needs));
+        }
+
+        if let Some(timeout) = self.timeout {
+            grub2 = grub2.option("config", json!({ "timeout": timeout }));
+        }
+
+        Ok(Some(grub2))
+    }
+
+    /// The bootloader option of the qemu assembler of version 1 manifests, it installs what the
+    /// image pipeline would.
+    pub fn assembler_option(&self, arch: &str, layout: &Layout) -> Option<serde_json::Value> {
+        if self.disabled {
+            return None;
+        }
+
+        if arch == "s390x" {
+            return Some(json!({ "type": "zipl" }));
+        }
+
+        legacy_platform(arch, layout)
+            .map(|platform| json!({ "type": "grub2", "platform": platform }))
+    }
+
+    /// The stage of the image pipeline that installs the bootloader onto the disk, booting with
+    /// UEFI only needs the files in the EFI system partition.
+    pub fn install_stage(&self, arch: &str, layout: &Layout) -> Result<Option<Stage>, String> {
+        if self.disabled {
+            return Ok(None);
+        }
+
+        if arch == "s390x" {
+            let index = zipl_boot(layout)?;
+
+            let kernel = match &self.kernel {
+                Some(kernel) => kernel,
+                None => {
+                    warn!(
+                        "zipl: the kernel version is not known without --kernel, the image won't \
+                         be bootable"
+                    );
+                    return Ok(None);
+                }
+            };
+
+            let zipl = Stage::new("org.osbuild.zipl.inst")
+                .option("kernel", json!(kernel))
+                .option("location", json!(layout.partitions[index].start))
+                .device("disk", image::loopback(None));
+
+            return Ok(Some(image::mounted(layout, zipl)));
+        }
+
+        let platform = match legacy_platform(arch, layout) {
+            Some(platform) => platform,
+            None => return Ok(None),
+        };
+
+        let boot = layout.boot().ok_or("there is no root filesystem")?;
+
+        let index = match boot.location {
+            Location::Partition(index) => index,
+            Location::LogVol { .. } => {
+                return Err(
+                    "grub2 can't load its modules from a logical volume, /boot needs its own \
+                     partition"
+                        .to_string(),
+                );
+            }
+        };
+
+        // The core image goes into the biosboot or PReP partition, on a dos partition table
+        // without those it goes into the gap after the MBR.
+        let fstype = match arch {
+            "ppc64le" => "prepboot",
+            _ => "biosboot",
+        };
+
+        let location = match layout.partition(fstype) {
+            Some((_, partition)) => partition.start,
+            None => 1,
+        };
+
+        let prefix = if boot.volume.mountpoint == "/boot" {
+            "/grub2"
+        } else {
+            "/boot/grub2"
+        };
+
+        Ok(Some(
+            Stage::new("org.osbuild.grub2.inst")
+                .option("filename", json!(image::FILENAME))
+                .option("platform", json!(platform))
+                .option("location", json!(location))
+                .option(
+                    "core",
+                    json!({
+                        "type": "mkimage",
+                        "partlabel": layout.label,
+                        "filesystem": boot.volume.fstype,
+                    }),
+                )
+                .option(
+                    "prefix",
+                    json!({
+                        "type": "partition",
+                        "partlabel": layout.label,
+                        "number": index,
+                        "path": prefix,
+                    }),
+                ),
+        ))
+    }
+}
+
+/// `zipl [--secure-boot|--force-secure-boot|--no-secure-boot]`
+pub fn zipl(args: &[String]) -> Result<SecureBoot, String> {
+    let options = Options::parse_known(
+        args,
+        &[],
+        &["--secure-boot", "--force-secure-boot", "--no-secure-boot"],
+    )?;
+
+    if let Some(arg) = options.positional().first() {
+        return Err(format!("unexpected argument '{}'", arg));
+    }
+
+    Ok(match options.names().last() {
+        Some("--force-secure-boot") => SecureBoot::Enabled,
+        Some("--no-secure-boot") => SecureBoot::Disabled,
+        _ => SecureBoot::Auto,
+    })
+}
+
+/// The partition holding `/boot` on s390x. zipl records the blocks of the kernel in the boot
+/// record, which it can only do when they are on a plain partition.
+fn zipl_boot(layout: &Layout) -> Result<usize, String> {
+    match layout.boot().map(|boot| &boot.location) {
+        Some(Location::Partition(index)) => Ok(*index),
+        Some(Location::LogVol { .. }) => {
+            Err("zipl can't boot from a logical volume, /boot needs its own partition".to_string())
+        }
+        None => Err("there is no root filesystem".to_string()),
+    }
+}
+
+/// The kernel argument for the console of `arch`, images usually run without a screen.
+pub fn console(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" => Some("console=ttyS0,115200n8"),
+        "aarch64" => Some("console=ttyAMA0,115200n8"),
+        "ppc64le" => Some("console=hvc0"),
+        "s390x" => Some("console=ttysclp0"),
+        _ => None,
+    }
+}
+
+/// The grub2 platform for booting without UEFI, if the layout allows it.
+fn legacy_platform(arch: &str, layout: &Layout) -> Option<&'static str> {
+    match arch {
+        "x86_64" if layout.label == "dos" || layout.partition("biosboot").is_some() => {
+            Some("i386-pc")
+        }
+        "ppc64le" if layout.partition("prepboot").is_some() => Some("powerpc-ieee1275"),
+        _ => None,
+    }
+}
+
+#[test]
+fn boot_zipl() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert_eq!(zipl(&args("")), Ok(SecureBoot::Auto));
+    assert_eq!(zipl(&args("--no-secure-boot")), Ok(SecureBoot::Disabled));
+    assert!(zipl(&args("--bogus")).is_err());
+
+    let mut storage = super::storage::Storage::default();
+    storage.part(&args("/boot --size=1024"), None).unwrap();
+    storage.part(&args("/ --size=2048"), None).unwrap();
+
+    let layout = storage.layout("s390x", None, "").unwrap();
+    let bootloader = Bootloader {
+        kernel: Some("6.5.6-300.fc39.s390x".to_string()),
+        ..Bootloader::default()
+    };
+
+    assert!(bootloader
+        .config_stage("s390x", "fedora", &layout, &[])
+        .unwrap()
+        .is_some());
+    assert!(bootloader
+        .install_stage("s390x", &layout)
+        .unwrap()
+        .is_some());
+
+    let mut storage = super::storage::Storage::default();
+    storage.autopart("s390x", &args("--noboot")).unwrap();
+
+    let layout = storage.layout("s390x", None, "").unwrap();
+    assert!(bootloader.install_stage("s390x", &layout).is_err());
+}
@@ -0,0 +1,64 @@
+//! The build root is where osbuild runs the stages, the tools they call have to be in it. The
+//! distribution gives the packages every build root has, the others depend on the stages.
+
+/// The packages with the tools of a stage.
+const TOOLS: &[(&str, &[&str])] = &[
+    ("org.osbuild.mkfs.ext4", &["e2fsprogs"]),
+    ("org.osbuild.mkfs.xfs", &["xfsprogs"]),
+    ("org.osbuild.mkfs.btrfs", &["btrfs-progs"]),
+    ("org.osbuild.mkfs.fat", &["dosfstools"]),
+    ("org.osbuild.mkswap", &["util-linux"]),
+    ("org.osbuild.sfdisk", &["util-linux"]),
+    ("org.osbuild.lvm2.create", &["lvm2"]),
+    ("org.osbuild.lvm2.metadata", &["lvm2"]),
+    (
+        "org.osbuild.grub2.inst",
+        &["grub2-pc-modules", "grub2-tools"],
+    ),
+    ("org.osbuild.zipl.inst", &["s390utils-core"]),
+    ("org.osbuild.qemu", &["qemu-img"]),
+    ("org.osbuild.tar", &["tar"]),
+    ("org.osbuild.oci-archive", &["tar"]),
+    ("org.osbuild.selinux", &["policycoreutils"]),
+    (
+        "org.osbuild.bootiso.mono",
+        &["dosfstools", "squashfs-tools"],
+    ),
+    ("org.osbuild.xorrisofs", &["xorriso"]),
+    ("org.osbuild.implantisomd5", &["isomd5sum"]),
+];
+
+/// The packages of the build root for stages of the types in `kinds`, `base` are those of the
+/// distribution.
+pub fn packages(base: &[String], kinds: &[&str]) -> Vec<String> {
+    let mut packages = base.to_vec();
+
+    for (_, tools) in TOOLS.iter().filter(|(kind, _)| kinds.contains(kind)) {
+        for tool in *tools {
+            if !packages.iter().any(|package| package == tool) {
+                packages.push(tool.to_string());
+            }
+        }
+    }
+
+    packages
+}
+
+#[test]
+fn buildroot_packages() {
+    let base = vec!["rpm".to_string(), "dosfstools".to_string()];
+
+    assert_eq!(packages(&base, &["org.osbuild.timezone"]), base);
+    assert_eq!(
+        packages(
+            &base,
+            &[
+                "org.osbuild.mkfs.fat",
+                "org.osbuild.mkfs.xfs",
+                "org.osbuild.lvm2.metadata",
+                "org.osbuild.lvm2.create",
+            ]
+        ),
+        vec!["rpm", "dosfstools", "xfsprogs", "lvm2"]
+    );
+}
@@ -0,0 +1,253 @@
+//! The catalog of the commands and sections that are translated, with the options they are
+//! understood with and the stages they can be translated into. `osbuild-ks commands` prints it so
+//! support can be looked up before a kickstart is tried.
+
+use serde_json::{json, Value};
+
+use super::{boot, realm, storage};
+
+/// The stages of the disk, which every storage command adds to.
+const STORAGE: &[&str] = &[
+    "org.osbuild.truncate",
+    "org.osbuild.sfdisk",
+    "org.osbuild.lvm2.create",
+    "org.osbuild.lvm2.metadata",
+    "org.osbuild.mkfs.ext4",
+    "org.osbuild.mkfs.xfs",
+    "org.osbuild.mkfs.btrfs",
+    "org.osbuild.mkfs.fat",
+    "org.osbuild.mkswap",
+    "org.osbuild.fstab",
+];
+
+/// Files are embedded and copied into the tree.
+const FILES: &[&str] = &["org.osbuild.mkdir", "org.osbuild.copy"];
+
+const SYSTEMD: &[&str] = &["org.osbuild.systemd"];
+
+const UNITS: &[&str] = &["org.osbuild.systemd.unit.create", "org.osbuild.systemd"];
+
+const AUTHCONFIG_FLAGS: &[&str] = &[
+    "--enablesssd",
+    "--enablesssdauth",
+    "--enableldap",
+    "--enableldapauth",
+    "--enablekrb5",
+    "--enablewinbind",
+    "--enablewinbindauth",
+    "--enablemkhomedir",
+    "--enablefaillock",
+    "--enablefingerprint",
+    "--enablesmartcard",
+    "--enableshadow",
+    "--useshadow",
+];
+
+const PART_FLAGS: &[&str] = &["--grow", "--encrypted", "--noformat", "--onbiosdisk"];
+
+/// A command or section that is translated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Command {
+    /// The name of the command, or the section header starting with `%`.
+    pub name: &'static str,
+    /// The options that take a value.
+    pub values: &'static [&'static str],
+    pub flags: &'static [&'static str],
+    /// The stages it can be translated into, which of them depends on the rest of the kickstart.
+    stages: &'static [&'static [&'static str]],
+}
+
+impl Command {
+    const fn new(
+        name: &'static str,
+        values: &'static [&'static str],
+        flags: &'static [&'static str],
+        stages: &'static [&'static [&'static str]],
+    ) -> Self {
+        Self {
+            name,
+            values,
+            flags,
+            stages,
+        }
+    }
+
+    pub fn stages(&self) -> impl Iterator<Item = &'static str> {
+        self.stages.iter().copied().flatten().copied()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "values": self.values,
+            "flags": self.flags,
+            "stages": self.stages().collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command::new(
+        "auth",
+        &["--passalgo"],
+        AUTHCONFIG_FLAGS,
+        &[&["org.osbuild.authselect"]],
+    ),
+    Command::new(
+        "authconfig",
+        &["--passalgo"],
+        AUTHCONFIG_FLAGS,
+        &[&["org.osbuild.authselect"]],
+    ),
+    Command::new("authselect", &[], &[], &[&["org.osbuild.authselect"]]),
+    Command::new(
+        "firstboot",
+        &[],
+        &[
+            "--enable",
+            "--enabled",
+            "--disable",
+            "--disabled",
+            "--reconfig",
+        ],
+        &[SYSTEMD, FILES],
+    ),
+    Command::new("eula", &[], &["--agreed", "--agree"], &[FILES]),
+    Command::new("logging", &["--host", "--port", "--level"], &[], &[FILES]),
+    Command::new(
+        "timezone",
+        &["--ntpservers"],
+        &["--utc", "--isUtc", "--nontp"],
+        &[&["org.osbuild.timezone", "org.osbuild.chrony"]],
+    ),
+    Command::new(
+        "timesource",
+        &["--ntp-server", "--ntp-pool"],
+        &["--ntp-disable", "--nts"],
+        &[&["org.osbuild.chrony"]],
+    ),
+    Command::new(
+        "rhsm",
+        &[
+            "--organization",
+            "--activation-key",
+            "--server-hostname",
+            "--rhsm-baseurl",
+            "--proxy",
+        ],
+        &["--connect-to-insights"],
+        &[&["org.osbuild.rhsm"]],
+    ),
+    Command::new(
+        "syspurpose",
+        &["--role", "--sla", "--usage", "--addon"],
+        &[],
+        &[FILES],
+    ),
+    Command::new("realm", realm::VALUES, &["--no-password"], &[UNITS]),
+    Command::new(
+        "driverdisk",
+        &["--source", "--biospart"],
+        &[],
+        &[&["org.osbuild.rpm"]],
+    ),
+    Command::new(
+        "device",
+        &["--opts"],
+        &[],
+        &[&["org.osbuild.modprobe"], FILES],
+    ),
+    Command::new("part", storage::VOLUME_VALUES, PART_FLAGS, &[STORAGE]),
+    Command::new("partition", storage::VOLUME_VALUES, PART_FLAGS, &[STORAGE]),
+    Command::new(
+        "volgroup",
+        &["--pesize", "--reserved-space", "--reserved-percent"],
+        &["--useexisting", "--noformat"],
+        &[STORAGE],
+    ),
+    Command::new("logvol", storage::VOLUME_VALUES, PART_FLAGS, &[STORAGE]),
+    Command::new(
+        "autopart",
+        storage::AUTOPART_VALUES,
+        &["--encrypted", "--noboot"],
+        &[STORAGE],
+    ),
+    Command::new("reqpart", &[], &["--add-boot"], &[STORAGE]),
+    Command::new(
+        "clearpart",
+        &["--drives", "--disklabel", "--list"],
+        &["--all", "--linux", "--none", "--initlabel", "--cdl"],
+        &[STORAGE],
+    ),
+    Command::new("snapshot", &["--name", "--when"], &[], &[UNITS]),
+    Command::new("graphical", &[], &["--non-interactive"], &[SYSTEMD]),
+    Command::new("text", &[], &["--non-interactive"], &[SYSTEMD]),
+    Command::new("cmdline", &[], &[], &[SYSTEMD]),
+    Command::new("rescue", &[], &["--nomount", "--romount"], &[SYSTEMD]),
+    Command::new("vnc", &["--host", "--port", "--password"], &[], &[SYSTEMD]),
+    Command::new("install", &[], &[], &[SYSTEMD]),
+    Command::new(
+        "bootloader",
+        boot::VALUES,
+        boot::FLAGS,
+        &[&[
+            "org.osbuild.kernel-cmdline",
+            "org.osbuild.grub2",
+            "org.osbuild.grub2.inst",
+            "org.osbuild.zipl",
+            "org.osbuild.zipl.inst",
+        ]],
+    ),
+    Command::new(
+        "zipl",
+        &[],
+        &["--secure-boot", "--force-secure-boot", "--no-secure-boot"],
+        &[&["org.osbuild.zipl", "org.osbuild.zipl.inst"]],
+    ),
+    Command::new("%packages", &[], &[], &[&["org.osbuild.rpm"]]),
+    Command::new(
+        "%post",
+        &["--interpreter", "--log"],
+        &["--nochroot", "--erroronfail"],
+        &[&["org.osbuild.rpm", "org.osbuild.systemd"], FILES],
+    ),
+    Command::new(
+        "%certificate",
+        &["--filename", "--dir"],
+        &[],
+        &[FILES, &["org.osbuild.pki.update-ca-trust"]],
+    ),
+    Command::new(
+        "%addon",
+        &["--reserve-mb"],
+        &["--enable", "--enablefadump", "--disable"],
+        &[&[
+            "org.osbuild.kernel-cmdline",
+            "org.osbuild.systemd",
+            "org.osbuild.oscap.remediation",
+        ]],
+    ),
+];
+
+/// The command or section called `name`, if it's translated.
+pub fn lookup(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+#[test]
+fn catalog_commands() {
+    use crate::dialect;
+
+    for command in COMMANDS {
+        assert!(command.name.starts_with('%') || dialect::is_command(command.name));
+        assert!(command.stages().next().is_some());
+    }
+
+    let timezone = lookup("timezone").unwrap();
+    assert_eq!(
+        timezone.stages().collect::<Vec<_>>(),
+        ["org.osbuild.timezone", "org.osbuild.chrony"]
+    );
+    assert_eq!(timezone.to_json()["values"], json!(["--ntpservers"]));
+    assert!(lookup("rootpw").is_none());
+}
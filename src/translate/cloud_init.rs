@@ -0,0 +1,295 @@
+//! cloud-init provisions cloud instances from user-data when they first boot. The `#cloud-config`
+//! of a kickstart has its users and their SSH keys, packages, files and the time zone, and runs
+//! what's left of its `%post` scripts with `runcmd`. It's applied to a running instance, so
+//! services are started as they're enabled.
+
+use base64::Engine;
+use log::*;
+use serde_json::{json, Map, Value};
+
+use super::blueprint::Customizations;
+use super::{is_chroot_shell, Report, TranslateError, Translator};
+use crate::kickstart::{Kickstart, SectionKind};
+
+impl Translator {
+    /// The cloud config for `kickstart`, without the `#cloud-config` line that starts it.
+    pub fn cloud_init(mut self, kickstart: &Kickstart) -> Result<(Value, Report), TranslateError> {
+        self.customizations = Some(Customizations::default());
+
+        let layout = self.read(kickstart)?;
+        let customized = self.customizations.take().unwrap_or_default();
+
+        let mut config = Map::new();
+
+        // Root isn't a user cloud-init creates, its password is changed instead.
+        let (root, users): (Vec<_>, Vec<_>) = customized
+            .users
+            .iter()
+            .partition(|user| user["name"] == "root");
+
+        if !users.is_empty() {
+            let users: Vec<_> = users.into_iter().map(user).collect();
+            config.insert("users".to_string(), json!(users));
+        }
+
+        if let Some(password) = root
+            .first()
+            .and_then(|root| root.get("password"))
+            .and_then(Value::as_str)
+        {
+            config.insert(
+                "chpasswd".to_string(),
+                json!({
+                    "expire": false,
+                    "users": [{
+                        "name": "root",
+                        "password": password,
+                        "type": if is_hashed(password) { "hash" } else { "text" },
+                    }],
+                }),
+            );
+        }
+
+        if let Some(key) = root.first().and_then(|root| root.get("key")) {
+            config.insert("disable_root".to_string(), json!(false));
+            config.insert("ssh_authorized_keys".to_string(), json!([key]));
+        }
+
+        let groups: Vec<_> = customized
+            .groups
+            .iter()
+            .map(|group| {
+                if group.get("gid").is_some() {
+                    warn!(
+                        "cloud-init: groups have no id, leaving out that of '{}'",
+                        group["name"].as_str().unwrap_or_default()
+                    );
+                }
+
+                group["name"].clone()
+            })
+            .collect();
+
+        if !groups.is_empty() {
+            config.insert("groups".to_string(), json!(groups));
+        }
+
+        let mut packages = self.packages.include.clone();
+        packages.extend(self.packages.groups.iter().cloned());
+
+        if !packages.is_empty() {
+            config.insert("packages".to_string(), json!(packages));
+        }
+
+        if let Some(hostname) = &customized.hostname {
+            config.insert("hostname".to_string(), json!(hostname));
+        }
+
+        if let Some(zone) = &self.time.zone {
+            config.insert("timezone".to_string(), json!(zone));
+        }
+
+        if !self.time.timeservers().is_empty() && !self.time.ntp_disabled {
+            let mut ntp = Map::new();
+            ntp.insert("enabled".to_string(), json!(true));
+
+            if !self.time.servers.is_empty() {
+                ntp.insert("servers".to_string(), json!(self.time.servers));
+            }
+
+            if !self.time.pools.is_empty() {
+                ntp.insert("pools".to_string(), json!(self.time.pools));
+            }
+
+            config.insert("ntp".to_string(), Value::Object(ntp));
+        }
+
+        if let Some(language) = customized.languages.first() {
+            config.insert("locale".to_string(), json!(language));
+        }
+
+        if let Some(keyboard) = &customized.keyboard {
+            config.insert("keyboard".to_string(), json!({ "layout": keyboard }));
+        }
+
+        let files: Vec<_> = self
+            .files
+            .iter()
+            .map(|file| match std::str::from_utf8(&file.data) {
+                Ok(data) => json!({ "path": file.path, "content": data }),
+                Err(_) => json!({
+                    "path": file.path,
+                    "encoding": "b64",
+                    "content": base64::engine::general_purpose::STANDARD.encode(&file.data),
+                }),
+            })
+            .collect();
+
+        if !files.is_empty() {
+            config.insert("write_files".to_string(), json!(files));
+        }
+
+        let mut runcmd = Vec::new();
+
+        let mut enabled = self.enabled_services.clone();
+        enabled.extend(customized.enabled_services.iter().cloned());
+        let mut disabled = self.disabled_services.clone();
+        disabled.extend(customized.disabled_services.iter().cloned());
+
+        for (services, action) in [(enabled, "enable"), (disabled, "disable")] {
+            if !services.is_empty() {
+                let mut command = vec!["systemctl".to_string(), action.to_string()];
+                command.push("--now".to_string());
+                command.extend(services);
+                runcmd.push(json!(command));
+            }
+        }
+
+        // The scripts run in the instance as they would have in the installed system.
+        for section in &self.untranslated_sections {
+            if section.kind() == SectionKind::Post && is_chroot_shell(section.args()) {
+                runcmd.push(json!(section.data()));
+            }
+        }
+
+        if !runcmd.is_empty() {
+            config.insert("runcmd".to_string(), json!(runcmd));
+        }
+
+        for (left_out, what) in [
+            (!self.packages.exclude.is_empty(), "excluded packages"),
+            (layout.is_some(), "the disk layout"),
+            (!self.kernel_opts.is_empty(), "kernel arguments"),
+            (self.default_target.is_some(), "the default target"),
+            (!self.units.is_empty(), "systemd units"),
+            (self.authselect.is_some(), "the authselect profile"),
+            (!self.modprobe.is_empty(), "modprobe configuration"),
+            (
+                !self.gpgkeys.is_empty() || !self.gpgkeys_fromtree.is_empty(),
+                "GPG keys",
+            ),
+            (self.rhsm.is_some(), "the subscription"),
+            (self.oscap.is_some(), "the OpenSCAP remediation"),
+            (self.update_ca_trust, "the trust store update"),
+            (
+                !customized.ports.is_empty()
+                    || !customized.enabled_firewall.is_empty()
+                    || !customized.disabled_firewall.is_empty(),
+                "the firewall",
+            ),
+        ] {
+            if left_out {
+                warn!("cloud-init: {} can't be configured, leaving it out", what);
+            }
+        }
+
+        Ok((Value::Object(config), self.report))
+    }
+}
+
+/// The user of the cloud config for the blueprint `user`.
+fn user(user: &Map<String, Value>) -> Value {
+    let mut created = json!({ "name": user["name"] });
+
+    if let Some(password) = user.get("password").and_then(Value::as_str) {
+        match is_hashed(password) {
+            true => created["passwd"] = json!(password),
+            false => created["plain_text_passwd"] = json!(password),
+        }
+
+        created["lock_passwd"] = json!(false);
+    }
+
+    if let Some(key) = user.get("key") {
+        created["ssh_authorized_keys"] = json!([key]);
+    }
+
+    for (key, field) in [
+        ("groups", "groups"),
+        ("home", "homedir"),
+        ("shell", "shell"),
+        ("description", "gecos"),
+        ("uid", "uid"),
+    ] {
+        if let Some(value) = user.get(key) {
+            created[field] = value.clone();
+        }
+    }
+
+    if user.contains_key("gid") {
+        warn!(
+            "cloud-init: users have no group id, leaving out that of '{}'",
+            user["name"].as_str().unwrap_or_default()
+        );
+    }
+
+    created
+}
+
+/// Whether `password` is hashed by crypt, which is what kickstarts have with `--iscrypted`.
+fn is_hashed(password: &str) -> bool {
+    password.starts_with('$')
+}
+
+#[test]
+fn cloud_init_config() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-cloud-init-config");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "user --name=core --groups=wheel --password=hunter2 --plaintext\n\
+         sshkey --username=core \"ssh-ed25519 AAAA core@example\"\n\
+         rootpw --iscrypted $6$salt$hash\n\
+         group --name=admins --gid=1500\n\
+         timezone Europe/Amsterdam --ntpservers=ntp.example\n\
+         services --enabled=chronyd --disabled=kdump\n\
+         %packages\n@core\nvim\n%end\n\
+         %post\n\
+         cat > /etc/motd <<EOF\nWelcome\nEOF\n\
+         curl -o /tmp/setup https://example.com/setup && sh /tmp/setup\n\
+         %end\n\
+         %post --nochroot\ncp /etc/resolv.conf /mnt/sysimage/etc/\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (config, _) = Translator::new().cloud_init(&kickstart).unwrap();
+
+    assert_eq!(
+        config["users"],
+        json!([{
+            "name": "core",
+            "plain_text_passwd": "hunter2",
+            "lock_passwd": false,
+            "groups": ["wheel"],
+            "ssh_authorized_keys": ["ssh-ed25519 AAAA core@example"],
+        }])
+    );
+    assert_eq!(
+        config["chpasswd"]["users"],
+        json!([{ "name": "root", "password": "$6$salt$hash", "type": "hash" }])
+    );
+    assert_eq!(config["groups"], json!(["admins"]));
+    assert_eq!(config["packages"], json!(["vim", "@core"]));
+    assert_eq!(config["timezone"], "Europe/Amsterdam");
+    assert_eq!(config["ntp"]["servers"], json!(["ntp.example"]));
+    assert_eq!(
+        config["write_files"],
+        json!([{ "path": "/etc/motd", "content": "Welcome\n" }])
+    );
+    assert_eq!(
+        config["runcmd"],
+        json!([
+            ["systemctl", "enable", "--now", "chronyd"],
+            ["systemctl", "disable", "--now", "kdump"],
+            "curl -o /tmp/setup https://example.com/setup && sh /tmp/setup\n",
+        ])
+    );
+}
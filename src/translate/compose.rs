@@ -0,0 +1,259 @@
+//! Composes are requests to the Cloud API of osbuild-composer, which builds the image itself from
+//! an image type and customizations of it. What the kickstart says is translated into those
+//! customizations instead of stages, so less of it carries over than into a manifest.
+
+use log::*;
+use serde_json::{json, Map, Value};
+
+use super::{ImageFormat, Report, TranslateError, Translator};
+use crate::kickstart::Kickstart;
+
+/// The formats osbuild-composer has an image type for.
+pub const COMPOSE_FORMATS: &[&str] = &["raw", "qcow2", "vmdk", "vhd", "ami", "iso"];
+
+impl Translator {
+    /// The compose request for `kickstart`, to submit to osbuild-composer.
+    pub fn compose(mut self, kickstart: &Kickstart) -> Result<(Value, Report), TranslateError> {
+        let distribution = self.distro.composer.clone().ok_or_else(|| {
+            TranslateError::Unsupported(format!(
+                "{} has no osbuild-composer distribution",
+                self.distro.title
+            ))
+        })?;
+        let image_type = image_type(self.format)?;
+
+        let layout = self.read(kickstart)?;
+
+        let repositories: Vec<_> = self
+            .distro
+            .repositories
+            .iter()
+            .map(|repo| {
+                json!({
+                    "baseurl": repo.baseurl.replace("$basearch", &self.arch),
+                    "rhsm": self.distro.subscription,
+                })
+            })
+            .collect();
+
+        let mut image_request = json!({
+            "architecture": self.arch,
+            "image_type": image_type,
+            "repositories": repositories,
+        });
+
+        if let Some(size) = self.image_size {
+            image_request["size"] = json!(size * 1024 * 1024);
+        }
+
+        let mut customizations = Map::new();
+
+        let mut packages = self.packages.include.clone();
+        packages.extend(self.packages.groups.iter().cloned());
+
+        if !packages.is_empty() {
+            customizations.insert("packages".to_string(), json!(packages));
+        }
+
+        let ntpservers = self.time.timeservers();
+
+        if self.time.zone.is_some() || !ntpservers.is_empty() {
+            let mut timezone = Map::new();
+
+            if let Some(zone) = &self.time.zone {
+                timezone.insert("timezone".to_string(), json!(zone));
+            }
+
+            if !ntpservers.is_empty() && !self.time.ntp_disabled {
+                timezone.insert("ntpservers".to_string(), json!(ntpservers));
+            }
+
+            customizations.insert("timezone".to_string(), Value::Object(timezone));
+        }
+
+        if !self.enabled_services.is_empty() || !self.disabled_services.is_empty() {
+            let mut services = Map::new();
+
+            if !self.enabled_services.is_empty() {
+                services.insert("enabled".to_string(), json!(self.enabled_services));
+            }
+
+            if !self.disabled_services.is_empty() {
+                services.insert("disabled".to_string(), json!(self.disabled_services));
+            }
+
+            customizations.insert("services".to_string(), Value::Object(services));
+        }
+
+        if !self.kernel_opts.is_empty() {
+            customizations.insert(
+                "kernel".to_string(),
+                json!({ "append": self.kernel_opts.join(" ") }),
+            );
+        }
+
+        // The image type lays out the disk, only the sizes of the mountpoints can be asked for.
+        if let Some(layout) = &layout {
+            let filesystems: Vec<_> = layout
+                .filesystems
+                .iter()
+                .filter(|filesystem| filesystem.volume.is_mounted())
+                .map(|filesystem| {
+                    json!({
+                        "mountpoint": filesystem.volume.mountpoint,
+                        "min_size": filesystem.volume.size * 1024 * 1024,
+                    })
+                })
+                .collect();
+
+            if !filesystems.is_empty() {
+                customizations.insert("filesystem".to_string(), json!(filesystems));
+            }
+        }
+
+        let mut files = Vec::new();
+
+        for file in &self.files {
+            match std::str::from_utf8(&file.data) {
+                Ok(data) => files.push(json!({ "path": file.path, "data": data })),
+                Err(_) => warn!("compose: '{}' isn't text, leaving it out", file.path),
+            }
+        }
+
+        if !files.is_empty() {
+            customizations.insert("files".to_string(), json!(files));
+        }
+
+        if !self.packages.repos.is_empty() {
+            let repos: Vec<_> = self
+                .packages
+                .repos
+                .iter()
+                .map(|repo| json!({ "baseurl": repo.baseurl, "rhsm": false }))
+                .collect();
+
+            customizations.insert("payload_repositories".to_string(), json!(repos));
+        }
+
+        if let Some(profile) = self
+            .oscap
+            .as_ref()
+            .and_then(|oscap| oscap.get("profile_id"))
+        {
+            customizations.insert("openscap".to_string(), json!({ "profile_id": profile }));
+        }
+
+        for (left_out, what) in [
+            (!self.packages.exclude.is_empty(), "excluded packages"),
+            (self.authselect.is_some(), "the authselect profile"),
+            (!self.modprobe.is_empty(), "modprobe configuration"),
+            (!self.units.is_empty(), "systemd units"),
+            (self.default_target.is_some(), "the default target"),
+            (
+                !self.gpgkeys.is_empty() || !self.gpgkeys_fromtree.is_empty(),
+                "GPG keys",
+            ),
+            (self.rhsm.is_some(), "the subscription"),
+            (self.update_ca_trust, "the trust store update"),
+        ] {
+            if left_out {
+                warn!("compose: {} can't be customized, leaving it out", what);
+            }
+        }
+
+        let mut request = json!({
+            "distribution": distribution,
+            "image_request": image_request,
+        });
+
+        if !customizations.is_empty() {
+            request["customizations"] = Value::Object(customizations);
+        }
+
+        Ok((request, self.report))
+    }
+}
+
+/// The image type of osbuild-composer that makes `format`.
+fn image_type(format: ImageFormat) -> Result<&'static str, TranslateError> {
+    match format {
+        ImageFormat::Raw => Ok("minimal-raw"),
+        ImageFormat::Qcow2 => Ok("guest-image"),
+        ImageFormat::Vmdk => Ok("vsphere"),
+        ImageFormat::Vhd => Ok("azure"),
+        ImageFormat::Ami => Ok("aws"),
+        ImageFormat::Iso => Ok("image-installer"),
+        ImageFormat::Tar | ImageFormat::Oci => Err(TranslateError::Unsupported(format!(
+            "osbuild-composer has no image type for {} archives",
+            format
+        ))),
+    }
+}
+
+#[test]
+fn compose_request() {
+    use std::fs;
+
+    use crate::distro::Definitions;
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-compose-request");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "timezone Europe/Amsterdam --ntpservers=ntp.example\n\
+         firstboot --enable\n\
+         bootloader --append=\"console=ttyS0\"\n\
+         part / --size=4096 --fstype=xfs\n\
+         %packages\n@core\nvim\n-nano\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let translator = || {
+        Translator::new()
+            .arch("aarch64")
+            .format(ImageFormat::Qcow2)
+            .distro(Definitions::default().lookup("fedora").unwrap())
+    };
+
+    let (request, _) = translator()
+        .image_size(Some(8192))
+        .compose(&kickstart)
+        .unwrap();
+
+    assert_eq!(request["distribution"], "fedora-42");
+    assert_eq!(request["image_request"]["image_type"], "guest-image");
+    assert!(COMPOSE_FORMATS
+        .iter()
+        .all(|format| image_type(format.parse().unwrap()).is_ok()));
+    assert_eq!(request["image_request"]["size"], 8192_u64 * 1024 * 1024);
+    assert!(request["image_request"]["repositories"][0]["baseurl"]
+        .as_str()
+        .unwrap()
+        .ends_with("/42/Everything/aarch64/os/"));
+
+    let customizations = &request["customizations"];
+    assert_eq!(customizations["packages"], json!(["vim", "@core"]));
+    assert_eq!(
+        customizations["timezone"],
+        json!({ "timezone": "Europe/Amsterdam", "ntpservers": ["ntp.example"] })
+    );
+    assert_eq!(
+        customizations["services"],
+        json!({ "enabled": ["initial-setup.service"] })
+    );
+    assert_eq!(customizations["kernel"]["append"], "console=ttyS0");
+    assert_eq!(
+        customizations["filesystem"],
+        json!([{ "mountpoint": "/", "min_size": 4096_u64 * 1024 * 1024 }])
+    );
+
+    assert!(translator()
+        .format(ImageFormat::Tar)
+        .compose(&kickstart)
+        .is_err());
+}
@@ -0,0 +1,183 @@
+//! Commands and options that were deprecated or removed from kickstart, with the releases that
+//! happened in as pykickstart has them. Deprecated commands still work in the installer until
+//! they are removed, a kickstart for a later release that uses them fails to validate.
+
+use crate::dialect::{is_option, reached};
+use crate::version::Version;
+
+/// A deprecated command, or option of a command, and the releases it was deprecated and removed
+/// in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deprecated {
+    pub command: &'static str,
+    /// The option that is deprecated, the whole command when there is none.
+    pub option: Option<&'static str>,
+    pub deprecated: &'static str,
+    pub removed: Option<&'static str>,
+    /// What to use instead, if anything.
+    pub replacement: Option<&'static str>,
+}
+
+pub const COMMANDS: &[Deprecated] = &[
+    Deprecated {
+        command: "interactive",
+        option: None,
+        deprecated: "F14",
+        removed: Some("F18"),
+        replacement: None,
+    },
+    Deprecated {
+        command: "upgrade",
+        option: None,
+        deprecated: "F20",
+        removed: Some("F29"),
+        replacement: None,
+    },
+    Deprecated {
+        command: "auth",
+        option: None,
+        deprecated: "F28",
+        removed: None,
+        replacement: Some("authselect"),
+    },
+    Deprecated {
+        command: "authconfig",
+        option: None,
+        deprecated: "F28",
+        removed: None,
+        replacement: Some("authselect"),
+    },
+    Deprecated {
+        command: "install",
+        option: None,
+        deprecated: "F29",
+        removed: None,
+        replacement: None,
+    },
+    Deprecated {
+        command: "timezone",
+        option: Some("--ntpservers"),
+        deprecated: "F33",
+        removed: None,
+        replacement: Some("timesource --ntp-server"),
+    },
+    Deprecated {
+        command: "timezone",
+        option: Some("--nontp"),
+        deprecated: "F33",
+        removed: None,
+        replacement: Some("timesource --ntp-disable"),
+    },
+    Deprecated {
+        command: "autostep",
+        option: None,
+        deprecated: "F34",
+        removed: None,
+        replacement: None,
+    },
+];
+
+/// The deprecation entry for `command` as a whole.
+pub fn lookup(command: &str) -> Option<&'static Deprecated> {
+    COMMANDS
+        .iter()
+        .find(|entry| entry.command == command && entry.option.is_none())
+}
+
+/// The deprecation entries for `command` and for the options in `args` it is given with.
+pub fn find<'a>(
+    command: &'a str,
+    args: &'a [String],
+) -> impl Iterator<Item = &'static Deprecated> + 'a {
+    COMMANDS.iter().filter(move |entry| {
+        entry.command == command
+            && entry
+                .option
+                .is_none_or(|option| args.iter().any(|arg| is_option(arg, option)))
+    })
+}
+
+impl Deprecated {
+    /// The command, with the option if only the option is deprecated.
+    pub fn subject(&self) -> String {
+        match self.option {
+            Some(option) => format!("{} {}", self.command, option),
+            None => self.command.to_string(),
+        }
+    }
+
+    /// Whether the command is deprecated in kickstarts for `version`.
+    pub fn is_deprecated(&self, version: Version) -> bool {
+        reached(self.deprecated, version)
+    }
+
+    /// Whether the command no longer exists in kickstarts for `version`.
+    pub fn is_removed(&self, version: Version) -> bool {
+        self.removed
+            .is_some_and(|removed| reached(removed, version))
+    }
+
+    /// The diagnostic for using the command.
+    pub fn diagnostic(&self) -> String {
+        match (self.removed, self.replacement) {
+            (Some(removed), _) => format!(
+                "deprecated since {} and removed in {}, kickstarts for later releases fail to \
+                 validate",
+                self.deprecated, removed
+            ),
+            (None, Some(replacement)) => format!(
+                "deprecated since {}, use {} instead",
+                self.deprecated, replacement
+            ),
+            (None, None) => format!("deprecated since {} and has no effect", self.deprecated),
+        }
+    }
+
+    /// The error for using the command in a kickstart for `version`, which it was removed from.
+    pub fn removal(&self, version: Version) -> String {
+        format!(
+            "'{}' was removed in {} and can't be used in {} kickstarts",
+            self.subject(),
+            self.removed.unwrap_or(self.deprecated),
+            version
+        )
+    }
+}
+
+#[test]
+fn deprecated_lookup() {
+    assert_eq!(lookup("interactive").unwrap().removed, Some("F18"));
+    assert_eq!(
+        lookup("autostep").unwrap().diagnostic(),
+        "deprecated since F34 and has no effect"
+    );
+    assert!(lookup("part").is_none());
+
+    let interactive = lookup("interactive").unwrap();
+    assert!(!interactive.is_deprecated(Version::Fedora(13)));
+    assert!(interactive.is_deprecated(Version::Fedora(14)));
+    assert!(!interactive.is_removed(Version::Fedora(17)));
+    assert!(interactive.is_removed(Version::Rhel(7)));
+
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+    let subjects = |command, line| {
+        find(command, &args(line))
+            .map(Deprecated::subject)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        subjects("timezone", "UTC --ntpservers=ntp.example"),
+        vec!["timezone --ntpservers"]
+    );
+    assert!(subjects("timezone", "UTC --ntpserversx").is_empty());
+    assert_eq!(subjects("auth", "--enableshadow"), vec!["auth"]);
+    assert_eq!(
+        lookup("auth").unwrap().diagnostic(),
+        "deprecated since F28, use authselect instead"
+    );
+    assert_eq!(
+        lookup("upgrade").unwrap().removal(Version::Rhel(8)),
+        "'upgrade' was removed in F29 and can't be used in RHEL8 kickstarts"
+    );
+}
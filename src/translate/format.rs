@@ -0,0 +1,237 @@
+//! The artifact a manifest produces. The image pipeline writes a raw disk, the other disk formats
+//! are converted from it and archives are made from the tree of the os pipeline.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use super::image;
+use super::storage::Layout;
+use crate::osbuild::{Pipeline, Stage};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ImageFormat {
+    #[default]
+    Raw,
+    Qcow2,
+    Vmdk,
+    Vhd,
+    /// A raw disk, which is what AMIs are imported from.
+    Ami,
+    Tar,
+    Oci,
+    /// An installer that follows the original kickstart, see [`iso`](super::iso).
+    Iso,
+}
+
+pub const IMAGE_FORMATS: &[&str] = &[
+    "raw",
+    "qcow2",
+    "vmdk",
+    "vhd",
+    "ami",
+    "tar",
+    "oci",
+    "container",
+    "iso",
+];
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(ImageFormat::Raw),
+            "qcow2" => Ok(ImageFormat::Qcow2),
+            "vmdk" => Ok(ImageFormat::Vmdk),
+            "vhd" => Ok(ImageFormat::Vhd),
+            "ami" => Ok(ImageFormat::Ami),
+            "tar" => Ok(ImageFormat::Tar),
+            "oci" | "container" => Ok(ImageFormat::Oci),
+            "iso" => Ok(ImageFormat::Iso),
+            _ => Err(format!("'{}' is not an image format", s)),
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImageFormat::Raw => "raw",
+            ImageFormat::Qcow2 => "qcow2",
+            ImageFormat::Vmdk => "vmdk",
+            ImageFormat::Vhd => "vhd",
+            ImageFormat::Ami => "ami",
+            ImageFormat::Tar => "tar",
+            ImageFormat::Oci => "oci",
+            ImageFormat::Iso => "iso",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl ImageFormat {
+    /// Whether the artifact is a disk, made by the image pipeline from the storage commands.
+    pub fn is_disk(self) -> bool {
+        !matches!(self, ImageFormat::Tar | ImageFormat::Oci | ImageFormat::Iso)
+    }
+
+    /// The pipeline that makes the artifact for `arch`, none when the image pipeline already
+    /// does or, for ISOs, when the artifact isn't made from the os pipeline.
+    pub fn pipeline(self, arch: &str) -> Option<Pipeline> {
+        let (name, stage) = match self {
+            ImageFormat::Raw | ImageFormat::Ami | ImageFormat::Iso => return None,
+            ImageFormat::Qcow2 => (
+                "qcow2",
+                qemu("disk.qcow2", json!({ "type": "qcow2", "compat": "1.1" })),
+            ),
+            ImageFormat::Vmdk => (
+                "vmdk",
+                qemu(
+                    "disk.vmdk",
+                    json!({ "type": "vmdk", "subformat": "streamOptimized" }),
+                ),
+            ),
+            // Azure only takes fixed size VHDs.
+            ImageFormat::Vhd => (
+                "vhd",
+                qemu(
+                    "disk.vhd",
+                    json!({ "type": "vpc", "subformat": "fixed", "force_size": true }),
+                ),
+            ),
+            // Chroots and containers are unpacked as they are, with numeric owners that don't
+            // depend on the host and the labels and attributes of the tree.
+            ImageFormat::Tar => (
+                "archive",
+                Stage::new("org.osbuild.tar")
+                    .input("tree", os_tree())
+                    .option("filename", json!("root.tar"))
+                    .option("format", json!("posix"))
+                    .option("root-node", json!("omit"))
+                    .option("numeric-owner", json!(true))
+                    .option("acls", json!(true))
+                    .option("selinux", json!(true))
+                    .option("xattrs", json!(true)),
+            ),
+            ImageFormat::Oci => (
+                "container",
+                Stage::new("org.osbuild.oci-archive")
+                    .input("base", os_tree())
+                    .option("filename", json!("container.tar"))
+                    .option("architecture", json!(oci_arch(arch))),
+            ),
+        };
+
+        let mut pipeline = Pipeline::new(name);
+        pipeline.push(stage);
+        Some(pipeline)
+    }
+
+    /// The assembler of version 1 manifests that makes the artifact for `arch` from `layout`,
+    /// `bootloader` is the bootloader option of the qemu assembler.
+    pub fn assembler(
+        self,
+        arch: &str,
+        layout: Option<&Layout>,
+        bootloader: Option<Value>,
+    ) -> Result<Option<Stage>, String> {
+        let (format, filename) = match self {
+            ImageFormat::Raw | ImageFormat::Ami => ("raw", image::FILENAME),
+            ImageFormat::Qcow2 => ("qcow2", "disk.qcow2"),
+            ImageFormat::Vmdk => ("vmdk", "disk.vmdk"),
+            ImageFormat::Vhd => ("vpc", "disk.vhd"),
+            ImageFormat::Tar => {
+                return Ok(Some(
+                    Stage::new("org.osbuild.tar").option("filename", json!("root.tar")),
+                ))
+            }
+            ImageFormat::Oci => {
+                return Ok(Some(
+                    Stage::new("org.osbuild.oci-archive")
+                        .option("filename", json!("container.tar"))
+                        .option("architecture", json!(oci_arch(arch))),
+                ))
+            }
+            ImageFormat::Iso => {
+                return Err("installer ISOs have no version 1 assembler".to_string())
+            }
+        };
+
+        match layout {
+            Some(layout) => image::assembler(layout, format, filename, bootloader).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The stage converting the disk of the image pipeline to `filename` in `format`.
+fn qemu(filename: &str, format: serde_json::Value) -> Stage {
+    Stage::new("org.osbuild.qemu")
+        .input(
+            "image",
+            json!({
+                "type": "org.osbuild.files",
+                "origin": "org.osbuild.pipeline",
+                "references": { "name:image": { "file": image::FILENAME } },
+            }),
+        )
+        .option("filename", json!(filename))
+        .option("format", format)
+}
+
+/// The tree of the os pipeline as an input.
+fn os_tree() -> serde_json::Value {
+    json!({
+        "type": "org.osbuild.tree",
+        "origin": "org.osbuild.pipeline",
+        "references": ["name:os"],
+    })
+}
+
+/// The name OCI gives to `arch`.
+fn oci_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+#[test]
+fn format_pipeline() {
+    assert_eq!("qcow2".parse(), Ok(ImageFormat::Qcow2));
+    assert_eq!("container".parse(), Ok(ImageFormat::Oci));
+    assert!("iso9660".parse::<ImageFormat>().is_err());
+    assert!(ImageFormat::Ami.is_disk());
+    assert!(!ImageFormat::Oci.is_disk());
+    assert!(ImageFormat::Raw.pipeline("x86_64").is_none());
+
+    let json = |format: ImageFormat| {
+        let mut manifest = crate::osbuild::Manifest::new();
+        manifest.push(format.pipeline("aarch64").unwrap());
+        serde_json::from_str::<serde_json::Value>(&manifest.to_json()).unwrap()["pipelines"][0]
+            .clone()
+    };
+
+    let vhd = json(ImageFormat::Vhd);
+    assert_eq!(vhd["name"], "vhd");
+    assert_eq!(vhd["stages"][0]["options"]["format"]["type"], "vpc");
+    assert_eq!(
+        vhd["stages"][0]["inputs"]["image"]["references"]["name:image"]["file"],
+        "disk.img"
+    );
+
+    let tar = json(ImageFormat::Tar);
+    assert_eq!(tar["stages"][0]["options"]["root-node"], "omit");
+    assert_eq!(
+        tar["stages"][0]["inputs"]["tree"]["references"],
+        json!(["name:os"])
+    );
+
+    let oci = json(ImageFormat::Oci);
+    assert_eq!(oci["stages"][0]["type"], "org.osbuild.oci-archive");
+    assert_eq!(oci["stages"][0]["options"]["architecture"], "arm64");
+}
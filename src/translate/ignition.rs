@@ -0,0 +1,379 @@
+//! Ignition provisions Fedora CoreOS and similar systems from a JSON config when they first boot.
+//! The config of a kickstart has its users and their SSH keys, systemd units and the files it
+//! writes. Packages and the disk belong to the image the config is given to, they're left out.
+
+use base64::Engine;
+use log::*;
+use serde_json::{json, Map, Value};
+
+use super::blueprint::Customizations;
+use super::{Report, TranslateError, Translator};
+use crate::kickstart::Kickstart;
+
+/// The version of the config specification, the first with kernel arguments is 3.3.0.
+const VERSION: &str = "3.4.0";
+
+/// Units written into this directory become units of the config instead of files.
+const UNIT_DIR: &str = "/etc/systemd/system/";
+
+/// The kinds of units, by their suffix.
+const UNIT_SUFFIXES: &[&str] = &[
+    ".service",
+    ".socket",
+    ".timer",
+    ".target",
+    ".path",
+    ".mount",
+    ".automount",
+];
+
+impl Translator {
+    /// The Ignition config for `kickstart`.
+    pub fn ignition(mut self, kickstart: &Kickstart) -> Result<(Value, Report), TranslateError> {
+        self.customizations = Some(Customizations::default());
+
+        let layout = self.read(kickstart)?;
+        let customized = self.customizations.take().unwrap_or_default();
+
+        let mut config = json!({ "ignition": { "version": VERSION } });
+
+        let users: Vec<_> = customized.users.iter().map(user).collect();
+
+        if !users.is_empty() {
+            config["passwd"]["users"] = json!(users);
+        }
+
+        if !customized.groups.is_empty() {
+            config["passwd"]["groups"] = json!(customized.groups);
+        }
+
+        let mut units: Vec<Map<String, Value>> = self
+            .units
+            .iter()
+            .map(|unit| {
+                let mut created = Map::new();
+                created.insert("name".to_string(), unit["filename"].clone());
+                created.insert("contents".to_string(), json!(contents(&unit["config"])));
+
+                if unit["config"].get("Install").is_some() {
+                    created.insert("enabled".to_string(), json!(true));
+                }
+
+                created
+            })
+            .collect();
+
+        let mut files = Vec::new();
+
+        for embedded in &self.files {
+            let name = embedded.path.strip_prefix(UNIT_DIR).filter(|name| {
+                !name.contains('/') && UNIT_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+            });
+
+            match (name, std::str::from_utf8(&embedded.data)) {
+                (Some(name), Ok(data)) => {
+                    let mut created = Map::new();
+                    created.insert("name".to_string(), json!(name));
+                    created.insert("contents".to_string(), json!(data));
+                    units.push(created);
+                }
+                _ => files.push(file(&embedded.path, &embedded.data)),
+            }
+        }
+
+        let mut enabled = self.enabled_services.clone();
+        enabled.extend(customized.enabled_services.iter().cloned());
+        let mut disabled = self.disabled_services.clone();
+        disabled.extend(customized.disabled_services.iter().cloned());
+
+        for (services, state) in [(enabled, true), (disabled, false)] {
+            for service in services {
+                // Units without a suffix are services, as they are to systemctl.
+                let name = match service.contains('.') {
+                    true => service,
+                    false => format!("{}.service", service),
+                };
+
+                match units.iter_mut().find(|unit| unit["name"] == name.as_str()) {
+                    Some(unit) => {
+                        unit.insert("enabled".to_string(), json!(state));
+                    }
+                    None => {
+                        let mut unit = Map::new();
+                        unit.insert("name".to_string(), json!(name));
+                        unit.insert("enabled".to_string(), json!(state));
+                        units.push(unit);
+                    }
+                }
+            }
+        }
+
+        if !units.is_empty() {
+            config["systemd"]["units"] = json!(units);
+        }
+
+        if let Some(hostname) = &customized.hostname {
+            files.push(file("/etc/hostname", format!("{}\n", hostname).as_bytes()));
+        }
+
+        if !files.is_empty() {
+            config["storage"]["files"] = json!(files);
+        }
+
+        let mut links = Vec::new();
+
+        if let Some(zone) = &self.time.zone {
+            links.push(link(
+                "/etc/localtime",
+                &format!("../usr/share/zoneinfo/{}", zone),
+            ));
+        }
+
+        if let Some(target) = &self.default_target {
+            links.push(link(
+                "/etc/systemd/system/default.target",
+                &format!("/usr/lib/systemd/system/{}", target),
+            ));
+        }
+
+        if !links.is_empty() {
+            config["storage"]["links"] = json!(links);
+        }
+
+        if !self.kernel_opts.is_empty() {
+            config["kernelArguments"] = json!({ "shouldExist": self.kernel_opts });
+        }
+
+        for (left_out, what) in [
+            (
+                !self.packages.include.is_empty()
+                    || !self.packages.groups.is_empty()
+                    || !self.packages.exclude.is_empty(),
+                "packages",
+            ),
+            (layout.is_some(), "the disk layout"),
+            (!self.time.timeservers().is_empty(), "time servers"),
+            (self.authselect.is_some(), "the authselect profile"),
+            (!self.modprobe.is_empty(), "modprobe configuration"),
+            (
+                !self.gpgkeys.is_empty() || !self.gpgkeys_fromtree.is_empty(),
+                "GPG keys",
+            ),
+            (self.rhsm.is_some(), "the subscription"),
+            (self.oscap.is_some(), "the OpenSCAP remediation"),
+            (self.update_ca_trust, "the trust store update"),
+            (
+                !customized.ports.is_empty()
+                    || !customized.enabled_firewall.is_empty()
+                    || !customized.disabled_firewall.is_empty(),
+                "the firewall",
+            ),
+            (
+                !customized.languages.is_empty() || customized.keyboard.is_some(),
+                "the locale",
+            ),
+        ] {
+            if left_out {
+                warn!("ignition: {} can't be configured, leaving it out", what);
+            }
+        }
+
+        Ok((config, self.report))
+    }
+}
+
+/// The user of the config for the blueprint `user`, Ignition only takes hashed passwords.
+fn user(user: &Map<String, Value>) -> Value {
+    let name = user["name"].as_str().unwrap_or_default();
+    let mut created = json!({ "name": name });
+
+    if let Some(password) = user.get("password").and_then(Value::as_str) {
+        match password.starts_with('$') {
+            true => created["passwordHash"] = json!(password),
+            false => warn!(
+                "ignition: the password of '{}' isn't hashed, leaving it out",
+                name
+            ),
+        }
+    }
+
+    if let Some(key) = user.get("key") {
+        created["sshAuthorizedKeys"] = json!([key]);
+    }
+
+    for (key, field) in [
+        ("groups", "groups"),
+        ("home", "homeDir"),
+        ("shell", "shell"),
+        ("description", "gecos"),
+        ("uid", "uid"),
+    ] {
+        if let Some(value) = user.get(key) {
+            created[field] = value.clone();
+        }
+    }
+
+    if user.contains_key("gid") {
+        warn!(
+            "ignition: users have no group id, leaving out that of '{}'",
+            name
+        );
+    }
+
+    created
+}
+
+/// The file at `path` holding `data`, which replaces any file that's already there.
+fn file(path: &str, data: &[u8]) -> Value {
+    json!({
+        "path": path,
+        "mode": 0o644,
+        "overwrite": true,
+        "contents": {
+            "source": format!(
+                "data:;base64,{}",
+                base64::engine::general_purpose::STANDARD.encode(data)
+            ),
+        },
+    })
+}
+
+fn link(path: &str, target: &str) -> Value {
+    json!({ "path": path, "target": target, "overwrite": true })
+}
+
+/// The unit file for the `config` of the systemd unit stage, with an entry for every value of a
+/// list. `[Unit]` goes first and `[Install]` last, as they do in unit files.
+fn contents(config: &Value) -> String {
+    let mut sections: Vec<_> = config.as_object().into_iter().flatten().collect();
+    sections.sort_by_key(|(section, _)| match section.as_str() {
+        "Unit" => 0,
+        "Install" => 2,
+        _ => 1,
+    });
+
+    let mut contents = String::new();
+
+    for (section, entries) in sections {
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+
+        contents = contents + "[" + section + "]\n";
+
+        for (key, value) in entries.as_object().into_iter().flatten() {
+            let values = match value {
+                Value::Array(values) => values.clone(),
+                value => vec![value.clone()],
+            };
+
+            for value in values {
+                let value = match value {
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                };
+
+                contents = contents + key + "=" + &value + "\n";
+            }
+        }
+    }
+
+    contents
+}
+
+#[test]
+fn ignition_config() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-ignition-config");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "user --name=core --groups=wheel --password=$6$salt$hash --iscrypted\n\
+         sshkey --username=core \"ssh-ed25519 AAAA core@example\"\n\
+         rootpw hunter2\n\
+         timezone Europe/Amsterdam\n\
+         services --enabled=chronyd\n\
+         network --hostname=node.example\n\
+         bootloader --append=\"quiet\"\n\
+         %packages\nvim\n%end\n\
+         %post\n\
+         cat > /etc/systemd/system/hello.service <<'EOF'\n\
+         [Service]\nExecStart=/usr/bin/echo hello\nEOF\n\
+         systemctl enable hello.service\n\
+         cat > /etc/motd <<EOF\nWelcome\nEOF\n\
+         %end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (config, _) = Translator::new().ignition(&kickstart).unwrap();
+
+    assert_eq!(config["ignition"]["version"], VERSION);
+    assert_eq!(
+        config["passwd"]["users"],
+        json!([
+            {
+                "name": "core",
+                "passwordHash": "$6$salt$hash",
+                "groups": ["wheel"],
+                "sshAuthorizedKeys": ["ssh-ed25519 AAAA core@example"],
+            },
+            // The password isn't hashed.
+            { "name": "root" },
+        ])
+    );
+    assert_eq!(
+        config["systemd"]["units"],
+        json!([
+            {
+                "name": "hello.service",
+                "contents": "[Service]\nExecStart=/usr/bin/echo hello\n",
+                "enabled": true,
+            },
+            { "name": "chronyd.service", "enabled": true },
+        ])
+    );
+    assert_eq!(
+        config["storage"]["files"],
+        json!([
+            {
+                "path": "/etc/motd",
+                "mode": 0o644,
+                "overwrite": true,
+                "contents": { "source": "data:;base64,V2VsY29tZQo=" },
+            },
+            {
+                "path": "/etc/hostname",
+                "mode": 0o644,
+                "overwrite": true,
+                "contents": { "source": "data:;base64,bm9kZS5leGFtcGxlCg==" },
+            },
+        ])
+    );
+    assert_eq!(
+        config["storage"]["links"][0],
+        json!({
+            "path": "/etc/localtime",
+            "target": "../usr/share/zoneinfo/Europe/Amsterdam",
+            "overwrite": true,
+        })
+    );
+    assert_eq!(config["kernelArguments"]["shouldExist"], json!(["quiet"]));
+
+    assert_eq!(
+        contents(&json!({
+            "Install": { "WantedBy": ["multi-user.target"] },
+            "Service": { "ExecStart": ["a", "b"], "RemainAfterExit": true },
+            "Unit": { "Description": "Two" },
+        })),
+        "[Unit]\nDescription=Two\n\n\
+         [Service]\nExecStart=a\nExecStart=b\nRemainAfterExit=true\n\n\
+         [Install]\nWantedBy=multi-user.target\n"
+    );
+}
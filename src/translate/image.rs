@@ -0,0 +1,369 @@
+//! The image pipeline turns a [`Layout`] into a disk image: it partitions a file, creates the
+//! volume groups and filesystems and copies the tree of the os pipeline onto them.
+
+use serde_json::{json, Map, Value};
+
+use super::storage::{Filesystem, Layout, Location};
+use crate::osbuild::{Pipeline, Stage};
+
+/// The file in the tree of the image pipeline that holds the disk.
+pub const FILENAME: &str = "disk.img";
+
+/// Partition types by filesystem type, for GPT and for dos partition tables.
+fn partition_type(label: &str, fstype: &str) -> &'static str {
+    let gpt = label == "gpt";
+
+    match fstype {
+        "efi" | "vfat" if gpt => "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+        "efi" | "vfat" => "ef",
+        "biosboot" => "21686148-6449-6E6F-744E-656564454649",
+        "prepboot" if gpt => "9E1A2D38-C612-4316-AA26-8B49521E5A8B",
+        "prepboot" => "41",
+        "swap" if gpt => "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F",
+        "swap" => "82",
+        "lvmpv" if gpt => "E6D6D379-F507-44C2-A23C-238F2A3DF928",
+        "lvmpv" => "8e",
+        _ if gpt => "0FC63DAF-8483-4772-8E79-3D69D8477DE4",
+        _ => "83",
+    }
+}
+
+/// The name of the device, and mount, of a volume, derived from its mountpoint.
+pub fn device_name(mountpoint: &str) -> String {
+    match mountpoint {
+        "/" => "root".to_string(),
+        mountpoint => mountpoint.trim_start_matches('/').replace('/', "-"),
+    }
+}
+
+/// A loopback device over the whole disk, or over `start` and `size` sectors of it.
+pub fn loopback(part: Option<(u64, u64)>) -> Value {
+    let mut options = json!({ "filename": FILENAME, "lock": true });
+
+    if let Some((start, size)) = part {
+        options["start"] = json!(start);
+        options["size"] = json!(size);
+    }
+
+    json!({ "type": "org.osbuild.loopback", "options": options })
+}
+
+/// The devices a filesystem needs with its own device called `name`, a logical volume also
+/// needs the partition it's on.
+fn devices(layout: &Layout, filesystem: &Filesystem, name: &str) -> Map<String, Value> {
+    let mut devices = Map::new();
+
+    let pv = match &filesystem.location {
+        Location::Partition(index) => {
+            let partition = &layout.partitions[*index];
+            devices.insert(
+                name.to_string(),
+                loopback(Some((partition.start, partition.size))),
+            );
+            return devices;
+        }
+        Location::LogVol { pv, name: volume } => {
+            devices.insert(
+                name.to_string(),
+                json!({
+                    "type": "org.osbuild.lvm2.lv",
+                    "parent": device_name(&layout.partitions[*pv].volume.mountpoint),
+                    "options": { "volume": volume },
+                }),
+            );
+            *pv
+        }
+    };
+
+    let partition = &layout.partitions[pv];
+    devices.insert(
+        device_name(&partition.volume.mountpoint),
+        loopback(Some((partition.start, partition.size))),
+    );
+
+    devices
+}
+
+/// Mount all filesystems for `stage`, parents before their children.
+pub fn mounted(layout: &Layout, mut stage: Stage) -> Stage {
+    let mut filesystems: Vec<&Filesystem> = layout
+        .filesystems
+        .iter()
+        .filter(|filesystem| filesystem.volume.is_mounted())
+        .collect();
+
+    filesystems.sort_by_key(|filesystem| filesystem.volume.mountpoint.matches('/').count());
+    filesystems.sort_by_key(|filesystem| filesystem.volume.mountpoint != "/");
+
+    for filesystem in filesystems {
+        let name = device_name(&filesystem.volume.mountpoint);
+
+        for (name, device) in devices(layout, filesystem, &name) {
+            stage = stage.device(&name, device);
+        }
+
+        stage = stage.mount(json!({
+            "name": name,
+            "type": mount_type(&filesystem.volume.fstype),
+            "source": name,
+            "target": filesystem.volume.mountpoint,
+        }));
+    }
+
+    stage
+}
+
+fn mount_type(fstype: &str) -> &'static str {
+    match fstype {
+        "ext4" => "org.osbuild.ext4",
+        "btrfs" => "org.osbuild.btrfs",
+        "vfat" | "efi" => "org.osbuild.fat",
+        _ => "org.osbuild.xfs",
+    }
+}
+
+/// The volume id of a FAT filesystem, derived from its UUID.
+pub fn volid(uuid: &str) -> String {
+    uuid.replace('-', "")[..8].to_string()
+}
+
+/// The stage that creates `filesystem`.
+fn mkfs(layout: &Layout, filesystem: &Filesystem) -> Stage {
+    let volume = &filesystem.volume;
+
+    let mut stage = match volume.fstype.as_str() {
+        "vfat" | "efi" => {
+            Stage::new("org.osbuild.mkfs.fat").option("volid", json!(volid(&filesystem.uuid)))
+        }
+        "swap" => Stage::new("org.osbuild.mkswap").option("uuid", json!(filesystem.uuid)),
+        fstype => Stage::new(&format!("org.osbuild.mkfs.{}", fstype))
+            .option("uuid", json!(filesystem.uuid)),
+    };
+
+    if let Some(label) = &volume.label {
+        stage = stage.option("label", json!(label));
+    }
+
+    // Stages that create a filesystem expect it on `device`.
+    for (name, device) in devices(layout, filesystem, "device") {
+        stage = stage.device(&name, device);
+    }
+
+    stage
+}
+
+/// The `filesystems` of the `org.osbuild.fstab` stage.
+pub fn fstab(layout: &Layout) -> Value {
+    let mut filesystems: Vec<&Filesystem> = layout
+        .filesystems
+        .iter()
+        .filter(|filesystem| filesystem.volume.is_mounted() || filesystem.volume.fstype == "swap")
+        .collect();
+
+    filesystems.sort_by_key(|filesystem| filesystem.volume.mountpoint.matches('/').count());
+    filesystems.sort_by_key(|filesystem| filesystem.volume.mountpoint != "/");
+
+    let entries: Vec<Value> = filesystems
+        .iter()
+        .map(|filesystem| {
+            let volume = &filesystem.volume;
+
+            let (uuid, path, passno) = match volume.fstype.as_str() {
+                "swap" => (filesystem.uuid.clone(), "none", 0),
+                "vfat" | "efi" => {
+                    let volid = volid(&filesystem.uuid).to_uppercase();
+                    let uuid = format!("{}-{}", &volid[..4], &volid[4..]);
+                    (uuid, volume.mountpoint.as_str(), 2)
+                }
+                _ if volume.mountpoint == "/" => (filesystem.uuid.clone(), "/", 1),
+                _ => (filesystem.uuid.clone(), volume.mountpoint.as_str(), 2),
+            };
+
+            let vfs_type = match volume.fstype.as_str() {
+                "efi" => "vfat",
+                fstype => fstype,
+            };
+
+            json!({
+                "uuid": uuid,
+                "vfs_type": vfs_type,
+                "path": path,
+                "options": volume.fsoptions.as_deref().unwrap_or("defaults"),
+                "passno": passno,
+            })
+        })
+        .collect();
+
+    json!(entries)
+}
+
+/// The assembler of version 1 manifests that creates the disk in `format` as `filename`, it can
+/// only create filesystems on partitions. `bootloader` is its bootloader option.
+pub fn assembler(
+    layout: &Layout,
+    format: &str,
+    filename: &str,
+    bootloader: Option<Value>,
+) -> Result<Stage, String> {
+    let mut partitions = Vec::new();
+
+    for (index, partition) in layout.partitions.iter().enumerate() {
+        let mut entry = json!({
+            "start": partition.start,
+            "size": partition.size,
+            "type": partition_type(&layout.label, &partition.volume.fstype),
+        });
+
+        if layout.label == "dos" && partition.volume.fstype == "prepboot" {
+            entry["bootable"] = json!(true);
+        }
+
+        let filesystem = layout
+            .filesystems
+            .iter()
+            .find(|filesystem| filesystem.location == Location::Partition(index));
+
+        if let Some(filesystem) = filesystem.filter(|filesystem| filesystem.volume.is_mounted()) {
+            let volume = &filesystem.volume;
+
+            let (fstype, uuid) = match volume.fstype.as_str() {
+                "vfat" | "efi" => {
+                    let volid = volid(&filesystem.uuid).to_uppercase();
+                    ("vfat", format!("{}-{}", &volid[..4], &volid[4..]))
+                }
+                fstype => (fstype, filesystem.uuid.clone()),
+            };
+
+            entry["filesystem"] = json!({
+                "type": fstype,
+                "uuid": uuid,
+                "mountpoint": volume.mountpoint,
+            });
+
+            if let Some(label) = &volume.label {
+                entry["filesystem"]["label"] = json!(label);
+            }
+        }
+
+        partitions.push(entry);
+    }
+
+    if let Some(filesystem) = layout.filesystems.iter().find(|filesystem| {
+        matches!(filesystem.location, Location::LogVol { .. }) || filesystem.volume.fstype == "swap"
+    }) {
+        return Err(format!(
+            "the qemu assembler can't create '{}', only filesystems on partitions",
+            filesystem.volume.mountpoint
+        ));
+    }
+
+    let mut stage = Stage::new("org.osbuild.qemu")
+        .option("format", json!(format))
+        .option("filename", json!(filename))
+        .option("size", json!(layout.size))
+        .option("pttype", json!(layout.label))
+        .option("partitions", json!(partitions));
+
+    if let Some(bootloader) = bootloader {
+        stage = stage.option("bootloader", bootloader);
+    }
+
+    Ok(stage)
+}
+
+/// The pipeline that creates the disk, `bootloader` installs the bootloader on it.
+pub fn pipeline(layout: &Layout, bootloader: Option<Stage>) -> Pipeline {
+    let mut pipeline = Pipeline::new("image");
+
+    pipeline.push(
+        Stage::new("org.osbuild.truncate")
+            .option("filename", json!(FILENAME))
+            .option("size", json!(layout.size.to_string())),
+    );
+
+    let partitions: Vec<Value> = layout
+        .partitions
+        .iter()
+        .map(|partition| {
+            let mut entry = json!({
+                "start": partition.start,
+                "size": partition.size,
+                "type": partition_type(&layout.label, &partition.volume.fstype),
+            });
+
+            // PReP boot partitions are found through the boot flag on dos partition tables.
+            if layout.label == "dos" && partition.volume.fstype == "prepboot" {
+                entry["bootable"] = json!(true);
+            }
+
+            entry
+        })
+        .collect();
+
+    pipeline.push(
+        Stage::new("org.osbuild.sfdisk")
+            .option("label", json!(layout.label))
+            .option("partitions", json!(partitions))
+            .device("device", loopback(None)),
+    );
+
+    for (_, pv) in &layout.volgroups {
+        let partition = &layout.partitions[*pv];
+
+        let volumes: Vec<Value> = layout
+            .filesystems
+            .iter()
+            .filter_map(|filesystem| match &filesystem.location {
+                Location::LogVol { pv: on, name } if on == pv => {
+                    Some(json!({ "name": name, "size": format!("{}B", filesystem.size) }))
+                }
+                _ => None,
+            })
+            .collect();
+
+        pipeline.push(
+            Stage::new("org.osbuild.lvm2.create")
+                .option("volumes", json!(volumes))
+                .device("device", loopback(Some((partition.start, partition.size)))),
+        );
+    }
+
+    for filesystem in &layout.filesystems {
+        pipeline.push(mkfs(layout, filesystem));
+    }
+
+    // The volume groups are created with a generated name, which is set at the end.
+    for (name, pv) in &layout.volgroups {
+        let partition = &layout.partitions[*pv];
+
+        pipeline.push(
+            Stage::new("org.osbuild.lvm2.metadata")
+                .option("vg_name", json!(name))
+                .option("creation_host", json!("osbuild"))
+                .option("description", json!("created by osbuild-ks"))
+                .device("device", loopback(Some((partition.start, partition.size)))),
+        );
+    }
+
+    let copy = Stage::new("org.osbuild.copy")
+        .input(
+            "root-tree",
+            json!({
+                "type": "org.osbuild.tree",
+                "origin": "org.osbuild.pipeline",
+                "references": ["name:os"],
+            }),
+        )
+        .option(
+            "paths",
+            json!([{ "from": "input://root-tree/", "to": "mount://root/" }]),
+        );
+
+    pipeline.push(mounted(layout, copy));
+
+    if let Some(bootloader) = bootloader {
+        pipeline.push(bootloader);
+    }
+
+    pipeline
+}
@@ -0,0 +1,129 @@
+//! Commands that change how the installer runs rather than what it installs. They are parsed so
+//! the report can say what they did, and otherwise have no effect on the image.
+
+use super::options::Options;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Installer {
+    /// `graphical [--non-interactive]` or `text [--non-interactive]`
+    Display { mode: String, non_interactive: bool },
+    /// `cmdline`
+    Cmdline,
+    /// `rescue [--nomount|--romount]`
+    Rescue,
+    /// `vnc [--host=<host>] [--port=<port>] [--password=<password>]`
+    Vnc { host: Option<String> },
+    /// `updates [<url>]`
+    Updates(Option<String>),
+    /// `mediacheck`
+    MediaCheck,
+    /// `install`, which older kickstarts start with.
+    Install,
+}
+
+impl Installer {
+    /// Parse one of the installer commands, `name` is the command.
+    pub fn from_args(name: &str, args: &[String]) -> Result<Self, String> {
+        let (values, flags): (&[&str], &[&str]) = match name {
+            "graphical" | "text" => (&[], &["--non-interactive"]),
+            "rescue" => (&[], &["--nomount", "--romount"]),
+            "vnc" => (&["--host", "--port", "--password"], &[]),
+            "cmdline" | "updates" | "mediacheck" | "install" => (&[], &[]),
+            name => return Err(format!("'{}' is not an installer command", name)),
+        };
+
+        let options = Options::parse_known(args, values, flags)?;
+
+        // Only `updates` takes the location of the updates image.
+        match (name, options.positional()) {
+            (_, []) | ("updates", [_]) => {}
+            (_, [arg, ..]) => return Err(format!("unexpected argument '{}'", arg)),
+        }
+
+        Ok(match name {
+            "graphical" | "text" => Installer::Display {
+                mode: name.to_string(),
+                non_interactive: options.flag("--non-interactive"),
+            },
+            "cmdline" => Installer::Cmdline,
+            "rescue" => Installer::Rescue,
+            "updates" => Installer::Updates(options.positional().first().cloned()),
+            "mediacheck" => Installer::MediaCheck,
+            "install" => Installer::Install,
+            _ => Installer::Vnc {
+                host: options.value("--host").map(str::to_string),
+            },
+        })
+    }
+
+    /// Why this has no effect on the image.
+    pub fn reason(&self) -> String {
+        match self {
+            Installer::Display {
+                mode,
+                non_interactive: false,
+            } => format!("only selects the {} mode of the installer", mode),
+            Installer::Display { mode, .. } => {
+                format!(
+                    "only selects the non-interactive {} mode of the installer",
+                    mode
+                )
+            }
+            Installer::Cmdline => "only selects the command line mode of the installer".to_string(),
+            Installer::Rescue => "rescue mode doesn't install anything".to_string(),
+            Installer::Vnc { host: Some(host) } => {
+                format!("only connects the installer to the VNC viewer on {}", host)
+            }
+            Installer::Vnc { host: None } => {
+                "only starts a VNC server in the installer".to_string()
+            }
+            Installer::Updates(Some(url)) => format!("only updates the installer from {}", url),
+            Installer::Updates(None) => "only updates the installer".to_string(),
+            Installer::MediaCheck => "only checks the installation media".to_string(),
+            Installer::Install => "building the image is the installation".to_string(),
+        }
+    }
+
+    /// The systemd target for the display mode, when the mode may pick one for the image.
+    pub fn default_target(&self) -> Option<&'static str> {
+        match self {
+            Installer::Display { mode, .. } if mode == "graphical" => Some("graphical.target"),
+            Installer::Display { .. } => Some("multi-user.target"),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn installer_parse() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert_eq!(
+        Installer::from_args("text", &args("--non-interactive")),
+        Ok(Installer::Display {
+            mode: "text".to_string(),
+            non_interactive: true
+        })
+    );
+    assert_eq!(
+        Installer::from_args("vnc", &args("--host viewer.example --password=secret"))
+            .unwrap()
+            .reason(),
+        "only connects the installer to the VNC viewer on viewer.example"
+    );
+    assert_eq!(
+        Installer::from_args("graphical", &[])
+            .unwrap()
+            .default_target(),
+        Some("graphical.target")
+    );
+    assert_eq!(
+        Installer::from_args("updates", &args("https://example.com/updates.img")),
+        Ok(Installer::Updates(Some(
+            "https://example.com/updates.img".to_string()
+        )))
+    );
+    assert!(Installer::from_args("mediacheck", &args("--bogus")).is_err());
+    assert!(Installer::from_args("rescue", &args("--bogus")).is_err());
+    assert!(Installer::from_args("vnc", &args("--host")).is_err());
+}
@@ -0,0 +1,196 @@
+//! The installer ISO boots Anaconda, which installs the system from the original kickstart
+//! instead of a translation of it. Anaconda runs from the tree of the anaconda-tree pipeline,
+//! which is packed into the ISO with the kernel and the kickstart.
+
+use serde_json::json;
+
+use super::copy_stage;
+use crate::distro::Distro;
+use crate::osbuild::{Manifest, Pipeline, Stage};
+use crate::version::Version;
+
+/// The file in the tree of the bootiso pipeline that holds the ISO.
+pub const FILENAME: &str = "installer.iso";
+
+/// Where the kickstart is on the ISO.
+const KICKSTART_PATH: &str = "/osbuild.ks";
+
+/// The EFI architectures the ISO boots on, for the architectures it can be built for.
+fn efi_architectures(arch: &str) -> Option<&'static [&'static str]> {
+    match arch {
+        "x86_64" => Some(&["X64", "IA32"]),
+        "aarch64" => Some(&["AA64"]),
+        _ => None,
+    }
+}
+
+/// The volume label of the ISO, the installer finds the kickstart through it. Labels are at most
+/// 32 characters.
+fn label(distro: &Distro, release: u32, arch: &str) -> String {
+    let mut label = format!("{}-{}-{}", distro.title.replace(' ', "-"), release, arch);
+    label.truncate(32);
+    label
+}
+
+/// Add the pipelines that build an ISO installing `distro` on `arch` with the `kernel` of the
+/// installer to `manifest`, Anaconda follows `kickstart` which is embedded in it.
+pub fn pipelines(
+    manifest: &mut Manifest,
+    distro: &Distro,
+    version: Version,
+    arch: &str,
+    kernel: &str,
+    kickstart: &str,
+) -> Result<(), String> {
+    let efi = efi_architectures(arch)
+        .ok_or_else(|| format!("installer ISOs can't be built for {}", arch))?;
+    let release = match version {
+        Version::Fedora(release) | Version::Rhel(release) => release,
+    };
+    let isolabel = label(distro, release, arch);
+    let isolinux = arch == "x86_64";
+
+    // The packages of the installer aren't depsolved, like those of the os pipeline.
+    let mut anaconda = Pipeline::new("anaconda-tree");
+    anaconda.push(Stage::new("org.osbuild.rpm").input(
+        "packages",
+        json!({
+            "type": "org.osbuild.files",
+            "origin": "org.osbuild.source",
+            "references": {},
+        }),
+    ));
+    anaconda.push(
+        Stage::new("org.osbuild.buildstamp")
+            .option("arch", json!(arch))
+            .option("product", json!(distro.title))
+            .option("version", json!(release.to_string()))
+            .option("final", json!(true))
+            .option("variant", json!(""))
+            .option("bugurl", json!("")),
+    );
+
+    let anaconda_tree = json!({
+        "type": "org.osbuild.tree",
+        "origin": "org.osbuild.pipeline",
+        "references": ["name:anaconda-tree"],
+    });
+
+    let mut tree = Pipeline::new("bootiso-tree");
+    tree.push(
+        Stage::new("org.osbuild.bootiso.mono")
+            .input("rootfs", anaconda_tree.clone())
+            .input("kernel", anaconda_tree)
+            .option(
+                "product",
+                json!({ "name": distro.title, "version": release.to_string() }),
+            )
+            .option("kernel", json!(kernel))
+            .option("isolabel", json!(isolabel))
+            .option(
+                "efi",
+                json!({ "architectures": efi, "vendor": distro.efi_vendor }),
+            )
+            .option("isolinux", json!({ "enabled": isolinux }))
+            .option(
+                "kernel_opts",
+                json!(format!("inst.ks=hd:LABEL={}:{}", isolabel, KICKSTART_PATH)),
+            )
+            .option(
+                "rootfs",
+                json!({ "size": 9216, "compression": { "method": "xz" } }),
+            ),
+    );
+    tree.push(copy_stage(
+        &manifest.inline(kickstart.as_bytes()),
+        KICKSTART_PATH,
+    ));
+
+    let mut xorrisofs = Stage::new("org.osbuild.xorrisofs")
+        .input(
+            "tree",
+            json!({
+                "type": "org.osbuild.tree",
+                "origin": "org.osbuild.pipeline",
+                "references": ["name:bootiso-tree"],
+            }),
+        )
+        .option("filename", json!(FILENAME))
+        .option("volid", json!(isolabel))
+        .option("efi", json!("images/efiboot.img"));
+
+    if isolinux {
+        xorrisofs = xorrisofs
+            .option(
+                "boot",
+                json!({ "image": "isolinux/isolinux.bin", "catalog": "isolinux/boot.cat" }),
+            )
+            .option("isohybridmbr", json!("/usr/share/syslinux/isohdpfx.bin"));
+    }
+
+    let mut iso = Pipeline::new("bootiso");
+    iso.push(xorrisofs);
+    iso.push(Stage::new("org.osbuild.implantisomd5").option("filename", json!(FILENAME)));
+
+    manifest.push(anaconda);
+    manifest.push(tree);
+    manifest.push(iso);
+
+    Ok(())
+}
+
+#[test]
+fn iso_pipelines() {
+    let distro = Distro::default();
+
+    assert_eq!(label(&distro, 42, "x86_64"), "Fedora-42-x86_64");
+    let mut manifest = Manifest::new();
+
+    assert!(pipelines(
+        &mut manifest,
+        &distro,
+        Version::Fedora(42),
+        "s390x",
+        "6.14.0",
+        ""
+    )
+    .is_err());
+
+    pipelines(
+        &mut manifest,
+        &distro,
+        Version::Fedora(42),
+        "aarch64",
+        "6.14.0",
+        "lang en_US\n",
+    )
+    .unwrap();
+
+    let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let tree = &json["pipelines"][1];
+
+    assert_eq!(
+        tree["stages"][0]["options"]["kernel_opts"],
+        "inst.ks=hd:LABEL=Fedora-42-aarch64:/osbuild.ks"
+    );
+    assert_eq!(
+        tree["stages"][0]["options"]["efi"]["architectures"],
+        json!(["AA64"])
+    );
+    assert_eq!(tree["stages"][1]["type"], "org.osbuild.copy");
+    assert_eq!(
+        tree["stages"][1]["options"]["paths"][0]["to"],
+        "tree:///osbuild.ks"
+    );
+
+    let id = crate::osbuild::checksum(b"lang en_US\n");
+    assert!(tree["stages"][1]["inputs"]["files"]["references"]
+        .get(&id)
+        .is_some());
+    assert!(json["sources"]["org.osbuild.inline"]["items"]
+        .get(&id)
+        .is_some());
+    assert!(json["pipelines"][2]["stages"][0]["options"]
+        .get("boot")
+        .is_none());
+}
@@ -0,0 +1,2192 @@
+//! Translation of a parsed Kickstart into an osbuild manifest. The translator walks the sections
+//! of a Kickstart and accumulates what it learns, after which it is turned into the stages of the
+//! pipelines in the manifest.
+
+use std::fmt;
+
+use log::*;
+use serde_json::{json, Map, Value};
+
+use crate::dialect;
+use crate::distro::Distro;
+use crate::exit;
+use crate::fetch::{self, FetchError};
+use crate::kickstart::{Kickstart, Section, SectionKind, Span};
+use crate::osbuild::{checksum, Manifest, Pipeline, Stage};
+use crate::version::Version;
+
+mod addon;
+mod anaconda;
+mod attach;
+mod auth;
+pub mod blueprint;
+mod boot;
+mod buildroot;
+pub mod catalog;
+mod cloud_init;
+mod compose;
+pub mod deprecated;
+mod format;
+mod ignition;
+mod image;
+mod installer;
+mod iso;
+mod mpp;
+pub mod options;
+mod order;
+mod otk;
+pub mod packages;
+mod post;
+mod realm;
+mod report;
+mod rhsm;
+pub mod storage;
+mod time;
+pub mod words;
+
+use addon::{Kdump, Oscap};
+use attach::Attach;
+use auth::Authselect;
+use blueprint::Customizations;
+pub use boot::ARCHES;
+use boot::{Bootloader, SecureBoot};
+pub use compose::COMPOSE_FORMATS;
+pub use format::{ImageFormat, IMAGE_FORMATS};
+use installer::Installer;
+use options::Options;
+use packages::{PackageSet, Packages, Repo, Rpm};
+use post::{GpgKey, Post};
+pub use report::{Entry, Outcome, Report};
+use rhsm::{Rhsm, Syspurpose};
+use storage::{Layout, Problem, Snapshot, Storage};
+use time::Time;
+
+#[derive(Debug)]
+pub enum TranslateError {
+    Fetch(String, FetchError),
+    /// Something was skipped that isn't allowed to be skipped in strict mode.
+    Strict(String),
+    /// The storage commands don't describe a disk that can be created.
+    Storage(String),
+    /// Problems with the storage plan, found before working out the layout.
+    StoragePlan(Vec<Problem>),
+    /// The bootloader can't be installed on the described disk.
+    Bootloader(String),
+    /// The kickstart asks for something an image build can't do at all.
+    Unsupported(String),
+    /// A command or option was removed in the kickstart version.
+    Removed(String),
+    /// A pipeline to export or checkpoint isn't in the manifest.
+    UnknownPipeline(String),
+    /// The manifest can't be written in the asked for manifest version.
+    ManifestVersion(String),
+    /// Something is remote while network access isn't allowed.
+    Network(String),
+}
+
+impl fmt::Display for TranslateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TranslateError::Fetch(url, err) => write!(f, "failed to fetch '{}': {}", url, err),
+            TranslateError::Strict(err) => write!(f, "{} (not allowed with --strict)", err),
+            TranslateError::Storage(err) => write!(f, "invalid storage layout: {}", err),
+            TranslateError::StoragePlan(problems) => {
+                let problems: Vec<_> = problems.iter().map(ToString::to_string).collect();
+                write!(f, "invalid storage layout: {}", problems.join("\n"))
+            }
+            TranslateError::Bootloader(err) => write!(f, "can't install bootloader: {}", err),
+            TranslateError::Unsupported(err) => write!(f, "{}", err),
+            TranslateError::Removed(err) => write!(f, "{}", err),
+            TranslateError::UnknownPipeline(err) => write!(f, "{}", err),
+            TranslateError::ManifestVersion(err) => {
+                write!(f, "can't write a version 1 manifest: {}", err)
+            }
+            TranslateError::Network(url) => write!(
+                f,
+                "'{}' is remote and network access isn't allowed, see --allow-network",
+                url
+            ),
+        }
+    }
+}
+
+impl TranslateError {
+    pub fn code(&self) -> exit::Code {
+        match self {
+            TranslateError::Fetch(..) => exit::Code::IO,
+            TranslateError::Storage(_)
+            | TranslateError::StoragePlan(_)
+            | TranslateError::Bootloader(_) => exit::Code::Invalid,
+            TranslateError::Strict(_)
+            | TranslateError::Unsupported(_)
+            | TranslateError::Removed(_)
+            | TranslateError::ManifestVersion(_) => exit::Code::Unsupported,
+            TranslateError::UnknownPipeline(_) | TranslateError::Network(_) => exit::Code::Usage,
+        }
+    }
+}
+
+/// Where the passthrough kickstart is written in the tree of the `kickstart` pipeline, this is
+/// the location Anaconda reads its defaults from.
+const PASSTHROUGH_PATH: &str = "/usr/share/anaconda/interactive-defaults.ks";
+
+/// Certificates placed below this directory end up in the system trust store.
+const CA_TRUST_SOURCE: &str = "/etc/pki/ca-trust/source";
+
+/// The service that runs Initial Setup, toggled by `firstboot`.
+const INITIAL_SETUP: &str = "initial-setup.service";
+
+/// Commands about the disk and booting from it, which archives have neither of.
+const DISK_COMMANDS: &[&str] = &[
+    "part",
+    "partition",
+    "volgroup",
+    "logvol",
+    "raid",
+    "btrfs",
+    "autopart",
+    "reqpart",
+    "clearpart",
+    "zerombr",
+    "ignoredisk",
+    "snapshot",
+    "bootloader",
+    "zipl",
+];
+
+/// Where the EULA acceptance marker is written unless told otherwise.
+pub const DEFAULT_EULA_MARKER: &str = "/var/lib/initial-setup/eula-accepted";
+
+/// A file that is embedded in the manifest and copied into the tree.
+#[derive(Clone, Debug)]
+struct EmbeddedFile {
+    path: String,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+pub struct Translator {
+    /// Emit everything we could not translate into a kickstart in its own pipeline.
+    passthrough: bool,
+    /// Fail instead of skipping commands that have no image equivalent.
+    strict: bool,
+    /// Skip commands that were removed or can't be built instead of failing on them.
+    permissive: bool,
+    /// Forward logs from the installed system to the host given by `logging --host`.
+    remote_logging: bool,
+    /// Put credentials, such as activation keys, into the image.
+    embed_secrets: bool,
+    /// Use driver disks served over HTTP as package repositories.
+    driverdisk_repos: bool,
+    /// Allow retrieving GPG keys given by URL.
+    network: bool,
+    /// How GPG keys given by URL are retrieved.
+    fetch: fetch::Options,
+    /// Let the display mode of the installer pick the default target of the image.
+    display_target: bool,
+    /// Path of the marker file that records acceptance of the EULA.
+    eula_marker: String,
+    /// The kickstart syntax version, which decides what is deprecated or removed.
+    version: Version,
+    /// The distribution the kickstart installs.
+    distro: Distro,
+    /// The architecture of the image.
+    arch: String,
+    /// The artifact the manifest produces.
+    format: ImageFormat,
+    /// The version of the manifest format, 1 or 2.
+    manifest_version: u32,
+    /// Size of the disk image in MiB, growing partitions fill it.
+    image_size: Option<u64>,
+    /// The packages the depsolve picked, the rpm stage installs them.
+    depsolved: Vec<Rpm>,
+    /// The packages the depsolve picked for the build root.
+    depsolved_build: Vec<Rpm>,
+    /// Pipelines osbuild should export, recorded in the metadata of the manifest.
+    exports: Vec<String>,
+    /// Pipelines osbuild should checkpoint, recorded in the metadata of the manifest.
+    checkpoints: Vec<String>,
+    /// When the manifest was made in seconds since the epoch, manifests are reproducible
+    /// without it.
+    timestamp: Option<u64>,
+    /// The command line the manifest is made with, it's recorded in the metadata along with the
+    /// kickstart itself when it's given.
+    provenance: Option<Vec<String>>,
+    /// What the UUIDs of filesystems are derived from, the kickstart unless it's given.
+    seed: Option<String>,
+    /// ASCII armored GPG keys to import into the RPM database.
+    gpgkeys: Vec<String>,
+    /// Paths of GPG keys inside the tree to import into the RPM database.
+    gpgkeys_fromtree: Vec<String>,
+    /// Arguments for the kernel command line.
+    kernel_opts: Vec<String>,
+    /// Services to enable in the installed system.
+    enabled_services: Vec<String>,
+    /// Services to disable in the installed system.
+    disabled_services: Vec<String>,
+    /// The systemd target the installed system boots into.
+    default_target: Option<String>,
+    /// Configuration for the OpenSCAP remediation stage.
+    oscap: Option<serde_json::Value>,
+    /// The authselect profile to select.
+    authselect: Option<Authselect>,
+    /// Options for the subscription manager stage.
+    rhsm: Option<serde_json::Value>,
+    /// Commands for the modprobe configuration.
+    modprobe: Vec<serde_json::Value>,
+    /// Packages to install.
+    packages: Packages,
+    /// Options for units to create.
+    units: Vec<serde_json::Value>,
+    /// Time zone and time sources.
+    time: Time,
+    /// The disk layout.
+    storage: Storage,
+    /// How the image boots.
+    bootloader: Bootloader,
+    /// Files to write into the tree.
+    files: Vec<EmbeddedFile>,
+    /// Whether certificates were added to the system trust store.
+    update_ca_trust: bool,
+    /// Command lines that were not translated.
+    untranslated_commands: Vec<String>,
+    /// Sections, or the remainder of sections, that were not translated.
+    untranslated_sections: Vec<Section>,
+    /// What happened to every command and section.
+    report: Report,
+    /// What the commands only blueprints have customizations for customize, when translating
+    /// into a blueprint.
+    customizations: Option<Customizations>,
+}
+
+impl Translator {
+    pub fn new() -> Self {
+        Self {
+            eula_marker: DEFAULT_EULA_MARKER.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            manifest_version: 2,
+            ..Self::default()
+        }
+    }
+
+    pub fn passthrough(mut self, passthrough: bool) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn permissive(mut self, permissive: bool) -> Self {
+        self.permissive = permissive;
+        self
+    }
+
+    pub fn remote_logging(mut self, remote_logging: bool) -> Self {
+        self.remote_logging = remote_logging;
+        self
+    }
+
+    pub fn embed_secrets(mut self, embed_secrets: bool) -> Self {
+        self.embed_secrets = embed_secrets;
+        self
+    }
+
+    pub fn driverdisk_repos(mut self, driverdisk_repos: bool) -> Self {
+        self.driverdisk_repos = driverdisk_repos;
+        self
+    }
+
+    pub fn network(mut self, network: bool) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn fetch(mut self, fetch: fetch::Options) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    pub fn display_target(mut self, display_target: bool) -> Self {
+        self.display_target = display_target;
+        self
+    }
+
+    pub fn eula_marker(mut self, path: &str) -> Self {
+        self.eula_marker = path.to_string();
+        self
+    }
+
+    pub fn image_size(mut self, size: Option<u64>) -> Self {
+        self.image_size = size;
+        self
+    }
+
+    pub fn depsolved(mut self, depsolved: Vec<Rpm>) -> Self {
+        self.depsolved = depsolved;
+        self
+    }
+
+    pub fn exports(mut self, exports: &[&str]) -> Self {
+        self.exports = exports.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    pub fn checkpoints(mut self, checkpoints: &[&str]) -> Self {
+        self.checkpoints = checkpoints.iter().map(|name| name.to_string()).collect();
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: Option<u64>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn provenance(mut self, provenance: Option<Vec<String>>) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    pub fn depsolved_build(mut self, depsolved: Vec<Rpm>) -> Self {
+        self.depsolved_build = depsolved;
+        self
+    }
+
+    pub fn seed(mut self, seed: Option<&str>) -> Self {
+        self.seed = seed.map(str::to_string);
+        self
+    }
+
+    pub fn version(mut self, version: Version) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn arch(mut self, arch: &str) -> Self {
+        self.arch = arch.to_string();
+        self
+    }
+
+    pub fn format(mut self, format: ImageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn manifest_version(mut self, version: u32) -> Self {
+        self.manifest_version = version;
+        self
+    }
+
+    /// The version of the kernel in the image, for stages that have to find it in `/boot`.
+    pub fn kernel(mut self, kernel: Option<&str>) -> Self {
+        self.bootloader.kernel = kernel.map(str::to_string);
+        self
+    }
+
+    pub fn distro(mut self, distro: &Distro) -> Self {
+        self.distro = distro.clone();
+        self
+    }
+
+    pub fn translate(
+        mut self,
+        kickstart: &Kickstart,
+    ) -> Result<(Manifest, Report), TranslateError> {
+        let metadata = self.metadata(kickstart);
+
+        // The installer follows the kickstart itself, there is nothing to translate.
+        if self.format == ImageFormat::Iso {
+            let manifest = self.installer_manifest(kickstart)?;
+            return Ok((with_metadata(manifest, metadata)?, Report::default()));
+        }
+
+        let layout = self.read(kickstart)?;
+        let report = self.report.clone();
+
+        if !self.depsolved.is_empty() {
+            info!("Installing {} depsolved packages", self.depsolved.len());
+        } else if !self.packages.include.is_empty() || !self.packages.groups.is_empty() {
+            warn!(
+                "{} packages and {} groups from {} repositories are not depsolved, the rpm stage \
+                 doesn't reference them",
+                self.packages.include.len(),
+                self.packages.groups.len(),
+                self.distro.repositories.len() + self.packages.repos.len()
+            );
+        }
+
+        let manifest = self.manifest(layout.as_ref())?;
+
+        Ok((with_metadata(manifest, metadata)?, report))
+    }
+
+    /// The manifest for `kickstart` with rpm stages that install nothing, and the package set of
+    /// each pipeline that has one. They are for `tool`, which depsolves the packages itself.
+    fn undepsolved(
+        mut self,
+        kickstart: &Kickstart,
+        tool: &str,
+    ) -> Result<(Manifest, Vec<PackageSet>, Report), TranslateError> {
+        if self.format == ImageFormat::Iso {
+            return Err(TranslateError::Unsupported(format!(
+                "installer ISOs install their packages themselves, {} can't depsolve them",
+                tool
+            )));
+        }
+
+        if self.manifest_version == 1 {
+            return Err(TranslateError::ManifestVersion(format!(
+                "{} only depsolves version 2 manifests",
+                tool
+            )));
+        }
+
+        let (releasever, module_platform_id) = match (
+            self.distro.releasever.clone(),
+            self.distro.module_platform_id.clone(),
+        ) {
+            (Some(releasever), Some(module_platform_id)) => (releasever, module_platform_id),
+            _ => {
+                return Err(TranslateError::Unsupported(format!(
+                    "{} has no release for {} to depsolve packages for",
+                    self.distro.title, tool
+                )))
+            }
+        };
+
+        let layout = self.read(kickstart)?;
+        let report = self.report.clone();
+
+        let repos: Vec<_> = self
+            .distro
+            .repositories
+            .iter()
+            .map(|repo| Repo {
+                id: repo.id.clone(),
+                baseurl: repo.baseurl.replace("$basearch", &self.arch),
+            })
+            .collect();
+
+        // The build root only needs the repositories of the distribution.
+        let mut build = PackageSet {
+            pipeline: "build".to_string(),
+            arch: self.arch.clone(),
+            releasever,
+            module_platform_id,
+            repos: repos.clone(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+        };
+
+        let mut os = build.clone();
+        os.pipeline = "os".to_string();
+        os.repos.extend(self.packages.repos.iter().cloned());
+        os.include = self.packages.include.clone();
+        os.include.extend(self.packages.groups.iter().cloned());
+        os.exclude = self.packages.exclude.clone();
+
+        let base = self.distro.buildroot.clone();
+        let manifest = self.manifest(layout.as_ref())?;
+
+        let mut sets = vec![os];
+
+        if manifest.names().contains(&"build") {
+            build.include = buildroot::packages(&base, &manifest.kinds());
+            sets.insert(0, build);
+        }
+
+        Ok((manifest, sets, report))
+    }
+
+    /// Go through the commands and sections of `kickstart`, the disk is laid out once they're
+    /// all known.
+    fn read(&mut self, kickstart: &Kickstart) -> Result<Option<Layout>, TranslateError> {
+        if self.seed.is_none() {
+            self.seed = Some(kickstart.file().to_string());
+        }
+
+        for section in kickstart.sections() {
+            if section.kind() == SectionKind::Command {
+                self.commands(section)?;
+                continue;
+            }
+
+            let outcome = match section.kind() {
+                SectionKind::Packages => {
+                    self.packages.parse(section.args(), section.data());
+
+                    for group in &self.packages.groups {
+                        if let Some(reason) = self.distro.unknown_group(group) {
+                            warn!("%packages: {}", reason);
+                        }
+                    }
+
+                    Outcome::Translated
+                }
+                SectionKind::Post => self.post(section)?,
+                SectionKind::Addon => self.addon(section),
+                SectionKind::Anaconda => self.anaconda(section),
+                SectionKind::Certificate => self.certificate(section),
+                SectionKind::OnError | SectionKind::Traceback => self.installer_script(section),
+                _ => {
+                    self.untranslated_sections.push(section.clone());
+                    Outcome::Untranslated("section is not supported".to_string())
+                }
+            };
+
+            self.report
+                .record(&section_header(section), Some(section.span()), outcome);
+        }
+
+        let layout = if self.storage.is_empty() {
+            None
+        } else {
+            let problems = self.storage.validate(self.image_size);
+
+            if !problems.is_empty() {
+                return Err(TranslateError::StoragePlan(problems));
+            }
+
+            Some(
+                self.storage
+                    .layout(
+                        &self.arch,
+                        self.image_size,
+                        self.seed.as_deref().unwrap_or_default(),
+                    )
+                    .map_err(TranslateError::Storage)?,
+            )
+        };
+
+        if let Some(layout) = &layout {
+            self.snapshots(layout);
+        }
+
+        Ok(layout)
+    }
+
+    /// What goes in the metadata of the manifest: the tool and the kickstart it was made from,
+    /// the pipelines to export and checkpoint and, if they're given, when and how it was made.
+    fn metadata(&self, kickstart: &Kickstart) -> Map<String, Value> {
+        let mut metadata = Map::new();
+        let flattened = kickstart.file().to_string();
+
+        metadata.insert(
+            "generators".to_string(),
+            json!([{ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") }]),
+        );
+        metadata.insert(
+            "kickstart".to_string(),
+            json!({ "checksum": checksum(flattened.as_bytes()) }),
+        );
+
+        // The kickstart is kept as it was checksummed, so the checksum can be checked against it.
+        if let Some(invocation) = &self.provenance {
+            metadata["kickstart"]["data"] = json!(flattened);
+            metadata.insert("invocation".to_string(), json!(invocation));
+        }
+
+        if !self.exports.is_empty() {
+            metadata.insert("exports".to_string(), json!(self.exports));
+        }
+
+        if !self.checkpoints.is_empty() {
+            metadata.insert("checkpoints".to_string(), json!(self.checkpoints));
+        }
+
+        if let Some(timestamp) = self.timestamp {
+            metadata.insert("timestamp".to_string(), json!(timestamp));
+        }
+
+        metadata
+    }
+
+    fn commands(&mut self, section: &Section) -> Result<(), TranslateError> {
+        for (line, span) in section.lines() {
+            let mut parts = match words::split(line) {
+                Ok(parts) => parts.into_iter(),
+                Err(err) => {
+                    self.untranslated_commands.push(line.to_string());
+                    self.report
+                        .record(&redact(line), span, Outcome::Untranslated(err));
+                    continue;
+                }
+            };
+
+            let name = match parts.next() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let args: Vec<String> = parts.collect();
+
+            // Commands that fail the translation are named with where they are.
+            let element = match span {
+                Some(span) => format!("{}: '{}'", span, line),
+                None => format!("'{}'", line),
+            };
+
+            // Deprecated commands and options are translated like any other, with a warning.
+            let mut deprecations = Vec::new();
+            let mut removal = None;
+
+            for entry in deprecated::find(&name, &args) {
+                if entry.is_removed(self.version) {
+                    removal = Some(entry.removal(self.version));
+                    break;
+                }
+
+                if entry.is_deprecated(self.version) {
+                    deprecations.push(entry);
+                }
+            }
+
+            if let Some(removal) = removal {
+                if !self.permissive {
+                    return Err(TranslateError::Removed(format!("{}: {}", element, removal)));
+                }
+
+                self.report
+                    .record(&redact(line), span, Outcome::Skipped(removal));
+                continue;
+            }
+
+            // Commands and options the version doesn't have yet are left to the user.
+            if let Err(err) = dialect::check(&name, &args, self.version) {
+                self.untranslated_commands.push(line.to_string());
+                self.report
+                    .record(&redact(line), span, Outcome::Untranslated(err));
+                continue;
+            }
+
+            if let Some(reason) = self.distro.unsupported(&name) {
+                self.report
+                    .record(&redact(line), span, Outcome::Skipped(reason));
+                continue;
+            }
+
+            if !self.format.is_disk() && DISK_COMMANDS.contains(&name.as_str()) {
+                let reason = format!("{} archives have no disk to partition or boot", self.format);
+                self.report
+                    .record(&redact(line), span, Outcome::Skipped(reason));
+                continue;
+            }
+
+            let outcome = match name.as_str() {
+                "auth" | "authconfig" => self.authconfig(&args),
+                "authselect" => self.authselect(&args),
+                "firstboot" => self.firstboot(&args),
+                "eula" => self.eula(&args),
+                "logging" => self.logging(&args),
+                "timezone" => self.time(Time::timezone, &args),
+                "timesource" => self.time(Time::timesource, &args),
+                "rhsm" => self.rhsm(&args),
+                "syspurpose" => self.syspurpose(&args),
+                "realm" => self.realm(&args),
+                "driverdisk" => self.driverdisk(&args),
+                "device" => self.device(&args),
+                "part" | "partition" => self.storage(Storage::part, &args, span),
+                "volgroup" => self.storage(Storage::volgroup, &args, span),
+                "logvol" => self.storage(Storage::logvol, &args, span),
+                "raid" | "btrfs" => {
+                    let parse: storage::Command = match name.as_str() {
+                        "raid" => Storage::raid,
+                        _ => Storage::btrfs,
+                    };
+
+                    // They are validated along with the rest of the plan, but can't be created.
+                    match self.storage(parse, &args, span) {
+                        Outcome::Translated => Outcome::Untranslated(format!(
+                            "{} volumes are not supported by the image stages",
+                            name
+                        )),
+                        outcome => outcome,
+                    }
+                }
+                "autopart" => self.autopart(&args),
+                "reqpart" => self.reqpart(&args),
+                "clearpart" => self.clearpart(&args),
+                "zerombr" | "ignoredisk" => {
+                    Outcome::Skipped("the disk of an image starts out empty".to_string())
+                }
+                "iscsi" | "iscsiname" | "fcoe" | "zfcp" | "nvdimm" => attach(&name, &args),
+                "snapshot" => self.snapshot(&args),
+                "graphical" | "text" | "cmdline" | "rescue" | "vnc" | "install" => {
+                    self.installer(&name, &args)
+                }
+                "bootloader" => self.bootloader(&args),
+                "zipl" => self.zipl(&args),
+                "upgrade" => {
+                    let reason = "upgrades cannot be expressed as image builds";
+
+                    if !self.permissive {
+                        return Err(TranslateError::Unsupported(format!(
+                            "{}: {}",
+                            element, reason
+                        )));
+                    }
+
+                    Outcome::Skipped(reason.to_string())
+                }
+                "reboot" | "shutdown" | "halt" | "poweroff" => {
+                    let reason = "installer flow control has no image equivalent";
+
+                    if self.strict {
+                        return Err(TranslateError::Strict(format!("{}: {}", element, reason)));
+                    }
+
+                    Outcome::Skipped(reason.to_string())
+                }
+                name => match self
+                    .customizations
+                    .as_mut()
+                    .and_then(|customizations| customizations.command(name, &args))
+                {
+                    Some(outcome) => outcome,
+                    None => match deprecated::lookup(name) {
+                        Some(entry) => {
+                            // The reason it's skipped already says it's deprecated.
+                            deprecations.retain(|deprecation| deprecation.option.is_some());
+                            Outcome::Skipped(entry.diagnostic())
+                        }
+                        None => Outcome::Untranslated("command is not supported".to_string()),
+                    },
+                },
+            };
+
+            if let Outcome::Untranslated(_) = outcome {
+                self.untranslated_commands.push(line.to_string());
+            }
+
+            self.report.record(&redact(line), span, outcome).deprecated = deprecations
+                .iter()
+                .map(|entry| format!("'{}' is {}", entry.subject(), entry.diagnostic()))
+                .collect();
+        }
+
+        Ok(())
+    }
+
+    fn authconfig(&mut self, args: &[String]) -> Outcome {
+        let (authselect, unmapped) =
+            Authselect::from_authconfig(args, self.distro.authselect_profile.as_deref());
+
+        if let Some(authselect) = authselect {
+            self.set_authselect(authselect);
+        }
+
+        if unmapped.is_empty() {
+            Outcome::Translated
+        } else {
+            Outcome::Untranslated(format!(
+                "options '{}' have no authselect equivalent",
+                unmapped.join(" ")
+            ))
+        }
+    }
+
+    fn authselect(&mut self, args: &[String]) -> Outcome {
+        match Authselect::from_args(args) {
+            Ok(authselect) => {
+                self.set_authselect(authselect);
+                Outcome::Translated
+            }
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    /// `firstboot` toggles Initial Setup, `--reconfig` has it run in reconfiguration mode which
+    /// it does when it finds a marker file.
+    fn firstboot(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(
+            args,
+            &[],
+            &[
+                "--enable",
+                "--enabled",
+                "--disable",
+                "--disabled",
+                "--reconfig",
+            ],
+        ) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        match options.names().last() {
+            Some("--enable" | "--enabled") => {
+                self.enabled_services.push(INITIAL_SETUP.to_string());
+            }
+            Some("--disable" | "--disabled") => {
+                self.disabled_services.push(INITIAL_SETUP.to_string());
+            }
+            Some("--reconfig") => {
+                self.enabled_services.push(INITIAL_SETUP.to_string());
+                self.files.push(EmbeddedFile {
+                    path: "/etc/reconfigSys".to_string(),
+                    data: Vec::new(),
+                });
+            }
+            _ => {
+                return Outcome::Untranslated(
+                    "needs one of --enable, --disable or --reconfig".to_string(),
+                );
+            }
+        }
+
+        Outcome::Translated
+    }
+
+    /// `eula --agreed` accepts the license up front, written as a marker file so Initial Setup
+    /// doesn't ask on first boot.
+    fn eula(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(args, &[], &["--agreed", "--agree"]) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        if !options.flag("--agreed") && !options.flag("--agree") {
+            return Outcome::Skipped("eula without --agreed has no effect".to_string());
+        }
+
+        self.files.push(EmbeddedFile {
+            path: self.eula_marker.clone(),
+            data: Vec::new(),
+        });
+
+        Outcome::Translated
+    }
+
+    /// `logging` configures the installer's logging. A remote host is forwarded to from the
+    /// installed system with rsyslog, but only when asked for as the meaning differs.
+    fn logging(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(args, &["--host", "--port", "--level"], &[]) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        let host = match options.value("--host") {
+            Some(host) => host,
+            None => return Outcome::Skipped("only configures installer logging".to_string()),
+        };
+
+        if !self.remote_logging {
+            return Outcome::Skipped(
+                "remote logging from the image needs --remote-logging".to_string(),
+            );
+        }
+
+        let port = options.value("--port").unwrap_or("514");
+
+        self.files.push(EmbeddedFile {
+            path: "/etc/rsyslog.d/osbuild-ks-remote.conf".to_string(),
+            data: format!("*.* @@{}:{}\n", host, port).into_bytes(),
+        });
+        self.enabled_services.push("rsyslog.service".to_string());
+
+        Outcome::Translated
+    }
+
+    fn rhsm(&mut self, args: &[String]) -> Outcome {
+        let rhsm = match Rhsm::from_args(args) {
+            Ok(rhsm) => rhsm,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        self.rhsm = Some(rhsm.stage_options());
+
+        if let Some(commands) = rhsm.register_commands() {
+            if self.embed_secrets {
+                self.firstboot_unit(
+                    "osbuild-ks-rhsm-register.service",
+                    "Register with Red Hat Subscription Management",
+                    Some("!/etc/pki/consumer/cert.pem"),
+                    commands,
+                    true,
+                );
+            } else {
+                warn!("rhsm: not embedding the activation key without --embed-secrets");
+            }
+        }
+
+        Outcome::Translated
+    }
+
+    fn syspurpose(&mut self, args: &[String]) -> Outcome {
+        let syspurpose = match Syspurpose::from_args(args) {
+            Ok(syspurpose) => syspurpose,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        self.files.push(EmbeddedFile {
+            path: "/etc/rhsm/syspurpose/syspurpose.json".to_string(),
+            data: syspurpose.to_json().into_bytes(),
+        });
+
+        Outcome::Translated
+    }
+
+    /// `realm join` is done on first boot. Without a password the join needs a computer account
+    /// that was set up beforehand, with a one time password only if it may be embedded.
+    fn realm(&mut self, args: &[String]) -> Outcome {
+        let join = match realm::Join::from_args(args) {
+            Ok(join) => join,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        self.packages.require(realm::PACKAGES);
+
+        let password = join.one_time_password.is_some() && self.embed_secrets;
+
+        if !join.no_password && !password {
+            return Outcome::Skipped(format!(
+                "joining '{}' needs credentials, join after boot or use --embed-secrets",
+                join.domain
+            ));
+        }
+
+        self.firstboot_unit(
+            "osbuild-ks-realm-join.service",
+            "Join the realm configured in the kickstart",
+            Some("!/etc/krb5.keytab"),
+            vec![join.command(self.embed_secrets)],
+            true,
+        );
+
+        Outcome::Translated
+    }
+
+    /// `driverdisk` loads drivers into the installer. When the driver disk is served over HTTP it
+    /// can be used as a package repository instead, if asked for.
+    fn driverdisk(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(args, &["--source", "--biospart"], &[]) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        let source = match options.value("--source") {
+            Some(source) => source.to_string(),
+            None => {
+                return Outcome::Skipped(format!(
+                    "driver disk '{}' is only available to the installer",
+                    options
+                        .positional()
+                        .first()
+                        .map(String::as_str)
+                        .unwrap_or_default()
+                ));
+            }
+        };
+
+        if !fetch::is_url(&source) {
+            return Outcome::Skipped(format!(
+                "driver disk source '{}' is only available to the installer",
+                source
+            ));
+        }
+
+        if !self.driverdisk_repos {
+            return Outcome::Skipped(format!(
+                "driver disks are loaded by the installer, use --driverdisk-repos to install \
+                 packages from '{}'",
+                source
+            ));
+        }
+
+        let id = format!("driverdisk-{}", self.packages.repos.len());
+
+        info!("Using driver disk '{}' as repository '{}'", source, id);
+
+        self.packages.repos.push(Repo {
+            id,
+            baseurl: source,
+        });
+
+        Outcome::Translated
+    }
+
+    /// `device [type] <module> [--opts=<options>]` loads a kernel module with options, older
+    /// kickstarts put the device type before the module.
+    fn device(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(args, &["--opts"], &[]) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        let positional: Vec<&str> = options.positional().iter().map(String::as_str).collect();
+
+        let module = match positional[..] {
+            [module] => module,
+            ["scsi" | "eth", module] => module,
+            _ => return Outcome::Untranslated("needs a single module name".to_string()),
+        };
+
+        let mut cmdline = format!("/sbin/modprobe --ignore-install {}", module);
+
+        if let Some(opts) = options.value("--opts") {
+            cmdline = format!("{} {}", cmdline, opts);
+        }
+
+        self.modprobe.push(json!({
+            "command": "install",
+            "modulename": module,
+            "cmdline": cmdline,
+        }));
+
+        self.files.push(EmbeddedFile {
+            path: format!("/etc/modules-load.d/{}.conf", module),
+            data: format!("{}\n", module).into_bytes(),
+        });
+
+        Outcome::Translated
+    }
+
+    /// There is no stage that snapshots a logical volume, so snapshots are taken on first boot
+    /// before anything else changed, from space the layout set aside in the volume group.
+    fn snapshots(&mut self, layout: &Layout) {
+        for snapshot in &self.storage.snapshots {
+            if !layout.snapshots.iter().any(|(taken, _)| taken == snapshot) {
+                warn!(
+                    "snapshot: logical volume '{}/{}' is not in the layout, not taking '{}'",
+                    snapshot.vgname, snapshot.lvname, snapshot.name
+                );
+            }
+        }
+
+        if !layout.snapshots.is_empty() {
+            self.packages.require(&["lvm2"]);
+        }
+
+        for (snapshot, size) in &layout.snapshots {
+            self.firstboot_unit(
+                &format!("osbuild-ks-snapshot-{}.service", snapshot.name),
+                &format!(
+                    "Snapshot {}/{} after installing",
+                    snapshot.vgname, snapshot.lvname
+                ),
+                Some(&format!("!/dev/{}/{}", snapshot.vgname, snapshot.name)),
+                vec![format!(
+                    "/usr/sbin/lvcreate --snapshot --name {} --size {}m {}/{}",
+                    snapshot.name, size, snapshot.vgname, snapshot.lvname
+                )],
+                false,
+            );
+        }
+    }
+
+    /// A oneshot unit that runs `commands` on boot until `condition` no longer holds, for things
+    /// that need a running system and possibly network access.
+    fn firstboot_unit(
+        &mut self,
+        name: &str,
+        description: &str,
+        condition: Option<&str>,
+        commands: Vec<String>,
+        network: bool,
+    ) {
+        let mut unit = json!({ "Description": description });
+
+        if network {
+            unit["Wants"] = json!(["network-online.target"]);
+            unit["After"] = json!(["network-online.target"]);
+        }
+
+        if let Some(condition) = condition {
+            unit["ConditionPathExists"] = json!(condition);
+        }
+
+        self.units.push(json!({
+            "filename": name,
+            "unit-type": "system",
+            "config": {
+                "Unit": unit,
+                "Service": {
+                    "Type": "oneshot",
+                    "RemainAfterExit": true,
+                    "ExecStart": commands,
+                },
+                "Install": {
+                    "WantedBy": ["multi-user.target"],
+                },
+            },
+        }));
+
+        self.enabled_services.push(name.to_string());
+    }
+
+    fn time(
+        &mut self,
+        parse: fn(&mut Time, &[String]) -> Result<(), String>,
+        args: &[String],
+    ) -> Outcome {
+        match parse(&mut self.time, args) {
+            Ok(()) => Outcome::Translated,
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    fn storage(
+        &mut self,
+        parse: storage::Command,
+        args: &[String],
+        span: Option<&Span>,
+    ) -> Outcome {
+        match parse(&mut self.storage, args, span) {
+            Ok(()) => Outcome::Translated,
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    fn autopart(&mut self, args: &[String]) -> Outcome {
+        if !self.storage.is_empty() {
+            return Outcome::Untranslated("can't be combined with other partitioning".to_string());
+        }
+
+        match self.storage.autopart(&self.arch, args) {
+            Ok(()) => Outcome::Translated,
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    fn reqpart(&mut self, args: &[String]) -> Outcome {
+        match self.storage.reqpart(&self.arch, args) {
+            Ok(()) => Outcome::Translated,
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    /// `clearpart` removes partitions, which an image doesn't have. Only the type of partition
+    /// table it asks for matters.
+    fn clearpart(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(
+            args,
+            &["--drives", "--disklabel", "--list"],
+            &["--all", "--linux", "--none", "--initlabel", "--cdl"],
+        ) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        match options.value("--disklabel") {
+            Some(label) => {
+                self.storage.disklabel = Some(label.to_string());
+                Outcome::Translated
+            }
+            None => Outcome::Skipped("the disk of an image starts out empty".to_string()),
+        }
+    }
+
+    /// Installer only commands are skipped, except that the display mode can pick the default
+    /// target when asked to.
+    fn installer(&mut self, name: &str, args: &[String]) -> Outcome {
+        let command = match Installer::from_args(name, args) {
+            Ok(command) => command,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        match command.default_target() {
+            Some(target) if self.display_target => {
+                self.default_target = Some(target.to_string());
+                Outcome::Translated
+            }
+            _ => Outcome::Skipped(command.reason()),
+        }
+    }
+
+    fn snapshot(&mut self, args: &[String]) -> Outcome {
+        match Snapshot::from_args(args) {
+            Ok(snapshot) if snapshot.when == "pre-install" => Outcome::Skipped(
+                "there is nothing to snapshot before the image is built".to_string(),
+            ),
+            Ok(snapshot) => {
+                self.storage.snapshots.push(snapshot);
+                Outcome::Translated
+            }
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    fn bootloader(&mut self, args: &[String]) -> Outcome {
+        let options = match Options::parse_known(args, boot::VALUES, boot::FLAGS) {
+            Ok(options) => options,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        for append in options.values("--append") {
+            self.kernel_opts
+                .extend(append.split_whitespace().map(str::to_string));
+        }
+
+        match self.bootloader.parse(&options) {
+            Ok(unsupported) if unsupported.is_empty() => Outcome::Translated,
+            Ok(unsupported) => Outcome::Untranslated(format!(
+                "options '{}' are not supported by the bootloader stages",
+                unsupported.join(" ")
+            )),
+            Err(err) => Outcome::Untranslated(err),
+        }
+    }
+
+    /// `zipl` sets the secure boot mode of s390x, leaving it to zipl is all the stages can do.
+    fn zipl(&mut self, args: &[String]) -> Outcome {
+        let secure_boot = match boot::zipl(args) {
+            Ok(secure_boot) => secure_boot,
+            Err(err) => return Outcome::Untranslated(err),
+        };
+
+        if self.arch != "s390x" {
+            return Outcome::Skipped(format!("only applies to s390x, not {}", self.arch));
+        }
+
+        match secure_boot {
+            SecureBoot::Auto => Outcome::Translated,
+            SecureBoot::Enabled | SecureBoot::Disabled => Outcome::Untranslated(
+                "the zipl stages can't change the secure boot mode".to_string(),
+            ),
+        }
+    }
+
+    fn set_authselect(&mut self, authselect: Authselect) {
+        if self.authselect.is_some() {
+            warn!("authentication is configured more than once, the last one wins");
+        }
+
+        self.authselect = Some(authselect);
+    }
+
+    fn post(&mut self, section: &Section) -> Result<Outcome, TranslateError> {
+        if !is_chroot_shell(section.args()) {
+            self.untranslated_sections.push(section.clone());
+            return Ok(Outcome::Untranslated(
+                "only shell scripts inside the installed system are understood".to_string(),
+            ));
+        }
+
+        let post = Post::from_script(section.data());
+        let empty = post.is_empty();
+
+        for key in post.gpgkeys {
+            match key {
+                GpgKey::Url(url) => {
+                    // The rpm stage wants the armored key itself, there's no source it can take
+                    // keys from, so remote keys are retrieved at conversion time.
+                    if !self.network {
+                        return Err(TranslateError::Network(url));
+                    }
+
+                    let data = fetch::fetch(&url, &self.fetch)
+                        .map_err(|err| TranslateError::Fetch(url, err))?;
+                    self.gpgkeys.push(data);
+                }
+                GpgKey::Path(path) => self.gpgkeys_fromtree.push(path),
+            }
+        }
+
+        self.enabled_services.extend(post.enabled);
+        self.disabled_services.extend(post.disabled);
+
+        for (path, data) in post.files {
+            if path.starts_with(CA_TRUST_SOURCE) {
+                self.update_ca_trust = true;
+            }
+
+            self.files.push(EmbeddedFile {
+                path,
+                data: data.into_bytes(),
+            });
+        }
+
+        if !empty {
+            self.untranslated_sections
+                .push(section.with_data(post.script));
+            return Ok(Outcome::Untranslated(
+                "contains shell that could not be translated".to_string(),
+            ));
+        }
+
+        Ok(Outcome::Translated)
+    }
+
+    fn addon(&mut self, section: &Section) -> Outcome {
+        match section.args().first().map(String::as_str) {
+            Some("com_redhat_kdump") => {
+                let kdump = Kdump::from_args(section.args());
+
+                if !kdump.enabled {
+                    return Outcome::Translated;
+                }
+
+                match kdump.crashkernel() {
+                    Some(crashkernel) => self.kernel_opts.push(crashkernel),
+                    // The distribution reserves its default on every image already.
+                    None if self
+                        .distro
+                        .kernel_opts
+                        .iter()
+                        .any(|opt| opt.starts_with("crashkernel=")) => {}
+                    None => {
+                        self.untranslated_sections.push(section.clone());
+                        return Outcome::Untranslated(format!(
+                            "{} has no default crashkernel to reserve for --reserve-mb=auto",
+                            self.distro.title
+                        ));
+                    }
+                }
+
+                self.enabled_services.push("kdump.service".to_string());
+                Outcome::Translated
+            }
+            Some("org_fedora_oscap" | "com_redhat_oscap") => self.oscap(section),
+            _ => {
+                self.untranslated_sections.push(section.clone());
+                Outcome::Untranslated("addon is not supported".to_string())
+            }
+        }
+    }
+
+    /// The `%anaconda` section only configures the installer, we validate it and pass it on.
+    fn anaconda(&mut self, section: &Section) -> Outcome {
+        for command in anaconda::parse(section.data()) {
+            match command {
+                anaconda::Command::PwPolicy(policy) => {
+                    for problem in policy.validate() {
+                        warn!("%anaconda: {}", problem);
+                    }
+                }
+                anaconda::Command::Unknown(name) => {
+                    warn!("%anaconda: unknown command '{}'", name);
+                }
+            }
+        }
+
+        self.untranslated_sections.push(section.clone());
+
+        Outcome::Skipped("only configures the installer".to_string())
+    }
+
+    /// `%onerror` and `%traceback` run when the installer fails, there is no installer while
+    /// building an image so these are skipped unless they are passed through.
+    fn installer_script(&mut self, section: &Section) -> Outcome {
+        if let Err(err) = Options::parse_known(
+            section.args(),
+            &["--interpreter", "--log"],
+            &["--erroronfail"],
+        ) {
+            warn!("{}: {}", section.name(), err);
+        }
+
+        self.untranslated_sections.push(section.clone());
+
+        Outcome::Skipped("only runs in the installer".to_string())
+    }
+
+    /// `%certificate --filename=<name> --dir=<dir>` installs the certificate in its body.
+    fn certificate(&mut self, section: &Section) -> Outcome {
+        let options = Options::parse(section.args(), &["--filename", "--dir"]).unwrap_or_default();
+
+        let (filename, dir) = match (options.value("--filename"), options.value("--dir")) {
+            (Some(filename), Some(dir)) => (filename, dir),
+            _ => {
+                self.untranslated_sections.push(section.clone());
+                return Outcome::Untranslated("needs both --filename and --dir".to_string());
+            }
+        };
+
+        let path = format!("{}/{}", dir.trim_end_matches('/'), filename);
+
+        debug!(
+            "Translator.certificate: installing certificate at '{}'",
+            path
+        );
+
+        if dir.starts_with(CA_TRUST_SOURCE) {
+            self.update_ca_trust = true;
+        }
+
+        self.files.push(EmbeddedFile {
+            path,
+            data: section.data().as_bytes().to_vec(),
+        });
+
+        Outcome::Translated
+    }
+
+    fn oscap(&mut self, section: &Section) -> Outcome {
+        let oscap = Oscap::from_data(section.data());
+
+        let guide = self.distro.oscap_datastream.as_deref();
+
+        let (datastream, profile) = match (oscap.datastream(guide), oscap.profile) {
+            (Some(datastream), Some(profile)) => (datastream, profile),
+            _ => {
+                self.untranslated_sections.push(section.clone());
+                return Outcome::Untranslated(
+                    "only local content with a profile is understood".to_string(),
+                );
+            }
+        };
+
+        let mut config = json!({
+            "datastream": datastream,
+            "profile_id": profile,
+        });
+
+        if let Some(id) = oscap.xccdf_id {
+            config["xccdf_id"] = json!(id);
+        }
+
+        if let Some(path) = oscap.tailoring_path {
+            config["tailoring"] = json!(path);
+        }
+
+        warn!("%addon oscap needs openscap-scanner and scap-security-guide in %packages");
+        self.oscap = Some(config);
+
+        Outcome::Translated
+    }
+
+    fn manifest(mut self, layout: Option<&Layout>) -> Result<Manifest, TranslateError> {
+        let mut manifest = Manifest::new();
+        let mut os = Pipeline::new("os");
+
+        self.kernel_opts = kernel_opts(&self.distro.kernel_opts, &self.arch, &self.kernel_opts);
+
+        // The kernel command line has to be in place before the kernel is installed, archives
+        // aren't booted.
+        if !self.kernel_opts.is_empty() && self.format.is_disk() {
+            os.push(
+                Stage::new("org.osbuild.kernel-cmdline")
+                    .option("kernel_opts", json!(self.kernel_opts.join(" "))),
+            );
+        }
+
+        let mut rpm = rpm_stage(&mut manifest, &self.depsolved);
+
+        if !self.gpgkeys.is_empty() {
+            rpm = rpm.option("gpgkeys", json!(self.gpgkeys));
+        }
+
+        if !self.gpgkeys_fromtree.is_empty() {
+            rpm = rpm.option("gpgkeys.fromtree", json!(self.gpgkeys_fromtree));
+        }
+
+        os.push(rpm);
+
+        if let Some(layout) = layout {
+            os.push(Stage::new("org.osbuild.fstab").option("filesystems", image::fstab(layout)));
+
+            if let Some(stage) = self
+                .bootloader
+                .config_stage(
+                    &self.arch,
+                    &self.distro.efi_vendor,
+                    layout,
+                    &self.kernel_opts,
+                )
+                .map_err(TranslateError::Bootloader)?
+            {
+                os.push(stage);
+            }
+        }
+
+        if let Some(zone) = &self.time.zone {
+            os.push(Stage::new("org.osbuild.timezone").option("zone", json!(zone)));
+        }
+
+        for option in &self.time.unsupported {
+            warn!(
+                "timesource: option '{}' is not supported by the chrony stage",
+                option
+            );
+        }
+
+        let timeservers = self.time.timeservers();
+
+        if self.time.ntp_disabled {
+            if !timeservers.is_empty() {
+                warn!("NTP is disabled, ignoring the configured time servers");
+            }
+
+            self.disabled_services.push("chronyd.service".to_string());
+        } else if !timeservers.is_empty() {
+            os.push(Stage::new("org.osbuild.chrony").option("timeservers", json!(timeservers)));
+        }
+
+        if !self.files.is_empty() {
+            self.files_stages(&mut manifest, &mut os);
+        }
+
+        if !self.modprobe.is_empty() {
+            os.push(
+                Stage::new("org.osbuild.modprobe")
+                    .option("filename", json!("osbuild-ks.conf"))
+                    .option("commands", json!(self.modprobe)),
+            );
+        }
+
+        if self.update_ca_trust {
+            os.push(Stage::new("org.osbuild.pki.update-ca-trust"));
+        }
+
+        if let Some(authselect) = &self.authselect {
+            os.push(
+                Stage::new("org.osbuild.authselect")
+                    .option("profile", json!(authselect.profile))
+                    .option("features", json!(authselect.features)),
+            );
+        }
+
+        if let Some(options) = &self.rhsm {
+            os.push(Stage::new("org.osbuild.rhsm").options(options));
+        }
+
+        for unit in &self.units {
+            os.push(Stage::new("org.osbuild.systemd.unit.create").options(unit));
+        }
+
+        if !self.enabled_services.is_empty()
+            || !self.disabled_services.is_empty()
+            || self.default_target.is_some()
+        {
+            let mut systemd = Stage::new("org.osbuild.systemd");
+
+            if !self.enabled_services.is_empty() {
+                systemd = systemd.option("enabled_services", json!(self.enabled_services));
+            }
+
+            if !self.disabled_services.is_empty() {
+                systemd = systemd.option("disabled_services", json!(self.disabled_services));
+            }
+
+            if let Some(target) = &self.default_target {
+                systemd = systemd.option("default_target", json!(target));
+            }
+
+            os.push(systemd);
+        }
+
+        // Remediation goes last so it sees the fully configured tree.
+        if let Some(config) = &self.oscap {
+            os.push(
+                Stage::new("org.osbuild.oscap.remediation")
+                    .option("data_dir", json!("/oscap_data"))
+                    .option("config", config.clone()),
+            );
+        }
+
+        os.order(order::before);
+        manifest.push(os);
+
+        match layout {
+            Some(layout) => {
+                let bootloader = self
+                    .bootloader
+                    .install_stage(&self.arch, layout)
+                    .map_err(TranslateError::Bootloader)?;
+
+                manifest.push(image::pipeline(layout, bootloader));
+            }
+            None if self.format.is_disk() && self.format != ImageFormat::Raw => {
+                return Err(TranslateError::Unsupported(format!(
+                    "{} images need a disk, the kickstart has no storage commands",
+                    self.format
+                )));
+            }
+            None => {}
+        }
+
+        if let Some(pipeline) = self.format.pipeline(&self.arch) {
+            manifest.push(pipeline);
+        }
+
+        if self.passthrough {
+            let pipeline = self.passthrough_pipeline(&mut manifest);
+            manifest.push(pipeline);
+        }
+
+        if !self.distro.buildroot.is_empty() {
+            let build = self.build_pipeline(&mut manifest);
+            manifest.build(build);
+        }
+
+        if self.manifest_version == 1 {
+            if self.passthrough {
+                return Err(TranslateError::ManifestVersion(
+                    "the passthrough kickstart needs its own pipeline".to_string(),
+                ));
+            }
+
+            let bootloader =
+                layout.and_then(|layout| self.bootloader.assembler_option(&self.arch, layout));
+            let assembler = self
+                .format
+                .assembler(&self.arch, layout, bootloader)
+                .map_err(TranslateError::ManifestVersion)?;
+
+            manifest
+                .v1("os", assembler.as_ref())
+                .map_err(TranslateError::ManifestVersion)?;
+        }
+
+        Ok(manifest)
+    }
+
+    /// Embed all files in the manifest and copy them into the tree, creating any directories
+    /// they live in.
+    fn files_stages(&self, manifest: &mut Manifest, os: &mut Pipeline) {
+        let mut dirs: Vec<&str> = Vec::new();
+        let mut references = serde_json::Map::new();
+        let mut paths = Vec::new();
+
+        for file in &self.files {
+            if let Some((dir, _)) = file.path.rsplit_once('/') {
+                if !dir.is_empty() && !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+
+            let id = manifest.inline(&file.data);
+
+            paths.push(json!({
+                "from": format!("input://files/{}", id),
+                "to": format!("tree://{}", file.path),
+            }));
+            references.insert(id, json!({}));
+        }
+
+        let dirs: Vec<_> = dirs
+            .iter()
+            .map(|dir| json!({ "path": dir, "parents": true, "exist_ok": true }))
+            .collect();
+
+        os.push(Stage::new("org.osbuild.mkdir").option("paths", json!(dirs)));
+        os.push(
+            Stage::new("org.osbuild.copy")
+                .input(
+                    "files",
+                    json!({
+                        "type": "org.osbuild.files",
+                        "origin": "org.osbuild.source",
+                        "references": references,
+                    }),
+                )
+                .option("paths", json!(paths)),
+        );
+    }
+
+    /// The manifest of an installer ISO that installs with `kickstart`.
+    fn installer_manifest(&self, kickstart: &Kickstart) -> Result<Manifest, TranslateError> {
+        if self.manifest_version == 1 {
+            return Err(TranslateError::ManifestVersion(
+                "installer ISOs are built by several pipelines".to_string(),
+            ));
+        }
+
+        let kernel = self.bootloader.kernel.as_deref().ok_or_else(|| {
+            TranslateError::Unsupported(
+                "installer ISOs need the version of their kernel, given with --kernel".to_string(),
+            )
+        })?;
+
+        let mut manifest = Manifest::new();
+
+        iso::pipelines(
+            &mut manifest,
+            &self.distro,
+            self.version,
+            &self.arch,
+            kernel,
+            &kickstart.file().to_string(),
+        )
+        .map_err(TranslateError::Unsupported)?;
+
+        if !self.distro.buildroot.is_empty() {
+            let build = self.build_pipeline(&mut manifest);
+            manifest.build(build);
+        }
+
+        Ok(manifest)
+    }
+
+    /// The build root of the distribution with the tools the stages of `manifest` need.
+    fn build_pipeline(&self, manifest: &mut Manifest) -> Pipeline {
+        let packages = buildroot::packages(&self.distro.buildroot, &manifest.kinds());
+
+        if self.depsolved_build.is_empty() {
+            info!(
+                "Using a build root of {} packages from {}, they are not depsolved: {}",
+                packages.len(),
+                self.distro.title,
+                packages.join(" ")
+            );
+        } else {
+            info!(
+                "Using a build root of {} depsolved packages from {}",
+                self.depsolved_build.len(),
+                self.distro.title
+            );
+        }
+
+        let mut pipeline = Pipeline::new("build");
+
+        if let Some(runner) = &self.distro.runner {
+            pipeline.runner(runner);
+        }
+
+        pipeline.push(rpm_stage(manifest, &self.depsolved_build));
+        pipeline
+    }
+
+    /// A pipeline containing only a kickstart with everything that could not be translated,
+    /// embedded in `manifest`. Installer pipelines can pull this tree in to still act on the
+    /// remainder.
+    fn passthrough_pipeline(&self, manifest: &mut Manifest) -> Pipeline {
+        let mut kickstart = String::new();
+
+        for command in &self.untranslated_commands {
+            kickstart = kickstart + command + "\n";
+        }
+
+        for section in &self.untranslated_sections {
+            kickstart = kickstart + "\n" + &section.to_string();
+        }
+
+        info!(
+            "Passing through {} commands and {} sections",
+            self.untranslated_commands.len(),
+            self.untranslated_sections.len()
+        );
+
+        let id = manifest.inline(kickstart.as_bytes());
+        let dir = PASSTHROUGH_PATH.rsplit_once('/').unwrap().0;
+
+        let mut pipeline = Pipeline::new("kickstart");
+        pipeline.push(
+            Stage::new("org.osbuild.mkdir")
+                .option("paths", json!([{ "path": dir, "parents": true }])),
+        );
+        pipeline.push(copy_stage(&id, PASSTHROUGH_PATH));
+        pipeline
+    }
+}
+
+/// The copy stage that puts the inline source `id` at `path` in the tree.
+fn copy_stage(id: &str, path: &str) -> Stage {
+    Stage::new("org.osbuild.copy")
+        .input(
+            "files",
+            json!({
+                "type": "org.osbuild.files",
+                "origin": "org.osbuild.source",
+                "references": { id: {} },
+            }),
+        )
+        .option(
+            "paths",
+            json!([{ "from": format!("input://files/{}", id), "to": format!("tree://{}", path) }]),
+        )
+}
+
+/// The rpm stage installing `rpms`, which `manifest` downloads.
+fn rpm_stage(manifest: &mut Manifest, rpms: &[Rpm]) -> Stage {
+    let references: Map<String, Value> = rpms
+        .iter()
+        .map(|rpm| {
+            (
+                manifest.curl(&rpm.checksum, &rpm.remote_location),
+                json!({}),
+            )
+        })
+        .collect();
+
+    Stage::new("org.osbuild.rpm").input(
+        "packages",
+        json!({
+            "type": "org.osbuild.files",
+            "origin": "org.osbuild.source",
+            "references": references,
+        }),
+    )
+}
+
+/// Add `metadata` to `manifest`, after checking that the pipelines to export and checkpoint are
+/// in it.
+fn with_metadata(
+    mut manifest: Manifest,
+    metadata: Map<String, Value>,
+) -> Result<Manifest, TranslateError> {
+    for (key, action) in [("exports", "export"), ("checkpoints", "checkpoint")] {
+        for name in metadata
+            .get(key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let name = name.as_str().unwrap_or_default();
+
+            if !manifest.names().contains(&name) {
+                return Err(TranslateError::UnknownPipeline(format!(
+                    "there is no pipeline '{}' to {}, the manifest has {}",
+                    name,
+                    action,
+                    manifest.names().join(", ")
+                )));
+            }
+        }
+    }
+
+    for (key, value) in metadata {
+        manifest.metadata(&key, value);
+    }
+
+    Ok(manifest)
+}
+
+/// The kernel arguments of the image, those of the distribution and the console of `arch` come
+/// before those of the kickstart. The console is left out when the kickstart picks one.
+fn kernel_opts(distro: &[String], arch: &str, kickstart: &[String]) -> Vec<String> {
+    let console = boot::console(arch)
+        .filter(|_| !kickstart.iter().any(|opt| opt.starts_with("console=")))
+        .map(str::to_string);
+
+    distro
+        .iter()
+        .cloned()
+        .chain(console)
+        .chain(kickstart.iter().cloned())
+        .collect()
+}
+
+/// Options whose values are credentials and must not end up in logs or reports.
+const SECRET_OPTIONS: &[&str] = &[
+    "--one-time-password",
+    "--activation-key",
+    "--password",
+    "--reverse-password",
+];
+
+/// Storage the installer attaches is reported the same way for every kind of storage, the disks
+/// of an image are created along with it.
+fn attach(name: &str, args: &[String]) -> Outcome {
+    match Attach::from_args(name, args) {
+        Ok(attach) => Outcome::Skipped(format!(
+            "{} is installer-only storage, the image brings its own disk",
+            attach.describe()
+        )),
+        Err(err) => Outcome::Untranslated(err),
+    }
+}
+
+/// A command line with the values of secret options replaced.
+fn redact(line: &str) -> String {
+    let mut redacted = Vec::new();
+
+    // A line that doesn't split is reported as it is, with each secret replaced up to the next
+    // whitespace.
+    let parts = words::split(line)
+        .unwrap_or_else(|_| line.split_whitespace().map(str::to_string).collect());
+    let mut parts = parts.iter();
+
+    while let Some(part) = parts.next() {
+        match part.split_once('=') {
+            Some((option, _)) if SECRET_OPTIONS.contains(&option) => {
+                redacted.push(format!("{}=***", option));
+            }
+            _ if SECRET_OPTIONS.contains(&part.as_str()) => {
+                redacted.push(part.to_string());
+
+                if parts.next().is_some() {
+                    redacted.push("***".to_string());
+                }
+            }
+            _ => redacted.push(words::quote(part)),
+        }
+    }
+
+    redacted.join(" ")
+}
+
+/// The header line of a section as it was written in the kickstart.
+fn section_header(section: &Section) -> String {
+    section
+        .to_string()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Strip one level of matching quotes from an option value.
+fn unquote(value: &str) -> String {
+    for quote in ['\'', '"'] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner.to_string();
+        }
+    }
+
+    value.to_string()
+}
+
+/// Whether the arguments to a script section describe a shell script that runs inside the
+/// installed system, which is the only kind of script our heuristics understand.
+fn is_chroot_shell(args: &[String]) -> bool {
+    let options = match Options::parse(args, &["--interpreter", "--log"]) {
+        Ok(options) => options,
+        Err(_) => return false,
+    };
+
+    !options.flag("--nochroot")
+        && options.values("--interpreter").all(|interpreter| {
+            matches!(
+                interpreter,
+                "/bin/sh" | "/bin/bash" | "/usr/bin/sh" | "/usr/bin/bash"
+            )
+        })
+}
+
+#[test]
+fn translate_is_chroot_shell() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert!(is_chroot_shell(&args("")));
+    assert!(is_chroot_shell(&args(
+        "--interpreter /bin/bash --erroronfail"
+    )));
+    assert!(!is_chroot_shell(&args("--nochroot")));
+    assert!(!is_chroot_shell(&args("--interpreter=/usr/bin/python3")));
+}
+
+#[test]
+fn translate_redact() {
+    assert_eq!(
+        redact("realm join --one-time-password=hunter2 ad.example"),
+        "realm join --one-time-password=*** ad.example"
+    );
+    assert_eq!(
+        redact("rhsm --organization 1 --activation-key key"),
+        "rhsm --organization 1 --activation-key ***"
+    );
+    assert_eq!(
+        redact("user --name=john --gecos=\"John Doe\" --password=\"two words\""),
+        "user --name=john '--gecos=John Doe' --password=***"
+    );
+}
+
+#[test]
+fn translate_kernel_opts() {
+    let opts = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    assert_eq!(
+        kernel_opts(&opts("crashkernel=auto"), "aarch64", &opts("quiet")),
+        opts("crashkernel=auto console=ttyAMA0,115200n8 quiet")
+    );
+    assert_eq!(
+        kernel_opts(&[], "x86_64", &opts("console=tty0")),
+        opts("console=tty0")
+    );
+    assert_eq!(kernel_opts(&[], "riscv64", &[]), Vec::<String>::new());
+}
+
+#[test]
+fn translate_container() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-translate-container");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "part / --size=1024\nbootloader --append=quiet\ntimezone UTC\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (manifest, report) = Translator::new()
+        .format("container".parse().unwrap())
+        .translate(&kickstart)
+        .unwrap();
+
+    let outcomes: Vec<_> = report
+        .entries()
+        .iter()
+        .map(|entry| matches!(entry.outcome, Outcome::Skipped(_)))
+        .collect();
+    assert_eq!(outcomes, vec![true, true, false]);
+
+    let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let names: Vec<_> = json["pipelines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|pipeline| pipeline["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, vec!["build", "os", "container"]);
+    assert_eq!(json["pipelines"][0]["runner"], "org.osbuild.fedora42");
+}
+
+#[test]
+fn translate_manifest_v1() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-translate-manifest-v1");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "part biosboot --size=1\npart /boot --size=512\npart / --size=1024 --fstype=xfs\ntimezone UTC\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("lvm.ks"),
+        "bootloader --location=none\npart pv.01 --size=2048\nvolgroup vg pv.01\nlogvol / --vgname=vg --name=root --size=1024\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (manifest, _) = Translator::new()
+        .arch("x86_64")
+        .format(ImageFormat::Qcow2)
+        .manifest_version(1)
+        .translate(&kickstart)
+        .unwrap();
+
+    let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let assembler = &json["pipeline"]["assembler"];
+    assert!(json.get("version").is_none());
+    assert_eq!(assembler["name"], "org.osbuild.qemu");
+    assert_eq!(assembler["options"]["format"], "qcow2");
+    assert_eq!(
+        assembler["options"]["partitions"][2]["filesystem"]["type"],
+        "xfs"
+    );
+    assert_eq!(assembler["options"]["bootloader"]["platform"], "i386-pc");
+
+    let lvm = Kickstart::from_path(&dir.join("lvm.ks"), &includes, Version::LATEST).unwrap();
+    assert!(matches!(
+        Translator::new().manifest_version(1).translate(&lvm),
+        Err(TranslateError::ManifestVersion(_))
+    ));
+}
+
+#[test]
+fn translate_metadata() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-translate-metadata");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(dir.join("main.ks"), "timezone UTC\n").unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (manifest, _) = Translator::new()
+        .exports(&["os"])
+        .checkpoints(&["build"])
+        .translate(&kickstart)
+        .unwrap();
+
+    let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let metadata = &json["metadata"];
+    assert_eq!(metadata["generators"][0]["name"], "osbuild-ks");
+    assert_eq!(
+        metadata["kickstart"]["checksum"],
+        checksum(b"timezone UTC\n")
+    );
+    assert_eq!(metadata["exports"], json!(["os"]));
+    assert!(metadata.get("timestamp").is_none());
+    assert!(metadata.get("invocation").is_none());
+    assert!(metadata["kickstart"].get("data").is_none());
+
+    let invocation = vec!["osbuild-ks".to_string(), "convert".to_string()];
+    let (manifest, _) = Translator::new()
+        .provenance(Some(invocation.clone()))
+        .translate(&kickstart)
+        .unwrap();
+
+    let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let metadata = &json["metadata"];
+    assert_eq!(metadata["kickstart"]["data"], "timezone UTC\n");
+    assert_eq!(metadata["invocation"], json!(invocation));
+
+    assert!(matches!(
+        Translator::new().exports(&["image"]).translate(&kickstart),
+        Err(TranslateError::UnknownPipeline(_))
+    ));
+}
+
+#[test]
+fn translate_permissive() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-translate-permissive");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(dir.join("main.ks"), "upgrade\ntimezone UTC\n").unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    assert!(matches!(
+        Translator::new().translate(&kickstart),
+        Err(TranslateError::Removed(_))
+    ));
+
+    let (_, report) = Translator::new()
+        .permissive(true)
+        .translate(&kickstart)
+        .unwrap();
+    assert!(matches!(
+        report.entries()[0].outcome,
+        Outcome::Skipped(ref reason) if reason.contains("was removed")
+    ));
+}
+
+#[test]
+fn translate_kdump() {
+    use std::fs;
+
+    use crate::distro::Definitions;
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-translate-kdump");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%addon com_redhat_kdump --enable --reserve-mb=auto\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let translate = |name| {
+        let mut translator = Translator::new().distro(Definitions::default().lookup(name).unwrap());
+        let outcome = translator.addon(&kickstart.sections()[0]);
+        (translator, outcome)
+    };
+
+    let (rhel, outcome) = translate("rhel");
+    assert_eq!(outcome, Outcome::Translated);
+    assert!(rhel.kernel_opts.is_empty());
+    assert_eq!(rhel.enabled_services, ["kdump.service"]);
+
+    let (fedora, outcome) = translate("fedora");
+    assert!(
+        matches!(outcome, Outcome::Untranslated(ref reason) if reason.contains("--reserve-mb=auto"))
+    );
+    assert!(fedora.enabled_services.is_empty());
+}
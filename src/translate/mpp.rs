@@ -0,0 +1,235 @@
+//! osbuild-mpp preprocesses manifests, it replaces the `mpp-` nodes in them by what they ask for.
+//! The manifest of a kickstart for it leaves the packages to `mpp-depsolve` and has its text
+//! files in `mpp-embed` nodes, so the preprocessor resolves the sources instead.
+
+use base64::Engine;
+use serde_json::{json, Map, Value};
+
+use super::{PackageSet, Report, TranslateError, Translator};
+use crate::kickstart::Kickstart;
+
+impl Translator {
+    /// The manifest for `kickstart` to preprocess with osbuild-mpp.
+    pub fn mpp(self, kickstart: &Kickstart) -> Result<(Value, Report), TranslateError> {
+        let (manifest, sets, report) = self.undepsolved(kickstart, "osbuild-mpp")?;
+        let mut manifest = serde_json::to_value(&manifest).unwrap();
+
+        // Files that aren't text stay inline sources, `mpp-embed` only takes text.
+        let texts: Map<String, Value> = manifest["sources"]["org.osbuild.inline"]["items"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, item)| {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(item["data"].as_str()?)
+                    .ok()?;
+                let text = String::from_utf8(data).ok()?;
+
+                Some((id.clone(), json!(text)))
+            })
+            .collect();
+
+        let mut embedded = Vec::new();
+
+        for pipeline in manifest["pipelines"].as_array_mut().unwrap() {
+            let set = sets
+                .iter()
+                .find(|set| pipeline["name"] == set.pipeline.as_str());
+
+            for stage in pipeline["stages"].as_array_mut().unwrap() {
+                match stage["type"].as_str() {
+                    Some("org.osbuild.rpm") => {
+                        if let (Some(set), Some(packages)) =
+                            (set, stage["inputs"]["packages"].as_object_mut())
+                        {
+                            packages.remove("references");
+                            packages.insert("mpp-depsolve".to_string(), depsolve(set));
+                        }
+                    }
+                    Some("org.osbuild.copy") => embed(stage, &texts, &mut embedded),
+                    _ => {}
+                }
+            }
+        }
+
+        let sources = manifest["sources"].as_object_mut().unwrap();
+
+        if let Some(items) = sources
+            .get_mut("org.osbuild.inline")
+            .and_then(|inline| inline["items"].as_object_mut())
+        {
+            items.retain(|id, _| !embedded.contains(id));
+
+            if items.is_empty() {
+                sources.remove("org.osbuild.inline");
+            }
+        }
+
+        Ok((manifest, report))
+    }
+}
+
+/// The `mpp-depsolve` node depsolving `set`.
+fn depsolve(set: &PackageSet) -> Value {
+    let repos: Vec<_> = set
+        .repos
+        .iter()
+        .map(|repo| json!({ "id": repo.id, "baseurl": repo.baseurl }))
+        .collect();
+
+    json!({
+        "architecture": set.arch,
+        "module-platform-id": set.module_platform_id,
+        "releasever": set.releasever,
+        "repos": repos,
+        "packages": set.include,
+        "excludes": set.exclude,
+    })
+}
+
+/// Give each file the copy `stage` takes from `texts` an input of its own with an `mpp-embed`
+/// node, there is one for each input. The checksums of the files are added to `embedded`.
+fn embed(stage: &mut Value, texts: &Map<String, Value>, embedded: &mut Vec<String>) {
+    let mut paths = match stage["options"]["paths"].as_array() {
+        Some(paths) => paths.clone(),
+        None => return,
+    };
+
+    for path in &mut paths {
+        let (input, id) = match path["from"]
+            .as_str()
+            .and_then(|from| from.strip_prefix("input://"))
+            .and_then(|from| from.split_once('/'))
+        {
+            Some((input, id)) => (input.to_string(), id.to_string()),
+            None => continue,
+        };
+
+        let text = match texts.get(&id) {
+            Some(text) => text,
+            None => continue,
+        };
+
+        let name = format!("embed{}", embedded.len());
+
+        stage["inputs"][&name] = json!({
+            "type": "org.osbuild.files",
+            "origin": "org.osbuild.source",
+            "mpp-embed": { "id": name, "text": text },
+        });
+        path["from"] = json!({
+            "mpp-format-string": format!("input://{}/{{embedded['{}']}}", name, name),
+        });
+
+        if let Some(references) = stage["inputs"][&input]["references"].as_object_mut() {
+            references.remove(&id);
+
+            if references.is_empty() {
+                stage["inputs"].as_object_mut().unwrap().remove(&input);
+            }
+        }
+
+        embedded.push(id);
+    }
+
+    stage["options"]["paths"] = json!(paths);
+}
+
+#[test]
+fn mpp_manifest() {
+    use std::fs;
+
+    use super::ImageFormat;
+    use crate::distro::Definitions;
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-mpp-manifest");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "reqpart\n\
+         part / --size=4096 --fstype=xfs\n\
+         eula --agreed\n\
+         logging --host=log.example\n\
+         %packages\n@core\nvim\n-nano\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let translator = || {
+        Translator::new()
+            .arch("x86_64")
+            .format(ImageFormat::Qcow2)
+            .remote_logging(true)
+            .distro(Definitions::default().lookup("fedora").unwrap())
+    };
+
+    let (manifest, _) = translator().mpp(&kickstart).unwrap();
+
+    let stages = |pipeline: &str, kind: &str| -> Vec<Value> {
+        manifest["pipelines"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|candidate| candidate["name"] == pipeline)
+            .flat_map(|candidate| candidate["stages"].as_array().unwrap().clone())
+            .filter(|stage| stage["type"] == kind)
+            .collect()
+    };
+
+    let packages = &stages("os", "org.osbuild.rpm")[0]["inputs"]["packages"];
+    assert!(packages.get("references").is_none());
+    assert_eq!(
+        packages["mpp-depsolve"],
+        json!({
+            "architecture": "x86_64",
+            "module-platform-id": "platform:f42",
+            "releasever": "42",
+            "repos": [
+                {
+                    "id": "fedora",
+                    "baseurl": "https://dl.fedoraproject.org/pub/fedora/linux/releases/42/Everything/x86_64/os/",
+                },
+                {
+                    "id": "updates",
+                    "baseurl": "https://dl.fedoraproject.org/pub/fedora/linux/updates/42/Everything/x86_64/",
+                },
+            ],
+            "packages": ["vim", "@core"],
+            "excludes": ["nano"],
+        })
+    );
+    assert!(
+        stages("build", "org.osbuild.rpm")[0]["inputs"]["packages"]["mpp-depsolve"]["packages"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("xfsprogs"))
+    );
+
+    let copy = &stages("os", "org.osbuild.copy")[0];
+    assert_eq!(
+        copy["inputs"],
+        json!({
+            "embed0": {
+                "type": "org.osbuild.files",
+                "origin": "org.osbuild.source",
+                "mpp-embed": { "id": "embed0", "text": "" },
+            },
+            "embed1": {
+                "type": "org.osbuild.files",
+                "origin": "org.osbuild.source",
+                "mpp-embed": { "id": "embed1", "text": "*.* @@log.example:514\n" },
+            },
+        })
+    );
+    assert_eq!(
+        copy["options"]["paths"][1]["from"],
+        json!({ "mpp-format-string": "input://embed1/{embedded['embed1']}" })
+    );
+    assert_eq!(manifest["sources"], json!({}));
+
+    assert!(translator().manifest_version(1).mpp(&kickstart).is_err());
+}
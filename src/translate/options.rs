@@ -0,0 +1,119 @@
+//! Options of kickstart commands. Every command takes its options the same way: a value is given
+//! as `--option=value` or as the next argument, options without a value are flags, options may
+//! be repeated and anything that isn't an option is a positional argument.
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    /// The options in the order they were given, with their value if they have one.
+    options: Vec<(String, Option<String>)>,
+    positional: Vec<String>,
+}
+
+impl Options {
+    /// Parse `args`, the options in `values` take a value. Other options only take a value when
+    /// it's given with `=`.
+    pub fn parse(args: &[String], values: &[&str]) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                options.positional.push(arg.clone());
+                continue;
+            }
+
+            let (option, value) = match arg.split_once('=') {
+                Some((option, value)) => (option, Some(value.to_string())),
+                None if values.contains(&arg.as_str()) => match args.next() {
+                    Some(value) => (arg.as_str(), Some(value.clone())),
+                    None => return Err(format!("option '{}' needs a value", arg)),
+                },
+                None => (arg.as_str(), None),
+            };
+
+            options.options.push((option.to_string(), value));
+        }
+
+        Ok(options)
+    }
+
+    /// Parse `args` like [`Options::parse`], failing on options that aren't in `values` or
+    /// `flags`.
+    pub fn parse_known(args: &[String], values: &[&str], flags: &[&str]) -> Result<Self, String> {
+        let options = Self::parse(args, values)?;
+
+        if let Some(option) = options
+            .names()
+            .find(|option| !values.contains(option) && !flags.contains(option))
+        {
+            return Err(format!("unknown option '{}'", option));
+        }
+
+        Ok(options)
+    }
+
+    /// The options in the order they were given, with their value if they have one.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.options
+            .iter()
+            .map(|(option, value)| (option.as_str(), value.as_deref()))
+    }
+
+    /// The names of the options in the order they were given.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.iter().map(|(option, _)| option)
+    }
+
+    /// Whether `option` was given.
+    pub fn flag(&self, option: &str) -> bool {
+        self.names().any(|name| name == option)
+    }
+
+    /// The value of `option`, the last one when it was given more than once.
+    pub fn value(&self, option: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .filter(|(name, _)| name == option)
+            .find_map(|(_, value)| value.as_deref())
+    }
+
+    /// All values of `option` in the order they were given.
+    pub fn values<'a>(&'a self, option: &'a str) -> impl Iterator<Item = &'a str> {
+        self.options
+            .iter()
+            .filter(move |(name, _)| name == option)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    /// The arguments that aren't options.
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+}
+
+#[test]
+fn options_parse() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    let options = Options::parse(
+        &args("--filename=a.pem --dir /etc/pki --addon=a b --addon c --quiet --filenames=b.pem"),
+        &["--dir", "--addon"],
+    )
+    .unwrap();
+
+    assert_eq!(options.value("--dir"), Some("/etc/pki"));
+    assert_eq!(options.value("--filename"), Some("a.pem"));
+    assert_eq!(
+        options.values("--addon").collect::<Vec<_>>(),
+        vec!["a", "c"]
+    );
+    assert_eq!(options.value("--addon"), Some("c"));
+    assert!(options.flag("--quiet"));
+    assert!(!options.flag("--file"));
+    assert_eq!(options.positional(), ["b"]);
+
+    assert!(Options::parse(&args("--dir"), &["--dir"]).is_err());
+    assert!(Options::parse_known(&args("--dir=/ --bogus"), &["--dir"], &[]).is_err());
+    assert!(Options::parse_known(&args("--dir / --quiet"), &["--dir"], &["--quiet"]).is_ok());
+}
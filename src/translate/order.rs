@@ -0,0 +1,112 @@
+//! Stages of the os pipeline are added in the order the translation gets to them, but some of
+//! them act on what others leave in the tree. The kernel is installed by the rpm stage with the
+//! command line that's already there, units are enabled once their files exist and the tree is
+//! relabeled once nothing changes anymore.
+
+use crate::osbuild::Stage;
+
+/// Stages that have to run before others, `*` is any other stage. A rule between two named
+/// stages wins over one with `*`.
+const RULES: &[(&str, &str)] = &[
+    ("org.osbuild.kernel-cmdline", "org.osbuild.rpm"),
+    ("org.osbuild.rpm", "*"),
+    ("org.osbuild.kernel-cmdline", "org.osbuild.grub2"),
+    ("org.osbuild.fstab", "org.osbuild.grub2"),
+    ("org.osbuild.mkdir", "org.osbuild.copy"),
+    ("org.osbuild.copy", "org.osbuild.pki.update-ca-trust"),
+    ("org.osbuild.copy", "org.osbuild.systemd"),
+    ("org.osbuild.systemd.unit.create", "org.osbuild.systemd"),
+    ("org.osbuild.users", "org.osbuild.selinux"),
+    ("org.osbuild.oscap.remediation", "org.osbuild.selinux"),
+    ("*", "org.osbuild.oscap.remediation"),
+    ("*", "org.osbuild.selinux"),
+];
+
+/// Whether `first` has to run before `second`.
+pub fn before(first: &Stage, second: &Stage) -> bool {
+    let (first, second) = (first.kind(), second.kind());
+
+    if first == second {
+        return false;
+    }
+
+    if RULES.contains(&(first, second)) {
+        return true;
+    }
+
+    if RULES.contains(&(second, first)) {
+        return false;
+    }
+
+    RULES.contains(&(first, "*")) || RULES.contains(&("*", second))
+}
+
+#[test]
+fn order_before() {
+    use crate::osbuild::{Manifest, Pipeline};
+
+    let stage = Stage::new;
+
+    assert!(before(
+        &stage("org.osbuild.kernel-cmdline"),
+        &stage("org.osbuild.rpm")
+    ));
+    assert!(!before(
+        &stage("org.osbuild.rpm"),
+        &stage("org.osbuild.kernel-cmdline")
+    ));
+    assert!(before(
+        &stage("org.osbuild.rpm"),
+        &stage("org.osbuild.timezone")
+    ));
+    assert!(!before(
+        &stage("org.osbuild.timezone"),
+        &stage("org.osbuild.chrony")
+    ));
+    assert!(!before(
+        &stage("org.osbuild.selinux"),
+        &stage("org.osbuild.oscap.remediation")
+    ));
+
+    let mut os = Pipeline::new("os");
+
+    for kind in [
+        "org.osbuild.selinux",
+        "org.osbuild.timezone",
+        "org.osbuild.systemd",
+        "org.osbuild.rpm",
+        "org.osbuild.copy",
+        "org.osbuild.mkdir",
+        "org.osbuild.kernel-cmdline",
+        "org.osbuild.chrony",
+    ] {
+        os.push(stage(kind));
+    }
+
+    os.order(before);
+
+    let mut manifest = Manifest::new();
+    manifest.push(os);
+
+    let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+    let kinds: Vec<_> = json["pipelines"][0]["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| stage["type"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(
+        kinds,
+        vec![
+            "org.osbuild.kernel-cmdline",
+            "org.osbuild.rpm",
+            "org.osbuild.timezone",
+            "org.osbuild.mkdir",
+            "org.osbuild.copy",
+            "org.osbuild.systemd",
+            "org.osbuild.chrony",
+            "org.osbuild.selinux",
+        ]
+    );
+}
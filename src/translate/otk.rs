@@ -0,0 +1,176 @@
+//! otk compiles omnifests, YAML files in which externals work out what a manifest needs, into
+//! manifests. The omnifest of a kickstart is its manifest with the packages left to the depsolve
+//! externals of otk, so they're resolved when it's compiled instead of when it's translated.
+
+use serde_json::{json, Map, Value};
+
+use super::{PackageSet, Report, TranslateError, Translator};
+use crate::kickstart::Kickstart;
+
+/// The version of the omnifest format.
+const VERSION: &str = "1";
+
+/// The external that depsolves a package set.
+const DEPSOLVE: &str = "otk.external.otk-gen-depsolve-dnf4";
+
+/// The externals that turn a depsolved package set into the rpm stage and the source of its
+/// packages.
+const RPM_STAGE: &str = "otk.external.otk-make-depsolve-dnf4-rpm-stage";
+const CURL_SOURCE: &str = "otk.external.otk-make-depsolve-dnf4-curl-source";
+
+impl Translator {
+    /// The omnifest for `kickstart`, with a package set for each pipeline that installs
+    /// packages. It has a single target, named after the image format.
+    pub fn otk(self, kickstart: &Kickstart) -> Result<(Value, Report), TranslateError> {
+        let target = format!("otk.target.osbuild.{}", self.format);
+        let (manifest, sets, report) = self.undepsolved(kickstart, "otk")?;
+        let mut manifest = serde_json::to_value(&manifest).unwrap();
+
+        let mut packages = Map::new();
+        let mut packagesets = Vec::new();
+
+        for set in &sets {
+            packages.insert(set.pipeline.clone(), depsolve(set));
+            packagesets.push(json!(format!("${{packages.{}}}", set.pipeline)));
+        }
+
+        for pipeline in manifest["pipelines"].as_array_mut().unwrap() {
+            let packageset = format!("${{packages.{}}}", pipeline["name"].as_str().unwrap());
+
+            for stage in pipeline["stages"].as_array_mut().unwrap() {
+                if stage["type"] != "org.osbuild.rpm" {
+                    continue;
+                }
+
+                // The GPG keys stay options of the stage, the external adds the packages.
+                let mut options = stage
+                    .get("options")
+                    .and_then(Value::as_object)
+                    .cloned()
+                    .unwrap_or_default();
+                options.insert("packageset".to_string(), json!(packageset));
+
+                *stage = json!({ RPM_STAGE: options });
+            }
+        }
+
+        // The depsolve external says where the packages are downloaded from, other sources are
+        // joined with it.
+        let sources = manifest["sources"].as_object().cloned().unwrap_or_default();
+        let curl = json!({ CURL_SOURCE: { "packagesets": packagesets } });
+
+        let sources = match sources.is_empty() {
+            true => curl,
+            false => json!({ "otk.op.join": { "values": [curl, sources] } }),
+        };
+
+        let omnifest = json!({
+            "otk.version": VERSION,
+            "otk.define": { "packages": packages },
+            target: {
+                "pipelines": manifest["pipelines"],
+                "sources": sources,
+            },
+        });
+
+        Ok((omnifest, report))
+    }
+}
+
+/// The external depsolving `set`.
+fn depsolve(set: &PackageSet) -> Value {
+    let repositories: Vec<_> = set
+        .repos
+        .iter()
+        .map(|repo| json!({ "id": repo.id, "baseurl": repo.baseurl }))
+        .collect();
+
+    json!({
+        DEPSOLVE: {
+            "architecture": set.arch,
+            "module_platform_id": set.module_platform_id,
+            "releasever": set.releasever,
+            "repositories": repositories,
+            "packages": { "include": set.include, "exclude": set.exclude },
+        },
+    })
+}
+#[test]
+fn otk_omnifest() {
+    use std::fs;
+
+    use super::ImageFormat;
+    use crate::distro::Definitions;
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-otk-omnifest");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "reqpart\n\
+         part / --size=4096 --fstype=xfs\n\
+         %packages\n@core\nvim\n-nano\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let translator = || {
+        Translator::new()
+            .arch("x86_64")
+            .format(ImageFormat::Qcow2)
+            .distro(Definitions::default().lookup("fedora").unwrap())
+    };
+
+    let (omnifest, _) = translator().otk(&kickstart).unwrap();
+
+    assert_eq!(omnifest["otk.version"], "1");
+
+    let os = &omnifest["otk.define"]["packages"]["os"][DEPSOLVE];
+    assert_eq!(os["architecture"], "x86_64");
+    assert_eq!(os["releasever"], "42");
+    assert_eq!(os["module_platform_id"], "platform:f42");
+    assert_eq!(
+        os["packages"],
+        json!({ "include": ["vim", "@core"], "exclude": ["nano"] })
+    );
+    assert_eq!(
+        os["repositories"][0]["baseurl"],
+        "https://dl.fedoraproject.org/pub/fedora/linux/releases/42/Everything/x86_64/os/"
+    );
+
+    let build = &omnifest["otk.define"]["packages"]["build"][DEPSOLVE];
+    assert!(build["packages"]["include"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("xfsprogs")));
+    assert_eq!(build["repositories"].as_array().unwrap().len(), 2);
+
+    let target = &omnifest["otk.target.osbuild.qcow2"];
+    let stages: Vec<_> = target["pipelines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|pipeline| pipeline["stages"].as_array().unwrap())
+        .filter(|stage| stage.get(RPM_STAGE).is_some())
+        .collect();
+
+    assert_eq!(
+        stages,
+        [
+            &json!({ RPM_STAGE: { "packageset": "${packages.build}" } }),
+            &json!({ RPM_STAGE: { "packageset": "${packages.os}" } }),
+        ]
+    );
+    assert_eq!(
+        target["sources"],
+        json!({ CURL_SOURCE: { "packagesets": ["${packages.build}", "${packages.os}"] } })
+    );
+
+    assert!(translator()
+        .format(ImageFormat::Iso)
+        .otk(&kickstart)
+        .is_err());
+}
@@ -0,0 +1,160 @@
+//! The package set of the installed system, from `%packages` and from commands that need
+//! packages to work. Packages are collected here to be depsolved, the rpm stage refers to the
+//! results of the depsolve.
+//!
+//! The depsolve isn't done here, its result is read from the JSON `osbuild-depsolve-dnf` writes.
+
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// The contents of the `%packages` section plus any packages commands asked for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Packages {
+    /// The arguments given to `%packages`, such as `--nocore` or `--excludedocs`.
+    pub args: Vec<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Groups and environments, including their leading `@` or `@^`.
+    pub groups: Vec<String>,
+    /// Repositories to depsolve against.
+    pub repos: Vec<Repo>,
+}
+
+/// A repository packages can come from.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Repo {
+    pub id: String,
+    pub baseurl: String,
+}
+
+/// The packages of a pipeline, for tools that depsolve them themselves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageSet {
+    /// The name of the pipeline.
+    pub pipeline: String,
+    pub arch: String,
+    /// The release and module platform of the distribution, such as `42` and `platform:f42`.
+    pub releasever: String,
+    pub module_platform_id: String,
+    pub repos: Vec<Repo>,
+    /// Packages and groups to install, groups with their leading `@`.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// A package picked by the depsolve.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Rpm {
+    pub name: String,
+    /// The checksum of the RPM file, such as `sha256:` followed by the digest.
+    pub checksum: String,
+    /// The URL the RPM file is downloaded from.
+    pub remote_location: String,
+}
+
+/// The result of a depsolve, only the packages are used.
+#[derive(Deserialize)]
+struct Depsolve {
+    packages: Vec<Rpm>,
+}
+
+/// The packages of the depsolve result in the file at `path`.
+pub fn depsolved(path: &Path) -> Result<Vec<Rpm>, String> {
+    let data = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read '{}': {}", path.display(), err))?;
+    let depsolve: Depsolve = serde_json::from_str(&data)
+        .map_err(|err| format!("failed to parse '{}': {}", path.display(), err))?;
+
+    for rpm in &depsolve.packages {
+        let valid = match rpm.checksum.split_once(':') {
+            Some(("sha256" | "sha384" | "sha512", digest)) => {
+                !digest.is_empty() && digest.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            _ => false,
+        };
+
+        if !valid {
+            return Err(format!(
+                "'{}' in '{}' has checksum '{}', which isn't a SHA-2 digest",
+                rpm.name,
+                path.display(),
+                rpm.checksum
+            ));
+        }
+    }
+
+    Ok(depsolve.packages)
+}
+
+impl Packages {
+    /// Add the body of a `%packages` section.
+    pub fn parse(&mut self, args: &[String], data: &str) {
+        self.args.extend(args.iter().cloned());
+
+        for line in data.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if let Some(package) = line.strip_prefix('-') {
+                self.exclude.push(package.to_string());
+            } else if line.starts_with('@') {
+                self.groups.push(line.to_string());
+            } else {
+                self.include.push(line.to_string());
+            }
+        }
+    }
+
+    /// Add packages needed by a command, unless they are already there.
+    pub fn require(&mut self, packages: &[&str]) {
+        for package in packages {
+            if !self.include.iter().any(|include| include == package) {
+                self.include.push(package.to_string());
+            }
+        }
+    }
+}
+
+#[test]
+fn packages_parse() {
+    let mut packages = Packages::default();
+
+    packages.parse(
+        &["--nocore".to_string()],
+        "@^workstation-product-environment\n@core\nvim\n-nano\n\n",
+    );
+    packages.require(&["vim", "sssd"]);
+
+    assert_eq!(packages.args, vec!["--nocore"]);
+    assert_eq!(
+        packages.groups,
+        vec!["@^workstation-product-environment", "@core"]
+    );
+    assert_eq!(packages.include, vec!["vim", "sssd"]);
+    assert_eq!(packages.exclude, vec!["nano"]);
+}
+
+#[test]
+fn packages_depsolved() {
+    let dir = std::env::temp_dir().join("osbuild-ks-packages-depsolved");
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::write(
+        dir.join("depsolve.json"),
+        r#"{"packages": [{"name": "vim", "epoch": 2, "version": "9.1", "release": "1.fc42",
+            "arch": "x86_64", "checksum": "sha256:0a1b",
+            "remote_location": "https://example.com/vim.rpm"}], "repos": {}}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.join("md5.json"),
+        r#"{"packages": [{"name": "vim", "checksum": "md5:0a1b", "remote_location": ""}]}"#,
+    )
+    .unwrap();
+
+    let packages = depsolved(&dir.join("depsolve.json")).unwrap();
+
+    assert_eq!(packages.len(), 1);
+    assert_eq!(packages[0].checksum, "sha256:0a1b");
+    assert!(depsolved(&dir.join("md5.json")).is_err());
+    assert!(depsolved(&dir.join("missing.json")).is_err());
+}
@@ -0,0 +1,320 @@
+//! Heuristics for `%post` scripts. Kickstart allows arbitrary shell in `%post`, osbuild has no
+//! stage to run arbitrary shell in a tree. Some shell commands are common enough that we recognize
+//! them and translate them into the stage that performs the same action.
+//!
+//! The heuristics only recognize simple lines; anything involving shell expansion, redirection,
+//! or command chaining is left in the script as we can't know what it evaluates to. The one
+//! redirection that is recognized is writing a file with `cat` and a here-document.
+
+#[cfg(test)]
+use std::fs;
+
+use log::*;
+#[cfg(test)]
+use serde_json::{json, Value};
+
+use crate::fetch;
+#[cfg(test)]
+use crate::kickstart::{Includes, Kickstart};
+#[cfg(test)]
+use crate::translate::{TranslateError, Translator};
+#[cfg(test)]
+use crate::version::Version;
+
+/// Characters that make a line too clever for us to translate safely.
+const SHELL_SPECIAL: &[char] = &[
+    '$', '`', '|', '&', ';', '<', '>', '(', ')', '\'', '"', '*', '?', '\\', '{', '}',
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpgKey {
+    /// A key that is fetched from a remote location.
+    Url(String),
+    /// A key at a path inside the image tree.
+    Path(String),
+}
+
+/// A `%post` script after heuristics have removed the lines they translated.
+#[derive(Clone, Debug, Default)]
+pub struct Post {
+    pub script: String,
+    pub gpgkeys: Vec<GpgKey>,
+    /// Units given to `systemctl enable` and `systemctl disable`.
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>,
+    /// The paths and contents of files written by here-documents.
+    pub files: Vec<(String, String)>,
+}
+
+impl Post {
+    pub fn from_script(script: &str) -> Self {
+        let mut post = Self::default();
+        let mut lines = script.lines();
+
+        while let Some(line) = lines.next() {
+            if let Some(keys) = rpm_import(line) {
+                debug!("post: translated '{}' into {} gpgkeys", line, keys.len());
+                post.gpgkeys.extend(keys);
+            } else if let Some((enable, units)) = systemctl(line) {
+                debug!("post: translated '{}' into {} services", line, units.len());
+
+                match enable {
+                    true => post.enabled.extend(units),
+                    false => post.disabled.extend(units),
+                }
+            } else if let Some((path, data, rest)) = heredoc(line, lines.clone()) {
+                debug!("post: translated '{}' into file '{}'", line, path);
+                post.files.push((path, data));
+                lines = rest;
+            } else {
+                post.script = post.script + line + "\n";
+            }
+        }
+
+        post
+    }
+
+    /// Whether nothing but comments, such as the shebang, is left of the script.
+    pub fn is_empty(&self) -> bool {
+        self.script.lines().all(|line| {
+            let line = line.trim();
+            line.is_empty() || line.starts_with('#')
+        })
+    }
+}
+
+/// Recognize `systemctl enable <unit>...` and `systemctl disable <unit>...`, whether the units
+/// are enabled and which they are.
+fn systemctl(line: &str) -> Option<(bool, Vec<String>)> {
+    if line.contains(SHELL_SPECIAL) {
+        return None;
+    }
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    if parts.len() < 3 || parts[0] != "systemctl" {
+        return None;
+    }
+
+    // Options such as `--now` act on the running system, there is none.
+    if parts[2..].iter().any(|part| part.starts_with('-')) {
+        return None;
+    }
+
+    let enable = match parts[1] {
+        "enable" => true,
+        "disable" => false,
+        _ => return None,
+    };
+
+    Some((
+        enable,
+        parts[2..].iter().map(|unit| unit.to_string()).collect(),
+    ))
+}
+
+/// Recognize `cat > <path> <<EOF` followed by the lines of the file and `EOF`, the path and
+/// contents of the file and the lines after it. Unless the delimiter is quoted the contents
+/// can't have anything the shell expands.
+fn heredoc<'a, I>(line: &str, mut lines: I) -> Option<(String, String, I)>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut parts = line.split_whitespace();
+
+    if parts.next() != Some("cat") {
+        return None;
+    }
+
+    let mut path = None;
+    let mut delimiter = None;
+
+    while let Some(part) = parts.next() {
+        if let Some(rest) = part.strip_prefix("<<") {
+            delimiter = Some(match rest {
+                "" => parts.next()?,
+                rest => rest,
+            });
+        } else if let Some(rest) = part.strip_prefix('>') {
+            path = Some(match rest {
+                "" => parts.next()?,
+                rest => rest,
+            });
+        } else {
+            return None;
+        }
+    }
+
+    let path = path.filter(|path| path.starts_with('/') && !path.contains(SHELL_SPECIAL))?;
+    let delimiter = delimiter?;
+
+    let (delimiter, quoted) = match delimiter
+        .strip_prefix('\'')
+        .and_then(|rest| rest.strip_suffix('\''))
+        .or_else(|| {
+            delimiter
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+        }) {
+        Some(delimiter) => (delimiter, true),
+        None => (delimiter, false),
+    };
+
+    if delimiter.is_empty()
+        || !delimiter
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        return None;
+    }
+
+    let mut data = String::new();
+
+    loop {
+        let line = lines.next()?;
+
+        if line == delimiter {
+            break;
+        }
+
+        if !quoted && line.contains(['$', '`', '\\']) {
+            return None;
+        }
+
+        data = data + line + "\n";
+    }
+
+    Some((path.to_string(), data, lines))
+}
+
+/// Recognize `rpm --import <key>...` where every key is either a URL or an absolute path.
+fn rpm_import(line: &str) -> Option<Vec<GpgKey>> {
+    if line.contains(SHELL_SPECIAL) {
+        return None;
+    }
+
+    let parts: Vec<&str> = line.split_whitespace().collect();
+
+    if parts.len() < 3 || parts[0] != "rpm" || parts[1] != "--import" {
+        return None;
+    }
+
+    parts[2..]
+        .iter()
+        .map(|part| {
+            if fetch::is_url(part) {
+                Some(GpgKey::Url(part.to_string()))
+            } else if part.starts_with('/') {
+                Some(GpgKey::Path(part.to_string()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn post_rpm_import() {
+    let post = Post::from_script(
+        "rpm --import https://example.com/RPM-GPG-KEY\nrpm --import /etc/pki/rpm-gpg/a /etc/pki/rpm-gpg/b\necho hi\n",
+    );
+
+    assert_eq!(
+        post.gpgkeys,
+        vec![
+            GpgKey::Url("https://example.com/RPM-GPG-KEY".to_string()),
+            GpgKey::Path("/etc/pki/rpm-gpg/a".to_string()),
+            GpgKey::Path("/etc/pki/rpm-gpg/b".to_string()),
+        ]
+    );
+    assert_eq!(post.script, "echo hi\n");
+    assert!(!post.is_empty());
+
+    assert!(Post::from_script("#!/bin/sh\nrpm --import /etc/pki/rpm-gpg/a\n").is_empty());
+}
+
+#[test]
+fn post_rpm_import_manifest() {
+    let dir = std::env::temp_dir().join("osbuild-ks-post-rpm-import-manifest");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "%post\nrpm --import /etc/pki/rpm-gpg/a\n%end\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("remote.ks"),
+        "%post\nrpm --import https://example.com/RPM-GPG-KEY\n%end\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let (manifest, _) = Translator::new().translate(&kickstart).unwrap();
+    let json: Value = serde_json::from_str(&manifest.to_json()).unwrap();
+
+    let rpm = json["pipelines"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|pipeline| pipeline["name"] == "os")
+        .flat_map(|pipeline| pipeline["stages"].as_array().unwrap())
+        .find(|stage| stage["type"] == "org.osbuild.rpm")
+        .unwrap();
+
+    assert_eq!(
+        rpm["options"]["gpgkeys.fromtree"],
+        json!(["/etc/pki/rpm-gpg/a"])
+    );
+    assert!(rpm["options"].get("gpgkeys_fromtree").is_none());
+
+    let remote = Kickstart::from_path(&dir.join("remote.ks"), &includes, Version::LATEST).unwrap();
+
+    assert!(matches!(
+        Translator::new().translate(&remote),
+        Err(TranslateError::Network(url)) if url == "https://example.com/RPM-GPG-KEY"
+    ));
+}
+
+#[test]
+fn post_rpm_import_shell() {
+    let script = "rpm --import /etc/pki/rpm-gpg/RPM-GPG-KEY-fedora-$releasever\nrpm --import key.asc || :\nrpm --import relative/key\n";
+    let post = Post::from_script(script);
+
+    assert!(post.gpgkeys.is_empty());
+    assert_eq!(post.script, script);
+}
+
+#[test]
+fn post_systemctl() {
+    let post = Post::from_script(
+        "systemctl enable cockpit.socket podman\nsystemctl disable kdump\nsystemctl enable --now sshd\n",
+    );
+
+    assert_eq!(post.enabled, vec!["cockpit.socket", "podman"]);
+    assert_eq!(post.disabled, vec!["kdump"]);
+    assert_eq!(post.script, "systemctl enable --now sshd\n");
+}
+
+#[test]
+fn post_heredoc() {
+    let post = Post::from_script(
+        "cat > /etc/motd <<EOF\nHello\n\nthere\nEOF\n\
+         cat <<'END' >/usr/local/bin/hi\necho $HOME\nEND\n\
+         cat > /etc/issue <<EOF\n$HOSTNAME\nEOF\n\
+         cat > /etc/unfinished <<EOF\nnever ends\n",
+    );
+
+    assert_eq!(
+        post.files,
+        vec![
+            ("/etc/motd".to_string(), "Hello\n\nthere\n".to_string()),
+            ("/usr/local/bin/hi".to_string(), "echo $HOME\n".to_string()),
+        ]
+    );
+    assert_eq!(
+        post.script,
+        "cat > /etc/issue <<EOF\n$HOSTNAME\nEOF\ncat > /etc/unfinished <<EOF\nnever ends\n"
+    );
+}
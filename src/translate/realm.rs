@@ -0,0 +1,108 @@
+//! `realm join` joins the system to a domain while installing. Joining needs the network and
+//! credentials, neither of which exist while building an image, so the join is deferred to the
+//! first boot when that's possible.
+
+use super::options::Options;
+
+/// Packages realmd needs to join Active Directory and IPA domains.
+pub const PACKAGES: &[&str] = &[
+    "realmd",
+    "sssd",
+    "adcli",
+    "oddjob",
+    "oddjob-mkhomedir",
+    "samba-common-tools",
+    "krb5-workstation",
+];
+
+/// The options of `realm join` that take a value.
+pub const VALUES: &[&str] = &[
+    "--one-time-password",
+    "--client-software",
+    "--server-software",
+    "--membership-software",
+    "--computer-ou",
+    "--os-name",
+    "--os-version",
+    "--user",
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Join {
+    pub domain: String,
+    /// Options passed on to `realm join`, without the one time password.
+    pub options: Vec<String>,
+    pub one_time_password: Option<String>,
+    pub no_password: bool,
+}
+
+impl Join {
+    /// Parse the arguments following `realm`.
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let options = Options::parse(args, VALUES)?;
+        let mut positional = options.positional().iter();
+
+        match positional.next().map(String::as_str) {
+            Some("join") => {}
+            Some(command) => return Err(format!("realm '{}' is not supported", command)),
+            None => return Err("realm needs a command".to_string()),
+        }
+
+        let passed = options
+            .iter()
+            .filter(|(option, _)| *option != "--one-time-password")
+            .map(|(option, value)| match value {
+                Some(value) => format!("{}={}", option, value),
+                None => option.to_string(),
+            })
+            .collect();
+
+        Ok(Self {
+            domain: positional
+                .last()
+                .ok_or("realm join needs a domain")?
+                .to_string(),
+            options: passed,
+            one_time_password: options.value("--one-time-password").map(str::to_string),
+            no_password: options.flag("--no-password"),
+        })
+    }
+
+    /// The command that performs the join, `password` decides whether the one time password is
+    /// included.
+    pub fn command(&self, password: bool) -> String {
+        let mut command = vec!["/usr/sbin/realm".to_string(), "join".to_string()];
+
+        command.extend(self.options.iter().cloned());
+
+        if password {
+            if let Some(otp) = &self.one_time_password {
+                command.push(format!("--one-time-password={}", otp));
+            }
+        }
+
+        command.push(self.domain.clone());
+        command.join(" ")
+    }
+}
+
+#[test]
+fn realm_join() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    let join = Join::from_args(&args(
+        "join --one-time-password=secret --client-software=sssd ad.example",
+    ))
+    .unwrap();
+
+    assert_eq!(join.domain, "ad.example");
+    assert_eq!(
+        join.command(false),
+        "/usr/sbin/realm join --client-software=sssd ad.example"
+    );
+    assert_eq!(
+        join.command(true),
+        "/usr/sbin/realm join --client-software=sssd --one-time-password=secret ad.example"
+    );
+    assert!(Join::from_args(&args("discover ad.example")).is_err());
+}
@@ -0,0 +1,205 @@
+//! The conversion report records what happened to every command and section of a kickstart, so
+//! it can be told afterwards what was translated and why anything was left out.
+
+use log::*;
+use serde_json::{json, Value};
+
+use super::catalog;
+use crate::kickstart::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    /// Turned into stages or influenced other stages.
+    Translated,
+    /// Understood, but intentionally left out of the manifest.
+    Skipped(String),
+    /// Not understood or not expressible, carried along in passthrough mode.
+    Untranslated(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    /// The command line or section header this entry is about.
+    pub element: String,
+    /// Where the element is, in the kickstart or in a file it includes.
+    pub span: Option<Span>,
+    pub outcome: Outcome,
+    /// Why the element, or options it is given with, are deprecated in the kickstart version.
+    pub deprecated: Vec<String>,
+}
+
+impl Entry {
+    /// The name of the command, or the section header starting with `%`.
+    fn name(&self) -> &str {
+        self.element.split_whitespace().next().unwrap_or_default()
+    }
+
+    /// The stages of those in `kinds` that the element can be in. Untranslated elements are
+    /// copied into the kickstart pipeline if they're passed through.
+    pub fn stages<'a>(&self, kinds: &[&'a str]) -> Vec<&'a str> {
+        let stages: Vec<&str> = match &self.outcome {
+            Outcome::Translated => catalog::lookup(self.name())
+                .map(|command| command.stages().collect())
+                .unwrap_or_default(),
+            Outcome::Untranslated(_) => vec!["org.osbuild.copy"],
+            Outcome::Skipped(_) => Vec::new(),
+        };
+
+        kinds
+            .iter()
+            .copied()
+            .filter(|kind| stages.contains(kind))
+            .collect()
+    }
+
+    fn to_json(&self, kinds: &[&str]) -> Value {
+        let (outcome, reason) = match &self.outcome {
+            Outcome::Translated => ("translated", None),
+            Outcome::Skipped(reason) => ("skipped", Some(reason)),
+            Outcome::Untranslated(reason) => ("untranslated", Some(reason)),
+        };
+
+        let mut entry = json!({
+            "element": self.element,
+            "outcome": outcome,
+            "stages": self.stages(kinds),
+        });
+
+        if let Some(reason) = reason {
+            entry["reason"] = json!(reason);
+        }
+
+        if let Some(span) = &self.span {
+            entry["file"] = json!(span.path);
+            entry["line"] = json!(span.line);
+            entry["column"] = json!(span.column);
+        }
+
+        if !self.deprecated.is_empty() {
+            entry["deprecated"] = json!(self.deprecated);
+        }
+
+        entry
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    entries: Vec<Entry>,
+}
+
+impl Report {
+    /// Record what happened to `element`, the entry is returned so more can be added to it.
+    pub fn record(&mut self, element: &str, span: Option<&Span>, outcome: Outcome) -> &mut Entry {
+        debug!("Report.record: '{}' {:?}", element, outcome);
+
+        self.entries.push(Entry {
+            element: element.to_string(),
+            span: span.cloned(),
+            outcome,
+            deprecated: Vec::new(),
+        });
+
+        self.entries.last_mut().unwrap()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// How many elements were translated, skipped and not translated, to print once a kickstart
+    /// is done.
+    pub fn summary(&self) -> String {
+        let count = |outcome: fn(&Outcome) -> bool| {
+            self.entries
+                .iter()
+                .filter(|entry| outcome(&entry.outcome))
+                .count()
+        };
+
+        format!(
+            "{} commands and sections translated, {} skipped, {} unsupported",
+            count(|outcome| *outcome == Outcome::Translated),
+            count(|outcome| matches!(outcome, Outcome::Skipped(_))),
+            count(|outcome| matches!(outcome, Outcome::Untranslated(_))),
+        )
+    }
+
+    /// Every entry with the stages of those in `kinds`, the types of the stages of the manifest,
+    /// it's in.
+    pub fn to_json(&self, kinds: &[&str]) -> Value {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| entry.to_json(kinds))
+            .collect();
+
+        json!({ "entries": entries })
+    }
+}
+
+#[test]
+fn report_to_json() {
+    let span = Span {
+        path: "main.ks".to_string(),
+        line: 3,
+        column: 1,
+    };
+
+    let mut report = Report::default();
+    report.record("timezone UTC", Some(&span), Outcome::Translated);
+    report
+        .record("rootpw --plaintext ***", None, Outcome::Translated)
+        .deprecated = vec!["'--plaintext' is deprecated".to_string()];
+    report.record("reboot", None, Outcome::Skipped("flow control".to_string()));
+    report.record(
+        "%pre",
+        None,
+        Outcome::Untranslated("not supported".to_string()),
+    );
+
+    let kinds = [
+        "org.osbuild.rpm",
+        "org.osbuild.timezone",
+        "org.osbuild.copy",
+    ];
+
+    assert_eq!(
+        report.to_json(&kinds),
+        json!({
+            "entries": [
+                {
+                    "element": "timezone UTC",
+                    "outcome": "translated",
+                    "stages": ["org.osbuild.timezone"],
+                    "file": "main.ks",
+                    "line": 3,
+                    "column": 1,
+                },
+                {
+                    "element": "rootpw --plaintext ***",
+                    "outcome": "translated",
+                    "stages": [],
+                    "deprecated": ["'--plaintext' is deprecated"],
+                },
+                {
+                    "element": "reboot",
+                    "outcome": "skipped",
+                    "reason": "flow control",
+                    "stages": [],
+                },
+                {
+                    "element": "%pre",
+                    "outcome": "untranslated",
+                    "reason": "not supported",
+                    "stages": ["org.osbuild.copy"],
+                },
+            ]
+        })
+    );
+    assert!(report.entries()[3].stages(&kinds[..2]).is_empty());
+    assert_eq!(
+        report.summary(),
+        "2 commands and sections translated, 1 skipped, 1 unsupported"
+    );
+}
@@ -0,0 +1,157 @@
+//! Red Hat Subscription Management through `rhsm` and `syspurpose`. The installer registers the
+//! system while installing, an image can't be registered at build time so the best we can do is
+//! configure subscription-manager and optionally register on first boot.
+
+use serde_json::{json, Value};
+
+use super::options::Options;
+
+/// The `rhsm` command.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Rhsm {
+    pub organization: Option<String>,
+    pub activation_key: Option<String>,
+    pub server_hostname: Option<String>,
+    pub insights: bool,
+}
+
+impl Rhsm {
+    /// `rhsm --organization=<org> --activation-key=<key> [--server-hostname=<host>]
+    /// [--connect-to-insights]`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let options = Options::parse_known(
+            args,
+            &[
+                "--organization",
+                "--activation-key",
+                "--server-hostname",
+                "--rhsm-baseurl",
+                "--proxy",
+            ],
+            &["--connect-to-insights"],
+        )?;
+
+        let value = |option| options.value(option).map(str::to_string);
+
+        Ok(Self {
+            organization: value("--organization"),
+            activation_key: value("--activation-key"),
+            server_hostname: value("--server-hostname"),
+            insights: options.flag("--connect-to-insights"),
+        })
+    }
+
+    /// Options for the `org.osbuild.rhsm` stage.
+    pub fn stage_options(&self) -> Value {
+        let mut options = json!({
+            "dnf-plugins": {
+                "product-id": { "enabled": true },
+                "subscription-manager": { "enabled": true },
+            },
+            "subscription-manager": {
+                "rhsm": { "manage_repos": true },
+                "rhsmcertd": { "auto_registration": true },
+            },
+        });
+
+        if let Some(hostname) = &self.server_hostname {
+            options["subscription-manager"]["server"] = json!({ "hostname": hostname });
+        }
+
+        options
+    }
+
+    /// The commands that register the system, these contain the credentials.
+    pub fn register_commands(&self) -> Option<Vec<String>> {
+        let organization = self.organization.as_ref()?;
+        let activation_key = self.activation_key.as_ref()?;
+
+        let mut commands = vec![format!(
+            "/usr/sbin/subscription-manager register --org={} --activationkey={}",
+            organization, activation_key
+        )];
+
+        if self.insights {
+            commands.push("/usr/bin/insights-client --register".to_string());
+        }
+
+        Some(commands)
+    }
+}
+
+/// The `syspurpose` command, written to the syspurpose file subscription-manager reads.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Syspurpose {
+    pub role: Option<String>,
+    pub sla: Option<String>,
+    pub usage: Option<String>,
+    pub addons: Vec<String>,
+}
+
+impl Syspurpose {
+    /// `syspurpose [--role=<role>] [--sla=<sla>] [--usage=<usage>] [--addon=<addon>]...`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let known = ["--role", "--sla", "--usage", "--addon"];
+        let options = Options::parse_known(args, &known, &[])?;
+
+        let value = |option| options.value(option).map(str::to_string);
+
+        Ok(Self {
+            role: value("--role"),
+            sla: value("--sla"),
+            usage: value("--usage"),
+            addons: options.values("--addon").map(str::to_string).collect(),
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut purpose = json!({});
+
+        if let Some(role) = &self.role {
+            purpose["role"] = json!(role);
+        }
+
+        if let Some(sla) = &self.sla {
+            purpose["service_level_agreement"] = json!(sla);
+        }
+
+        if let Some(usage) = &self.usage {
+            purpose["usage"] = json!(usage);
+        }
+
+        if !self.addons.is_empty() {
+            purpose["addons"] = json!(self.addons);
+        }
+
+        serde_json::to_string_pretty(&purpose).unwrap() + "\n"
+    }
+}
+
+#[test]
+fn rhsm_register() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    let rhsm = Rhsm::from_args(&args("--organization=1234 --activation-key=key")).unwrap();
+    assert_eq!(
+        rhsm.register_commands(),
+        Some(vec![
+            "/usr/sbin/subscription-manager register --org=1234 --activationkey=key".to_string()
+        ])
+    );
+    assert_eq!(
+        Rhsm::from_args(&args("--organization 1234"))
+            .unwrap()
+            .register_commands(),
+        None
+    );
+}
+
+#[test]
+fn rhsm_syspurpose() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+
+    let syspurpose =
+        Syspurpose::from_args(&args("--role=Server --addon=a --sla Premium --addon b")).unwrap();
+    assert_eq!(syspurpose.addons, vec!["a", "b"]);
+    assert_eq!(syspurpose.sla.as_deref(), Some("Premium"));
+}
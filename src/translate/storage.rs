@@ -0,0 +1,1055 @@
+//! Storage commands describe the disks the installer partitions. The disk of an image is a file
+//! we create ourselves, so `part`, `volgroup` and `logvol` turn into the partition table and
+//! filesystems of the image pipeline while the commands that pick disks have nothing to pick.
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use super::options::Options;
+use crate::kickstart::Span;
+
+/// Sizes in a kickstart are in MiB, the partition table is in sectors.
+pub const MIB: u64 = 1024 * 1024;
+pub const SECTOR: u64 = 512;
+
+/// Space in MiB kept free before the first partition and after the last one, for the partition
+/// table and the backup GPT.
+const HEAD: u64 = 1;
+const TAIL: u64 = 1;
+
+/// LVM allocates in extents of this many MiB, one extent of each volume group is left for its
+/// metadata.
+const EXTENT: u64 = 4;
+
+/// Filesystem types, and the special types of partitions that don't hold a filesystem, that we
+/// can create.
+const FSTYPES: &[&str] = &[
+    "xfs", "ext4", "btrfs", "vfat", "efi", "swap", "biosboot", "prepboot", "lvmpv", "raid",
+];
+
+/// Options that refer to storage that already exists, an image starts out without any.
+const EXISTING_OPTIONS: &[&str] = &[
+    "--onpart",
+    "--usepart",
+    "--noformat",
+    "--useexisting",
+    "--onbiosdisk",
+];
+
+/// The options of `part` and `logvol` that take a value.
+pub const VOLUME_VALUES: &[&str] = &[
+    "--fstype",
+    "--size",
+    "--maxsize",
+    "--label",
+    "--fsoptions",
+    "--mkfsoptions",
+    "--fsprofile",
+    "--ondisk",
+    "--ondrive",
+    "--onpart",
+    "--usepart",
+    "--start",
+    "--end",
+    "--vgname",
+    "--name",
+    "--percent",
+    "--resize",
+    "--passphrase",
+    "--cipher",
+    "--luks-version",
+    "--escrowcert",
+    "--poolname",
+    "--chunksize",
+    "--metadatasize",
+    "--cachesize",
+    "--cachemode",
+    "--cachepvs",
+];
+
+/// The options of `raid` that take a value.
+const RAID_VALUES: &[&str] = &[
+    "--level",
+    "--device",
+    "--fstype",
+    "--label",
+    "--fsoptions",
+    "--mkfsoptions",
+    "--fsprofile",
+    "--chunksize",
+    "--spares",
+    "--passphrase",
+    "--cipher",
+    "--luks-version",
+    "--escrowcert",
+];
+
+/// The options of `btrfs` that take a value.
+const BTRFS_VALUES: &[&str] = &["--data", "--metadata", "--label", "--name", "--mkfsoptions"];
+
+/// The options of `autopart` that take a value.
+pub const AUTOPART_VALUES: &[&str] = &[
+    "--type",
+    "--fstype",
+    "--passphrase",
+    "--cipher",
+    "--luks-version",
+    "--escrowcert",
+];
+
+/// Parses the arguments of a storage command at a span into a [`Storage`].
+pub type Command = fn(&mut Storage, &[String], Option<&Span>) -> Result<(), String>;
+
+/// A partition or logical volume as given in the kickstart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Volume {
+    /// The mountpoint, or one of the special names `swap`, `biosboot`, `prepboot` or `pv.<id>`.
+    pub mountpoint: String,
+    pub fstype: String,
+    /// The minimum size in MiB.
+    pub size: u64,
+    pub grow: bool,
+    pub maxsize: Option<u64>,
+    pub label: Option<String>,
+    pub fsoptions: Option<String>,
+    /// Where the command is, volumes that `autopart` and `reqpart` add have none.
+    pub span: Option<Span>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VolGroup {
+    pub name: String,
+    pub pvs: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogVol {
+    pub vgname: String,
+    pub name: String,
+    pub volume: Volume,
+}
+
+/// `raid <mountpoint> --level=<level> --device=<name> <member>...`, the members are partitions
+/// named `raid.<id>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raid {
+    pub mountpoint: String,
+    pub members: Vec<String>,
+    pub span: Option<Span>,
+}
+
+/// `btrfs <mountpoint> <member>...` or `btrfs <mountpoint> --subvol --name=<name> <parent>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Btrfs {
+    pub mountpoint: String,
+    pub subvol: bool,
+    pub members: Vec<String>,
+    pub span: Option<Span>,
+}
+
+/// A problem with the storage plan, found before working out the layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Problem {
+    /// What kind of problem this is, stable so it can be matched on.
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+/// `snapshot <vg>/<lv> --name=<name> --when=post-install`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub vgname: String,
+    pub lvname: String,
+    pub name: String,
+    /// `pre-install` or `post-install`.
+    pub when: String,
+}
+
+/// Everything the storage commands said about the layout of the disk.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Storage {
+    pub partitions: Vec<Volume>,
+    pub volgroups: Vec<VolGroup>,
+    pub logvols: Vec<LogVol>,
+    /// Software RAID and btrfs can't be created by the image stages, they are only validated.
+    pub raids: Vec<Raid>,
+    pub btrfs: Vec<Btrfs>,
+    /// Snapshots to take after installing.
+    pub snapshots: Vec<Snapshot>,
+    /// The partition table type from `clearpart --disklabel`.
+    pub disklabel: Option<String>,
+}
+
+/// Where a filesystem lives in a [`Layout`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// The index of the partition.
+    Partition(usize),
+    /// A logical volume in the volume group on the partition with this index.
+    LogVol { pv: usize, name: String },
+}
+
+/// A partition with its place on the disk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placed {
+    pub volume: Volume,
+    /// Start and size in sectors.
+    pub start: u64,
+    pub size: u64,
+}
+
+/// A filesystem, or swap, to create.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filesystem {
+    pub volume: Volume,
+    pub location: Location,
+    pub uuid: String,
+    /// Size in bytes, for logical volumes.
+    pub size: u64,
+}
+
+/// The disk layout with all sizes and positions worked out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    /// `gpt` or `dos`.
+    pub label: String,
+    /// The size of the disk in bytes.
+    pub size: u64,
+    pub partitions: Vec<Placed>,
+    /// Volume groups by name and the index of their physical volume.
+    pub volgroups: Vec<(String, usize)>,
+    pub filesystems: Vec<Filesystem>,
+    /// Snapshots of logical volumes in the layout and the MiB set aside for them.
+    pub snapshots: Vec<(Snapshot, u64)>,
+}
+
+impl Volume {
+    /// A volume of `size` MiB with the default filesystem for `mountpoint`.
+    fn new(mountpoint: &str, size: u64) -> Self {
+        Self {
+            mountpoint: mountpoint.to_string(),
+            fstype: default_fstype(mountpoint).to_string(),
+            size,
+            grow: false,
+            maxsize: None,
+            label: None,
+            fsoptions: None,
+            span: None,
+        }
+    }
+
+    /// The volume from the options of `part` or `logvol`, the mountpoint is the positional argument.
+    fn from_options(options: &Options) -> Result<Self, String> {
+        for option in options.names() {
+            if EXISTING_OPTIONS.contains(&option) {
+                return Err(format!("{} refers to existing storage", option));
+            }
+
+            if option == "--encrypted" {
+                return Err("encrypted volumes are not supported".to_string());
+            }
+        }
+
+        let mountpoint = options.positional().first().ok_or("needs a mountpoint")?;
+
+        let mut volume = Self::new(mountpoint, default_size(mountpoint));
+
+        if let Some(fstype) = options.value("--fstype") {
+            volume.fstype = fstype.to_string();
+        }
+
+        if !FSTYPES.contains(&volume.fstype.as_str()) {
+            return Err(format!(
+                "filesystem type '{}' is not supported",
+                volume.fstype
+            ));
+        }
+
+        if let Some(size) = options.value("--size") {
+            volume.size = parse_size("--size", size)?;
+        }
+
+        if let Some(maxsize) = options.value("--maxsize") {
+            volume.maxsize = Some(parse_size("--maxsize", maxsize)?);
+        }
+
+        volume.grow = options.flag("--grow");
+        volume.label = options.value("--label").map(str::to_string);
+        volume.fsoptions = options.value("--fsoptions").map(str::to_string);
+
+        Ok(volume)
+    }
+
+    /// Whether this is mounted, as opposed to swap and the special partitions.
+    pub fn is_mounted(&self) -> bool {
+        self.mountpoint.starts_with('/')
+    }
+
+    /// Whether this holds a filesystem or swap, as opposed to the special partitions.
+    pub fn has_filesystem(&self) -> bool {
+        !matches!(
+            self.fstype.as_str(),
+            "biosboot" | "prepboot" | "lvmpv" | "raid"
+        )
+    }
+}
+
+impl Snapshot {
+    /// `snapshot <vg>/<lv> --name=<name> --when=pre-install|post-install`
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let options = Options::parse(args, &["--name", "--when"])?;
+
+        let origin = options
+            .positional()
+            .first()
+            .ok_or("needs a volume group and logical volume")?;
+
+        let (vgname, lvname) = origin
+            .split_once('/')
+            .ok_or(format!("'{}' is not <vg>/<lv>", origin))?;
+
+        let when = options.value("--when").ok_or("needs --when")?;
+
+        if !["pre-install", "post-install"].contains(&when) {
+            return Err(format!("unknown --when '{}'", when));
+        }
+
+        Ok(Self {
+            vgname: vgname.to_string(),
+            lvname: lvname.to_string(),
+            name: options.value("--name").ok_or("needs --name")?.to_string(),
+            when: when.to_string(),
+        })
+    }
+}
+
+impl Storage {
+    /// Whether any storage was described, without it there is no disk to create.
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    /// `part <mountpoint> [--size=<MiB>] [--grow] [--maxsize=<MiB>] [--fstype=<type>]`
+    pub fn part(&mut self, args: &[String], span: Option<&Span>) -> Result<(), String> {
+        let options = Options::parse(args, VOLUME_VALUES)?;
+
+        let mut volume = Volume::from_options(&options)?;
+        volume.span = span.cloned();
+
+        self.partitions.push(volume);
+        Ok(())
+    }
+
+    /// `volgroup <name> <pv>...`
+    pub fn volgroup(&mut self, args: &[String], _span: Option<&Span>) -> Result<(), String> {
+        let options = Options::parse(
+            args,
+            &["--pesize", "--reserved-space", "--reserved-percent"],
+        )?;
+
+        let mut positional = options.positional().iter();
+
+        let name = positional.next().ok_or("needs a name")?;
+        let pvs: Vec<String> = positional.cloned().collect();
+
+        match pvs.len() {
+            0 => return Err("needs a physical volume".to_string()),
+            1 => {}
+            _ => return Err("only a single physical volume is supported".to_string()),
+        }
+
+        if options.flag("--useexisting") || options.flag("--noformat") {
+            return Err("refers to existing storage".to_string());
+        }
+
+        self.volgroups.push(VolGroup {
+            name: name.clone(),
+            pvs,
+        });
+
+        Ok(())
+    }
+
+    /// `logvol <mountpoint> --vgname=<vg> --name=<lv> [--size=<MiB>] [--grow]`
+    pub fn logvol(&mut self, args: &[String], span: Option<&Span>) -> Result<(), String> {
+        let options = Options::parse(args, VOLUME_VALUES)?;
+        let mut volume = Volume::from_options(&options)?;
+        volume.span = span.cloned();
+
+        if !volume.has_filesystem() {
+            return Err(format!("'{}' can't be a logical volume", volume.mountpoint));
+        }
+
+        self.logvols.push(LogVol {
+            vgname: options
+                .value("--vgname")
+                .ok_or("needs --vgname")?
+                .to_string(),
+            name: options.value("--name").ok_or("needs --name")?.to_string(),
+            volume,
+        });
+
+        Ok(())
+    }
+
+    /// `raid <mountpoint> --level=<level> --device=<name> <member>...`
+    pub fn raid(&mut self, args: &[String], span: Option<&Span>) -> Result<(), String> {
+        let options = Options::parse(args, RAID_VALUES)?;
+        let (mountpoint, members) = options
+            .positional()
+            .split_first()
+            .ok_or("needs a mountpoint")?;
+
+        if !options.flag("--useexisting") && options.value("--level").is_none() {
+            return Err("needs --level".to_string());
+        }
+
+        self.raids.push(Raid {
+            mountpoint: mountpoint.clone(),
+            members: members.to_vec(),
+            span: span.cloned(),
+        });
+
+        Ok(())
+    }
+
+    /// `btrfs <mountpoint> [--subvol --name=<name>] <member>...`
+    pub fn btrfs(&mut self, args: &[String], span: Option<&Span>) -> Result<(), String> {
+        let options = Options::parse(args, BTRFS_VALUES)?;
+        let (mountpoint, members) = options
+            .positional()
+            .split_first()
+            .ok_or("needs a mountpoint")?;
+
+        let subvol = options.flag("--subvol");
+
+        if subvol && options.value("--name").is_none() {
+            return Err("--subvol needs --name".to_string());
+        }
+
+        self.btrfs.push(Btrfs {
+            mountpoint: mountpoint.clone(),
+            subvol,
+            members: members.to_vec(),
+            span: span.cloned(),
+        });
+
+        Ok(())
+    }
+
+    /// The problems with the storage plan that can be told without working out the layout, each
+    /// on its own so they can all be fixed at once.
+    pub fn validate(&self, image_size: Option<u64>) -> Vec<Problem> {
+        let mut problems = Vec::new();
+
+        let mounted: Vec<(&str, Option<&Span>)> = self
+            .partitions
+            .iter()
+            .chain(self.logvols.iter().map(|lv| &lv.volume))
+            .map(|volume| (volume.mountpoint.as_str(), volume.span.as_ref()))
+            .chain(
+                self.raids
+                    .iter()
+                    .map(|raid| (raid.mountpoint.as_str(), raid.span.as_ref())),
+            )
+            .chain(
+                self.btrfs
+                    .iter()
+                    .map(|btrfs| (btrfs.mountpoint.as_str(), btrfs.span.as_ref())),
+            )
+            .filter(|(mountpoint, _)| mountpoint.starts_with('/'))
+            .collect();
+
+        for (index, (mountpoint, span)) in mounted.iter().enumerate() {
+            if let Some((_, first)) = mounted[..index]
+                .iter()
+                .find(|(known, _)| known == mountpoint)
+            {
+                problems.push(Problem {
+                    code: "duplicate-mountpoint",
+                    message: match first {
+                        Some(first) => {
+                            format!(
+                                "'{}' is mounted more than once, first at {}",
+                                mountpoint, first
+                            )
+                        }
+                        None => format!("'{}' is mounted more than once", mountpoint),
+                    },
+                    span: span.cloned(),
+                });
+            }
+        }
+
+        if !mounted.iter().any(|(mountpoint, _)| *mountpoint == "/") {
+            problems.push(Problem {
+                code: "missing-root",
+                message: "there is no root filesystem, add a partition or volume for '/'"
+                    .to_string(),
+                span: None,
+            });
+        }
+
+        for btrfs in self.btrfs.iter().filter(|btrfs| btrfs.subvol) {
+            if btrfs.mountpoint == "swap" {
+                problems.push(Problem {
+                    code: "swap-on-subvolume",
+                    message: "swap can't be a btrfs subvolume, use a partition or logical volume"
+                        .to_string(),
+                    span: btrfs.span.clone(),
+                });
+            }
+        }
+
+        for raid in &self.raids {
+            for member in &raid.members {
+                if !self
+                    .partitions
+                    .iter()
+                    .any(|partition| partition.mountpoint == *member)
+                {
+                    problems.push(Problem {
+                        code: "undefined-raid-member",
+                        message: format!(
+                            "RAID '{}' uses '{}', which isn't a partition",
+                            raid.mountpoint, member
+                        ),
+                        span: raid.span.clone(),
+                    });
+                }
+            }
+        }
+
+        if let Some(image_size) = image_size {
+            let needed = HEAD + self.sizes().iter().sum::<u64>() + TAIL;
+
+            if needed > image_size {
+                problems.push(Problem {
+                    code: "image-too-small",
+                    message: format!(
+                        "the partitions need {} MiB but the image is {} MiB, pass a larger \
+                         --image-size or make them smaller",
+                        needed, image_size
+                    ),
+                    span: None,
+                });
+            }
+        }
+
+        problems
+    }
+
+    /// `autopart [--type=lvm|plain] [--fstype=<type>] [--noboot]` lays out a disk like the
+    /// installer would, with the partitions the platform needs followed by `/boot` and `/`.
+    pub fn autopart(&mut self, arch: &str, args: &[String]) -> Result<(), String> {
+        let options = Options::parse(args, AUTOPART_VALUES)?;
+
+        if options.flag("--encrypted") {
+            return Err("encrypted volumes are not supported".to_string());
+        }
+
+        let fstype = options.value("--fstype").unwrap_or("xfs").to_string();
+
+        if !["xfs", "ext4"].contains(&fstype.as_str()) {
+            return Err(format!("filesystem type '{}' is not supported", fstype));
+        }
+
+        let lvm = match options.value("--type") {
+            None | Some("lvm") => true,
+            Some("plain") => false,
+            Some(kind) => return Err(format!("--type={} is not supported", kind)),
+        };
+
+        self.partitions.extend(platform_partitions(arch));
+
+        if !options.flag("--noboot") {
+            let mut boot = Volume::new("/boot", default_size("/boot"));
+            boot.fstype = fstype.clone();
+            self.partitions.push(boot);
+        }
+
+        let mut root = Volume::new("/", 2048);
+        root.fstype = fstype;
+        root.grow = true;
+
+        if lvm {
+            let mut pv = Volume::new("pv.autopart", root.size + EXTENT);
+            pv.grow = true;
+
+            self.partitions.push(pv);
+            self.volgroups.push(VolGroup {
+                name: "rootvg".to_string(),
+                pvs: vec!["pv.autopart".to_string()],
+            });
+            self.logvols.push(LogVol {
+                vgname: "rootvg".to_string(),
+                name: "rootlv".to_string(),
+                volume: root,
+            });
+        } else {
+            self.partitions.push(root);
+        }
+
+        Ok(())
+    }
+
+    /// `reqpart [--add-boot]` adds the partitions the platform needs to boot.
+    pub fn reqpart(&mut self, arch: &str, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(args, &[], &["--add-boot"])?;
+
+        self.partitions.extend(platform_partitions(arch));
+
+        if options.flag("--add-boot") {
+            self.partitions
+                .push(Volume::new("/boot", default_size("/boot")));
+        }
+
+        Ok(())
+    }
+
+    /// Work out where everything goes. Growing partitions and logical volumes share the space
+    /// that's left when an image size is given, otherwise the disk is as small as it can be.
+    /// The UUIDs of the filesystems are derived from `seed`.
+    pub fn layout(
+        &self,
+        arch: &str,
+        image_size: Option<u64>,
+        seed: &str,
+    ) -> Result<Layout, String> {
+        let label = match self.disklabel.as_deref() {
+            Some("gpt") => "gpt",
+            Some("msdos" | "dos" | "mbr") => "dos",
+            Some(label) => return Err(format!("disk label '{}' is not supported", label)),
+            None if arch == "s390x" || arch == "ppc64le" => "dos",
+            None => "gpt",
+        };
+
+        if label == "dos" && self.partitions.len() > 4 {
+            return Err("a dos partition table holds at most 4 partitions".to_string());
+        }
+
+        if !self.raids.is_empty() || self.partitions.iter().any(|p| p.fstype == "raid") {
+            return Err("software RAID is not supported".to_string());
+        }
+
+        if !self.btrfs.is_empty() {
+            return Err("btrfs volumes are not supported, use --fstype=btrfs".to_string());
+        }
+
+        let mut sizes = self.sizes();
+
+        let needed = HEAD + sizes.iter().sum::<u64>() + TAIL;
+
+        let total = match image_size {
+            Some(image_size) if image_size < needed => {
+                return Err(format!(
+                    "the partitions need {} MiB but the image is {} MiB",
+                    needed, image_size
+                ));
+            }
+            Some(image_size) => image_size,
+            None => needed,
+        };
+
+        let growing: Vec<&Volume> = self.partitions.iter().collect();
+        grow(&mut sizes, &growing, total - needed, 1);
+
+        let mut partitions = Vec::new();
+        let mut start = HEAD;
+
+        for (partition, size) in self.partitions.iter().zip(&sizes) {
+            partitions.push(Placed {
+                volume: partition.clone(),
+                start: start * MIB / SECTOR,
+                size: size * MIB / SECTOR,
+            });
+
+            start += size;
+        }
+
+        let mut volgroups = Vec::new();
+        let mut filesystems = Vec::new();
+
+        for (index, partition) in self.partitions.iter().enumerate() {
+            if partition.has_filesystem() {
+                filesystems.push(Filesystem {
+                    volume: partition.clone(),
+                    location: Location::Partition(index),
+                    uuid: seeded_uuid(seed, &format!("partition {}", index)),
+                    size: sizes[index] * MIB,
+                });
+            }
+        }
+
+        for group in &self.volgroups {
+            let pv = self
+                .partitions
+                .iter()
+                .position(|partition| partition.mountpoint == group.pvs[0])
+                .ok_or(format!(
+                    "volume group '{}' uses undefined physical volume '{}'",
+                    group.name, group.pvs[0]
+                ))?;
+
+            if self.partitions[pv].fstype != "lvmpv" {
+                return Err(format!(
+                    "'{}' in volume group '{}' is not a physical volume",
+                    group.pvs[0], group.name
+                ));
+            }
+
+            let logvols: Vec<&LogVol> = self
+                .logvols
+                .iter()
+                .filter(|lv| lv.vgname == group.name)
+                .collect();
+
+            let mut lv_sizes: Vec<u64> = logvols
+                .iter()
+                .map(|lv| round_up(lv.volume.size, EXTENT))
+                .collect();
+
+            let reserved: u64 = logvols.iter().map(|lv| self.snapshot_size(lv)).sum();
+            let usable = (sizes[pv] - EXTENT) / EXTENT * EXTENT;
+            let free = usable - reserved - lv_sizes.iter().sum::<u64>();
+            let volumes: Vec<&Volume> = logvols.iter().map(|lv| &lv.volume).collect();
+            grow(&mut lv_sizes, &volumes, free, EXTENT);
+
+            for (lv, size) in logvols.iter().zip(lv_sizes) {
+                filesystems.push(Filesystem {
+                    volume: lv.volume.clone(),
+                    location: Location::LogVol {
+                        pv,
+                        name: lv.name.clone(),
+                    },
+                    uuid: seeded_uuid(seed, &format!("logvol {}/{}", group.name, lv.name)),
+                    size: size * MIB,
+                });
+            }
+
+            volgroups.push((group.name.clone(), pv));
+        }
+
+        if let Some(lv) = self
+            .logvols
+            .iter()
+            .find(|lv| !self.volgroups.iter().any(|group| group.name == lv.vgname))
+        {
+            return Err(format!(
+                "logical volume '{}' is in undefined volume group '{}'",
+                lv.name, lv.vgname
+            ));
+        }
+
+        let snapshots = self
+            .snapshots
+            .iter()
+            .filter_map(|snapshot| {
+                let lv = self
+                    .logvols
+                    .iter()
+                    .find(|lv| lv.vgname == snapshot.vgname && lv.name == snapshot.lvname)?;
+
+                Some((snapshot.clone(), round_up(lv.volume.size, EXTENT)))
+            })
+            .collect();
+
+        Ok(Layout {
+            label: label.to_string(),
+            size: total * MIB,
+            partitions,
+            volgroups,
+            filesystems,
+            snapshots,
+        })
+    }
+
+    /// The minimum size of every partition in MiB. Physical volumes have to fit the logical
+    /// volumes in their group.
+    fn sizes(&self) -> Vec<u64> {
+        let mut sizes: Vec<u64> = Vec::new();
+
+        for partition in &self.partitions {
+            let mut size = partition.size;
+
+            if partition.fstype == "lvmpv" {
+                let needed: u64 = self
+                    .logvols_on(&partition.mountpoint)
+                    .map(|lv| round_up(lv.volume.size, EXTENT) + self.snapshot_size(lv))
+                    .sum();
+
+                size = size.max(needed + EXTENT);
+            }
+
+            sizes.push(size);
+        }
+
+        sizes
+    }
+
+    /// The space set aside for snapshots of `lv`, each is as large as its minimum size so the
+    /// snapshot can't fill up before the volume has grown past its original contents.
+    fn snapshot_size(&self, lv: &LogVol) -> u64 {
+        let count = self
+            .snapshots
+            .iter()
+            .filter(|snapshot| snapshot.vgname == lv.vgname && snapshot.lvname == lv.name)
+            .count() as u64;
+
+        count * round_up(lv.volume.size, EXTENT)
+    }
+
+    /// The logical volumes in the volume group on physical volume `pv`.
+    fn logvols_on<'a>(&'a self, pv: &'a str) -> impl Iterator<Item = &'a LogVol> {
+        self.volgroups
+            .iter()
+            .filter(move |group| group.pvs.iter().any(|name| name == pv))
+            .flat_map(move |group| self.logvols.iter().filter(|lv| lv.vgname == group.name))
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{}: {}", span, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Layout {
+    /// The filesystem mounted at `mountpoint`.
+    pub fn filesystem(&self, mountpoint: &str) -> Option<&Filesystem> {
+        self.filesystems
+            .iter()
+            .find(|filesystem| filesystem.volume.mountpoint == mountpoint)
+    }
+
+    /// The filesystem holding `/boot`, which is the root filesystem unless it has its own.
+    pub fn boot(&self) -> Option<&Filesystem> {
+        self.filesystem("/boot").or_else(|| self.filesystem("/"))
+    }
+
+    /// The first partition of type `fstype`.
+    pub fn partition(&self, fstype: &str) -> Option<(usize, &Placed)> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .find(|(_, partition)| partition.volume.fstype == fstype)
+    }
+}
+
+/// Hand out `free` MiB, in units of `unit`, to the growing volumes. Each gets an equal share
+/// unless that would go over its maximum size.
+fn grow(sizes: &mut [u64], volumes: &[&Volume], free: u64, unit: u64) {
+    let growing: Vec<usize> = (0..volumes.len())
+        .filter(|&index| volumes[index].grow)
+        .collect();
+
+    if growing.is_empty() {
+        return;
+    }
+
+    let share = free / growing.len() as u64 / unit * unit;
+
+    for index in growing {
+        let max = volumes[index]
+            .maxsize
+            .map_or(u64::MAX, |max| round_up(max, unit));
+        sizes[index] = (sizes[index] + share).min(max.max(sizes[index]));
+    }
+}
+
+/// The partitions the bootloader of `arch` needs, as `reqpart` adds them.
+fn platform_partitions(arch: &str) -> Vec<Volume> {
+    match arch {
+        "x86_64" => vec![Volume::new("biosboot", 1), Volume::new("/boot/efi", 600)],
+        "aarch64" => vec![Volume::new("/boot/efi", 600)],
+        "ppc64le" => vec![Volume::new("prepboot", 4)],
+        _ => Vec::new(),
+    }
+}
+
+fn default_fstype(mountpoint: &str) -> &'static str {
+    match mountpoint {
+        "swap" => "swap",
+        "biosboot" => "biosboot",
+        "prepboot" => "prepboot",
+        "/boot/efi" => "efi",
+        pv if pv.starts_with("pv.") => "lvmpv",
+        raid if raid.starts_with("raid.") => "raid",
+        _ => "xfs",
+    }
+}
+
+/// The size the installer gives a partition without `--size`.
+fn default_size(mountpoint: &str) -> u64 {
+    match mountpoint {
+        "biosboot" => 1,
+        "prepboot" => 4,
+        "/boot/efi" => 600,
+        "swap" => 2048,
+        _ => 1024,
+    }
+}
+
+fn parse_size(option: &str, size: &str) -> Result<u64, String> {
+    size.parse()
+        .map_err(|_| format!("{} needs a size in MiB, not '{}'", option, size))
+}
+
+fn round_up(size: u64, unit: u64) -> u64 {
+    size.div_ceil(unit) * unit
+}
+
+/// A UUID that only depends on `seed` and the `name` of what it identifies.
+fn seeded_uuid(seed: &str, name: &str) -> String {
+    let digest = Sha256::digest(format!("{}\0{}", seed, name));
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&digest[..16]);
+
+    uuid::Builder::from_random_bytes(bytes)
+        .into_uuid()
+        .to_string()
+}
+
+#[test]
+fn storage_layout() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+    let mut storage = Storage::default();
+
+    storage.part(&args("/boot --size=1024"), None).unwrap();
+    storage
+        .part(&args("pv.01 --size 100 --grow"), None)
+        .unwrap();
+    storage.volgroup(&args("vg pv.01"), None).unwrap();
+    storage
+        .logvol(&args("/ --vgname=vg --name=root --size=2046"), None)
+        .unwrap();
+    storage
+        .logvol(
+            &args("/home --vgname=vg --name=home --size=10 --grow"),
+            None,
+        )
+        .unwrap();
+
+    let layout = storage.layout("x86_64", Some(8192), "").unwrap();
+
+    assert_eq!(layout.label, "gpt");
+    assert_eq!(layout.partitions[0].start, 2048);
+    assert_eq!(layout.partitions[1].start, 1025 * MIB / SECTOR);
+    assert_eq!(layout.partitions[1].size, (8192 - 1026) * MIB / SECTOR);
+    assert_eq!(layout.boot().unwrap().volume.mountpoint, "/boot");
+
+    let home = layout.filesystem("/home").unwrap();
+    assert_eq!(home.size, (8192 - 1026 - 4 - 2048) / 4 * 4 * MIB);
+
+    let uuid = |seed: &str| {
+        let layout = storage.layout("x86_64", None, seed).unwrap();
+        layout.filesystem("/home").unwrap().uuid.clone()
+    };
+    assert_eq!(home.uuid, uuid(""));
+    assert_ne!(home.uuid, uuid("other"));
+    assert_ne!(home.uuid, layout.filesystem("/").unwrap().uuid);
+    assert_eq!(&home.uuid[14..15], "4");
+
+    for snapshot in ["vg/home --name=home-0", "vg/data --name=data-0"] {
+        storage.snapshots.push(
+            Snapshot::from_args(&args(&format!("{} --when=post-install", snapshot))).unwrap(),
+        );
+    }
+
+    let layout = storage.layout("x86_64", Some(8192), "").unwrap();
+    assert_eq!(layout.snapshots.len(), 1);
+    assert_eq!(layout.snapshots[0].1, 12);
+    assert_eq!(
+        layout.filesystem("/home").unwrap().size,
+        (8192 - 1026 - 4 - 2048 - 12) / 4 * 4 * MIB
+    );
+    assert!(Snapshot::from_args(&args("vg --name=x --when=post-install")).is_err());
+
+    assert!(storage.layout("x86_64", Some(1024), "").is_err());
+    assert!(storage.part(&args("/data --onpart=sda3"), None).is_err());
+    assert!(storage.part(&args("/data --fstype=ext3"), None).is_err());
+}
+
+#[test]
+fn storage_autopart() {
+    let mut storage = Storage::default();
+
+    storage.autopart("s390x", &[]).unwrap();
+
+    let layout = storage.layout("s390x", None, "").unwrap();
+
+    assert_eq!(layout.label, "dos");
+    assert_eq!(layout.boot().unwrap().location, Location::Partition(0));
+    assert_eq!(
+        layout.filesystem("/").unwrap().location,
+        Location::LogVol {
+            pv: 1,
+            name: "rootlv".to_string()
+        }
+    );
+}
+
+#[test]
+fn storage_validate() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+    let span = |line| Span {
+        path: "main.ks".to_string(),
+        line,
+        column: 1,
+    };
+
+    let mut storage = Storage::default();
+
+    storage
+        .part(&args("/boot --size=1024"), Some(&span(1)))
+        .unwrap();
+    storage
+        .part(&args("raid.01 --size=512"), Some(&span(2)))
+        .unwrap();
+    storage
+        .raid(
+            &args("/data --level=1 --device=md0 raid.01 raid.02"),
+            Some(&span(3)),
+        )
+        .unwrap();
+    storage
+        .btrfs(
+            &args("swap --subvol --name=swap LABEL=data"),
+            Some(&span(4)),
+        )
+        .unwrap();
+    storage
+        .part(&args("/boot --size=512"), Some(&span(5)))
+        .unwrap();
+
+    let problems = storage.validate(Some(1024));
+
+    assert_eq!(
+        problems
+            .iter()
+            .map(|problem| (problem.code, problem.span.as_ref().map(|span| span.line)))
+            .collect::<Vec<_>>(),
+        vec![
+            ("duplicate-mountpoint", Some(5)),
+            ("missing-root", None),
+            ("swap-on-subvolume", Some(4)),
+            ("undefined-raid-member", Some(3)),
+            ("image-too-small", None),
+        ]
+    );
+    assert_eq!(
+        problems[0].to_string(),
+        "main.ks:5:1: '/boot' is mounted more than once, first at main.ks:1:1"
+    );
+
+    let mut storage = Storage::default();
+    storage.part(&args("/ --size=1024"), None).unwrap();
+
+    assert!(storage.validate(Some(1026)).is_empty());
+    assert!(storage.raid(&args("/data raid.01"), None).is_err());
+}
@@ -0,0 +1,100 @@
+//! Time configuration is spread over `timezone`, which sets the zone and used to carry the NTP
+//! servers, and `timesource`, which replaced the NTP part of it. Both end up in a single chrony
+//! configuration.
+
+use super::options::Options;
+
+/// Everything that was said about time across all commands.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Time {
+    pub zone: Option<String>,
+    pub servers: Vec<String>,
+    pub pools: Vec<String>,
+    pub ntp_disabled: bool,
+    /// Options we understood but the chrony stage can't express.
+    pub unsupported: Vec<String>,
+}
+
+impl Time {
+    /// `timezone [--utc|--isUtc] [--nontp] [--ntpservers=<a,b>] <zone>`
+    pub fn timezone(&mut self, args: &[String]) -> Result<(), String> {
+        // The hardware clock is always UTC in an image.
+        let options =
+            Options::parse_known(args, &["--ntpservers"], &["--utc", "--isUtc", "--nontp"])?;
+
+        for servers in options.values("--ntpservers") {
+            self.add_servers(servers);
+        }
+
+        if options.flag("--nontp") {
+            self.ntp_disabled = true;
+        }
+
+        if let Some(zone) = options.positional().last() {
+            self.zone = Some(zone.clone());
+        }
+
+        Ok(())
+    }
+
+    /// `timesource [--ntp-server=<host>|--ntp-pool=<host>|--ntp-disable] [--nts]`
+    pub fn timesource(&mut self, args: &[String]) -> Result<(), String> {
+        let options = Options::parse_known(
+            args,
+            &["--ntp-server", "--ntp-pool"],
+            &["--ntp-disable", "--nts"],
+        )?;
+
+        self.servers
+            .extend(options.values("--ntp-server").map(str::to_string));
+        self.pools
+            .extend(options.values("--ntp-pool").map(str::to_string));
+
+        if options.flag("--ntp-disable") {
+            self.ntp_disabled = true;
+        }
+
+        if options.flag("--nts") {
+            self.unsupported.push("--nts".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn add_servers(&mut self, servers: &str) {
+        self.servers.extend(
+            servers
+                .split(',')
+                .filter(|server| !server.is_empty())
+                .map(str::to_string),
+        );
+    }
+
+    /// All hosts chrony should use, pools are configured like servers.
+    pub fn timeservers(&self) -> Vec<String> {
+        let mut hosts = self.servers.clone();
+        hosts.extend(self.pools.iter().cloned());
+        hosts
+    }
+}
+
+#[test]
+fn time_merge() {
+    let args = |s: &str| s.split_whitespace().map(str::to_string).collect::<Vec<_>>();
+    let mut time = Time::default();
+
+    time.timezone(&args(
+        "Europe/Amsterdam --utc --ntpservers=a.example,b.example",
+    ))
+    .unwrap();
+    time.timesource(&args("--ntp-pool pool.example --nts"))
+        .unwrap();
+
+    assert_eq!(time.zone.as_deref(), Some("Europe/Amsterdam"));
+    assert_eq!(
+        time.timeservers(),
+        vec!["a.example", "b.example", "pool.example"]
+    );
+    assert_eq!(time.unsupported, vec!["--nts"]);
+    assert!(time.timesource(&args("--bogus")).is_err());
+}
@@ -0,0 +1,91 @@
+//! Splitting command lines into words the way a shell does, kickstart commands quote their values
+//! like `user --gecos="John Doe"` or `bootloader --append='console=ttyS0,115200 quiet'`.
+
+/// The words of a command line. Single quotes keep everything up to the closing quote, double
+/// quotes keep everything except that a backslash escapes `"`, `\`, `$` and `` ` ``, and outside
+/// of quotes a backslash escapes any character.
+pub fn split(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".to_string()),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+
+    Ok(words)
+}
+
+/// A word quoted so that [`split`] gives it back, words without special characters are left as
+/// they are.
+pub fn quote(word: &str) -> String {
+    if !word.is_empty()
+        && !word
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'))
+    {
+        return word.to_string();
+    }
+
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+#[test]
+fn words_split() {
+    assert_eq!(
+        split(r#"user --name=john --gecos="John \"JD\" Doe""#).unwrap(),
+        vec!["user", "--name=john", r#"--gecos=John "JD" Doe"#]
+    );
+    assert_eq!(
+        split("bootloader --append='console=ttyS0,115200 quiet'").unwrap(),
+        vec!["bootloader", "--append=console=ttyS0,115200 quiet"]
+    );
+    assert_eq!(
+        split(r#"rootpw a\ b "" c"#).unwrap(),
+        vec!["rootpw", "a b", "", "c"]
+    );
+    assert!(split("user --gecos=\"John").is_err());
+
+    for word in ["plain", "two words", "it's", ""] {
+        assert_eq!(split(&quote(word)).unwrap(), vec![word]);
+    }
+}
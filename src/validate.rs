@@ -0,0 +1,192 @@
+//! Validation checks a kickstart against a syntax version the way pykickstart's `ksvalidator`
+//! does, so a kickstart can be checked in CI before it is ever used to build an image.
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::dialect;
+use crate::distro::Distro;
+use crate::kickstart::{Kickstart, KickstartError, SectionKind};
+use crate::translate::deprecated;
+use crate::translate::words;
+use crate::version::Version;
+
+/// Check `kickstart` against `version` and `distro`, `problems` are those found while reading it
+/// and are all errors.
+pub fn validate(
+    kickstart: &Kickstart,
+    problems: &[KickstartError],
+    version: Version,
+    distro: &Distro,
+) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = problems
+        .iter()
+        .flat_map(Diagnostic::from_kickstart)
+        .collect();
+
+    for section in kickstart.sections() {
+        if section.kind() == SectionKind::Unknown {
+            diagnostics.push(
+                Diagnostic::new(
+                    "unknown-section",
+                    Severity::Error,
+                    format!("'{}' is not a kickstart section", section.name()),
+                )
+                .at(Some(section.span())),
+            );
+        }
+
+        if section.kind() == SectionKind::Packages {
+            for (line, span) in section.lines() {
+                if let Some(reason) = distro.unknown_group(line.trim()) {
+                    diagnostics.push(
+                        Diagnostic::new("unknown-environment", Severity::Warning, reason).at(span),
+                    );
+                }
+            }
+        }
+
+        if section.kind() != SectionKind::Command {
+            continue;
+        }
+
+        for (line, span) in section.lines() {
+            let words = match words::split(line) {
+                Ok(words) => words,
+                Err(err) => {
+                    diagnostics.push(
+                        Diagnostic::new("parse", Severity::Error, format!("'{}': {}", line, err))
+                            .at(span),
+                    );
+                    continue;
+                }
+            };
+
+            let (name, args) = match words.split_first() {
+                Some((name, args)) => (name.as_str(), args),
+                None => continue,
+            };
+
+            if deprecated::lookup(name).is_none() && !dialect::is_command(name) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        "unknown-command",
+                        Severity::Error,
+                        format!("'{}' is not a kickstart command", name),
+                    )
+                    .at(span),
+                );
+                continue;
+            }
+
+            if let Err(err) = dialect::check(name, args, version) {
+                diagnostics.push(Diagnostic::new("not-in-version", Severity::Error, err).at(span));
+            }
+
+            if let Some(reason) = distro.unsupported(name) {
+                diagnostics
+                    .push(Diagnostic::new("not-in-distro", Severity::Warning, reason).at(span));
+            }
+
+            for entry in deprecated::find(name, args) {
+                let diagnostic = if entry.is_removed(version) {
+                    Diagnostic::new("removed-command", Severity::Error, entry.removal(version))
+                } else if entry.is_deprecated(version) {
+                    Diagnostic::new(
+                        "deprecated-command",
+                        Severity::Warning,
+                        format!("'{}' is {}", entry.subject(), entry.diagnostic()),
+                    )
+                } else {
+                    continue;
+                };
+
+                diagnostics.push(diagnostic.at(span));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+#[test]
+fn validate_kickstart() {
+    use std::fs;
+
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-validate-kickstart");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "lang en_US\ninteractive\nautostep\nbogus --yes\n%bogus\n%end\n%end\n\
+         timezone UTC --ntpservers=ntp.example\ntimesource --ntp-server=ntp.example\n",
+    )
+    .unwrap();
+
+    let codes = |version: Version| {
+        let (kickstart, problems) =
+            Kickstart::read(&dir.join("main.ks"), &includes, version).unwrap();
+
+        validate(&kickstart, &problems, version, &Distro::default())
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.line))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        codes(Version::LATEST),
+        vec![
+            ("parse", Some(7)),
+            ("unknown-section", Some(5)),
+            ("removed-command", Some(2)),
+            ("deprecated-command", Some(3)),
+            ("unknown-command", Some(4)),
+            ("deprecated-command", Some(8)),
+        ]
+    );
+    assert_eq!(
+        codes(Version::Fedora(14)),
+        vec![
+            ("parse", Some(7)),
+            ("unknown-section", Some(5)),
+            ("deprecated-command", Some(2)),
+            ("unknown-command", Some(4)),
+            ("not-in-version", Some(9)),
+        ]
+    );
+}
+
+#[test]
+fn validate_distro() {
+    use std::fs;
+
+    use crate::distro::Definitions;
+    use crate::kickstart::Includes;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-validate-distro");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "syspurpose --role=server\n%packages\n@^kde-desktop-environment\n@core\n%end\n",
+    )
+    .unwrap();
+
+    let (kickstart, problems) =
+        Kickstart::read(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+
+    let codes = |distro: &Distro| {
+        validate(&kickstart, &problems, distro.version, distro)
+            .iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.line))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(codes(&Distro::default()), vec![("not-in-distro", Some(1))]);
+    assert_eq!(
+        codes(Definitions::default().lookup("rhel").unwrap()),
+        vec![("unknown-environment", Some(3))]
+    );
+}
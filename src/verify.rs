@@ -0,0 +1,235 @@
+//! Verification runs a manifest through `osbuild --inspect`, which checks it against the schemas
+//! of the stages of the installed osbuild. Its errors point into the manifest, they are pointed
+//! back at the line of the kickstart the stage most likely comes from.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::process::Command;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::kickstart::{Kickstart, SectionKind, Span};
+
+/// The commands and sections that stages come from, a stage isn't always from the first one that
+/// is in the kickstart.
+const ORIGINS: &[(&str, &[&str])] = &[
+    ("org.osbuild.kernel-cmdline", &["bootloader"]),
+    ("org.osbuild.rpm", &["%packages"]),
+    (
+        "org.osbuild.fstab",
+        &["part", "partition", "logvol", "autopart", "reqpart"],
+    ),
+    ("org.osbuild.grub2", &["bootloader"]),
+    ("org.osbuild.grub2.inst", &["bootloader"]),
+    ("org.osbuild.zipl", &["zipl", "bootloader"]),
+    ("org.osbuild.zipl.inst", &["zipl", "bootloader"]),
+    ("org.osbuild.timezone", &["timezone"]),
+    ("org.osbuild.chrony", &["timesource", "timezone"]),
+    ("org.osbuild.modprobe", &["device"]),
+    ("org.osbuild.pki.update-ca-trust", &["%certificate"]),
+    (
+        "org.osbuild.authselect",
+        &["authselect", "auth", "authconfig"],
+    ),
+    ("org.osbuild.rhsm", &["rhsm"]),
+    ("org.osbuild.systemd.unit.create", &["%post"]),
+    (
+        "org.osbuild.systemd",
+        &["firstboot", "logging", "graphical", "text", "%addon"],
+    ),
+    ("org.osbuild.oscap.remediation", &["%addon"]),
+    (
+        "org.osbuild.sfdisk",
+        &["part", "partition", "autopart", "reqpart"],
+    ),
+    (
+        "org.osbuild.lvm2.create",
+        &["volgroup", "logvol", "autopart"],
+    ),
+    (
+        "org.osbuild.mkfs.ext4",
+        &["part", "partition", "logvol", "autopart"],
+    ),
+    (
+        "org.osbuild.mkfs.xfs",
+        &["part", "partition", "logvol", "autopart"],
+    ),
+    (
+        "org.osbuild.mkfs.btrfs",
+        &["part", "partition", "logvol", "autopart"],
+    ),
+    (
+        "org.osbuild.mkfs.fat",
+        &["part", "partition", "reqpart", "autopart"],
+    ),
+    ("org.osbuild.mkswap", &["part", "partition", "logvol"]),
+];
+
+/// What `osbuild --inspect` prints when the manifest isn't valid.
+#[derive(Deserialize)]
+struct Validation {
+    #[serde(default)]
+    errors: Vec<ValidationError>,
+}
+
+#[derive(Deserialize)]
+struct ValidationError {
+    message: String,
+    /// The keys and indices leading to what isn't valid.
+    #[serde(default)]
+    path: Vec<Value>,
+}
+
+/// Check `manifest`, which was translated from `kickstart`, with osbuild. It's an error when
+/// osbuild isn't installed or doesn't say what's wrong.
+pub fn verify(manifest: &str, kickstart: &Kickstart) -> Result<Vec<Diagnostic>, String> {
+    let path = std::env::temp_dir().join(format!("osbuild-ks-verify-{}.json", std::process::id()));
+
+    fs::write(&path, manifest)
+        .map_err(|err| format!("failed to write '{}': {}", path.display(), err))?;
+
+    let output = Command::new("osbuild").arg("--inspect").arg(&path).output();
+    let _ = fs::remove_file(&path);
+
+    let output = match output {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            return Err("osbuild isn't installed".to_string())
+        }
+        Err(err) => return Err(format!("failed to run osbuild: {}", err)),
+    };
+
+    if output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let validation: Validation = serde_json::from_slice(&output.stdout).map_err(|_| {
+        format!(
+            "osbuild failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    })?;
+
+    let manifest: Value = serde_json::from_str(manifest).unwrap_or_default();
+
+    Ok(validation
+        .errors
+        .iter()
+        .map(|error| diagnostic(error, &manifest, kickstart))
+        .collect())
+}
+
+/// The diagnostic for `error`, at the line of the kickstart the stage it's in comes from.
+fn diagnostic(error: &ValidationError, manifest: &Value, kickstart: &Kickstart) -> Diagnostic {
+    let mut location = String::new();
+    let mut node = manifest;
+    let mut stage = None;
+
+    for (index, step) in error.path.iter().enumerate() {
+        node = match step {
+            Value::String(key) => {
+                location.push_str(&format!(".{}", key));
+                &node[key.as_str()]
+            }
+            Value::Number(number) => {
+                location.push_str(&format!("[{}]", number));
+                &node[number.as_u64().unwrap_or_default() as usize]
+            }
+            _ => break,
+        };
+
+        // Stages have a `type` in version 2 manifests and a `name` in version 1 manifests.
+        if index > 0 && error.path[index - 1] == "stages" {
+            stage = node["type"].as_str().or_else(|| node["name"].as_str());
+        }
+    }
+
+    let message = if location.is_empty() {
+        error.message.clone()
+    } else {
+        format!("{}: {}", location, error.message)
+    };
+
+    Diagnostic::new("invalid-manifest", Severity::Error, message)
+        .at(stage.and_then(|stage| origin(kickstart, stage)))
+}
+
+/// Where in `kickstart` the stage of type `stage` most likely comes from.
+fn origin<'a>(kickstart: &'a Kickstart, stage: &str) -> Option<&'a Span> {
+    let (_, origins) = ORIGINS.iter().find(|(kind, _)| *kind == stage)?;
+
+    for section in kickstart.sections() {
+        if section.kind() != SectionKind::Command {
+            if origins.contains(&section.name()) {
+                return Some(section.span());
+            }
+
+            continue;
+        }
+
+        for (line, span) in section.lines() {
+            let name = line.split_whitespace().next().unwrap_or_default();
+
+            if origins.contains(&name) {
+                return span;
+            }
+        }
+    }
+
+    None
+}
+
+#[test]
+fn verify_diagnostic() {
+    use serde_json::json;
+
+    use crate::kickstart::Includes;
+    use crate::version::Version;
+
+    let dir = std::env::temp_dir().join("osbuild-ks-verify-diagnostic");
+    fs::create_dir_all(&dir).unwrap();
+    let includes = Includes::new(&[&dir]).unwrap();
+
+    fs::write(
+        dir.join("main.ks"),
+        "lang en_US\ntimesource --ntp-server=ntp.example\ntimezone Bogus/Zone\n",
+    )
+    .unwrap();
+
+    let kickstart = Kickstart::from_path(&dir.join("main.ks"), &includes, Version::LATEST).unwrap();
+    let manifest = json!({
+        "pipelines": [
+            { "name": "build", "stages": [{ "type": "org.osbuild.rpm" }] },
+            { "name": "os", "stages": [
+                { "type": "org.osbuild.rpm" },
+                { "type": "org.osbuild.timezone", "options": { "zone": "Bogus/Zone" } },
+            ] },
+        ],
+    });
+
+    let validation: Validation = serde_json::from_value(json!({
+        "errors": [
+            {
+                "message": "'Bogus/Zone' does not match '^[A-Za-z]+/[A-Za-z_]+$'",
+                "path": ["pipelines", 1, "stages", 1, "options", "zone"],
+            },
+            { "message": "'sources' is a required property", "path": [] },
+        ],
+    }))
+    .unwrap();
+
+    let diagnostics: Vec<_> = validation
+        .errors
+        .iter()
+        .map(|error| diagnostic(error, &manifest, &kickstart))
+        .collect();
+
+    assert_eq!(diagnostics[0].line, Some(3));
+    assert!(diagnostics[0]
+        .message
+        .starts_with(".pipelines[1].stages[1].options.zone: "));
+    assert_eq!(diagnostics[1].line, None);
+    assert_eq!(diagnostics[1].message, "'sources' is a required property");
+}
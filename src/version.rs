@@ -0,0 +1,121 @@
+//! Kickstart syntax versions. The syntax follows the releases of Fedora and RHEL, pykickstart
+//! names them `FC6`, `F38`, `RHEL9` and so on. Every RHEL release branched from a Fedora release
+//! and has the syntax of that release.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// Versions are written as they are given to `--ks-version` in definition files.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum Version {
+    Fedora(u32),
+    Rhel(u32),
+}
+
+/// The Fedora release each RHEL release branched from.
+const RHEL_BASES: &[(u32, u32)] = &[(5, 6), (6, 12), (7, 19), (8, 28), (9, 34), (10, 40)];
+
+impl Version {
+    /// The version used when none is given.
+    pub const LATEST: Version = Version::Fedora(42);
+
+    /// The first version in which sections have to end with `%end`.
+    pub const END_REQUIRED: Version = Version::Fedora(8);
+
+    /// The first version in which root can't log in over SSH with a password unless
+    /// `rootpw --allow-ssh` says so.
+    pub const ROOT_SSH_DENIED: Version = Version::Fedora(37);
+
+    /// The Fedora release whose syntax this version has.
+    pub fn fedora(self) -> u32 {
+        match self {
+            Version::Fedora(release) => release,
+            Version::Rhel(release) => RHEL_BASES
+                .iter()
+                .rev()
+                .find(|(rhel, _)| *rhel <= release)
+                .map_or(0, |(_, fedora)| *fedora),
+        }
+    }
+
+    /// Whether sections have to end with `%end`, older kickstarts end a section where the next
+    /// one starts.
+    pub fn requires_end(self) -> bool {
+        self.fedora() >= Version::END_REQUIRED.fedora()
+    }
+
+    /// Whether root can log in over SSH with a password without `rootpw --allow-ssh`.
+    pub fn root_ssh_password(self) -> bool {
+        self.fedora() < Version::ROOT_SSH_DENIED.fedora()
+    }
+}
+
+impl Default for Version {
+    fn default() -> Self {
+        Version::LATEST
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+
+        let (constructor, release): (fn(u32) -> Version, &str) =
+            if let Some(release) = upper.strip_prefix("RHEL") {
+                (Version::Rhel, release)
+            } else if let Some(release) = upper.strip_prefix("FC") {
+                (Version::Fedora, release)
+            } else if let Some(release) = upper.strip_prefix('F') {
+                (Version::Fedora, release)
+            } else {
+                return Err(format!(
+                    "'{}' is not a kickstart version such as F38 or RHEL9",
+                    s
+                ));
+            };
+
+        release
+            .parse()
+            .map(constructor)
+            .map_err(|_| format!("'{}' is not a kickstart version such as F38 or RHEL9", s))
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Version::Fedora(release) if *release < 7 => write!(f, "FC{}", release),
+            Version::Fedora(release) => write!(f, "F{}", release),
+            Version::Rhel(release) => write!(f, "RHEL{}", release),
+        }
+    }
+}
+
+#[test]
+fn version_parse() {
+    assert_eq!("F38".parse(), Ok(Version::Fedora(38)));
+    assert_eq!("fc6".parse(), Ok(Version::Fedora(6)));
+    assert_eq!("RHEL9".parse(), Ok(Version::Rhel(9)));
+    assert!("Ubuntu22".parse::<Version>().is_err());
+    assert!("F".parse::<Version>().is_err());
+
+    assert_eq!(Version::Fedora(6).to_string(), "FC6");
+    assert_eq!(Version::Rhel(9).fedora(), 34);
+    assert!(!Version::Rhel(5).requires_end());
+    assert!(Version::Rhel(6).requires_end());
+    assert!(Version::Rhel(9).root_ssh_password());
+    assert!(!Version::Fedora(38).root_ssh_password());
+}
@@ -0,0 +1,141 @@
+//! Warnings can be made errors or silenced by their code, with `-W` or in a config file that is
+//! shared between the kickstarts of a project. A code also stands for the codes that start with
+//! it and a dash, `deprecated` covers `deprecated-command`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::diagnostics::{Diagnostic, Severity};
+
+/// What becomes of the warnings with a code.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Error,
+    Warn,
+    Ignore,
+}
+
+/// The config file, codes are given an action: `{"warnings": {"deprecated": "error"}}`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    #[serde(default)]
+    warnings: BTreeMap<String, Action>,
+}
+
+/// What becomes of warnings, the last control for a code wins.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Controls {
+    controls: Vec<(String, Action)>,
+}
+
+impl Controls {
+    /// Control warnings as the config file at `path` says.
+    pub fn config(mut self, path: &Path) -> Result<Self, String> {
+        let data = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read '{}': {}", path.display(), err))?;
+        let config: Config = serde_json::from_str(&data)
+            .map_err(|err| format!("failed to parse '{}': {}", path.display(), err))?;
+
+        self.controls.extend(config.warnings);
+
+        Ok(self)
+    }
+
+    /// Control warnings as `-W` says: `error=<code>` makes them errors, `no-<code>` silences them
+    /// and `<code>` keeps them warnings.
+    pub fn add(mut self, control: &str) -> Result<Self, String> {
+        let (code, action) = match control.split_once('=') {
+            Some(("error", code)) => (code, Action::Error),
+            Some(_) => return Err(format!("'{}' is not a warning control", control)),
+            None => match control.strip_prefix("no-") {
+                Some(code) => (code, Action::Ignore),
+                None => (control, Action::Warn),
+            },
+        };
+
+        if code.is_empty() {
+            return Err(format!("'{}' doesn't name a warning", control));
+        }
+
+        self.controls.push((code.to_string(), action));
+
+        Ok(self)
+    }
+
+    fn action(&self, code: &str) -> Option<Action> {
+        self.controls
+            .iter()
+            .rev()
+            .find(|(name, _)| {
+                code == name
+                    || code
+                        .strip_prefix(name.as_str())
+                        .is_some_and(|rest| rest.starts_with('-'))
+            })
+            .map(|(_, action)| *action)
+    }
+
+    /// `diagnostics` with their warnings and notes controlled, errors are left alone.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                if diagnostic.severity == Severity::Error {
+                    return Some(diagnostic);
+                }
+
+                match self.action(diagnostic.code) {
+                    Some(Action::Ignore) => return None,
+                    Some(Action::Error) => diagnostic.severity = Severity::Error,
+                    Some(Action::Warn) | None => {}
+                }
+
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn warnings_controls() {
+    let diagnostics = vec![
+        Diagnostic::new("deprecated-command", Severity::Warning, "x".to_string()),
+        Diagnostic::new("skipped", Severity::Warning, "y".to_string()),
+        Diagnostic::new("plaintext-password", Severity::Warning, "z".to_string()),
+        Diagnostic::new("parse", Severity::Error, "w".to_string()),
+    ];
+
+    let controls = Controls::default()
+        .add("no-deprecated")
+        .unwrap()
+        .add("error=skipped")
+        .unwrap()
+        .add("no-plaintext")
+        .unwrap()
+        .add("plaintext-password")
+        .unwrap()
+        .add("no-parse")
+        .unwrap();
+
+    let severities: Vec<_> = controls
+        .apply(diagnostics)
+        .iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.severity))
+        .collect();
+
+    assert_eq!(
+        severities,
+        [
+            ("skipped", Severity::Error),
+            ("plaintext-password", Severity::Warning),
+            ("parse", Severity::Error),
+        ]
+    );
+    assert!(Controls::default().add("warn=skipped").is_err());
+    assert!(Controls::default().add("error=").is_err());
+}
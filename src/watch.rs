@@ -0,0 +1,86 @@
+//! Waiting for a kickstart or what it includes to change, for `convert --watch`. The files are
+//! polled for when they were last modified, which works the same on every filesystem.
+
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How long to wait between looking at the files.
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Files with when they were last modified, those that are gone have no time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Files {
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Files {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let files = paths
+            .into_iter()
+            .map(|path| {
+                let modified = modified(&path);
+                (path, modified)
+            })
+            .collect();
+
+        Self { files }
+    }
+
+    /// The files that were modified, created or removed since they were looked at.
+    pub fn changed(&self) -> Vec<&PathBuf> {
+        self.files
+            .iter()
+            .filter(|(path, modified)| self::modified(path) != *modified)
+            .map(|(path, _)| path)
+            .collect()
+    }
+
+    /// Wait until any of the files changes, and return those that did.
+    pub fn wait(&self) -> Vec<&PathBuf> {
+        loop {
+            let changed = self.changed();
+
+            if !changed.is_empty() {
+                return changed;
+            }
+
+            thread::sleep(INTERVAL);
+        }
+    }
+}
+
+#[test]
+fn watch_files() {
+    let dir = std::env::temp_dir().join("osbuild-ks-watch-files");
+    fs::create_dir_all(&dir).unwrap();
+
+    let main = dir.join("main.ks");
+    let part = dir.join("part.ks");
+
+    fs::write(&main, "%include part.ks\n").unwrap();
+    fs::write(&part, "timezone UTC\n").unwrap();
+
+    let files = Files::new(vec![main.clone(), part.clone()]);
+    assert!(files.changed().is_empty());
+
+    let later = SystemTime::now() + Duration::from_secs(60);
+    fs::File::options()
+        .write(true)
+        .open(&part)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+
+    assert_eq!(files.wait(), [&part]);
+
+    fs::remove_file(&main).unwrap();
+    assert_eq!(files.changed(), [&main, &part]);
+}
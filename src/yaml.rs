@@ -0,0 +1,182 @@
+//! YAML is written for manifests that are read or diffed by hand, it holds the same values as the
+//! JSON. Only what a manifest needs is written: mappings and sequences in block style, strings
+//! plain where YAML reads them back as strings and multi-line strings as literal blocks.
+
+use serde_json::{Map, Value};
+
+/// The YAML document of `value`.
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+
+    match value {
+        Value::Object(map) if !map.is_empty() => mapping(&mut out, map, 0),
+        Value::Array(items) if !items.is_empty() => sequence(&mut out, items, 0),
+        _ => {
+            out.push_str(&scalar(value, 0));
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn mapping(out: &mut String, map: &Map<String, Value>, indent: usize) {
+    for (key, value) in map {
+        out.push_str(&" ".repeat(indent));
+        out.push_str(&string(key, None));
+        out.push(':');
+        node(out, value, indent);
+    }
+}
+
+fn sequence(out: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        out.push_str(&" ".repeat(indent));
+        out.push('-');
+
+        match item {
+            // The first key goes on the line of the dash.
+            Value::Object(map) if !map.is_empty() => {
+                let mut nested = String::new();
+                mapping(&mut nested, map, indent + 2);
+                out.push(' ');
+                out.push_str(&nested[indent + 2..]);
+            }
+            item => node(out, item, indent),
+        }
+    }
+}
+
+/// Write `value` after the key or dash of a node at `indent`.
+fn node(out: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            out.push('\n');
+            mapping(out, map, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            out.push('\n');
+            sequence(out, items, indent + 2);
+        }
+        value => {
+            out.push(' ');
+            out.push_str(&scalar(value, indent + 2));
+            out.push('\n');
+        }
+    }
+}
+
+fn scalar(value: &Value, indent: usize) -> String {
+    match value {
+        Value::String(s) => string(s, Some(indent)),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// `s` as a scalar, a literal block indented by `indent` if it's given and `s` has more than one
+/// line. Everything else that can't be plain is double quoted, JSON strings are valid there.
+fn string(s: &str, indent: Option<usize>) -> String {
+    if let Some(indent) = indent.filter(|_| is_literal(s)) {
+        let mut block = String::from(if s.ends_with('\n') { "|" } else { "|-" });
+
+        for line in s.strip_suffix('\n').unwrap_or(s).split('\n') {
+            block.push('\n');
+
+            // Empty lines aren't indented, that would add trailing whitespace.
+            if !line.is_empty() {
+                block.push_str(&" ".repeat(indent));
+                block.push_str(line);
+            }
+        }
+
+        return block;
+    }
+
+    if is_plain(s) {
+        return s.to_string();
+    }
+
+    serde_json::to_string(s).unwrap()
+}
+
+/// Whether `s` can be a literal block that reads back the same. Blocks keep one trailing newline
+/// at most and the indentation of their first line is that of the block.
+fn is_literal(s: &str) -> bool {
+    s.contains('\n')
+        && !s.starts_with([' ', '\n'])
+        && !s.ends_with("\n\n")
+        && !s.chars().any(|c| c.is_control() && c != '\n' && c != '\t')
+}
+
+/// Whether `s` reads back as the same string without quotes, rather than as another type or not
+/// at all.
+fn is_plain(s: &str) -> bool {
+    const RESERVED: &[&str] = &["true", "false", "null", "yes", "no", "on", "off", "y", "n"];
+
+    let mut chars = s.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '/')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_./:@+=-".contains(c))
+        && !s.ends_with(':')
+        && !RESERVED.contains(&s.to_ascii_lowercase().as_str())
+}
+
+#[test]
+fn yaml_to_string() {
+    use serde_json::json;
+
+    let value = json!({
+        "version": "2",
+        "pipelines": [
+            {
+                "name": "os",
+                "stages": [
+                    {
+                        "type": "org.osbuild.kickstart",
+                        "options": {
+                            "kickstart": "lang en_US\n\n%post\necho hi\n",
+                            "path": "/osbuild.ks",
+                            "script": "  indented",
+                        },
+                    },
+                ],
+            },
+        ],
+        "sources": {},
+        "list": [["yes", 1], [], null, true, "", "a: b", "1.0", "x\n"],
+    });
+
+    // Keys are sorted by serde_json.
+    assert_eq!(
+        to_string(&value),
+        "list:\n  \
+           -\n    \
+             - \"yes\"\n    \
+             - 1\n  \
+           - []\n  \
+           - null\n  \
+           - true\n  \
+           - \"\"\n  \
+           - \"a: b\"\n  \
+           - \"1.0\"\n  \
+           - |\n    \
+             x\n\
+         pipelines:\n  \
+           - name: os\n    \
+             stages:\n      \
+               - options:\n          \
+                   kickstart: |\n            \
+                     lang en_US\n\n            \
+                     %post\n            \
+                     echo hi\n          \
+                   path: /osbuild.ks\n          \
+                   script: \"  indented\"\n        \
+                 type: org.osbuild.kickstart\n\
+         sources: {}\n\
+         version: \"2\"\n"
+    );
+}